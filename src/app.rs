@@ -1,92 +1,439 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
 
 use crate::{
+    bloom::BloomFilter,
     database::Database,
-    utils::compute_fee_rate,
-    worker::{get_absolute_fee, Task, TaskContext},
-    zmq_factory::BitcoinZmqFactory,
+    rpc::ResilientClient,
+    shutdown_snapshot::ShutdownSnapshot,
+    store::Store,
+    utils::{compute_fee_rate, get_inputs_hash, is_batch_payout, is_inputs_sampled},
+    worker::{get_absolute_fee, InputsLocks, QueueLag, Task, TaskContext, WorkerConfig},
+    zmq_factory::{BitcoinZmqFactory, SequenceGaps, ZmqTopic},
 };
 
 use anyhow::Result;
-use async_channel::{bounded, Receiver, Sender};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
+use bitcoin::{Amount, FeeRate, Transaction, Txid};
+use bitcoincore_zmq::Message;
 use bitcoind_async_client::{traits::Reader, Client};
-use futures_util::{future, StreamExt};
-use log::{error, info};
-use tokio::signal::ctrl_c;
+use futures_util::{future, stream, StreamExt};
+use log::{debug, error, info, warn};
+use tokio::{signal::ctrl_c, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
-pub struct App {
+pub struct App<S: Store = Database> {
     zmq_factory: BitcoinZmqFactory,
-    db: Database,
+    db: S,
     tasks_tx: Sender<Task>,
     tasks_rx: Receiver<Task>,
+    /// Scheduled tasks, which workers take before any queued tx
+    maintenance_tx: Sender<Task>,
+    maintenance_rx: Receiver<Task>,
     rpc_client: Client,
     num_workers: usize,
-    mempool_state_check_interval: Duration,
+    /// No mempool state snapshots are taken when unset
+    mempool_state_check_interval: Option<Duration>,
     prune_check_interval: Duration,
     disable_prune_check: bool,
     mining_info_interval: Option<Duration>,
+    audit_interval: Option<Duration>,
+    node_info_interval: Option<Duration>,
+    /// Retry deferred fee lookups this often, see `WorkerConfig::max_fee_retries`
+    recompute_fees_interval: Option<Duration>,
+    /// Cross-check stuck txs with the node this often, see `WorkerConfig::stuck_tx_age`
+    stuck_tx_check_interval: Option<Duration>,
+    /// Hint at more workers when the task queue stays deep, sampled every `QUEUE_LAG_SAMPLE_INTERVAL`
+    queue_lag: Option<QueueLag>,
+    /// How many mempool txs are fetched in parallel during the startup scan
+    startup_concurrency: usize,
+    memory_limits: MemoryLimits,
+    overflow_policies: OverflowPolicies,
+    shutdown_snapshot: Option<ShutdownSnapshot>,
+    worker_config: WorkerConfig,
+    known_inputs: Arc<BloomFilter>,
+    /// Spawned in `init`, awaited on shutdown
+    worker_handles: Vec<JoinHandle<Result<()>>>,
+}
+
+const KNOWN_INPUTS_FALSE_POSITIVE_RATE: f64 = 0.01;
+/// Scheduled tasks are few and taken first, a short queue is plenty
+const MAINTENANCE_CHANNEL_CAPACITY: usize = 64;
+/// Shards of the per inputs hash lock shared by workers
+const INPUTS_LOCK_SHARDS: usize = 256;
+/// How long shutdown waits for workers to drain the queued tasks
+const WORKER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the task queue depth is sampled when `queue_lag` is set
+const QUEUE_LAG_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Typical memory held by a queued rawtx task, including allocation overhead
+const TASK_BYTES: u64 = 1_024;
+/// Bits per key of a bloom filter at `KNOWN_INPUTS_FALSE_POSITIVE_RATE`, rounded up
+const KNOWN_INPUTS_BITS_PER_KEY: u64 = 10;
+/// Typical memory held by a recent script, a p2wpkh to p2tr script with its map and queue entries
+const RECENT_SCRIPT_BYTES: u64 = 96;
+/// Below this the task queue is too short to absorb a burst of txs
+pub const MIN_MEMORY_MB: u64 = 16;
+
+/// Sizes of the bounded in-memory structures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimits {
+    pub task_channel_capacity: usize,
+    /// Minimum number of inputs hashes the `tx_exists` filter is sized for
+    pub known_inputs_capacity: usize,
+    /// Most inputs hashes the filter is sized for however many the db holds, unbounded if None
+    pub max_known_inputs_capacity: Option<usize>,
+    /// Most scripts `--address-reuse-scripts` may keep, unbounded if None
+    pub max_recent_scripts: Option<usize>,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            task_channel_capacity: 100_000,
+            known_inputs_capacity: 1_000_000,
+            max_known_inputs_capacity: None,
+            max_recent_scripts: None,
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// Split a memory budget: half for the task queue, a quarter for the known inputs filter,
+    /// an eighth for the recent scripts, the rest is left for the db pool and per tx processing
+    pub fn from_max_memory_mb(max_memory_mb: u64) -> Result<Self> {
+        if max_memory_mb < MIN_MEMORY_MB {
+            return Err(anyhow::anyhow!(
+                "max memory must be at least {} MB",
+                MIN_MEMORY_MB
+            ));
+        }
+        let budget = max_memory_mb * 1024 * 1024;
+        let known_inputs_capacity = (budget / 4 * 8 / KNOWN_INPUTS_BITS_PER_KEY) as usize;
+        Ok(Self {
+            task_channel_capacity: (budget / 2 / TASK_BYTES) as usize,
+            known_inputs_capacity,
+            max_known_inputs_capacity: Some(known_inputs_capacity),
+            max_recent_scripts: Some((budget / 8 / RECENT_SCRIPT_BYTES) as usize),
+        })
+    }
+
+    /// Capacity of the known inputs filter seeded with `inputs_hashes` hashes, leaving room to grow
+    pub fn known_inputs_filter_capacity(&self, inputs_hashes: usize) -> usize {
+        let capacity = (inputs_hashes * 2).max(self.known_inputs_capacity);
+        self.max_known_inputs_capacity
+            .map_or(capacity, |max| capacity.min(max))
+    }
+}
+
+/// What to do with a task when the task queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for room. For rawtx this stops reading the zmq socket, so bitcoind may drop
+    /// messages once its high-water mark is reached.
+    #[default]
+    Block,
+    /// Make room by dropping the oldest queued task, a tx on the rawtx queue
+    DropOldest,
+    /// Drop the task
+    DropNewest,
+}
+
+impl OverflowPolicy {
+    pub const ALL: [OverflowPolicy; 3] = [
+        OverflowPolicy::Block,
+        OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop-oldest",
+            OverflowPolicy::DropNewest => "drop-newest",
+        }
+    }
+
+    /// Queue `task` following the policy, returns whether a task was dropped
+    pub async fn send(
+        self,
+        tasks_tx: &Sender<Task>,
+        tasks_rx: &Receiver<Task>,
+        task: Task,
+    ) -> Result<bool> {
+        if self == OverflowPolicy::Block {
+            tasks_tx.send(task).await?;
+            return Ok(false);
+        }
+        let mut task = task;
+        let mut dropped = false;
+        loop {
+            match tasks_tx.try_send(task) {
+                Ok(()) => return Ok(dropped),
+                Err(TrySendError::Full(_)) if self == OverflowPolicy::DropNewest => {
+                    return Ok(true)
+                }
+                Err(TrySendError::Full(full)) => {
+                    task = full;
+                    // Fails when workers emptied the queue since, the retry then has room
+                    if tasks_rx.try_recv().is_ok() {
+                        dropped = true;
+                    }
+                }
+                Err(TrySendError::Closed(_)) => return Err(anyhow::anyhow!("task queue closed")),
+            }
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-impl App {
+impl FromStr for OverflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OverflowPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| {
+                let known = OverflowPolicy::ALL.map(|policy| policy.name()).join(", ");
+                anyhow::anyhow!("Unknown overflow policy {}, expected one of {}", s, known)
+            })
+    }
+}
+
+/// Overflow policies by kind of task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverflowPolicies {
+    /// Txs from zmq
+    pub raw_tx: OverflowPolicy,
+    /// Mempool state, prune check and the other periodic tasks
+    pub scheduled: OverflowPolicy,
+}
+
+impl<S: Store> App<S> {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         rpc_client: Client,
         zmq_factory: BitcoinZmqFactory,
-        db: Database,
+        db: S,
         num_workers: usize,
-        mempool_state_check_interval: Duration,
+        mempool_state_check_interval: Option<Duration>,
         prune_check_interval: Duration,
         disable_prune_check: bool,
         mining_info_interval: Option<Duration>,
+        audit_interval: Option<Duration>,
+        node_info_interval: Option<Duration>,
+        recompute_fees_interval: Option<Duration>,
+        stuck_tx_check_interval: Option<Duration>,
+        queue_lag: Option<QueueLag>,
+        startup_concurrency: usize,
+        memory_limits: MemoryLimits,
+        overflow_policies: OverflowPolicies,
+        shutdown_snapshot: Option<ShutdownSnapshot>,
+        worker_config: WorkerConfig,
     ) -> Self {
-        let (sender, receiver) = bounded(100_000);
+        info!(
+            "Task queue capacity {}, known inputs filter capacity {}",
+            memory_limits.task_channel_capacity, memory_limits.known_inputs_capacity
+        );
+        let (sender, receiver) = bounded(memory_limits.task_channel_capacity);
+        let (maintenance_tx, maintenance_rx) = bounded(MAINTENANCE_CHANNEL_CAPACITY);
         Self {
             rpc_client,
             zmq_factory,
             db,
             tasks_tx: sender,
             tasks_rx: receiver,
+            maintenance_tx,
+            maintenance_rx,
             num_workers,
             mempool_state_check_interval,
             prune_check_interval,
             disable_prune_check,
             mining_info_interval,
+            audit_interval,
+            node_info_interval,
+            recompute_fees_interval,
+            stuck_tx_check_interval,
+            queue_lag,
+            startup_concurrency,
+            memory_limits,
+            overflow_policies,
+            shutdown_snapshot,
+            worker_config,
+            known_inputs: Arc::new(BloomFilter::new(
+                memory_limits.known_inputs_capacity,
+                KNOWN_INPUTS_FALSE_POSITIVE_RATE,
+            )),
+            worker_handles: vec![],
+        }
+    }
+
+    /// Wait for the workers to finish the queued tasks once `tasks_tx` and `maintenance_tx` are closed.
+    /// The first worker error or panic is returned after every worker is awaited, workers still
+    /// running after `WORKER_SHUTDOWN_TIMEOUT` are aborted and an error is returned.
+    async fn join_workers(&mut self) -> Result<()> {
+        let handles = std::mem::take(&mut self.worker_handles);
+        let abort_handles = handles
+            .iter()
+            .map(|handle| handle.abort_handle())
+            .collect::<Vec<_>>();
+        let joined = tokio::time::timeout(WORKER_SHUTDOWN_TIMEOUT, future::join_all(handles)).await;
+        let results = match joined {
+            Ok(results) => results,
+            Err(_) => {
+                for abort_handle in abort_handles {
+                    abort_handle.abort();
+                }
+                return Err(anyhow::anyhow!(
+                    "Workers did not finish within {:?}, aborted them with tasks still queued",
+                    WORKER_SHUTDOWN_TIMEOUT
+                ));
+            }
+        };
+        let mut first_error = None;
+        for result in results {
+            let error = match result {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => anyhow::anyhow!("Worker failed: {}", e),
+                Err(e) if e.is_panic() => anyhow::anyhow!("Worker panicked: {}", e),
+                Err(e) => anyhow::anyhow!("Worker was cancelled: {}", e),
+            };
+            error!("{}", error);
+            first_error.get_or_insert(error);
         }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Rebuild the `tx_exists` filter from every inputs hash in the db
+    async fn seed_known_inputs(&mut self) -> Result<()> {
+        let inputs_hashes = self.db.inputs_hashes().await?;
+        let capacity = self
+            .memory_limits
+            .known_inputs_filter_capacity(inputs_hashes.len());
+        if inputs_hashes.len() > capacity {
+            warn!(
+                "{} inputs hashes exceed the known inputs filter capacity {}, expect more false positives",
+                inputs_hashes.len(),
+                capacity
+            );
+        }
+        let known_inputs = BloomFilter::new(capacity, KNOWN_INPUTS_FALSE_POSITIVE_RATE);
+        for inputs_hash in inputs_hashes.iter() {
+            known_inputs.insert(inputs_hash);
+        }
+        info!("Seeded known inputs with {} hashes", inputs_hashes.len());
+        self.known_inputs = Arc::new(known_inputs);
+        Ok(())
     }
 
     async fn extract_existing_mempool(&self) -> Result<()> {
         // let bitcoind = connect_bitcoind(&self.bitcoind_url, self.bitcoind_auth.clone())?;
         let mempool = self.rpc_client.get_raw_mempool_verbose().await?;
         info!("Found {} transactions in mempool", mempool.len());
+        // Keep txs tracked before the restart so their found_at is preserved
+        let kept = self
+            .db
+            .remove_stale_txs(mempool.keys().copied().collect())
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        info!("Kept {} txs still in mempool", kept.len());
 
-        for (txid, mempool_tx) in mempool.iter() {
-            let pool_entrance_time = mempool_tx.time;
-            match self
-                .rpc_client
-                .get_raw_transaction_verbosity_zero(txid)
-                .await
-            {
-                Ok(tx_info) => {
-                    let tx = tx_info.transaction()?;
-                    let absolute_fee = get_absolute_fee(&tx, &self.rpc_client).await?;
-                    let fee_rate = compute_fee_rate(&tx, absolute_fee)?;
-                    self.db.insert_mempool_tx(
-                        tx,
-                        Some(pool_entrance_time),
-                        absolute_fee,
-                        fee_rate,
-                    )?;
-                }
-                Err(e) => {
-                    error!("Error getting transaction info: {}", e);
+        let new_txs = mempool
+            .iter()
+            .filter(|(txid, _)| !kept.contains(*txid))
+            .map(|(txid, mempool_tx)| (*txid, mempool_tx.time))
+            .collect::<Vec<_>>();
+
+        // Fetch txs and their fees concurrently, a failing tx is logged and skipped
+        let fetched = stream::iter(new_txs)
+            .map(|(txid, pool_entrance_time)| async move {
+                let result = self.fetch_mempool_tx(&txid).await;
+                (txid, pool_entrance_time, result)
+            })
+            .buffer_unordered(self.startup_concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut txs = vec![];
+        for (txid, pool_entrance_time, result) in fetched {
+            match result {
+                Ok(Some((tx, absolute_fee, fee_rate))) => {
+                    if let Some(reason) = self.worker_config.filter.rejection(&tx, fee_rate) {
+                        self.db.record_filtered_tx(reason).await?;
+                        continue;
+                    }
+                    txs.push((tx, Some(pool_entrance_time), absolute_fee, fee_rate))
                 }
+                Ok(None) => {}
+                Err(e) => error!("Error getting transaction info for {}: {}", txid, e),
+            }
+        }
+        // Fetched out of order, parents have to be stored first for their children to link to them
+        sort_parents_first(&mut txs);
+        if let Some(live_fee_rates) = &self.worker_config.live_fee_rates {
+            for (tx, _, _, fee_rate) in &txs {
+                live_fee_rates.insert(tx.compute_txid(), *fee_rate);
             }
+            info!("Tracking {} txs from mempool in memory", txs.len());
+            return Ok(());
         }
+        let batch_payouts = txs
+            .iter()
+            .map(|(tx, ..)| {
+                (
+                    tx.compute_txid(),
+                    is_batch_payout(tx, self.worker_config.batch_payout_min_outputs),
+                )
+            })
+            .collect();
+        let inserted = self.db.insert_mempool_txs(txs).await?;
+        self.db.record_batch_payouts(batch_payouts).await?;
+        info!("Inserted {} txs from mempool", inserted);
 
         Ok(())
     }
 
+    /// Fetch a mempool tx with its fee, `None` if it wasn't sampled or was oversized and only noted
+    async fn fetch_mempool_tx(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<(Transaction, Amount, FeeRate)>> {
+        let tx = self
+            .rpc_client
+            .get_raw_transaction_verbosity_zero(txid)
+            .await?
+            .transaction()?;
+        // Sampled by inputs like the workers, which needs the tx itself
+        if !is_inputs_sampled(
+            &get_inputs_hash(tx.input.clone())?,
+            self.worker_config.sample_rate,
+        ) {
+            return Ok(None);
+        }
+        if self.worker_config.is_oversized(&tx) {
+            self.db.record_oversized_tx(&tx).await?;
+            return Ok(None);
+        }
+        let rpc_client = ResilientClient::new(self.rpc_client.clone());
+        let absolute_fee = get_absolute_fee(&tx, &rpc_client).await?;
+        let fee_rate = compute_fee_rate(&tx, absolute_fee)?;
+        Ok(Some((tx, absolute_fee, fee_rate)))
+    }
+
     pub async fn init(&mut self) -> Result<()> {
         let blockchain_info = self.rpc_client.get_blockchain_info().await?;
         info!("Blockchain info: {:?}", blockchain_info);
@@ -109,92 +456,265 @@ impl App {
         info!("Initializing mempool tracker");
         // Run migrations
         info!("Running migrations");
-        self.db.run_migrations()?;
-        // Any txs that are neither pruned nor mined should be removed
-        info!("Removing stale txs");
-        self.db.remove_stale_txs()?;
-        // Extract existing mempool
-        info!("Extracting existing mempool");
-        self.extract_existing_mempool().await?;
+        self.db.run_migrations().await?;
+        for (table, version) in self.db.row_versions().await?.newer_than_supported() {
+            warn!(
+                "{} has rows with version {}, written by a newer release. They are read as best effort",
+                table, version
+            );
+        }
+        // Stamped on the txs of the startup scan, the first mempool state snapshot updates it
+        self.db
+            .set_tip_height(Some(self.rpc_client.get_block_count().await?));
+        let snapshot_txs = match &self.shutdown_snapshot {
+            // Only a full scan fills the in memory fee rates of aggregates only mode
+            Some(_) if self.worker_config.live_fee_rates.is_some() => None,
+            Some(shutdown_snapshot) => shutdown_snapshot.take_fresh()?,
+            None => None,
+        };
+        let snapshot_matches = match snapshot_txs {
+            Some(mut snapshot_txs) => {
+                // The scan would replace rows that left and came back while stopped, losing
+                // their first seen times. Skipping it is only safe if the db wasn't written since.
+                let mut tracked = self.db.unconfirmed_found_at().await?;
+                snapshot_txs.sort_unstable();
+                tracked.sort_unstable();
+                if snapshot_txs != tracked {
+                    warn!("Shutdown snapshot doesn't match the tracked txs, scanning the mempool");
+                }
+                snapshot_txs == tracked
+            }
+            None => false,
+        };
+        if snapshot_matches {
+            // Txs that arrived or left while stopped are caught by zmq and the prune check
+            info!("Shutdown snapshot matches the tracked txs, skipping mempool scan");
+        } else {
+            // Unconfirmed txs that left the mempool are removed, new ones are added
+            info!("Extracting existing mempool");
+            self.extract_existing_mempool().await?;
+        }
+        // The scan fetches txs concurrently, so a child may be stored before its parent
+        let linked = self.db.reconcile_cpfp_links().await?;
+        if linked > 0 {
+            info!("Set {} missing CPFP links", linked);
+        }
+        self.seed_known_inputs().await?;
+        if let Some(max_arrival_seq) = self.db.max_arrival_seq().await? {
+            self.worker_config
+                .arrival_seq
+                .store(max_arrival_seq + 1, Ordering::Relaxed);
+        }
         // Start workers
-        let mut task_handles = vec![];
+        let inputs_locks = Arc::new(InputsLocks::new(INPUTS_LOCK_SHARDS));
         for _ in 0..self.num_workers {
             let bitcoind = self.rpc_client.clone();
-            let mut task_context =
-                TaskContext::new(bitcoind, self.db.clone(), self.tasks_rx.clone());
-            task_handles.push(tokio::spawn(async move { task_context.run().await }));
+            let mut task_context = TaskContext::new(
+                bitcoind,
+                self.db.clone(),
+                self.tasks_rx.clone(),
+                self.maintenance_rx.clone(),
+                self.worker_config.clone(),
+                self.known_inputs.clone(),
+                inputs_locks.clone(),
+            );
+            self.worker_handles
+                .push(tokio::spawn(async move { task_context.run().await }));
         }
         Ok(())
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("===== Starting mempool tracker =====");
-        let tasks_tx = self.tasks_tx.clone();
-        let tasks_tx_2 = self.tasks_tx.clone();
-        let tasks_tx_3 = self.tasks_tx.clone();
-
-        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
-        let shutdown_rx_1 = shutdown_tx.subscribe();
-        let shutdown_rx_3 = shutdown_tx.subscribe();
-
-        let mempool_state_check_interval = self.mempool_state_check_interval;
-        let prune_check_interval = self.prune_check_interval;
-        let disable_prune_check = self.disable_prune_check;
-
-        let mempool_state_handle = tokio::spawn(async move {
-            let mut shutdown = shutdown_rx_1;
-            loop {
-                tokio::select! {
-                    _ = shutdown.recv() => {
-                        info!("Shutting down mempool state task");
-                        break;
-                    }
-                    _ = tokio::time::sleep(mempool_state_check_interval) => {
-                        tasks_tx.send(Task::MempoolState).await?;
-                    }
-                }
-            }
-            Ok::<(), anyhow::Error>(())
-        });
-
-        // Conditionally start prune check task
-        let prune_check_handle = if !disable_prune_check {
-            let shutdown_rx_2 = shutdown_tx.subscribe();
-            Some(tokio::spawn(async move {
-                let mut shutdown = shutdown_rx_2;
-                loop {
-                    tokio::select! {
-                        _ = shutdown.recv() => {
-                            info!("Shutting down prune check task");
-                            break;
-                        }
-                        _ = tokio::time::sleep(prune_check_interval) => {
-                            tasks_tx_2.send(Task::PruneCheck).await?;
-                        }
-                    }
-                }
-                Ok::<(), anyhow::Error>(())
-            }))
+        // Cancelled on ctrl-c, every task holds a clone
+        let shutdown = CancellationToken::new();
+
+        let mut scheduled = Vec::new();
+        if let Some(mempool_state_check_interval) = self.mempool_state_check_interval {
+            scheduled.push((
+                "Mempool state",
+                spawn_scheduled(
+                    "mempool state",
+                    mempool_state_check_interval,
+                    Task::MempoolState,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        } else {
+            info!("Mempool state tracking disabled");
+        }
+        if !self.disable_prune_check {
+            scheduled.push((
+                "Prune check",
+                spawn_scheduled(
+                    "prune check",
+                    self.prune_check_interval,
+                    Task::PruneCheck,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
         } else {
             info!("Prune check disabled");
-            None
-        };
+        }
+        if let Some(mining_info_interval) = self.mining_info_interval {
+            info!(
+                "Mining info tracking enabled with interval: {:?}",
+                mining_info_interval
+            );
+            scheduled.push((
+                "Mining info",
+                spawn_scheduled(
+                    "mining info",
+                    mining_info_interval,
+                    Task::MiningInfo,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        } else {
+            info!("Mining info tracking disabled");
+        }
+        if let Some(audit_interval) = self.audit_interval {
+            info!("Audit enabled with interval: {:?}", audit_interval);
+            scheduled.push((
+                "Audit",
+                spawn_scheduled(
+                    "audit",
+                    audit_interval,
+                    Task::Audit,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        }
+        if let Some(node_info_interval) = self.node_info_interval {
+            info!(
+                "Node info tracking enabled with interval: {:?}",
+                node_info_interval
+            );
+            scheduled.push((
+                "Node info",
+                spawn_scheduled(
+                    "node info",
+                    node_info_interval,
+                    Task::NodeInfo,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        }
+        if let Some(recompute_fees_interval) = self.recompute_fees_interval {
+            info!(
+                "Retrying failed fee lookups with interval: {:?}",
+                recompute_fees_interval
+            );
+            scheduled.push((
+                "Recompute fees",
+                spawn_scheduled(
+                    "recompute fees",
+                    recompute_fees_interval,
+                    Task::RecomputeFees,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        }
+        if let Some(stuck_tx_check_interval) = self.stuck_tx_check_interval {
+            info!(
+                "Reconciling stuck txs with interval: {:?}",
+                stuck_tx_check_interval
+            );
+            scheduled.push((
+                "Reconcile stuck txs",
+                spawn_scheduled(
+                    "reconcile stuck txs",
+                    stuck_tx_check_interval,
+                    Task::ReconcileStuckTxs,
+                    self.maintenance_tx.clone(),
+                    self.maintenance_rx.clone(),
+                    self.overflow_policies.scheduled,
+                    shutdown.clone(),
+                ),
+            ));
+        }
+        if let Some(queue_lag) = self.queue_lag.clone() {
+            info!(
+                "Monitoring task queue lag above {} tasks for {:?}",
+                queue_lag.threshold, queue_lag.sustain
+            );
+            scheduled.push((
+                "Queue lag monitor",
+                spawn_queue_lag_monitor(
+                    queue_lag,
+                    self.tasks_rx.clone(),
+                    self.num_workers,
+                    shutdown.clone(),
+                ),
+            ));
+        }
 
         let mut zmq_message_stream = self.zmq_factory.connect()?;
-        let zmq_handle = {
-            let mut shutdown = shutdown_rx_3;
+        let zmq_recv_timeout = self.zmq_factory.recv_timeout();
+        let mut zmq_handle = {
+            let shutdown = shutdown.clone();
+            let tasks_tx = self.tasks_tx.clone();
+            let tasks_rx = self.tasks_rx.clone();
+            let raw_tx_policy = self.overflow_policies.raw_tx;
+            let dropped_txs = self.worker_config.dropped_txs.clone();
             tokio::spawn(async move {
                 info!("Starting zmq handle");
+                let mut rawtx_gaps = SequenceGaps::default();
                 loop {
+                    // Restarted on every message, so only fires once the stream goes quiet
+                    let silence = async {
+                        match zmq_recv_timeout {
+                            Some(recv_timeout) => tokio::time::sleep(recv_timeout).await,
+                            None => future::pending().await,
+                        }
+                    };
                     tokio::select! {
-                        _ = shutdown.recv() => {
+                        _ = shutdown.cancelled() => {
                             info!("Shutting down zmq handle");
                             break;
                         }
+                        _ = silence => {
+                            warn!("No zmq messages received for {:?}", zmq_recv_timeout.unwrap_or_default());
+                        }
                         message = zmq_message_stream.next() => {
                             match message {
                                 Some(Ok(message)) => {
-                                    tasks_tx_3.send(Task::RawTx(message.serialize_data_to_vec())).await?;
+                                    let Message::Tx(_, sequence) = &message else {
+                                        // Other topics are subscribed to but not processed yet
+                                        debug!("Received zmq {} message", ZmqTopic::of(&message));
+                                        continue;
+                                    };
+                                    let dropped = rawtx_gaps.observe(*sequence);
+                                    if dropped > 0 {
+                                        warn!(
+                                            "zmq dropped {} rawtx messages, {} in total",
+                                            dropped, rawtx_gaps.total_dropped
+                                        );
+                                    }
+                                    let task = Task::RawTx(message.serialize_data_to_vec());
+                                    if raw_tx_policy.send(&tasks_tx, &tasks_rx, task).await? {
+                                        if let Some(summary) = dropped_txs.record("task queue full") {
+                                            warn!("{}", summary);
+                                        }
+                                    }
                                 }
                                 Some(Err(e)) => return Err(e.into()),
                                 None => break,
@@ -206,72 +726,250 @@ impl App {
             })
         };
 
-        // Conditionally start mining info task
-        let mining_info_handle = if let Some(mining_info_interval) = self.mining_info_interval {
-            info!(
-                "Mining info tracking enabled with interval: {:?}",
-                mining_info_interval
-            );
-            let tasks_tx_4 = self.tasks_tx.clone();
-            let shutdown_rx_4 = shutdown_tx.subscribe();
-            Some(tokio::spawn(async move {
-                let mut shutdown = shutdown_rx_4;
-                loop {
-                    tokio::select! {
-                        _ = shutdown.recv() => {
-                            info!("Shutting down mining info task");
-                            break;
-                        }
-                        _ = tokio::time::sleep(mining_info_interval) => {
-                            tasks_tx_4.send(Task::MiningInfo).await?;
-                        }
-                    }
-                }
-                Ok::<(), anyhow::Error>(())
-            }))
-        } else {
-            info!("Mining info tracking disabled");
-            None
-        };
-
-        // Create a pinned boxed future for prune check handle that never completes if disabled
-        #[allow(clippy::type_complexity)]
-        let prune_check_future: std::pin::Pin<
-            Box<
-                dyn std::future::Future<
-                        Output = Result<Result<(), anyhow::Error>, tokio::task::JoinError>,
-                    > + Send,
-            >,
-        > = if let Some(handle) = prune_check_handle {
-            Box::pin(handle)
-        } else {
-            Box::pin(future::pending())
-        };
-
+        // Scheduled tasks only finish early when they fail, zmq also when the stream ends
+        let mut zmq_finished = false;
         tokio::select! {
-            // Wait for ctrl-c
             _ = ctrl_c() => {
                 info!("Received shutdown signal");
-                shutdown_tx.send(()).map_err(|e| anyhow::anyhow!("Failed to send shutdown signal: {}", e))?;
             }
-            r = mempool_state_handle => r?.map_err(|e| anyhow::anyhow!("Mempool state task failed: {}", e))?,
-            r = prune_check_future => r?.map_err(|e| anyhow::anyhow!("Prune check task failed: {}", e))?,
-            r = zmq_handle => r?.map_err(|e| anyhow::anyhow!("ZMQ task failed: {}", e))?,
+            (r, i, _) = future::select_all(scheduled.iter_mut().map(|(_, handle)| handle)) => {
+                // Finished handles can't be awaited again below
+                let (name, _) = scheduled.remove(i);
+                r?.map_err(|e| anyhow::anyhow!("{} task failed: {}", name, e))?;
+            }
+            r = &mut zmq_handle => {
+                zmq_finished = true;
+                r?.map_err(|e| anyhow::anyhow!("ZMQ task failed: {}", e))?;
+            }
         };
-
-        // If mining info task is running, wait for it to complete
-        if let Some(handle) = mining_info_handle {
+        shutdown.cancel();
+        for (name, handle) in scheduled {
             handle
                 .await?
-                .map_err(|e| anyhow::anyhow!("Mining info task failed: {}", e))?;
+                .map_err(|e| anyhow::anyhow!("{} task failed: {}", name, e))?;
+        }
+        if !zmq_finished {
+            zmq_handle
+                .await?
+                .map_err(|e| anyhow::anyhow!("ZMQ task failed: {}", e))?;
         }
 
         // Clean up
         info!("Shutting down workers...");
         self.tasks_tx.close();
-        self.db.flush()?;
+        self.maintenance_tx.close();
+        // Still snapshot and flush what the other workers stored if one failed
+        let workers_result = self.join_workers().await;
+        if let Some(shutdown_snapshot) = &self.shutdown_snapshot {
+            let txs = self.db.unconfirmed_found_at().await?;
+            shutdown_snapshot.write(&txs)?;
+            info!("Wrote {} txs to shutdown snapshot", txs.len());
+        }
+        self.db.flush().await?;
+        workers_result?;
         info!("Shutdown complete");
 
         Ok(())
     }
 }
+
+/// Queue `task` every `interval` until `shutdown` is cancelled
+fn spawn_scheduled(
+    name: &'static str,
+    interval: Duration,
+    task: Task,
+    tasks_tx: Sender<Task>,
+    tasks_rx: Receiver<Task>,
+    policy: OverflowPolicy,
+    shutdown: CancellationToken,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    info!("Shutting down {} task", name);
+                    break;
+                }
+                _ = tokio::time::sleep(interval) => {
+                    if policy.send(&tasks_tx, &tasks_rx, task.clone()).await? {
+                        warn!("Task queue full, dropped a task while queueing a {} task", name);
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Reorder `txs` so each one comes after its parents in the batch, keeping the order otherwise
+fn sort_parents_first(txs: &mut Vec<(Transaction, Option<u64>, Amount, FeeRate)>) {
+    let positions = txs
+        .iter()
+        .enumerate()
+        .map(|(i, (tx, ..))| (tx.compute_txid(), i))
+        .collect::<HashMap<_, _>>();
+    let mut depths = vec![None; txs.len()];
+    for i in 0..txs.len() {
+        batch_depth(i, txs, &positions, &mut depths);
+    }
+    let mut slots = std::mem::take(txs)
+        .into_iter()
+        .zip(depths)
+        .collect::<Vec<_>>();
+    slots.sort_by_key(|(_, depth)| *depth);
+    *txs = slots.into_iter().map(|(tx, _)| tx).collect();
+}
+
+/// How many generations of ancestors tx `i` has within the batch
+fn batch_depth(
+    i: usize,
+    txs: &[(Transaction, Option<u64>, Amount, FeeRate)],
+    positions: &HashMap<Txid, usize>,
+    depths: &mut [Option<usize>],
+) -> usize {
+    if let Some(depth) = depths[i] {
+        return depth;
+    }
+    let depth = txs[i]
+        .0
+        .input
+        .iter()
+        .filter_map(|input| positions.get(&input.previous_output.txid))
+        .map(|parent| batch_depth(*parent, txs, positions, depths) + 1)
+        .max()
+        .unwrap_or(0);
+    depths[i] = Some(depth);
+    depth
+}
+
+/// Sample the task queue depth until `shutdown` is cancelled, warning when it stays deep
+fn spawn_queue_lag_monitor(
+    mut queue_lag: QueueLag,
+    tasks_rx: Receiver<Task>,
+    num_workers: usize,
+    shutdown: CancellationToken,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    info!("Shutting down queue lag monitor");
+                    break;
+                }
+                _ = tokio::time::sleep(QUEUE_LAG_SAMPLE_INTERVAL) => {
+                    let depth = tasks_rx.len();
+                    debug!("Task queue depth: {}", depth);
+                    if queue_lag.observe(depth, Instant::now()) {
+                        warn!(
+                            "Task queue held over {} tasks for {:?}, {} now. The {} workers can't keep up, consider raising --num-workers",
+                            queue_lag.threshold, queue_lag.sustain, depth, num_workers
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{outpoint, spending_tx};
+    use bitcoin::OutPoint;
+
+    #[test]
+    fn test_sort_parents_first() {
+        let grandparent = spending_tx(&[outpoint("01", 0)], 90_000);
+        let parent = spending_tx(&[OutPoint::new(grandparent.compute_txid(), 0)], 80_000);
+        let child = spending_tx(
+            &[
+                OutPoint::new(parent.compute_txid(), 0),
+                OutPoint::new(grandparent.compute_txid(), 1),
+            ],
+            70_000,
+        );
+        let unrelated = spending_tx(&[outpoint("02", 0)], 90_000);
+        let entry = |tx: &Transaction| (tx.clone(), None, Amount::ZERO, FeeRate::ZERO);
+        let mut txs = vec![
+            entry(&child),
+            entry(&parent),
+            entry(&unrelated),
+            entry(&grandparent),
+        ];
+        sort_parents_first(&mut txs);
+        assert_eq!(
+            txs.into_iter().map(|(tx, ..)| tx).collect::<Vec<_>>(),
+            vec![unrelated, grandparent, parent, child]
+        );
+    }
+
+    #[test]
+    fn test_known_inputs_filter_capacity() -> Result<()> {
+        // Without a budget the filter grows with the db
+        let unbounded = MemoryLimits::default();
+        assert_eq!(unbounded.known_inputs_filter_capacity(10), 1_000_000);
+        assert_eq!(
+            unbounded.known_inputs_filter_capacity(5_000_000),
+            10_000_000
+        );
+
+        // With one it stays within the quarter set aside for it
+        let bounded = MemoryLimits::from_max_memory_mb(MIN_MEMORY_MB)?;
+        assert_eq!(bounded.known_inputs_capacity, 3_355_443);
+        assert_eq!(bounded.known_inputs_filter_capacity(10), 3_355_443);
+        assert_eq!(bounded.known_inputs_filter_capacity(5_000_000), 3_355_443);
+        assert_eq!(unbounded.max_recent_scripts, None);
+        assert_eq!(bounded.max_recent_scripts, Some(21_845));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() -> Result<()> {
+        let (tasks_tx, tasks_rx) = bounded(1);
+        tasks_tx.send(Task::RawTx(vec![1])).await?;
+
+        // The queued task is kept
+        assert!(
+            OverflowPolicy::DropNewest
+                .send(&tasks_tx, &tasks_rx, Task::RawTx(vec![2]))
+                .await?
+        );
+        assert!(matches!(tasks_rx.try_recv()?, Task::RawTx(raw_tx) if raw_tx == vec![1]));
+
+        // The oldest queued tx is evicted, the newer ones keep their order
+        let (tasks_tx, tasks_rx) = bounded(2);
+        tasks_tx.send(Task::RawTx(vec![1])).await?;
+        tasks_tx.send(Task::RawTx(vec![2])).await?;
+        assert!(
+            OverflowPolicy::DropOldest
+                .send(&tasks_tx, &tasks_rx, Task::RawTx(vec![3]))
+                .await?
+        );
+        assert!(matches!(tasks_rx.try_recv()?, Task::RawTx(raw_tx) if raw_tx == vec![2]));
+        assert!(matches!(tasks_rx.try_recv()?, Task::RawTx(raw_tx) if raw_tx == vec![3]));
+        assert!(tasks_rx.is_empty());
+
+        // Nothing is dropped with room left
+        assert!(
+            !OverflowPolicy::DropNewest
+                .send(&tasks_tx, &tasks_rx, Task::MempoolState)
+                .await?
+        );
+
+        tasks_tx.close();
+        assert!(OverflowPolicy::DropOldest
+            .send(&tasks_tx, &tasks_rx, Task::MempoolState)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_policy_from_str() {
+        for policy in OverflowPolicy::ALL {
+            assert_eq!(policy.name().parse::<OverflowPolicy>().unwrap(), policy);
+        }
+        assert!("drop".parse::<OverflowPolicy>().is_err());
+    }
+}
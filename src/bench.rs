@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bitcoin::{
+    absolute::LockTime, consensus::encode::serialize, hashes::Hash, transaction::Version, Amount,
+    OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use log::info;
+
+use crate::{
+    database::{Database, DbOptions},
+    replay::replay,
+    utils::{compute_fee_rate, get_inputs_hash},
+    worker::WorkerConfig,
+};
+
+/// Throughput of the ingestion pipeline and of its per tx helpers, in txs per second
+#[derive(Debug, Clone, Copy)]
+pub struct BenchReport {
+    pub txs: usize,
+    /// Worker pipeline into a fresh sqlite db, offline so no rpc cost is included
+    pub pipeline: f64,
+    pub inputs_hash: f64,
+    pub fee_rate: f64,
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} synthetic txs", self.txs)?;
+        writeln!(f, "pipeline:         {:>12.0} txs/s", self.pipeline)?;
+        writeln!(f, "get_inputs_hash:  {:>12.0} txs/s", self.inputs_hash)?;
+        write!(f, "compute_fee_rate: {:>12.0} txs/s", self.fee_rate)
+    }
+}
+
+/// Distinct 2-in 2-out txs, none spending another so each is stored as a new tx
+pub fn synthetic_txs(n: usize) -> Vec<Transaction> {
+    (0..n)
+        .map(|i| {
+            let mut txid = [0u8; 32];
+            txid[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            let input = (0..2)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint {
+                        txid: Txid::from_byte_array(txid),
+                        vout,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::from_slice(&[vec![0u8; 72], vec![0u8; 33]]),
+                })
+                .collect();
+            let output = (0..2)
+                .map(|_| TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0u8; 22]),
+                })
+                .collect();
+            Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input,
+                output,
+            }
+        })
+        .collect()
+}
+
+fn per_sec(txs: usize, elapsed: Duration) -> f64 {
+    txs as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+/// Feed `n` synthetic txs through an offline worker into a temporary sqlite db, then time
+/// the hashing and fee rate helpers on their own. A pooled `:memory:` db would give every
+/// connection its own db, so the pipeline writes to a file that is removed afterwards.
+pub async fn run(n: usize, options: DbOptions) -> Result<BenchReport> {
+    let txs = synthetic_txs(n);
+
+    let dir = std::env::temp_dir().join(format!("mempool-tracker-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let db_path = dir.join("bench.db");
+    let db = Database::with_options(db_path.to_str().expect("temp dir path is utf-8"), options)?;
+    let raw_txs = txs.iter().map(serialize).collect::<Vec<_>>();
+    info!(
+        "Benchmarking the pipeline with {} txs in {}",
+        n,
+        dir.display()
+    );
+    let start = Instant::now();
+    let result = replay(db, raw_txs, WorkerConfig::default()).await;
+    let pipeline = per_sec(n, start.elapsed());
+    fs::remove_dir_all(&dir)?;
+    result?;
+
+    let start = Instant::now();
+    for tx in &txs {
+        black_box(get_inputs_hash(tx.input.iter().cloned())?);
+    }
+    let inputs_hash = per_sec(n, start.elapsed());
+
+    let start = Instant::now();
+    for tx in &txs {
+        black_box(compute_fee_rate(tx, Amount::from_sat(1_000))?);
+    }
+    let fee_rate = per_sec(n, start.elapsed());
+
+    Ok(BenchReport {
+        txs: n,
+        pipeline,
+        inputs_hash,
+        fee_rate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_synthetic_txs_are_distinct() -> Result<()> {
+        let txs = synthetic_txs(100);
+        let inputs_hashes = txs
+            .iter()
+            .map(|tx| get_inputs_hash(tx.input.iter().cloned()))
+            .collect::<Result<HashSet<_>>>()?;
+        assert_eq!(inputs_hashes.len(), 100);
+        Ok(())
+    }
+}
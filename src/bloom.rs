@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bitcoin_hashes::Sha256;
+
+/// Concurrent bloom filter over string keys, shared by all workers without locking.
+/// Never has false negatives, so a miss can be trusted while a hit must be confirmed.
+#[derive(Debug)]
+pub struct BloomFilter {
+    bits: Vec<AtomicU64>,
+    num_bits: u64,
+    num_hashes: u64,
+}
+
+impl BloomFilter {
+    /// Size the filter to hold `capacity` keys at the given false positive rate
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-capacity * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity) * ln2).round().max(1.0) as u64;
+        let bits = (0..num_bits.div_ceil(64))
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        Self {
+            bits,
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Bit positions of a key, using double hashing over one sha256
+    fn positions(&self, key: &str) -> impl Iterator<Item = u64> + '_ {
+        let hash = Sha256::hash(key.as_bytes());
+        let bytes = hash.as_byte_array();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().expect("sha256 is 32 bytes"));
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().expect("sha256 is 32 bytes"));
+        (0..self.num_hashes).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    pub fn insert(&self, key: &str) {
+        for pos in self.positions(key) {
+            self.bits[(pos / 64) as usize].fetch_or(1 << (pos % 64), Ordering::Relaxed);
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.positions(key).all(|pos| {
+            self.bits[(pos / 64) as usize].load(Ordering::Relaxed) & (1 << (pos % 64)) != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let filter = BloomFilter::new(1_000, 0.01);
+        for i in 0..1_000 {
+            filter.insert(&format!("key-{}", i));
+        }
+        for i in 0..1_000 {
+            assert!(filter.contains(&format!("key-{}", i)));
+        }
+
+        let false_positives = (0..1_000)
+            .filter(|i| filter.contains(&format!("other-{}", i)))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+}
@@ -1,43 +1,489 @@
-use std::{str::FromStr, time::SystemTime, vec};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+    vec,
+};
 
 use anyhow::Result;
 use bitcoin::{
-    consensus::{Decodable, Encodable},
-    Amount, BlockHash, FeeRate, Transaction, Txid,
+    consensus::{
+        encode::{deserialize_hex, serialize_hex},
+        Decodable, Encodable,
+    },
+    Amount, BlockHash, FeeRate, OutPoint, Transaction, Txid, Wtxid,
 };
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, OptionalExtension};
+use rusqlite::{
+    backup::Backup, params, Connection, OpenFlags, OptionalExtension, TransactionBehavior,
+};
 
-use crate::{migrations::run_migrations, utils::get_inputs_hash};
-use log::info;
+use crate::{
+    db_value::DbValue,
+    fees::{FeeSimulation, RecommendedFees},
+    migrations::run_migrations,
+    node_info::NodeInfo,
+    snapshot_file::FEE_RATE_PERCENTILES,
+    utils::{
+        burned_amount, data_output_count, fee_rate_percentile, fee_rate_sat_per_vb,
+        first_seen_hash, get_inputs_hash, is_final, is_truc, signals_rbf, truc_respects_topology,
+        uses_relative_timelock, whole_sat_per_vb_fee_rate, FIRST_SEEN_GENESIS_HASH,
+    },
+};
+use log::{error, info, warn};
 
 #[macro_export]
 macro_rules! now {
     () => {
-        SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
+        std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs()
     };
 }
 
 /// Versioning the database, scheme should be backwards compatible
-/// But may not always be forwards compatible
+/// But may not always be forwards compatible.
+/// Every row is stamped with the version of its table's row format when written. A binary reads any
+/// row up to its own version; rows from a newer binary are still read but warned about, since
+/// columns this binary doesn't know about may carry meaning it ignores. Bump a version when the
+/// meaning of existing columns changes, adding nullable columns doesn't need a bump.
 const MEMPOOL_TRANSACTION_VERSION: u32 = 1;
 const RBF_TRANSACTION_VERSION: u32 = 1;
 const COINBASE_TRANSACTION_VERSION: u32 = 0;
 const MEMPOOL_STATE_VERSION: u32 = 1;
 
+/// Highest row version seen in each versioned table, `None` when a table is empty
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowVersions {
+    pub transactions: Option<u32>,
+    pub rbf: Option<u32>,
+    pub mempool_state: Option<u32>,
+}
+
+impl RowVersions {
+    /// Each versioned table with its max version and the version this binary writes
+    pub fn with_supported(&self) -> [(&'static str, Option<u32>, u32); 3] {
+        [
+            (
+                "transactions",
+                self.transactions,
+                MEMPOOL_TRANSACTION_VERSION,
+            ),
+            ("rbf", self.rbf, RBF_TRANSACTION_VERSION),
+            ("mempool_state", self.mempool_state, MEMPOOL_STATE_VERSION),
+        ]
+    }
+
+    /// Tables holding rows written by a newer binary, with their max version
+    pub fn newer_than_supported(&self) -> Vec<(&'static str, u32)> {
+        self.with_supported()
+            .into_iter()
+            .filter_map(|(table, version, supported)| {
+                version
+                    .filter(|version| *version > supported)
+                    .map(|version| (table, version))
+            })
+            .collect()
+    }
+}
+
+/// Warn about a row written by a newer binary, it is still read
+fn warn_if_newer_row(table: &str, version: u32, supported: u32) {
+    if version > supported {
+        warn!(
+            "Reading {} row with version {}, newer than the supported {}",
+            table, version, supported
+        );
+    }
+}
+
+/// Coin age buckets, in blocks, used by [`Database::coin_age_distribution`]:
+/// under a block, an hour, a day, a week, a month, a year, and older
+pub const COIN_AGE_BUCKETS: [u64; 7] = [0, 1, 6, 144, 1_008, 4_320, 52_560];
+
+/// Number of txs whose average coin age falls in `[min_blocks, max_blocks)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoinAgeBucket {
+    pub min_blocks: u64,
+    /// `None` for the last, open ended bucket
+    pub max_blocks: Option<u64>,
+    pub tx_count: u64,
+}
+
+/// Bump delay buckets, in seconds, used by [`Database::bump_delay_distribution`]:
+/// under a minute, 5 minutes, 30 minutes, an hour, 6 hours, a day, and longer
+pub const BUMP_DELAY_BUCKETS: [u64; 7] = [0, 60, 300, 1_800, 3_600, 21_600, 86_400];
+
+/// Lower bounds in secs of the [`Database::time_drift_distribution`] buckets
+pub const TIME_DRIFT_BUCKETS: [u64; 6] = [0, 1, 5, 30, 60, 300];
+
+/// Lower bounds in vbytes of the [`Database::size_distribution_series`] buckets:
+/// small payments, larger multi input txs, consolidations, and inscription sized txs
+pub const SIZE_BUCKETS: [u64; 4] = [0, 250, 1_000, 10_000];
+
+/// Tracked unconfirmed txs of `[min_vsize, max_vsize)` vbytes at a mempool state snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBucketCount {
+    pub created_at: u64,
+    pub min_vsize: u64,
+    /// `None` for the last, open ended bucket
+    pub max_vsize: Option<u64>,
+    pub tx_count: u64,
+    pub total_vsize: u64,
+}
+
+/// SQL expression mapping a vsize to the lower bound of its [`SIZE_BUCKETS`] bucket
+pub(crate) fn size_bucket_sql(vsize: &str) -> String {
+    let cases = SIZE_BUCKETS
+        .iter()
+        .rev()
+        .map(|min_vsize| format!("WHEN {} >= {} THEN {}", vsize, min_vsize, min_vsize))
+        .collect::<Vec<_>>();
+    format!("CASE {} END", cases.join(" "))
+}
+
+/// Block capacity `blocks_to_clear` assumes, 4M weight units
+pub const BLOCK_MAX_VSIZE: u64 = 1_000_000;
+
+/// Lower bounds in sat/vB of the fee rate buckets of [`Database::outcome_by_feerate_bucket`]
+pub const FEE_RATE_BUCKETS: [u64; 8] = [0, 1, 2, 5, 10, 20, 50, 100];
+
+/// What became of the tracked txs paying `[min_fee_rate, max_fee_rate)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeRateOutcome {
+    pub min_fee_rate: FeeRate,
+    /// `None` for the last, open ended bucket
+    pub max_fee_rate: Option<FeeRate>,
+    pub mined: u64,
+    /// Evicted or otherwise gone from the mempool without being mined
+    pub pruned: u64,
+    /// Replaced by a conflicting tx
+    pub replaced: u64,
+    pub unconfirmed: u64,
+}
+
+/// How many evicted txs survived `blocks_survived` blocks between being first seen and pruned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionSurvival {
+    pub blocks_survived: u64,
+    pub tx_count: u64,
+}
+
+/// How many tracked txs were mined, evicted or replaced within a time bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleOutcomes {
+    pub bucket_start: u64,
+    pub mined: u64,
+    /// Gone from the mempool without being mined or replaced
+    pub pruned: u64,
+    /// Replaced by a tx spending the same or some of the same inputs
+    pub replaced: u64,
+}
+
+/// SQL expression mapping a sat/kwu fee rate to the lower bound in sat/vB of its [`FEE_RATE_BUCKETS`] bucket
+fn fee_rate_bucket_sql(kwu: &str) -> String {
+    let cases = FEE_RATE_BUCKETS
+        .iter()
+        .rev()
+        .map(|sat_vb| {
+            let min_kwu = whole_sat_per_vb_fee_rate(*sat_vb).to_sat_per_kwu();
+            format!("WHEN {} >= {} THEN {}", kwu, min_kwu, sat_vb)
+        })
+        .collect::<Vec<_>>();
+    format!("CASE {} END", cases.join(" "))
+}
+
+/// `meta` key of the `--instance-name` of the tracker writing the db
+pub const INSTANCE_NAME_KEY: &str = "instance_name";
+
+/// Width in secs of the time buckets in `feerate_targets`, about one block
+pub const FEERATE_TARGET_BUCKET_SECS: u64 = 600;
+
+/// The lowest fee rates that would have been mined within 1, 3 and 6 blocks of a time bucket,
+/// from the clearing fee rates in `blocks`. `None` until those blocks were seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeerateTarget {
+    pub bucket_start: u64,
+    /// Lowest chain tip height seen by the mempool state snapshots in the bucket
+    pub block_height: u64,
+    pub next_block: Option<FeeRate>,
+    pub next_3_blocks: Option<FeeRate>,
+    pub next_6_blocks: Option<FeeRate>,
+}
+
+/// Number of txs we first saw `[min_secs, max_secs)` after they entered the node's mempool.
+/// The first bucket also holds txs we saw before the node's entry time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeDriftBucket {
+    pub min_secs: u64,
+    /// `None` for the last, open ended bucket
+    pub max_secs: Option<u64>,
+    pub tx_count: u64,
+}
+
+/// How a replacement changed the fee, fee rate and vsize of the version it replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RbfDelta {
+    pub fee_delta: i64,
+    pub fee_rate_delta_kwu: i64,
+    pub vsize_delta: i64,
+}
+
+impl RbfDelta {
+    /// `prev` is the replaced version's fee, fee rate in sat/kwu and vsize,
+    /// unknown for rbfs recorded before vsizes were kept
+    pub(crate) fn between(
+        prev: (Option<i64>, Option<i64>, Option<i64>),
+        tx: &Transaction,
+        fee_total: u64,
+        fee_rate: FeeRate,
+    ) -> Option<Self> {
+        let (Some(prev_fee), Some(prev_fee_rate_kwu), Some(prev_vsize)) = prev else {
+            return None;
+        };
+        Some(Self {
+            fee_delta: fee_total as i64 - prev_fee,
+            fee_rate_delta_kwu: fee_rate.to_db_i64() - prev_fee_rate_kwu,
+            vsize_delta: tx.vsize() as i64 - prev_vsize,
+        })
+    }
+
+    /// Pays more in total but less per vbyte, by growing the tx
+    pub fn rate_down_abs_up(&self) -> bool {
+        self.fee_delta > 0 && self.fee_rate_delta_kwu < 0
+    }
+}
+
+/// A tx whose fee lookup failed, kept for `Task::RecomputeFees` to retry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferredTx {
+    pub tx: Transaction,
+    /// When the tx was first seen, stored as its found_at once the fee is known
+    pub found_at: u64,
+    pub arrival_seq: u64,
+    /// Failed retries so far
+    pub retries: u32,
+}
+
+/// A replacement that raised the absolute fee while lowering the fee rate,
+/// see [`Database::rate_down_abs_up_bumps`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateDownAbsUpBump {
+    /// The replacement
+    pub txid: Txid,
+    pub created_at: u64,
+    pub delta: RbfDelta,
+}
+
+/// Number of replacements made `[min_secs, max_secs)` after the original tx was first seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BumpDelayBucket {
+    pub min_secs: u64,
+    /// `None` for the last, open ended bucket
+    pub max_secs: Option<u64>,
+    pub rbf_count: u64,
+}
+
+/// Invariant violations found by [`Database::audit`], each field counts offending rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Txs with both mined_at and pruned_at set
+    pub mined_and_pruned: u64,
+    /// rbf rows whose replaced tx is missing from transactions
+    pub orphaned_rbfs: u64,
+    pub negative_fees: u64,
+    /// tx_inputs rows indexed for coinbase txs, which have no real inputs
+    pub coinbase_inputs: u64,
+}
+
+impl AuditReport {
+    pub fn violations(&self) -> u64 {
+        self.mined_and_pruned + self.orphaned_rbfs + self.negative_fees + self.coinbase_inputs
+    }
+}
+
+/// What [`Database::repair_rbf_links`] changed, each field counts rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RbfRepairReport {
+    /// rbf rows whose replaced tx is missing from transactions, deleted
+    pub dangling_rbfs: u64,
+    /// rbf rows older than the stored version of their tx, e.g. one stored again after being
+    /// pruned, deleted
+    pub stale_rbfs: u64,
+    /// Txs whose row wasn't overwritten by their latest replacement, e.g. after a partial write
+    pub heads_fixed: u64,
+    /// rbf rows pointing past a stale head that predate rbf.tx_data, so the replacement can't be
+    /// rebuilt, deleted
+    pub unrepairable_rbfs: u64,
+    /// replaced_by pointers to txs we have no version of, cleared
+    pub dangling_replaced_by: u64,
+}
+
+/// One row of the mempool state time series
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolStatePoint {
+    pub created_at: u64,
+    pub size: u64,
+    pub tx_count: u64,
+    pub block_height: Option<u64>,
+    pub block_hash: Option<BlockHash>,
+}
+
+/// Raw mempool_state columns, the block hash still hex encoded
+type MempoolStateRow = (u64, u64, u64, Option<u64>, Option<String>);
+
+impl MempoolStatePoint {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<MempoolStateRow> {
+        warn_if_newer_row("mempool_state", row.get(5)?, MEMPOOL_STATE_VERSION);
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+        ))
+    }
+
+    fn decode(
+        (created_at, size, tx_count, block_height, block_hash): MempoolStateRow,
+    ) -> Result<Self> {
+        let block_hash = block_hash
+            .map(|block_hash| {
+                let bytes = hex::decode(block_hash)?;
+                Ok::<_, anyhow::Error>(BlockHash::consensus_decode(&mut bytes.as_slice())?)
+            })
+            .transpose()?;
+        Ok(Self {
+            created_at,
+            size,
+            tx_count,
+            block_height,
+            block_hash,
+        })
+    }
+}
+
+/// A tx using an absolute or relative timelock, see [`Database::query_timelocked`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelockedTx {
+    pub txid: Txid,
+    pub found_at: u64,
+    pub lock_time: u32,
+    pub uses_csv: bool,
+    pub is_final: bool,
+    pub absolute_fee: Amount,
+}
+
+/// Absolute fees of the txs found in a window, see [`Database::fee_stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeStats {
+    pub tx_count: u64,
+    pub total: Amount,
+    pub mean: Amount,
+    /// Lower median for an even count
+    pub median: Amount,
+}
+
+/// One version of a tx keyed by an inputs_hash, see [`Database::tx_versions`]
+#[derive(Debug, Clone)]
+pub struct TxVersion {
+    pub txid: Txid,
+    pub tx: Transaction,
+    pub found_at: u64,
+    pub absolute_fee: Amount,
+    pub fee_rate: FeeRate,
+    /// When this version was replaced, `None` for the current version
+    pub archived_at: Option<u64>,
+}
+
+/// How two txs in a lineage are related, see [`Database::lineage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LineageKind {
+    /// `from` is an unconfirmed parent whose output `to` spends
+    Spends,
+    /// `from` was replaced by `to`, under the same inputs or by a conflicting tx
+    ReplacedBy,
+}
+
+/// An edge between two tracked txs, see [`Database::lineage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineageEdge {
+    pub from: Txid,
+    pub to: Txid,
+    pub kind: LineageKind,
+}
+
+/// A TRUC (v3) tx, see [`Database::truc_txs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrucTx {
+    pub txid: Txid,
+    pub found_at: u64,
+    pub unconfirmed_parents: u64,
+    /// Unconfirmed children when read
+    pub unconfirmed_children: u64,
+    /// At most one unconfirmed TRUC parent when found and one unconfirmed TRUC child now,
+    /// within the size limits
+    pub respects_topology: bool,
+    /// `None` once replaced under the same inputs, see [`Database::tx_versions`]
+    pub absolute_fee: Option<Amount>,
+}
+
+/// How many tracked unconfirmed txs had a given nVersion at a snapshot.
+/// This is the bitcoin tx version, not the row format `version` column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxVersionCount {
+    pub created_at: u64,
+    pub tx_version: i32,
+    pub count: u64,
+}
+
+/// Sqlite tuning, the defaults are sqlite's own
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DbOptions {
+    /// Only takes effect when the db is created, large pages suit the big hex encoded tx rows
+    pub page_size: Option<u32>,
+    /// Page cache of each pooled connection
+    pub cache_mb: Option<u64>,
+}
+
+/// The pool and the chain tip stamped on stored txs, shared by every clone
 #[derive(Debug, Clone)]
-pub struct Database(r2d2::Pool<SqliteConnectionManager>);
+pub struct Database(r2d2::Pool<SqliteConnectionManager>, Arc<Mutex<Option<u64>>>);
 
 impl Database {
     pub fn new(path: &str) -> Result<Self> {
-        let manager = SqliteConnectionManager::file(path);
+        Self::with_options(path, DbOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: DbOptions) -> Result<Self> {
+        let cache_mb = options.cache_mb;
+        let manager = SqliteConnectionManager::file(path).with_init(move |conn| {
+            if let Some(cache_mb) = cache_mb {
+                // Negative cache sizes are in KiB rather than pages
+                conn.execute_batch(&format!("PRAGMA cache_size = -{}", cache_mb * 1024))?;
+            }
+            Ok(())
+        });
         let pool = r2d2::Pool::new(manager)?;
         let conn = pool.get()?;
 
         conn.execute("PRAGMA foreign_keys = ON", [])?;
+        // Has to be set before WAL is enabled and any table is created
+        if let Some(page_size) = options.page_size {
+            if !(512..=65_536).contains(&page_size) || !page_size.is_power_of_two() {
+                return Err(anyhow::anyhow!(
+                    "page size must be a power of two between 512 and 65536"
+                ));
+            }
+            conn.execute_batch(&format!("PRAGMA page_size = {}", page_size))?;
+        }
+        // WAL lets snapshots read a consistent view without blocking writers
+        conn.query_row("PRAGMA journal_mode = WAL", [], |row| {
+            row.get::<_, String>(0)
+        })?;
 
         // Create tables if they don't exist
         conn.execute(
@@ -58,6 +504,28 @@ impl Database {
             // Cols added in migrations
             // child_txid TEXT,
             // seen_in_mempool BOOLEAN NOT NULL DEFAULT TRUE,
+            // replaced_by TEXT,
+            // fee_rate_kwu INTEGER, NOT NULL enforced by trigger since require_fee_rate_kwu
+            // signals_rbf BOOLEAN,
+            // lock_time INTEGER,
+            // uses_csv BOOLEAN,
+            // is_final BOOLEAN,
+            // burned_amount INTEGER,
+            // data_outputs INTEGER,
+            // coin_age_min INTEGER,
+            // coin_age_max INTEGER,
+            // coin_age_avg REAL,
+            // arrival_seq INTEGER,
+            // is_batch_payout BOOLEAN,
+            // tx_version INTEGER,
+            // prior_txids TEXT,
+            // unconfirmed_inputs INTEGER,
+            // mined_height INTEGER,
+            // fee_anomaly BOOLEAN,
+            // reused_outputs INTEGER,
+            // wtxid TEXT,
+            // blocks_survived INTEGER,
+            // found_at_height INTEGER,
             [],
         )?;
         // Create index
@@ -74,22 +542,42 @@ impl Database {
                 fee_total INTEGER NOT NULL,
                 version INTEGER NOT NULL
             )",
+            // Cols added in migrations
+            // replaces TEXT,
+            // fee_rate_kwu INTEGER,
+            // bump_delay_secs INTEGER,
+            // vsize INTEGER,
+            // fee_delta INTEGER,
+            // fee_rate_delta_kwu INTEGER,
+            // vsize_delta INTEGER,
+            // rate_down_abs_up BOOLEAN,
+            // tx_data TEXT,
             [],
         )?;
 
-        // Create the mempool table if it doesn't exist
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS mempool (
-                tx_id TEXT PRIMARY KEY,
-                created_at DATETIME NOT NULL,
-                size INTEGER NOT NULL,
-                tx_count INTEGER NOT NULL,
-                block_height INTEGER NOT NULL,
-                block_hash TEXT NOT NULL,
-                version INTEGER NOT NULL
-            )",
+        // Mempool state snapshots, migrations rename the table to mempool_state so it's only
+        // created for dbs they haven't run on yet
+        let has_mempool_state: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'mempool_state'",
             [],
+            |row| row.get(0),
         )?;
+        if !has_mempool_state {
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS mempool (
+                    tx_id TEXT PRIMARY KEY,
+                    created_at DATETIME NOT NULL,
+                    size INTEGER NOT NULL,
+                    tx_count INTEGER NOT NULL,
+                    block_height INTEGER NOT NULL,
+                    block_hash TEXT NOT NULL,
+                    version INTEGER NOT NULL
+                )",
+                // block_height and block_hash are made nullable in a migration
+                // and tx_id is replaced by id INTEGER PRIMARY KEY AUTOINCREMENT
+                [],
+            )?;
+        }
 
         // Migrations table tracking what migrations have been applied
         conn.execute(
@@ -107,119 +595,586 @@ impl Database {
             )",
             [],
         )?;
-        Ok(Self(pool))
-    }
 
-    pub(crate) fn flush(&self) -> Result<()> {
-        let conn = self.0.get()?;
-        conn.cache_flush()?;
-        Ok(())
-    }
+        // Unconfirmed txs that spend at least one of the same outpoints
+        // but have a different inputs_hash (i.e not caught by the rbf path)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS conflicts (
+                tx_id TEXT NOT NULL,
+                conflicting_txid TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                PRIMARY KEY (tx_id, conflicting_txid)
+            )",
+            [],
+        )?;
 
-    pub(crate) fn record_mempool_state(
-        &self,
-        mempool_size: u64,
-        mempool_tx_count: u64,
-        block_height: u64,
-        block_hash: BlockHash,
-    ) -> Result<()> {
-        let conn = self.0.get()?;
-        let now = now!();
-        let mut writer = vec![];
-        block_hash.consensus_encode(&mut writer)?;
-        let block_hash_str = hex::encode(writer);
+        // Prior versions of txs that were overwritten under the same inputs_hash
         conn.execute(
-            "INSERT OR REPLACE INTO mempool (created_at, size, tx_count, block_height, block_hash, version) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![now, mempool_size, mempool_tx_count, block_height, block_hash_str, MEMPOOL_STATE_VERSION],
+            "CREATE TABLE IF NOT EXISTS tx_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                inputs_hash TEXT NOT NULL,
+                tx_id TEXT NOT NULL,
+                tx_data TEXT NOT NULL,
+                found_at DATETIME NOT NULL,
+                absolute_fee INTEGER NOT NULL,
+                fee_rate_kwu INTEGER NOT NULL,
+                archived_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_history_inputs_hash ON tx_history(inputs_hash)",
+            [],
         )?;
-        Ok(())
-    }
 
-    pub(crate) fn record_coinbase_tx(&self, tx: &Transaction) -> Result<()> {
-        let conn = self.0.get()?;
-        if !tx.is_coinbase() {
-            return Ok(());
-        }
+        // Append-only, hash-chained record of when each txid was first seen
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS first_seen_log (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                tx_id TEXT NOT NULL UNIQUE,
+                found_at DATETIME NOT NULL,
+                prev_hash TEXT NOT NULL,
+                hash TEXT NOT NULL
+            )",
+            [],
+        )?;
 
-        // special case for coinbase tx, key is the txid
-        let tx_id = tx.compute_txid().to_string();
-        let found_at = now!();
-        let mined_at = now!();
-        let mut tx_bytes = vec![];
-        tx.consensus_encode(&mut tx_bytes)?;
-        let tx_str = hex::encode(&tx_bytes);
-        let size = tx_bytes.len() as i64;
-        let weight = tx.weight().to_wu() as i64;
+        // Txs over the configured max weight, noted without fetching their fees or storing them
         conn.execute(
-            "INSERT OR REPLACE INTO transactions
-            (inputs_hash, tx_data, tx_id, found_at, mined_at, absolute_fee, fee_rate, size, weight, version)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-            params![
-                tx_id,
-                tx_str,
-                tx_id,
-                found_at,
-                mined_at,
-                Amount::ZERO.to_sat(),
-                0.0,
-                size,
-                weight,
-                COINBASE_TRANSACTION_VERSION
-            ],
+            "CREATE TABLE IF NOT EXISTS oversized_txs (
+                tx_id TEXT PRIMARY KEY,
+                found_at DATETIME NOT NULL,
+                size INTEGER NOT NULL,
+                weight INTEGER NOT NULL
+            )",
+            [],
         )?;
 
-        Ok(())
-    }
+        // Lowest fee rate of the mined txs seen in each block, the block's clearing fee rate
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blocks (
+                height INTEGER PRIMARY KEY,
+                clearing_fee_rate_kwu INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                updated_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
 
-    pub(crate) fn record_mined_tx(&self, tx: &Transaction) -> Result<()> {
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-        let mut tx_bytes = vec![];
-        tx.consensus_encode(&mut tx_bytes)?;
-        let tx_str = hex::encode(tx_bytes);
-        let conn = self.0.get()?;
-        let mined_at = now!();
+        // Txs whose computed fee differs from the node's, see --validate-fees
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fee_discrepancies (
+                tx_id TEXT PRIMARY KEY,
+                found_at DATETIME NOT NULL,
+                computed_fee INTEGER NOT NULL,
+                node_fee INTEGER NOT NULL
+            )",
+            [],
+        )?;
 
-        let tx_in_mempool = self.tx_exists(&tx)?;
-        if !tx_in_mempool {
-            info!("Received tx that was not in my mempool: {}", inputs_hash);
-        }
+        // Outputs of tracked txs paying a script of the --watch-descriptor
         conn.execute(
-            "UPDATE transactions SET mined_at = ?1, tx_data = ?2, seen_in_mempool = ?3 WHERE inputs_hash = ?4",
-            params![mined_at, tx_str, tx_in_mempool, inputs_hash],
+            "CREATE TABLE IF NOT EXISTS watched_txs (
+                tx_id TEXT NOT NULL,
+                vout INTEGER NOT NULL,
+                value INTEGER NOT NULL,
+                found_at DATETIME NOT NULL,
+                PRIMARY KEY (tx_id, vout)
+            )",
+            [],
         )?;
 
-        Ok(())
-    }
+        // Fee rate percentiles of the live unconfirmed txs per mempool state snapshot, only
+        // recorded with --aggregates-only since they are otherwise derived from `transactions`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fee_rate_percentiles (
+                created_at DATETIME NOT NULL,
+                percentile INTEGER NOT NULL,
+                fee_rate_kwu INTEGER NOT NULL,
+                PRIMARY KEY (created_at, percentile)
+            )",
+            [],
+        )?;
 
-    pub(crate) fn txids_in_mempool(&self) -> Result<Vec<Txid>> {
-        let conn = self.0.get()?;
-        let query = "SELECT tx_id FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL";
-        let mut stmt = conn.prepare(query)?;
-        let txids = stmt.query_map([], |row| {
-            let txid_str: String = row.get(0)?;
-            let txid = Txid::from_str(&txid_str).expect("Valid txid");
-            Ok(txid)
-        })?;
-        Ok(txids.collect::<Result<Vec<_>, _>>()?)
-    }
+        // Tracked unconfirmed txs paying a recently seen script, summed on every mempool state
+        // snapshot with --address-reuse-scripts
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS address_reuse_counts (
+                created_at DATETIME PRIMARY KEY,
+                tx_count INTEGER NOT NULL,
+                reused_outputs INTEGER NOT NULL
+            )",
+            [],
+        )?;
 
-    pub(crate) fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
-        let mempool_txids = self.txids_in_mempool()?;
-        // If mempool is empty, don't mark anything as pruned
-        // This could be a temporary state or network issue
-        // We dont want to mark all txs as pruned
-        if txids.is_empty() {
-            return Ok(vec![]);
-        }
+        // Free-form user annotations, any number per tx, also for txs that aren't tracked
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tx_labels (
+                tx_id TEXT NOT NULL,
+                label TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                PRIMARY KEY (tx_id, label)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_labels_label ON tx_labels(label)",
+            [],
+        )?;
 
-        let txids_not_in_current_mempool = mempool_txids
-            .iter()
-            .filter(|txid| !txids.contains(txid))
-            .copied()
-            .collect::<Vec<_>>();
+        // Txs whose fee lookup failed, retried by `Task::RecomputeFees` then stored as usual
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS deferred_fee_txs (
+                tx_id TEXT PRIMARY KEY,
+                tx_data TEXT NOT NULL,
+                found_at DATETIME NOT NULL,
+                arrival_seq INTEGER NOT NULL,
+                retries INTEGER NOT NULL,
+                updated_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
 
-        Ok(txids_not_in_current_mempool)
-    }
+        // Txs our node relayed that break one of our standardness checks, see `--check-standardness`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS nonstandard_txs (
+                tx_id TEXT PRIMARY KEY,
+                reason TEXT NOT NULL,
+                found_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // Our first seen time of sampled txs vs the node's mempool entry time
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS time_drift (
+                tx_id TEXT PRIMARY KEY,
+                found_at DATETIME NOT NULL,
+                node_time DATETIME NOT NULL,
+                drift_secs INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // TRUC (v3) txs and whether they fit the v3 limits when found, their children are
+        // checked on read so unconfirmed_children stays 0
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS truc_txs (
+                tx_id TEXT PRIMARY KEY,
+                found_at DATETIME NOT NULL,
+                unconfirmed_parents INTEGER NOT NULL,
+                unconfirmed_children INTEGER NOT NULL,
+                respects_topology BOOLEAN NOT NULL
+            )",
+            [],
+        )?;
+
+        // The node's connection count and peers by protocol version, see --node-info-interval
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS node_info (
+                created_at DATETIME PRIMARY KEY,
+                connections INTEGER NOT NULL,
+                connections_in INTEGER,
+                connections_out INTEGER,
+                protocol_versions TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Tracked unconfirmed txs by their nVersion, counted on every mempool state snapshot
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tx_version_counts (
+                created_at DATETIME NOT NULL,
+                tx_version INTEGER NOT NULL,
+                count INTEGER NOT NULL,
+                PRIMARY KEY (created_at, tx_version)
+            )",
+            [],
+        )?;
+
+        // Key value facts about the db itself, e.g. which tracker instance writes it
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // Tracked unconfirmed txs by vsize bucket, counted on every mempool state snapshot
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS size_bucket_counts (
+                created_at DATETIME NOT NULL,
+                min_vsize INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                total_vsize INTEGER NOT NULL,
+                PRIMARY KEY (created_at, min_vsize)
+            )",
+            [],
+        )?;
+
+        // Fee rates that would have confirmed within 1, 3 and 6 blocks, per time bucket
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS feerate_targets (
+                bucket_start DATETIME PRIMARY KEY,
+                block_height INTEGER NOT NULL,
+                next_block_kwu INTEGER,
+                next_3_blocks_kwu INTEGER,
+                next_6_blocks_kwu INTEGER,
+                updated_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // How many txs the worker's filter rejected, by reason
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS filtered_txs (
+                reason TEXT PRIMARY KEY,
+                count INTEGER NOT NULL,
+                updated_at DATETIME NOT NULL
+            )",
+            [],
+        )?;
+
+        // Normalized index of the outpoints each tracked tx spends
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tx_inputs (
+                txid TEXT NOT NULL,
+                prev_txid TEXT NOT NULL,
+                prev_vout INTEGER NOT NULL,
+                PRIMARY KEY (txid, prev_txid, prev_vout)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tx_inputs_outpoint ON tx_inputs(prev_txid, prev_vout)",
+            [],
+        )?;
+
+        if let Some(page_size) = options.page_size {
+            let current: u32 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            if current != page_size {
+                warn!(
+                    "Page size {} only applies to new dbs, {} keeps {}",
+                    page_size, path, current
+                );
+            }
+        }
+        Ok(Self(pool, Arc::new(Mutex::new(None))))
+    }
+
+    /// Chain tip stored as the `found_at_height` of txs inserted from now on, None if unknown
+    pub fn set_tip_height(&self, height: Option<u64>) {
+        *self.1.lock().expect("tip height lock poisoned") = height;
+    }
+
+    fn tip_height(&self) -> Option<u64> {
+        *self.1.lock().expect("tip height lock poisoned")
+    }
+
+    /// Write a consistent copy of the db to `out` using sqlite's online backup api.
+    /// Safe to run while the tracker is ingesting.
+    pub fn snapshot(&self, out: &Path) -> Result<()> {
+        if out.exists() {
+            return Err(anyhow::anyhow!("{} already exists", out.display()));
+        }
+        let conn = self.0.get()?;
+        let mut dst = Connection::open(out)?;
+        let backup = Backup::new(&conn, &mut dst)?;
+        // Copy every page in a single step so the snapshot reflects one point in time
+        backup.run_to_completion(i32::MAX, Duration::ZERO, None)?;
+        Ok(())
+    }
+
+    /// Overwrite the db at `path` with the contents of the snapshot at `src`.
+    /// The tracker must not be running against `path`.
+    pub fn restore(path: &str, src: &Path) -> Result<()> {
+        let src = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut dst = Connection::open(path)?;
+        let backup = Backup::new(&src, &mut dst)?;
+        backup.run_to_completion(i32::MAX, Duration::ZERO, None)?;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.cache_flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn record_mempool_state(
+        &self,
+        mempool_size: u64,
+        mempool_tx_count: u64,
+        block_height: Option<u64>,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        let conn = self.0.get()?;
+        let now = now!();
+        let block_hash_str = block_hash
+            .map(|block_hash| {
+                let mut writer = vec![];
+                block_hash.consensus_encode(&mut writer)?;
+                Ok::<_, anyhow::Error>(hex::encode(writer))
+            })
+            .transpose()?;
+        // Full blocks of the tracked unconfirmed txs paying at least the last block's clearing
+        // fee rate, or of all of them before any block was recorded
+        conn.execute(
+            "INSERT INTO mempool_state (created_at, size, tx_count, block_height, block_hash, version, blocks_to_clear)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, (
+                SELECT COALESCE(SUM((weight + 3) / 4), 0) / ?7 FROM transactions
+                WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                AND fee_rate_kwu >= COALESCE(
+                    (SELECT clearing_fee_rate_kwu FROM blocks ORDER BY height DESC LIMIT 1), 0
+                )
+            ))",
+            params![now, mempool_size, mempool_tx_count, block_height, block_hash_str, MEMPOOL_STATE_VERSION, BLOCK_MAX_VSIZE as f64],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn record_coinbase_tx(&self, tx: &Transaction) -> Result<()> {
+        let conn = self.0.get()?;
+        if !tx.is_coinbase() {
+            return Ok(());
+        }
+
+        // special case for coinbase tx, key is the txid
+        let tx_id = tx.compute_txid().to_string();
+        let found_at = now!();
+        let mined_at = now!();
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let tx_str = hex::encode(&tx_bytes);
+        let size = tx_bytes.len() as i64;
+        let weight = tx.weight().to_wu() as i64;
+        conn.execute(
+            "INSERT OR REPLACE INTO transactions
+            (inputs_hash, tx_data, tx_id, found_at, mined_at, absolute_fee, fee_rate, fee_rate_kwu, size, weight, version)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                tx_id,
+                tx_str,
+                tx_id,
+                found_at,
+                mined_at,
+                Amount::ZERO.to_db_i64(),
+                fee_rate_sat_per_vb(FeeRate::ZERO),
+                FeeRate::ZERO.to_db_i64(),
+                size,
+                weight,
+                COINBASE_TRANSACTION_VERSION
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub(crate) fn record_mined_tx(&self, tx: &Transaction) -> Result<()> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let mut tx_bytes = vec![];
+        tx.consensus_encode(&mut tx_bytes)?;
+        let tx_str = hex::encode(tx_bytes);
+        let conn = self.0.get()?;
+        let mined_at = now!();
+
+        let tx_in_mempool = self.tx_exists(tx)?;
+        if !tx_in_mempool {
+            info!("Received tx that was not in my mempool: {}", inputs_hash);
+        }
+        conn.execute(
+            "UPDATE transactions SET mined_at = ?1, tx_data = ?2, seen_in_mempool = ?3 WHERE inputs_hash = ?4",
+            params![mined_at, tx_str, tx_in_mempool, inputs_hash],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the height of the block a tracked tx confirmed in
+    pub(crate) fn record_mined_height(&self, tx: &Transaction, height: u64) -> Result<()> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let conn = self.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET mined_height = ?1 WHERE inputs_hash = ?2",
+            params![height, inputs_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Txids of the tracked txs that confirmed at `height`, sorted. Taken from the mined tx_data,
+    /// since a replacement we never saw keeps the txid of the version we tracked.
+    #[allow(dead_code)]
+    pub fn tracked_txids_in_block(&self, height: u64) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare("SELECT tx_data FROM transactions WHERE mined_height = ?1")?;
+        let tx_datas = stmt
+            .query_map(params![height], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut txids = tx_datas
+            .into_iter()
+            .map(|tx_data| {
+                let bytes = hex::decode(tx_data)?;
+                let tx = Transaction::consensus_decode(&mut bytes.as_slice())?;
+                Ok(tx.compute_txid())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        txids.sort();
+        Ok(txids)
+    }
+
+    /// Tracked txs first seen at or before `found_before` (unix secs) paying at least `min_fee_rate`
+    /// that were neither mined nor replaced, oldest first. Pruned txs are included as they may
+    /// just have been dropped by peers.
+    pub fn rebroadcast_candidates(
+        &self,
+        found_before: u64,
+        min_fee_rate: FeeRate,
+    ) -> Result<Vec<Transaction>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_data FROM transactions
+            WHERE mined_at IS NULL AND replaced_by IS NULL AND found_at <= ?1
+            AND fee_rate_kwu >= ?2
+            ORDER BY found_at",
+        )?;
+        let tx_datas = stmt
+            .query_map(params![found_before, min_fee_rate.to_db_i64()], |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        tx_datas
+            .into_iter()
+            .map(|tx_data| {
+                let bytes = hex::decode(tx_data)?;
+                Ok(Transaction::consensus_decode(&mut bytes.as_slice())?)
+            })
+            .collect()
+    }
+
+    /// Tracked txs first seen more than `older_than` secs ago that were never mined, pruned or
+    /// replaced. Ones that left the node's mempool unnoticed, e.g. when a prune check missed them,
+    /// would otherwise count as unconfirmed forever.
+    pub fn stuck_txs(&self, older_than: u64) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions
+            WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL AND found_at < ?1
+            ORDER BY found_at",
+        )?;
+        let txids = stmt
+            .query_map(params![now!().saturating_sub(older_than)], |row| {
+                row.get::<_, String>(0)
+            })?
+            .map(|txid| Ok(Txid::from_str(&txid?)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(txids)
+    }
+
+    pub(crate) fn txids_in_mempool(&self) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        // Txs replaced by a conflicting tx are no longer in the mempool but were not pruned either
+        let query = "SELECT tx_id FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL";
+        let mut stmt = conn.prepare(query)?;
+        let txids = stmt.query_map([], |row| {
+            let txid_str: String = row.get(0)?;
+            let txid = Txid::from_str(&txid_str).expect("Valid txid");
+            Ok(txid)
+        })?;
+        Ok(txids.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub(crate) fn row_versions(&self) -> Result<RowVersions> {
+        let conn = self.0.get()?;
+        let max_version = |table: &str| -> Result<Option<u32>> {
+            Ok(
+                conn.query_row(&format!("SELECT MAX(version) FROM {}", table), [], |row| {
+                    row.get(0)
+                })?,
+            )
+        };
+        Ok(RowVersions {
+            transactions: max_version("transactions")?,
+            rbf: max_version("rbf")?,
+            mempool_state: max_version("mempool_state")?,
+        })
+    }
+
+    pub(crate) fn unconfirmed_found_at(&self) -> Result<Vec<(Txid, u64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, found_at FROM transactions
+            WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+        )?;
+        let txs = stmt.query_map([], |row| {
+            let txid_str: String = row.get(0)?;
+            let txid = Txid::from_str(&txid_str).expect("Valid txid");
+            Ok((txid, row.get(1)?))
+        })?;
+        Ok(txs.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Link tracked unconfirmed children and parents missing a CPFP link, e.g. when a mempool
+    /// scan stored a child before its parent. Existing links are kept, returns how many were set.
+    pub(crate) fn reconcile_cpfp_links(&self) -> Result<usize> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        let mut linked = db_tx.execute(
+            "UPDATE transactions SET parent_txid = (
+                SELECT p.tx_id FROM tx_inputs i JOIN transactions p ON p.tx_id = i.prev_txid
+                WHERE i.txid = transactions.tx_id AND p.mined_at IS NULL AND p.pruned_at IS NULL
+                ORDER BY p.tx_id LIMIT 1
+            )
+            WHERE parent_txid IS NULL AND mined_at IS NULL AND pruned_at IS NULL AND EXISTS (
+                SELECT 1 FROM tx_inputs i JOIN transactions p ON p.tx_id = i.prev_txid
+                WHERE i.txid = transactions.tx_id AND p.mined_at IS NULL AND p.pruned_at IS NULL
+            )",
+            [],
+        )?;
+        linked += db_tx.execute(
+            "UPDATE transactions SET child_txid = (
+                SELECT c.tx_id FROM tx_inputs i JOIN transactions c ON c.tx_id = i.txid
+                WHERE i.prev_txid = transactions.tx_id AND c.mined_at IS NULL AND c.pruned_at IS NULL
+                ORDER BY c.tx_id LIMIT 1
+            )
+            WHERE child_txid IS NULL AND mined_at IS NULL AND pruned_at IS NULL AND EXISTS (
+                SELECT 1 FROM tx_inputs i JOIN transactions c ON c.tx_id = i.txid
+                WHERE i.prev_txid = transactions.tx_id AND c.mined_at IS NULL AND c.pruned_at IS NULL
+            )",
+            [],
+        )?;
+        db_tx.commit()?;
+        Ok(linked)
+    }
+
+    pub(crate) fn unconfirmed_fee_rates(&self) -> Result<Vec<FeeRate>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT fee_rate_kwu AS kwu FROM transactions
+            WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL ORDER BY kwu",
+        )?;
+        let fee_rates = stmt.query_map([], |row| Ok(FeeRate::from_db_i64(row.get(0)?)))?;
+        Ok(fee_rates.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub(crate) fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        let mempool_txids = self.txids_in_mempool()?;
+        // If mempool is empty, don't mark anything as pruned
+        // This could be a temporary state or network issue
+        // We dont want to mark all txs as pruned
+        if txids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let txids_not_in_current_mempool = mempool_txids
+            .iter()
+            .filter(|txid| !txids.contains(txid))
+            .copied()
+            .collect::<Vec<_>>();
+
+        Ok(txids_not_in_current_mempool)
+    }
 
     pub(crate) fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
         if txids.is_empty() {
@@ -236,8 +1191,19 @@ impl Database {
             .collect::<Vec<String>>()
             .join(",");
         info!("txid_list: {}", txid_list);
+        // Blocks between the height at found time and the latest snapshot's, txs stored without
+        // found_at_height fall back to the snapshot before found_at. NULL without snapshots.
         let query = format!(
-            "UPDATE transactions SET pruned_at = ?1 WHERE tx_id IN ({})",
+            "UPDATE transactions SET pruned_at = ?1,
+                blocks_survived = (
+                    SELECT block_height FROM mempool_state WHERE block_height IS NOT NULL
+                    ORDER BY created_at DESC LIMIT 1
+                ) - COALESCE(found_at_height, (
+                    SELECT block_height FROM mempool_state
+                    WHERE block_height IS NOT NULL AND created_at <= transactions.found_at
+                    ORDER BY created_at DESC LIMIT 1
+                ))
+            WHERE tx_id IN ({})",
             txid_list
         );
         let mut stmt = conn.prepare(&query)?;
@@ -250,9 +1216,68 @@ impl Database {
         tx: Transaction,
         found_at: Option<u64>,
         absolute_fee: Amount,
-        _fee_rate: FeeRate,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let found_at_height = self.tip_height();
+        let mut conn = self.0.get()?;
+        // Take the write lock up front so concurrent workers can't chain off the same first-seen record
+        let db_tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.insert_tx(
+            &db_tx,
+            tx,
+            found_at,
+            absolute_fee,
+            fee_rate,
+            found_at_height,
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Store a batch of unconfirmed txs in one transaction, in order so parents come before their
+    /// children. A tx that fails is rolled back alone and logged, the count stored is returned.
+    pub(crate) fn insert_mempool_txs(
+        &self,
+        txs: Vec<(Transaction, Option<u64>, Amount, FeeRate)>,
+    ) -> Result<usize> {
+        let found_at_height = self.tip_height();
+        let mut conn = self.0.get()?;
+        let mut db_tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut inserted = 0;
+        for (tx, found_at, absolute_fee, fee_rate) in txs {
+            let txid = tx.compute_txid();
+            // Rolled back when dropped uncommitted
+            let savepoint = db_tx.savepoint()?;
+            let stored = self.insert_tx(
+                &savepoint,
+                tx,
+                found_at,
+                absolute_fee,
+                fee_rate,
+                found_at_height,
+            );
+            match stored {
+                Ok(()) => {
+                    savepoint.commit()?;
+                    inserted += 1;
+                }
+                Err(e) => error!("Error inserting tx {}: {}", txid, e),
+            }
+        }
+        db_tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Store an unconfirmed tx on `conn`, part of the caller's transaction
+    fn insert_tx(
+        &self,
+        conn: &rusqlite::Connection,
+        tx: Transaction,
+        found_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+        found_at_height: Option<u64>,
     ) -> Result<()> {
-        let conn = self.0.get()?;
         let inputs_hash = get_inputs_hash(tx.clone().input)?;
         let mut tx_bytes = vec![];
         tx.consensus_encode(&mut tx_bytes)?;
@@ -286,31 +1311,37 @@ impl Database {
             }
         }
 
-        // Calculate fee rate as decimal: satoshis per vbyte
-        let weight = tx.weight();
-        let vbytes = weight.to_vbytes_ceil();
-        let fee_rate_decimal = if vbytes == 0 {
-            0.0
-        } else {
-            absolute_fee.to_sat() as f64 / vbytes as f64
-        };
         let size = tx_bytes.len() as i64;
-        let weight = weight.to_wu() as i64;
+        let weight = tx.weight().to_wu() as i64;
+
+        let conflicting_txids = self.find_conflicting_txids(conn, &tx, &inputs_hash)?;
+        archive_tx_version(conn, &inputs_hash, &tx_id)?;
 
         conn.execute(
             "INSERT OR REPLACE INTO transactions
-            (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate, size, weight, version)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate, fee_rate_kwu, size, weight, version, signals_rbf,
+            lock_time, uses_csv, is_final, burned_amount, data_outputs, tx_version, wtxid, found_at_height)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
             params![
                 inputs_hash,
                 tx_id,
                 tx_str,
                 found_at,
-                absolute_fee.to_sat(),
-                fee_rate_decimal,
+                absolute_fee.to_db_i64(),
+                fee_rate_sat_per_vb(fee_rate),
+                fee_rate.to_db_i64(),
                 size,
                 weight,
-                MEMPOOL_TRANSACTION_VERSION
+                MEMPOOL_TRANSACTION_VERSION,
+                signals_rbf(&tx),
+                tx.lock_time.to_consensus_u32(),
+                uses_relative_timelock(&tx),
+                is_final(&tx),
+                burned_amount(&tx).to_db_i64(),
+                data_output_count(&tx),
+                tx.version.0,
+                tx.compute_wtxid().to_string(),
+                found_at_height
             ],
         )?;
 
@@ -321,15 +1352,94 @@ impl Database {
             )?;
         }
 
+        insert_tx_inputs(conn, &tx)?;
+        record_first_seen(conn, &tx_id, found_at)?;
+
+        for conflicting_txid in conflicting_txids {
+            info!(
+                "Transaction {} conflicts with tracked tx {}",
+                tx_id, conflicting_txid
+            );
+            conn.execute(
+                "INSERT OR IGNORE INTO conflicts (tx_id, conflicting_txid, created_at) VALUES (?1, ?2, ?3)",
+                params![tx_id, conflicting_txid, now!()],
+            )?;
+            // Our node accepted this tx, so the conflicting one was evicted in its favor
+            conn.execute(
+                "UPDATE transactions SET replaced_by = ?1 WHERE tx_id = ?2",
+                params![tx_id, conflicting_txid],
+            )?;
+        }
+
         Ok(())
     }
 
-    pub(crate) fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+    /// Walk the first-seen log in order, returning the seq of the first record
+    /// whose link to the previous record or own hash doesn't verify
+    #[allow(dead_code)]
+    pub fn verify_first_seen_chain(&self) -> Result<Option<i64>> {
         let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, tx_id, found_at, prev_hash, hash FROM first_seen_log ORDER BY seq",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
 
-        let count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
+        let mut expected_prev_hash = FIRST_SEEN_GENESIS_HASH.to_string();
+        for row in rows {
+            let (seq, tx_id, found_at, prev_hash, hash) = row?;
+            if prev_hash != expected_prev_hash
+                || hash != first_seen_hash(&prev_hash, &tx_id, found_at)
+            {
+                return Ok(Some(seq));
+            }
+            expected_prev_hash = hash;
+        }
+        Ok(None)
+    }
+
+    /// Find unconfirmed tracked txs that spend any of the same outpoints as `tx`
+    /// but are keyed under a different inputs_hash
+    fn find_conflicting_txids(
+        &self,
+        conn: &rusqlite::Connection,
+        tx: &Transaction,
+        inputs_hash: &str,
+    ) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT t.tx_id FROM tx_inputs i
+            JOIN transactions t ON t.tx_id = i.txid
+            WHERE i.prev_txid = ?1 AND i.prev_vout = ?2
+            AND t.mined_at IS NULL AND t.pruned_at IS NULL AND t.inputs_hash != ?3",
+        )?;
+
+        let mut conflicting_txids = BTreeSet::new();
+        for input in tx.input.iter() {
+            let prev_txid = input.previous_output.txid.to_string();
+            let rows = stmt.query_map(
+                params![prev_txid, input.previous_output.vout, inputs_hash],
+                |row| row.get::<_, String>(0),
+            )?;
+            for row in rows {
+                conflicting_txids.insert(row?);
+            }
+        }
+        Ok(conflicting_txids.into_iter().collect())
+    }
+
+    pub(crate) fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        let conn = self.0.get()?;
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+
+        let count: i32 = conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE inputs_hash = ?1",
             params![inputs_hash],
             |row| row.get(0),
         )?;
@@ -337,12 +1447,18 @@ impl Database {
         Ok(count > 0)
     }
 
+    pub(crate) fn inputs_hashes(&self) -> Result<Vec<String>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare("SELECT inputs_hash FROM transactions")?;
+        let hashes = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        Ok(hashes.collect::<Result<Vec<_>, _>>()?)
+    }
+
     pub(crate) fn record_rbf(
         &self,
         tx: &Transaction,
         fee_total: u64,
-        // TODO: Store the fee rate bump
-        _fee_rate: FeeRate,
+        fee_rate: FeeRate,
     ) -> Result<()> {
         let conn = self.0.get()?;
         let inputs_hash = get_inputs_hash(tx.clone().input)?;
@@ -353,25 +1469,120 @@ impl Database {
             return Ok(());
         }
 
+        // found_at is kept across replacements, so this is the delay since the original tx.
+        // The version being replaced is the last rbf, or the stored tx if there was none.
+        let created_at = now!();
+        let (found_at, prev_fee, prev_fee_rate_kwu, prev_vsize): (
+            u64,
+            Option<i64>,
+            Option<i64>,
+            Option<i64>,
+        ) = conn.query_row(
+            "SELECT t.found_at,
+            CASE WHEN r.inputs_hash IS NULL THEN t.absolute_fee ELSE r.fee_total END,
+            CASE WHEN r.inputs_hash IS NULL
+                THEN t.fee_rate_kwu ELSE r.fee_rate_kwu END,
+            CASE WHEN r.inputs_hash IS NULL THEN (t.weight + 3) / 4 ELSE r.vsize END
+            FROM transactions t LEFT JOIN rbf r ON r.inputs_hash = t.inputs_hash
+            WHERE t.inputs_hash = ?1",
+            params![inputs_hash],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let bump_delay_secs = created_at.saturating_sub(found_at);
+        let delta = RbfDelta::between(
+            (prev_fee, prev_fee_rate_kwu, prev_vsize),
+            tx,
+            fee_total,
+            fee_rate,
+        );
+
         // Insert new tx into rbf table
         let txid = tx.compute_txid().to_string();
         conn.execute(
-            "INSERT OR REPLACE INTO rbf (inputs_hash, created_at, fee_total, replaces, version) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![inputs_hash, now!(), fee_total, txid, RBF_TRANSACTION_VERSION],
+            "INSERT OR REPLACE INTO rbf (inputs_hash, created_at, fee_total, fee_rate_kwu, replaces, version, bump_delay_secs,
+            vsize, fee_delta, fee_rate_delta_kwu, vsize_delta, rate_down_abs_up, tx_data)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                inputs_hash,
+                created_at,
+                fee_total,
+                fee_rate.to_db_i64(),
+                txid,
+                RBF_TRANSACTION_VERSION,
+                bump_delay_secs,
+                tx.vsize() as i64,
+                delta.map(|delta| delta.fee_delta),
+                delta.map(|delta| delta.fee_rate_delta_kwu),
+                delta.map(|delta| delta.vsize_delta),
+                delta.map(|delta| delta.rate_down_abs_up()),
+                serialize_hex(tx),
+            ],
         )?;
 
         Ok(())
     }
 
-    pub(crate) fn update_txid_by_inputs_hash(&self, tx: &Transaction) -> Result<()> {
+    pub(crate) fn update_txid_by_inputs_hash(
+        &self,
+        tx: &Transaction,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        replace_tx_version(&db_tx, tx, absolute_fee, fee_rate)?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Every txid the tx keyed by `inputs_hash` went by, oldest first and the current one last.
+    /// Covers both versions archived in `tx_history` and RBFs that overwrote the txid in place.
+    #[allow(dead_code)]
+    pub fn txid_history(&self, inputs_hash: &str) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt =
+            conn.prepare("SELECT tx_id FROM tx_history WHERE inputs_hash = ?1 ORDER BY id")?;
+        let mut txids = stmt
+            .query_map(params![inputs_hash], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let current: Option<(String, Option<String>)> = conn
+            .query_row(
+                "SELECT tx_id, prior_txids FROM transactions WHERE inputs_hash = ?1",
+                params![inputs_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((tx_id, prior_txids)) = current {
+            if let Some(prior_txids) = prior_txids {
+                txids.extend(serde_json::from_str::<Vec<String>>(&prior_txids)?);
+            }
+            txids.push(tx_id);
+        }
+
+        let mut history = vec![];
+        for txid in txids {
+            let txid = Txid::from_str(&txid)?;
+            if !history.contains(&txid) {
+                history.push(txid);
+            }
+        }
+        Ok(history)
+    }
+
+    pub(crate) fn record_node_info(&self, node_info: &NodeInfo) -> Result<()> {
         let conn = self.0.get()?;
-        let inputs_hash = get_inputs_hash(tx.clone().input)?;
-        let tx_id = tx.compute_txid().to_string();
         conn.execute(
-            "UPDATE transactions SET tx_id = ?1 WHERE inputs_hash = ?2",
-            params![tx_id, inputs_hash],
+            "INSERT OR REPLACE INTO node_info
+            (created_at, connections, connections_in, connections_out, protocol_versions)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                now!(),
+                node_info.connections,
+                node_info.connections_in,
+                node_info.connections_out,
+                node_info.protocol_versions_json()
+            ],
         )?;
-
         Ok(())
     }
 
@@ -385,18 +1596,289 @@ impl Database {
         Ok(())
     }
 
-    /// Remove txs that are neither pruned nor mined
-    /// This should be called when the system if first started
-    /// As the db may include old txs that have been pruned or mined
-    pub(crate) fn remove_stale_txs(&self) -> Result<()> {
+    pub(crate) fn record_block_tx(&self, block_height: u64, fee_rate: FeeRate) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT INTO blocks (height, clearing_fee_rate_kwu, tx_count, updated_at) VALUES (?1, ?2, 1, ?3)
+            ON CONFLICT (height) DO UPDATE SET
+            clearing_fee_rate_kwu = MIN(clearing_fee_rate_kwu, excluded.clearing_fee_rate_kwu),
+            tx_count = tx_count + 1, updated_at = excluded.updated_at",
+            params![block_height, fee_rate.to_db_i64(), now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Lowest fee rate of the non-coinbase txs mined at `height`, among those the worker processed
+    #[allow(dead_code)]
+    pub fn block_clearing_feerate(&self, height: u64) -> Result<Option<FeeRate>> {
+        let conn = self.0.get()?;
+        let fee_rate_kwu: Option<i64> = conn
+            .query_row(
+                "SELECT clearing_fee_rate_kwu FROM blocks WHERE height = ?1",
+                params![height],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(fee_rate_kwu.map(FeeRate::from_db_i64))
+    }
+
+    /// Recompute the fee rate targets of the buckets whose next 6 blocks may have changed.
+    /// A block's clearing fee rate is only complete once its txs were processed,
+    /// so called on each new block it settles the targets up to the previous one.
+    pub(crate) fn update_feerate_targets(&self) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO feerate_targets
+            (bucket_start, block_height, next_block_kwu, next_3_blocks_kwu, next_6_blocks_kwu, updated_at)
+            SELECT bucket_start, block_height,
+                (SELECT MIN(clearing_fee_rate_kwu) FROM blocks WHERE height = b.block_height + 1),
+                (SELECT MIN(clearing_fee_rate_kwu) FROM blocks
+                    WHERE height BETWEEN b.block_height + 1 AND b.block_height + 3),
+                (SELECT MIN(clearing_fee_rate_kwu) FROM blocks
+                    WHERE height BETWEEN b.block_height + 1 AND b.block_height + 6),
+                ?2
+            FROM (
+                SELECT created_at - created_at % ?1 AS bucket_start, MIN(block_height) AS block_height
+                FROM mempool_state WHERE block_height >= (SELECT MAX(height) FROM blocks) - 7
+                GROUP BY bucket_start
+            ) b",
+            params![FEERATE_TARGET_BUCKET_SECS, now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Fee rate targets of the time buckets starting at or after `since` (unix secs), oldest first
+    pub fn feerate_target_series(&self, since: u64) -> Result<Vec<FeerateTarget>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT bucket_start, block_height, next_block_kwu, next_3_blocks_kwu, next_6_blocks_kwu
+            FROM feerate_targets WHERE bucket_start >= ?1 ORDER BY bucket_start",
+        )?;
+        let targets = stmt
+            .query_map(params![since], |row| {
+                let fee_rate = |i: usize| {
+                    row.get::<_, Option<i64>>(i)
+                        .map(|kwu| kwu.map(FeeRate::from_db_i64))
+                };
+                Ok(FeerateTarget {
+                    bucket_start: row.get(0)?,
+                    block_height: row.get(1)?,
+                    next_block: fee_rate(2)?,
+                    next_3_blocks: fee_rate(3)?,
+                    next_6_blocks: fee_rate(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(targets)
+    }
+
+    pub(crate) fn record_fee_discrepancy(
+        &self,
+        txid: &Txid,
+        computed_fee: Amount,
+        node_fee: Amount,
+    ) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO fee_discrepancies (tx_id, found_at, computed_fee, node_fee) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                txid.to_string(),
+                now!(),
+                computed_fee.to_db_i64(),
+                node_fee.to_db_i64()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn defer_fee_tx(&self, deferred: &DeferredTx) -> Result<()> {
         let conn = self.0.get()?;
         conn.execute(
-            "DELETE FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL",
+            "INSERT OR REPLACE INTO deferred_fee_txs (tx_id, tx_data, found_at, arrival_seq, retries, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                deferred.tx.compute_txid().to_string(),
+                serialize_hex(&deferred.tx),
+                deferred.found_at,
+                deferred.arrival_seq,
+                deferred.retries,
+                now!()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remove and return up to `limit` deferred txs, oldest first. Ones stored meanwhile,
+    /// e.g. from a rebroadcast, are dropped.
+    pub(crate) fn take_deferred_fee_txs(&self, limit: usize) -> Result<Vec<DeferredTx>> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        db_tx.execute(
+            "DELETE FROM deferred_fee_txs WHERE tx_id IN (SELECT tx_id FROM transactions)",
             [],
         )?;
+        let rows = {
+            let mut stmt = db_tx.prepare(
+                "SELECT tx_data, found_at, arrival_seq, retries FROM deferred_fee_txs
+                ORDER BY found_at, arrival_seq LIMIT ?1",
+            )?;
+            let rows = stmt
+                .query_map(params![limit], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, u64>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, u32>(3)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+        let mut deferred = vec![];
+        {
+            let mut stmt = db_tx.prepare("DELETE FROM deferred_fee_txs WHERE tx_id = ?1")?;
+            for (tx_data, found_at, arrival_seq, retries) in rows {
+                let tx: Transaction = deserialize_hex(&tx_data)?;
+                stmt.execute(params![tx.compute_txid().to_string()])?;
+                deferred.push(DeferredTx {
+                    tx,
+                    found_at,
+                    arrival_seq,
+                    retries,
+                });
+            }
+        }
+        db_tx.commit()?;
+        Ok(deferred)
+    }
+
+    pub(crate) fn record_oversized_tx(&self, tx: &Transaction) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO oversized_txs (tx_id, found_at, size, weight) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                tx.compute_txid().to_string(),
+                now!(),
+                tx.total_size(),
+                tx.weight().to_wu()
+            ],
+        )?;
         Ok(())
     }
 
+    /// Check the stored data against invariants the tracker should never break
+    pub(crate) fn audit(&self) -> Result<AuditReport> {
+        let conn = self.0.get()?;
+        let count =
+            |query: &str| -> Result<u64> { Ok(conn.query_row(query, [], |row| row.get(0))?) };
+        Ok(AuditReport {
+            mined_and_pruned: count(
+                "SELECT COUNT(*) FROM transactions WHERE mined_at IS NOT NULL AND pruned_at IS NOT NULL",
+            )?,
+            orphaned_rbfs: count(
+                "SELECT COUNT(*) FROM rbf r WHERE NOT EXISTS
+                (SELECT 1 FROM transactions t WHERE t.inputs_hash = r.inputs_hash)",
+            )?,
+            negative_fees: count("SELECT COUNT(*) FROM transactions WHERE absolute_fee < 0")?,
+            coinbase_inputs: count(&format!(
+                "SELECT COUNT(*) FROM tx_inputs i JOIN transactions t ON t.tx_id = i.txid
+                WHERE t.version = {}",
+                COINBASE_TRANSACTION_VERSION
+            ))?,
+        })
+    }
+
+    /// Check every rbf row and replaced_by pointer against transactions, removing dangling links
+    /// and overwriting each replaced tx with the latest replacement its rbf row recorded
+    pub fn repair_rbf_links(&self) -> Result<RbfRepairReport> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        let dangling_rbfs = db_tx.execute(
+            "DELETE FROM rbf WHERE NOT EXISTS
+            (SELECT 1 FROM transactions t WHERE t.inputs_hash = rbf.inputs_hash)",
+            [],
+        )?;
+        // found_at is kept across replacements, so a later one means the tx was stored anew
+        let stale_rbfs = db_tx.execute(
+            "DELETE FROM rbf WHERE EXISTS
+            (SELECT 1 FROM transactions t WHERE t.inputs_hash = rbf.inputs_hash
+            AND t.tx_id != rbf.replaces AND t.found_at > rbf.created_at)",
+            [],
+        )?;
+        let stale_heads = {
+            let mut stmt = db_tx.prepare(
+                "SELECT r.inputs_hash, r.tx_data, r.fee_total, r.fee_rate_kwu FROM rbf r
+                JOIN transactions t ON t.inputs_hash = r.inputs_hash WHERE t.tx_id != r.replaces",
+            )?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Option<i64>>(3)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+        let mut heads_fixed = 0;
+        let mut unrepairable_rbfs = 0;
+        for (inputs_hash, tx_data, fee_total, fee_rate_kwu) in stale_heads {
+            let (Some(tx_data), Some(fee_rate_kwu)) = (tx_data, fee_rate_kwu) else {
+                db_tx.execute(
+                    "DELETE FROM rbf WHERE inputs_hash = ?1",
+                    params![inputs_hash],
+                )?;
+                unrepairable_rbfs += 1;
+                continue;
+            };
+            let tx = deserialize_hex::<Transaction>(&tx_data)?;
+            replace_tx_version(
+                &db_tx,
+                &tx,
+                Amount::from_db_i64(fee_total),
+                FeeRate::from_db_i64(fee_rate_kwu),
+            )?;
+            heads_fixed += 1;
+        }
+        let dangling_replaced_by = db_tx.execute(
+            "UPDATE transactions SET replaced_by = NULL WHERE replaced_by IS NOT NULL
+            AND replaced_by NOT IN (SELECT tx_id FROM transactions)
+            AND replaced_by NOT IN (SELECT tx_id FROM tx_history)",
+            [],
+        )?;
+        db_tx.commit()?;
+        Ok(RbfRepairReport {
+            dangling_rbfs: dangling_rbfs as u64,
+            stale_rbfs: stale_rbfs as u64,
+            heads_fixed,
+            unrepairable_rbfs,
+            dangling_replaced_by: dangling_replaced_by as u64,
+        })
+    }
+
+    /// Remove unconfirmed txs that are no longer in the live mempool.
+    /// This should be called when the system is first started, as txs may have been
+    /// pruned or mined while it was down. Txs still in the mempool are kept with their
+    /// original found_at, their txids are returned.
+    pub(crate) fn remove_stale_txs(&self, live_txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        let live_txids = live_txids.into_iter().collect::<BTreeSet<_>>();
+        let (kept, stale): (Vec<_>, Vec<_>) = self
+            .txids_in_mempool()?
+            .into_iter()
+            .partition(|txid| live_txids.contains(txid));
+
+        let conn = self.0.get()?;
+        for txid in stale {
+            conn.execute(
+                "DELETE FROM transactions WHERE tx_id = ?1 AND pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+                params![txid.to_string()],
+            )?;
+        }
+        Ok(kept)
+    }
+
     pub(crate) fn run_migrations(&self) -> Result<()> {
         let conn = self.0.get()?;
         run_migrations(&conn)?;
@@ -418,6 +1900,50 @@ impl Database {
         }))
     }
 
+    /// Label a tx, returns false if it already had this label
+    pub fn add_label(&self, txid: &Txid, label: &str) -> Result<bool> {
+        if label.trim().is_empty() {
+            return Err(anyhow::anyhow!("label must not be empty"));
+        }
+        let conn = self.0.get()?;
+        let added = conn.execute(
+            "INSERT OR IGNORE INTO tx_labels (tx_id, label, created_at) VALUES (?1, ?2, ?3)",
+            params![txid.to_string(), label, now!()],
+        )?;
+        Ok(added > 0)
+    }
+
+    /// Txids labeled `label`, oldest label first
+    pub fn txs_with_label(&self, label: &str) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn
+            .prepare("SELECT tx_id FROM tx_labels WHERE label = ?1 ORDER BY created_at, tx_id")?;
+        let txids = stmt
+            .query_map(params![label], |row| row.get::<_, String>(0))?
+            .map(|txid| Ok(Txid::from_str(&txid?)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(txids)
+    }
+
+    /// Look a tracked tx up by its witness txid, e.g. to join with relay data keyed by wtxid
+    #[allow(dead_code)]
+    pub fn get_tx_by_wtxid(&self, wtxid: &Wtxid) -> Result<Option<Transaction>> {
+        let conn = self.0.get()?;
+        let tx_data: Option<String> = conn
+            .query_row(
+                "SELECT tx_data FROM transactions WHERE wtxid = ?1",
+                params![wtxid.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        tx_data
+            .map(|tx_data| {
+                let bytes = hex::decode(tx_data)?;
+                Ok(Transaction::consensus_decode(&mut bytes.as_slice())?)
+            })
+            .transpose()
+    }
+
     /// Check if a transaction is marked as a CPFP parent
     #[allow(dead_code)]
     pub fn child_txid(&self, txid: &Txid) -> Result<Option<Txid>> {
@@ -444,16 +1970,18 @@ impl Database {
         Ok(parent_txid.map(|txid| Txid::from_str(&txid).expect("Valid txid")))
     }
 
-    /// Check if a transaction is mined
-    #[allow(dead_code)]
+    /// Check if a transaction is mined, false for untracked txs
     pub fn is_mined(&self, txid: &Txid) -> Result<bool> {
         let conn = self.0.get()?;
         let txid_hex = txid.to_string();
-        let mined_at: Option<u64> = conn.query_row(
-            "SELECT mined_at FROM transactions WHERE tx_id = ?1",
-            params![txid_hex],
-            |row| row.get(0),
-        )?;
+        let mined_at: Option<u64> = conn
+            .query_row(
+                "SELECT mined_at FROM transactions WHERE tx_id = ?1",
+                params![txid_hex],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
         Ok(mined_at.is_some())
     }
 
@@ -470,53 +1998,3131 @@ impl Database {
         Ok(count > 0)
     }
 
-    /// Get the fee rate for a transaction
+    pub(crate) fn record_batch_payouts(&self, txs: Vec<(Txid, bool)>) -> Result<()> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        {
+            let mut stmt =
+                db_tx.prepare("UPDATE transactions SET is_batch_payout = ?1 WHERE tx_id = ?2")?;
+            for (txid, is_batch_payout) in txs {
+                stmt.execute(params![is_batch_payout, txid.to_string()])?;
+            }
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Txids of batch payouts found since `since` (unix secs)
     #[allow(dead_code)]
-    pub fn get_fee_rate(&self, txid: &Txid) -> Result<Option<f64>> {
+    pub fn batch_payouts(&self, since: u64) -> Result<Vec<Txid>> {
         let conn = self.0.get()?;
-        let txid_hex = txid.to_string();
-        let mut stmt = conn.prepare("SELECT fee_rate FROM transactions WHERE tx_id = ?1")?;
-        let fee_rate: Option<f64> = stmt
-            .query_row(params![txid_hex], |row| row.get(0))
-            .optional()?;
-        Ok(fee_rate)
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions WHERE is_batch_payout AND found_at >= ?1 ORDER BY found_at",
+        )?;
+        let txids = stmt
+            .query_map(params![since], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bitcoin::{absolute::LockTime, Amount, Transaction, TxOut};
-    use tempfile::TempDir;
+    /// Values of the `outpoints` spending tracked unconfirmed txs, the rest are left to the node
+    pub(crate) fn tracked_prevouts(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<HashMap<OutPoint, Amount>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_data FROM transactions WHERE tx_id = ?1
+            AND mined_at IS NULL AND pruned_at IS NULL AND replaced_by IS NULL",
+        )?;
+        let mut parents = vec![];
+        for txid in outpoints
+            .iter()
+            .map(|outpoint| outpoint.txid)
+            .collect::<BTreeSet<_>>()
+        {
+            let tx_data: Option<String> = stmt
+                .query_row(params![txid.to_string()], |row| row.get(0))
+                .optional()?;
+            if let Some(tx_data) = tx_data {
+                parents.push((txid, tx_data));
+            }
+        }
+        prevout_values(parents, outpoints)
+    }
 
-    #[test]
-    fn test_fee_rate_stored_as_decimal() -> Result<()> {
-        let tempdir = TempDir::new()?;
-        let db_path = tempdir.path().join("test.db");
-        let db = Database::new(db_path.to_str().unwrap())?;
+    /// How many inputs of a tx spent tracked unconfirmed parents, see `--prevouts-from-db`
+    pub(crate) fn record_unconfirmed_inputs(&self, txid: &Txid, count: usize) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET unconfirmed_inputs = ?1 WHERE tx_id = ?2",
+            params![count, txid.to_string()],
+        )?;
+        Ok(())
+    }
 
-        let tx = Transaction {
-            version: bitcoin::transaction::Version::TWO,
-            lock_time: LockTime::ZERO,
-            input: vec![],
-            output: vec![TxOut {
-                value: Amount::from_sat(100_000),
-                script_pubkey: bitcoin::ScriptBuf::new(),
-            }],
+    pub(crate) fn record_fee_anomaly(&self, txid: &Txid) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET fee_anomaly = TRUE WHERE tx_id = ?1",
+            params![txid.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Txs found since `since` (unix secs) with a fee above the anomaly ceiling, as txid,
+    /// absolute fee and found_at, largest fee first
+    #[allow(dead_code)]
+    pub fn fee_anomalies(&self, since: u64) -> Result<Vec<(Txid, Amount, u64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, absolute_fee, found_at FROM transactions
+            WHERE fee_anomaly AND found_at >= ?1 ORDER BY absolute_fee DESC, tx_id",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, u64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(txid, fee, found_at)| {
+                Ok((Txid::from_str(&txid)?, Amount::from_db_i64(fee), found_at))
+            })
+            .collect()
+    }
+
+    pub(crate) fn record_arrival_seq(&self, txid: &Txid, arrival_seq: u64) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET arrival_seq = ?1 WHERE tx_id = ?2",
+            params![arrival_seq, txid.to_string()],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn max_arrival_seq(&self) -> Result<Option<u64>> {
+        let conn = self.0.get()?;
+        let max_arrival_seq =
+            conn.query_row("SELECT MAX(arrival_seq) FROM transactions", [], |row| {
+                row.get(0)
+            })?;
+        Ok(max_arrival_seq)
+    }
+
+    /// Up to `limit` tracked txids starting with the hex `prefix`, in txid order
+    pub fn search_txid_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<Txid>> {
+        let prefix = prefix.to_lowercase();
+        if prefix.is_empty() || prefix.len() > 64 || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(anyhow::anyhow!("txid prefix must be 1 to 64 hex chars"));
+        }
+        let conn = self.0.get()?;
+        // Every continuation of the prefix sorts below prefix || 'g', so idx_transactions_tx_id serves the range
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions WHERE tx_id >= ?1 AND tx_id < ?2 ORDER BY tx_id LIMIT ?3",
+        )?;
+        let txids = stmt
+            .query_map(
+                params![prefix, format!("{}g", prefix), limit as u64],
+                |row| row.get::<_, String>(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+    }
+
+    /// Txids found in `[since, until]` (unix secs) in the order they arrived.
+    /// Txs from the startup mempool scan have no arrival seq and sort first within their second.
+    #[allow(dead_code)]
+    pub fn arrival_order(&self, since: u64, until: u64) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id FROM transactions WHERE found_at >= ?1 AND found_at <= ?2
+            ORDER BY found_at, arrival_seq",
+        )?;
+        let txids = stmt
+            .query_map(params![since, until], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+    }
+
+    /// Whether `a` arrived before `b`, `None` unless both have an arrival seq
+    #[allow(dead_code)]
+    pub fn arrived_before(&self, a: &Txid, b: &Txid) -> Result<Option<bool>> {
+        let conn = self.0.get()?;
+        let arrival_seq = |txid: &Txid| -> Result<Option<u64>> {
+            Ok(conn
+                .query_row(
+                    "SELECT arrival_seq FROM transactions WHERE tx_id = ?1",
+                    params![txid.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .flatten())
         };
+        Ok(match (arrival_seq(a)?, arrival_seq(b)?) {
+            (Some(a), Some(b)) => Some(a < b),
+            _ => None,
+        })
+    }
 
-        let absolute_fee = Amount::from_sat(150);
-        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
-        db.insert_mempool_tx(tx.clone(), None, absolute_fee, fee_rate)?;
+    /// Record a stored TRUC tx and whether it fits the v3 limits given its unconfirmed parents.
+    /// Its children are checked when read, see [`Self::truc_txs`].
+    /// Expects `tx` to already be stored with its inputs.
+    pub(crate) fn record_truc_tx(&self, tx: &Transaction) -> Result<()> {
+        if tx.is_coinbase() || !is_truc(tx) {
+            return Ok(());
+        }
+        let conn = self.0.get()?;
+        let tx_id = tx.compute_txid().to_string();
+        let parent_txids = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid.to_string())
+            .collect::<BTreeSet<_>>();
+        let mut unconfirmed_parents = 0;
+        let mut truc_parents = 0;
+        for parent_txid in parent_txids {
+            let parent_version: Option<Option<i32>> = conn
+                .query_row(
+                    "SELECT tx_version FROM transactions WHERE tx_id = ?1
+                    AND mined_at IS NULL AND pruned_at IS NULL AND replaced_by IS NULL",
+                    params![parent_txid],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(parent_version) = parent_version else {
+                continue;
+            };
+            unconfirmed_parents += 1;
+            if parent_version == Some(3) {
+                truc_parents += 1;
+            }
+        }
+        let respects_topology =
+            truc_respects_topology(tx, unconfirmed_parents, truc_parents == unconfirmed_parents);
+        // unconfirmed_children is left at 0, children come and go so they're counted on read
+        conn.execute(
+            "INSERT OR REPLACE INTO truc_txs
+            (tx_id, found_at, unconfirmed_parents, unconfirmed_children, respects_topology)
+            VALUES (?1, ?2, ?3, 0, ?4)",
+            params![tx_id, now!(), unconfirmed_parents, respects_topology],
+        )?;
+        Ok(())
+    }
 
-        let txid = tx.compute_txid();
-        let stored_fee_rate = db.get_fee_rate(&txid)?.expect("fee_rate should exist");
+    /// TRUC txs found since `since` (unix secs), oldest first. Children are those unconfirmed
+    /// now, so a tx whose second child was replaced or mined respects the topology again.
+    pub fn truc_txs(&self, since: u64) -> Result<Vec<TrucTx>> {
+        let conn = self.0.get()?;
+        // A second child, or a non TRUC one, breaks the parent's package
+        let mut stmt = conn.prepare(
+            "SELECT r.tx_id, r.found_at, r.unconfirmed_parents, COUNT(DISTINCT t.tx_id),
+            r.respects_topology AND COUNT(DISTINCT t.tx_id) <= 1
+            AND COALESCE(SUM(t.tx_id IS NOT NULL AND t.tx_version IS NOT 3), 0) = 0,
+            (SELECT absolute_fee FROM transactions WHERE tx_id = r.tx_id)
+            FROM truc_txs r
+            LEFT JOIN tx_inputs i ON i.prev_txid = r.tx_id
+            LEFT JOIN transactions t ON t.tx_id = i.txid
+                AND t.mined_at IS NULL AND t.pruned_at IS NULL AND t.replaced_by IS NULL
+            WHERE r.found_at >= ?1
+            GROUP BY r.tx_id ORDER BY r.found_at",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get::<_, Option<i64>>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(
+                |(
+                    txid,
+                    found_at,
+                    unconfirmed_parents,
+                    unconfirmed_children,
+                    respects_topology,
+                    absolute_fee,
+                )| {
+                    Ok(TrucTx {
+                        txid: Txid::from_str(&txid)?,
+                        found_at,
+                        unconfirmed_parents,
+                        unconfirmed_children,
+                        respects_topology,
+                        absolute_fee: absolute_fee.map(Amount::from_db_i64),
+                    })
+                },
+            )
+            .collect()
+    }
 
-        assert_ne!(
-            stored_fee_rate,
-            stored_fee_rate.floor(),
-            "fee_rate should be a decimal value"
-        );
+    /// Record the drift of txs as txid, our found_at and the node's entry time, once per tx
+    pub(crate) fn record_time_drifts(&self, txs: Vec<(Txid, u64, u64)>) -> Result<()> {
+        let mut conn = self.0.get()?;
+        let db_tx = conn.transaction()?;
+        {
+            let mut stmt = db_tx.prepare(
+                "INSERT OR IGNORE INTO time_drift (tx_id, found_at, node_time, drift_secs) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (txid, found_at, node_time) in txs {
+                let drift_secs = found_at as i64 - node_time as i64;
+                stmt.execute(params![txid.to_string(), found_at, node_time, drift_secs])?;
+            }
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn record_watched_outputs(
+        &self,
+        txid: &Txid,
+        outputs: Vec<(u32, Amount)>,
+    ) -> Result<()> {
+        let conn = self.0.get()?;
+        let found_at = now!();
+        for (vout, value) in outputs {
+            conn.execute(
+                "INSERT OR IGNORE INTO watched_txs (tx_id, vout, value, found_at) VALUES (?1, ?2, ?3, ?4)",
+                params![txid.to_string(), vout, value.to_db_i64(), found_at],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_nonstandard_tx(&self, txid: &Txid, reason: &str) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO nonstandard_txs (tx_id, reason, found_at) VALUES (?1, ?2, ?3)",
+            params![txid.to_string(), reason, now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Non-standard txs found since `since` (unix secs), counted by reason
+    #[allow(dead_code)]
+    pub fn nonstandard_reasons(&self, since: u64) -> Result<Vec<(String, u64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT reason, COUNT(*) FROM nonstandard_txs WHERE found_at >= ?1
+            GROUP BY reason ORDER BY COUNT(*) DESC, reason",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Watched outputs found since `since` (unix secs), as txid, vout and value
+    #[allow(dead_code)]
+    pub fn watched_outputs(&self, since: u64) -> Result<Vec<(Txid, u32, Amount)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, vout, value FROM watched_txs WHERE found_at >= ?1 ORDER BY found_at, tx_id, vout",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(txid, vout, value)| {
+                Ok((Txid::from_str(&txid)?, vout, Amount::from_db_i64(value)))
+            })
+            .collect()
+    }
+
+    /// Record how many outputs of `txid` pay a recently seen script
+    pub(crate) fn record_reused_outputs(&self, txid: &Txid, reused_outputs: u64) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET reused_outputs = ?1 WHERE tx_id = ?2",
+            params![reused_outputs, txid.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Count the tracked unconfirmed txs reusing scripts and their reused outputs, one snapshot per call
+    pub(crate) fn record_address_reuse(&self) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO address_reuse_counts (created_at, tx_count, reused_outputs)
+            SELECT ?1, COUNT(*), COALESCE(SUM(reused_outputs), 0) FROM transactions
+            WHERE reused_outputs IS NOT NULL AND pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+            params![now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Count the tracked unconfirmed txs by nVersion, one snapshot per call
+    pub(crate) fn record_tx_version_counts(&self) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO tx_version_counts (created_at, tx_version, count)
+            SELECT ?1, tx_version, COUNT(*) FROM transactions
+            WHERE tx_version IS NOT NULL AND pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+            GROUP BY tx_version",
+            params![now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Count the tracked unconfirmed txs and their vsize by [`SIZE_BUCKETS`] bucket, one snapshot per call
+    pub(crate) fn record_size_distribution(&self) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO size_bucket_counts (created_at, min_vsize, tx_count, total_vsize)
+                SELECT ?1, {} AS bucket, COUNT(*), SUM(vsize) FROM (
+                    SELECT (weight + 3) / 4 AS vsize FROM transactions
+                    WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                ) GROUP BY bucket",
+                size_bucket_sql("vsize")
+            ),
+            params![now!()],
+        )?;
+        Ok(())
+    }
+
+    /// Record the `FEE_RATE_PERCENTILES` of ascending `fee_rates`, one snapshot per call
+    pub(crate) fn record_fee_rate_percentiles(&self, fee_rates: &[FeeRate]) -> Result<()> {
+        let conn = self.0.get()?;
+        let created_at = now!();
+        let mut stmt = conn.prepare(
+            "INSERT OR REPLACE INTO fee_rate_percentiles (created_at, percentile, fee_rate_kwu)
+            VALUES (?1, ?2, ?3)",
+        )?;
+        for p in FEE_RATE_PERCENTILES {
+            if let Some(fee_rate) = fee_rate_percentile(fee_rates, p) {
+                stmt.execute(params![created_at, p, fee_rate.to_db_i64()])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Per snapshot fee rate percentiles recorded since `since` (unix secs), as created_at,
+    /// percentile and fee rate
+    #[allow(dead_code)]
+    pub fn fee_rate_percentile_series(&self, since: u64) -> Result<Vec<(u64, u8, FeeRate)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, percentile, fee_rate_kwu FROM fee_rate_percentiles
+            WHERE created_at >= ?1 ORDER BY created_at, percentile",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| {
+                Ok((row.get(0)?, row.get(1)?, FeeRate::from_db_i64(row.get(2)?)))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Per snapshot count of tracked unconfirmed txs paying a recently seen script and of their
+    /// outputs doing so, as `(created_at, tx_count, reused_outputs)` since `since` (unix secs)
+    #[allow(dead_code)]
+    pub fn address_reuse_series(&self, since: u64) -> Result<Vec<(u64, u64, u64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, tx_count, reused_outputs FROM address_reuse_counts
+            WHERE created_at >= ?1 ORDER BY created_at",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// `blocks_to_clear` of each mempool state snapshot since `since` (unix secs), as
+    /// (created_at, blocks). Snapshots taken before it was recorded are skipped.
+    #[allow(dead_code)]
+    pub fn blocks_to_clear_series(&self, since: u64) -> Result<Vec<(u64, f64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, blocks_to_clear FROM mempool_state
+            WHERE created_at >= ?1 AND blocks_to_clear IS NOT NULL ORDER BY created_at, id",
+        )?;
+        let rows = stmt
+            .query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Per snapshot counts and total vsize of tracked unconfirmed txs by size, since `since` (unix secs).
+    /// Empty buckets have no row.
+    #[allow(dead_code)]
+    pub fn size_distribution_series(&self, since: u64) -> Result<Vec<SizeBucketCount>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, min_vsize, tx_count, total_vsize FROM size_bucket_counts
+            WHERE created_at >= ?1 ORDER BY created_at, min_vsize",
+        )?;
+        let counts = stmt
+            .query_map(params![since], |row| {
+                let min_vsize: u64 = row.get(1)?;
+                Ok(SizeBucketCount {
+                    created_at: row.get(0)?,
+                    min_vsize,
+                    max_vsize: SIZE_BUCKETS
+                        .iter()
+                        .copied()
+                        .find(|bound| *bound > min_vsize),
+                    tx_count: row.get(2)?,
+                    total_vsize: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(counts)
+    }
+
+    /// How many tracked txs first seen since `since` (unix secs) were mined, pruned, replaced or
+    /// are still unconfirmed, by fee rate. Shows below what fee rate txs tend to get evicted.
+    pub fn outcome_by_feerate_bucket(&self, since: u64) -> Result<Vec<FeeRateOutcome>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} AS bucket,
+                SUM(mined_at IS NOT NULL),
+                SUM(mined_at IS NULL AND replaced_by IS NULL AND pruned_at IS NOT NULL),
+                SUM(mined_at IS NULL AND replaced_by IS NOT NULL),
+                SUM(mined_at IS NULL AND replaced_by IS NULL AND pruned_at IS NULL)
+            FROM (
+                SELECT fee_rate_kwu AS kwu, mined_at, pruned_at, replaced_by
+                FROM transactions WHERE found_at >= ?1 AND version != ?2
+            ) GROUP BY bucket ORDER BY bucket",
+            fee_rate_bucket_sql("kwu")
+        ))?;
+        let outcomes = stmt
+            .query_map(params![since, COINBASE_TRANSACTION_VERSION], |row| {
+                let min_sat_vb: u64 = row.get(0)?;
+                let max_fee_rate = FEE_RATE_BUCKETS
+                    .iter()
+                    .find(|sat_vb| **sat_vb > min_sat_vb)
+                    .map(|sat_vb| whole_sat_per_vb_fee_rate(*sat_vb));
+                Ok(FeeRateOutcome {
+                    min_fee_rate: whole_sat_per_vb_fee_rate(min_sat_vb),
+                    max_fee_rate,
+                    mined: row.get(1)?,
+                    pruned: row.get(2)?,
+                    replaced: row.get(3)?,
+                    unconfirmed: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(outcomes)
+    }
+
+    /// How many blocks the txs first seen since `since` (unix secs) that were evicted rather than
+    /// mined or replaced survived in the mempool, by number of blocks
+    #[allow(dead_code)]
+    pub fn eviction_survival_distribution(&self, since: u64) -> Result<Vec<EvictionSurvival>> {
+        let conn = self.0.get()?;
+        // A reorg can lower the tip while a tx is tracked, such txs count as surviving 0 blocks
+        let mut stmt = conn.prepare(
+            "SELECT MAX(blocks_survived, 0) AS blocks, COUNT(*) FROM transactions
+            WHERE found_at >= ?1 AND pruned_at IS NOT NULL AND mined_at IS NULL
+                AND replaced_by IS NULL AND blocks_survived IS NOT NULL
+            GROUP BY blocks ORDER BY blocks",
+        )?;
+        let distribution = stmt
+            .query_map(params![since], |row| {
+                Ok(EvictionSurvival {
+                    blocks_survived: row.get(0)?,
+                    tx_count: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(distribution)
+    }
+
+    /// Mined, pruned and replaced tracked txs per `bucket_secs` bucket since `since` (unix secs),
+    /// by when it happened. Replacements are the archived versions of same inputs replacements
+    /// plus the txs a conflicting tx displaced.
+    pub fn lifecycle_outcomes_series(
+        &self,
+        bucket_secs: u64,
+        since: u64,
+    ) -> Result<Vec<LifecycleOutcomes>> {
+        if bucket_secs == 0 {
+            return Err(anyhow::anyhow!("bucket size must be at least a second"));
+        }
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT at - at % ?1 AS bucket, SUM(outcome = 0), SUM(outcome = 1), SUM(outcome = 2)
+            FROM (
+                SELECT mined_at AS at, 0 AS outcome FROM transactions
+                WHERE mined_at >= ?2 AND version != ?3
+                UNION ALL
+                SELECT pruned_at, 1 FROM transactions
+                WHERE pruned_at >= ?2 AND mined_at IS NULL AND replaced_by IS NULL AND version != ?3
+                UNION ALL
+                SELECT archived_at, 2 FROM tx_history WHERE archived_at >= ?2
+                UNION ALL
+                SELECT created_at, 2 FROM conflicts WHERE created_at >= ?2
+            ) GROUP BY bucket ORDER BY bucket",
+        )?;
+        let series = stmt
+            .query_map(
+                params![bucket_secs, since, COINBASE_TRANSACTION_VERSION],
+                |row| {
+                    Ok(LifecycleOutcomes {
+                        bucket_start: row.get(0)?,
+                        mined: row.get(1)?,
+                        pruned: row.get(2)?,
+                        replaced: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(series)
+    }
+
+    /// Per snapshot counts of tracked unconfirmed txs by nVersion since `since` (unix secs)
+    #[allow(dead_code)]
+    pub fn tx_version_distribution(&self, since: u64) -> Result<Vec<TxVersionCount>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, tx_version, count FROM tx_version_counts
+            WHERE created_at >= ?1 ORDER BY created_at, tx_version",
+        )?;
+        let counts = stmt
+            .query_map(params![since], |row| {
+                Ok(TxVersionCount {
+                    created_at: row.get(0)?,
+                    tx_version: row.get(1)?,
+                    count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(counts)
+    }
+
+    pub(crate) fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value, updated_at) VALUES (?1, ?2, ?3)",
+            params![key, value, now!()],
+        )?;
+        Ok(())
+    }
+
+    pub fn meta(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.0.get()?;
+        Ok(conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub(crate) fn record_filtered_tx(&self, reason: &str) -> Result<()> {
+        let conn = self.0.get()?;
+        conn.execute(
+            "INSERT INTO filtered_txs (reason, count, updated_at) VALUES (?1, 1, ?2)
+            ON CONFLICT (reason) DO UPDATE SET count = count + 1, updated_at = excluded.updated_at",
+            params![reason, now!()],
+        )?;
+        Ok(())
+    }
+
+    /// How many txs each filter rejected, by reason
+    pub fn filtered_tx_counts(&self) -> Result<Vec<(String, u64)>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare("SELECT reason, count FROM filtered_txs ORDER BY reason")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(counts)
+    }
+
+    /// Store the min, max and average age of the coins spent by `tx`
+    pub(crate) fn record_coin_ages(&self, tx: &Transaction, coin_ages: Vec<u64>) -> Result<()> {
+        if coin_ages.is_empty() {
+            return Ok(());
+        }
+        let conn = self.0.get()?;
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let avg = coin_ages.iter().sum::<u64>() as f64 / coin_ages.len() as f64;
+        conn.execute(
+            "UPDATE transactions SET coin_age_min = ?1, coin_age_max = ?2, coin_age_avg = ?3 WHERE inputs_hash = ?4",
+            params![
+                coin_ages.iter().min(),
+                coin_ages.iter().max(),
+                avg,
+                inputs_hash
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Latest replacements recorded since `since` (unix secs) that raised the absolute fee
+    /// but lowered the fee rate. Only the latest rbf of each tx is kept in `rbf`.
+    #[allow(dead_code)]
+    pub fn rate_down_abs_up_bumps(&self, since: u64) -> Result<Vec<RateDownAbsUpBump>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT replaces, created_at, fee_delta, fee_rate_delta_kwu, vsize_delta FROM rbf
+            WHERE rate_down_abs_up AND created_at >= ?1 ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get(1)?,
+                RbfDelta {
+                    fee_delta: row.get(2)?,
+                    fee_rate_delta_kwu: row.get(3)?,
+                    vsize_delta: row.get(4)?,
+                },
+            ))
+        })?;
+        let mut bumps = vec![];
+        for row in rows {
+            let (txid, created_at, delta) = row?;
+            bumps.push(RateDownAbsUpBump {
+                txid: Txid::from_str(&txid)?,
+                created_at,
+                delta,
+            });
+        }
+        Ok(bumps)
+    }
+
+    /// Histogram of how long after the original tx was first seen the replacements recorded
+    /// since `since` (unix secs) were made, over [`BUMP_DELAY_BUCKETS`]
+    #[allow(dead_code)]
+    pub fn bump_delay_distribution(&self, since: u64) -> Result<Vec<BumpDelayBucket>> {
+        let conn = self.0.get()?;
+        let mut buckets = vec![];
+        for (i, min_secs) in BUMP_DELAY_BUCKETS.iter().enumerate() {
+            let max_secs = BUMP_DELAY_BUCKETS.get(i + 1).copied();
+            let rbf_count: u64 = conn.query_row(
+                "SELECT COUNT(*) FROM rbf WHERE created_at >= ?1
+                AND bump_delay_secs >= ?2 AND (?3 IS NULL OR bump_delay_secs < ?3)",
+                params![since, min_secs, max_secs],
+                |row| row.get(0),
+            )?;
+            buckets.push(BumpDelayBucket {
+                min_secs: *min_secs,
+                max_secs,
+                rbf_count,
+            });
+        }
+        Ok(buckets)
+    }
+
+    /// Histogram of how long after the node we first saw the sampled txs found since `since`
+    /// (unix secs), over [`TIME_DRIFT_BUCKETS`]
+    #[allow(dead_code)]
+    pub fn time_drift_distribution(&self, since: u64) -> Result<Vec<TimeDriftBucket>> {
+        let conn = self.0.get()?;
+        let mut buckets = vec![];
+        for (i, min_secs) in TIME_DRIFT_BUCKETS.iter().enumerate() {
+            let max_secs = TIME_DRIFT_BUCKETS.get(i + 1).copied();
+            let tx_count: u64 = conn.query_row(
+                "SELECT COUNT(*) FROM time_drift WHERE found_at >= ?1
+                AND (?2 = 0 OR drift_secs >= ?2) AND (?3 IS NULL OR drift_secs < ?3)",
+                params![since, min_secs, max_secs],
+                |row| row.get(0),
+            )?;
+            buckets.push(TimeDriftBucket {
+                min_secs: *min_secs,
+                max_secs,
+                tx_count,
+            });
+        }
+        Ok(buckets)
+    }
+
+    /// Histogram of the average coin age of txs found since `since` (unix secs), over [`COIN_AGE_BUCKETS`].
+    /// Txs without coin ages are not counted.
+    #[allow(dead_code)]
+    pub fn coin_age_distribution(&self, since: u64) -> Result<Vec<CoinAgeBucket>> {
+        let conn = self.0.get()?;
+        let mut buckets = vec![];
+        for (i, min_blocks) in COIN_AGE_BUCKETS.iter().enumerate() {
+            let max_blocks = COIN_AGE_BUCKETS.get(i + 1).copied();
+            let tx_count: u64 = conn.query_row(
+                "SELECT COUNT(*) FROM transactions WHERE found_at >= ?1
+                AND coin_age_avg >= ?2 AND (?3 IS NULL OR coin_age_avg < ?3)",
+                params![since, min_blocks, max_blocks],
+                |row| row.get(0),
+            )?;
+            buckets.push(CoinAgeBucket {
+                min_blocks: *min_blocks,
+                max_blocks,
+                tx_count,
+            });
+        }
+        Ok(buckets)
+    }
+
+    /// Total value burned by txs found since `since` (unix secs), excluding zero value data outputs
+    #[allow(dead_code)]
+    pub fn total_burned(&self, since: u64) -> Result<Amount> {
+        let conn = self.0.get()?;
+        let total: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(burned_amount), 0) FROM transactions WHERE found_at >= ?1",
+            params![since],
+            |row| row.get(0),
+        )?;
+        Ok(Amount::from_db_i64(total))
+    }
+
+    /// Total, mean and median absolute fee of the non-coinbase txs found since `since` (unix secs),
+    /// e.g. for miner revenue analysis
+    #[allow(dead_code)]
+    pub fn fee_stats(&self, since: u64) -> Result<FeeStats> {
+        let conn = self.0.get()?;
+        // Negative fees are audit violations, not revenue
+        let filter =
+            "FROM transactions WHERE found_at >= ?1 AND version != 0 AND absolute_fee >= 0";
+        let (tx_count, total): (u64, i64) = conn.query_row(
+            &format!("SELECT COUNT(*), COALESCE(SUM(absolute_fee), 0) {}", filter),
+            params![since],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if tx_count == 0 {
+            return Ok(FeeStats::default());
+        }
+        let median: i64 = conn.query_row(
+            &format!(
+                "SELECT absolute_fee {} ORDER BY absolute_fee LIMIT 1 OFFSET ?2",
+                filter
+            ),
+            params![since, (tx_count - 1) / 2],
+            |row| row.get(0),
+        )?;
+        Ok(FeeStats {
+            tx_count,
+            total: Amount::from_db_i64(total),
+            mean: Amount::from_db_i64(total / tx_count as i64),
+            median: Amount::from_db_i64(median),
+        })
+    }
+
+    /// mempool.space style fee estimates from the tracked unconfirmed txs
+    pub fn recommended_fees(&self) -> Result<RecommendedFees> {
+        Ok(RecommendedFees::from_fee_rates(
+            &self.unconfirmed_fee_rates()?,
+        ))
+    }
+
+    /// Where a tx of `vsize` vbytes paying `fee_rate` would land among the tracked unconfirmed
+    /// txs, and how many of the last `recent_blocks` blocks it would have cleared
+    pub fn simulate_fee_rate(
+        &self,
+        fee_rate: FeeRate,
+        vsize: u64,
+        recent_blocks: usize,
+    ) -> Result<FeeSimulation> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT fee_rate_kwu, (weight + 3) / 4
+            FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+        )?;
+        let mempool = stmt
+            .query_map([], |row| {
+                Ok((FeeRate::from_db_i64(row.get(0)?), row.get(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut stmt =
+            conn.prepare("SELECT clearing_fee_rate_kwu FROM blocks ORDER BY height DESC LIMIT ?1")?;
+        let recent_clearing_fee_rates = stmt
+            .query_map(params![recent_blocks], |row| {
+                Ok(FeeRate::from_db_i64(row.get(0)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FeeSimulation::simulate(
+            fee_rate,
+            vsize,
+            &mempool,
+            &recent_clearing_fee_rates,
+        ))
+    }
+
+    /// Mempool state snapshots recorded in `[since, until]` (unix secs), oldest first.
+    /// Long ranges are downsampled to at most `max_points` evenly strided snapshots.
+    #[allow(dead_code)]
+    pub fn mempool_state_series(
+        &self,
+        since: u64,
+        until: u64,
+        max_points: usize,
+    ) -> Result<Vec<MempoolStatePoint>> {
+        // Open ended bounds like u64::MAX don't fit in a sqlite integer
+        let since = i64::try_from(since).unwrap_or(i64::MAX);
+        let until = i64::try_from(until).unwrap_or(i64::MAX);
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT created_at, size, tx_count, block_height, block_hash, version FROM mempool_state
+            WHERE created_at >= ?1 AND created_at <= ?2 ORDER BY created_at, id",
+        )?;
+        let rows = stmt
+            .query_map(params![since, until], MempoolStatePoint::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let stride = rows.len().div_ceil(max_points.max(1)).max(1);
+        rows.into_iter()
+            .step_by(stride)
+            .map(MempoolStatePoint::decode)
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn latest_mempool_state(&self) -> Result<Option<MempoolStatePoint>> {
+        let conn = self.0.get()?;
+        let row = conn
+            .query_row(
+                "SELECT created_at, size, tx_count, block_height, block_hash, version FROM mempool_state
+                ORDER BY created_at DESC, id DESC LIMIT 1",
+                [],
+                MempoolStatePoint::from_row,
+            )
+            .optional()?;
+        row.map(MempoolStatePoint::decode).transpose()
+    }
+
+    /// Txs found since `since` (unix secs) with an absolute timelock in effect or a relative timelock
+    #[allow(dead_code)]
+    pub fn query_timelocked(&self, since: u64) -> Result<Vec<TimelockedTx>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, found_at, lock_time, uses_csv, is_final, absolute_fee FROM transactions
+            WHERE found_at >= ?1 AND (is_final = FALSE OR uses_csv = TRUE)
+            ORDER BY found_at",
+        )?;
+        let rows = stmt.query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, bool>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+
+        let mut txs = vec![];
+        for row in rows {
+            let (txid, found_at, lock_time, uses_csv, is_final, absolute_fee) = row?;
+            txs.push(TimelockedTx {
+                txid: Txid::from_str(&txid)?,
+                found_at,
+                lock_time,
+                uses_csv,
+                is_final,
+                absolute_fee: Amount::from_db_i64(absolute_fee),
+            });
+        }
+        Ok(txs)
+    }
+
+    /// Every version of the tx keyed by `inputs_hash`, oldest first and the current version last
+    #[allow(dead_code)]
+    pub fn tx_versions(&self, inputs_hash: &str) -> Result<Vec<TxVersion>> {
+        let conn = self.0.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT tx_id, tx_data, found_at, absolute_fee, fee_rate_kwu, archived_at FROM (
+                SELECT id, tx_id, tx_data, found_at, absolute_fee, fee_rate_kwu, archived_at
+                FROM tx_history WHERE inputs_hash = ?1
+                UNION ALL
+                SELECT NULL, tx_id, tx_data, found_at, absolute_fee,
+                fee_rate_kwu, NULL
+                FROM transactions WHERE inputs_hash = ?1
+            ) ORDER BY archived_at IS NULL, id",
+        )?;
+        let rows = stmt.query_map(params![inputs_hash], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<u64>>(5)?,
+            ))
+        })?;
+
+        let mut versions = vec![];
+        for row in rows {
+            let (txid, tx_data, found_at, absolute_fee, fee_rate_kwu, archived_at) = row?;
+            let bytes = hex::decode(tx_data)?;
+            versions.push(TxVersion {
+                txid: Txid::from_str(&txid)?,
+                tx: Transaction::consensus_decode(&mut bytes.as_slice())?,
+                found_at,
+                absolute_fee: Amount::from_db_i64(absolute_fee),
+                fee_rate: FeeRate::from_db_i64(fee_rate_kwu),
+                archived_at,
+            });
+        }
+        Ok(versions)
+    }
+
+    /// Direct package and replacement relations of `txid`: the tracked parents it spends,
+    /// the tracked children spending it, and every version replaced with or by it
+    pub fn lineage(&self, txid: &Txid) -> Result<Vec<LineageEdge>> {
+        let conn = self.0.get()?;
+        let txid_hex = txid.to_string();
+        let query_txids = |query: &str| -> Result<Vec<Txid>> {
+            let mut stmt = conn.prepare(query)?;
+            let txids = stmt
+                .query_map(params![txid_hex], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            txids.iter().map(|txid| Ok(Txid::from_str(txid)?)).collect()
+        };
+        let edge = |from, to, kind| LineageEdge { from, to, kind };
+
+        let mut edges = vec![];
+        for parent in query_txids(
+            "SELECT DISTINCT prev_txid FROM tx_inputs WHERE txid = ?1 AND (
+                prev_txid IN (SELECT tx_id FROM transactions) OR prev_txid IN (SELECT tx_id FROM tx_history)
+            )",
+        )? {
+            edges.push(edge(parent, *txid, LineageKind::Spends));
+        }
+        for child in query_txids("SELECT DISTINCT txid FROM tx_inputs WHERE prev_txid = ?1")? {
+            edges.push(edge(*txid, child, LineageKind::Spends));
+        }
+        for replacement in query_txids(
+            "SELECT replaced_by FROM transactions WHERE tx_id = ?1 AND replaced_by IS NOT NULL",
+        )? {
+            edges.push(edge(*txid, replacement, LineageKind::ReplacedBy));
+        }
+        for replaced in query_txids("SELECT tx_id FROM transactions WHERE replaced_by = ?1")? {
+            edges.push(edge(replaced, *txid, LineageKind::ReplacedBy));
+        }
+        // Versions under the same inputs, in the order they replaced each other
+        let mut stmt = conn.prepare(
+            "SELECT inputs_hash FROM transactions WHERE tx_id = ?1
+            UNION SELECT inputs_hash FROM tx_history WHERE tx_id = ?1",
+        )?;
+        let inputs_hashes = stmt
+            .query_map(params![txid_hex], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for inputs_hash in inputs_hashes {
+            let versions = self.tx_versions(&inputs_hash)?;
+            for pair in versions.windows(2) {
+                if pair[0].txid != pair[1].txid {
+                    edges.push(edge(pair[0].txid, pair[1].txid, LineageKind::ReplacedBy));
+                }
+            }
+        }
+        edges.sort();
+        edges.dedup();
+        Ok(edges)
+    }
+
+    /// Get the txids of all tracked txs spending an outpoint
+    #[allow(dead_code)]
+    pub fn spenders_of(&self, outpoint: &OutPoint) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let mut stmt =
+            conn.prepare("SELECT txid FROM tx_inputs WHERE prev_txid = ?1 AND prev_vout = ?2")?;
+        let txids = stmt.query_map(params![outpoint.txid.to_string(), outpoint.vout], |row| {
+            let txid_str: String = row.get(0)?;
+            Ok(Txid::from_str(&txid_str).expect("Valid txid"))
+        })?;
+        Ok(txids.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Get the txids of tracked txs that spend an outpoint also spent by this tx
+    #[allow(dead_code)]
+    pub fn conflicting_txids(&self, txid: &Txid) -> Result<Vec<Txid>> {
+        let conn = self.0.get()?;
+        let txid_hex = txid.to_string();
+        let mut stmt = conn.prepare(
+            "SELECT conflicting_txid FROM conflicts WHERE tx_id = ?1
+            UNION SELECT tx_id FROM conflicts WHERE conflicting_txid = ?1",
+        )?;
+        let txids = stmt.query_map(params![txid_hex], |row| {
+            let txid_str: String = row.get(0)?;
+            Ok(Txid::from_str(&txid_str).expect("Valid txid"))
+        })?;
+        Ok(txids.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Get the txid of the conflicting tx that replaced this one, if any
+    #[allow(dead_code)]
+    pub fn replaced_by(&self, txid: &Txid) -> Result<Option<Txid>> {
+        let conn = self.0.get()?;
+        let txid_hex = txid.to_string();
+        let replaced_by: Option<String> = conn
+            .query_row(
+                "SELECT replaced_by FROM transactions WHERE tx_id = ?1",
+                params![txid_hex],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(replaced_by.map(|txid| Txid::from_str(&txid).expect("Valid txid")))
+    }
+
+    /// Get the fee rate for a transaction at full sat/kwu precision
+    #[allow(dead_code)]
+    pub fn get_fee_rate_kwu(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        let conn = self.0.get()?;
+        let txid_hex = txid.to_string();
+        let fee_rate_kwu: Option<i64> = conn
+            .query_row(
+                "SELECT fee_rate_kwu FROM transactions WHERE tx_id = ?1",
+                params![txid_hex],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        Ok(fee_rate_kwu.map(FeeRate::from_db_i64))
+    }
+
+    /// Get the fee rate for a transaction, in sat/vB
+    #[allow(dead_code)]
+    pub fn get_fee_rate(&self, txid: &Txid) -> Result<Option<f64>> {
+        let conn = self.0.get()?;
+        let txid_hex = txid.to_string();
+        let mut stmt = conn.prepare("SELECT fee_rate FROM transactions WHERE tx_id = ?1")?;
+        let fee_rate: Option<f64> = stmt
+            .query_row(params![txid_hex], |row| row.get(0))
+            .optional()?;
+        Ok(fee_rate)
+    }
+}
+
+/// Append a txid to the first-seen log, unless it was already seen. `conn` has to hold the write
+/// lock, see `insert_mempool_tx`, so concurrent workers can't chain off the same record.
+fn record_first_seen(conn: &rusqlite::Connection, tx_id: &str, found_at: u64) -> Result<()> {
+    let seen: i32 = conn.query_row(
+        "SELECT COUNT(*) FROM first_seen_log WHERE tx_id = ?1",
+        params![tx_id],
+        |row| row.get(0),
+    )?;
+    if seen > 0 {
+        return Ok(());
+    }
+    let prev_hash = conn
+        .query_row(
+            "SELECT hash FROM first_seen_log ORDER BY seq DESC LIMIT 1",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .unwrap_or(FIRST_SEEN_GENESIS_HASH.to_string());
+    let hash = first_seen_hash(&prev_hash, tx_id, found_at);
+    conn.execute(
+        "INSERT INTO first_seen_log (tx_id, found_at, prev_hash, hash) VALUES (?1, ?2, ?3, ?4)",
+        params![tx_id, found_at, prev_hash, hash],
+    )?;
+    Ok(())
+}
+
+/// Copy the row keyed by `inputs_hash` into tx_history before it is overwritten by `new_txid`
+pub(crate) fn archive_tx_version(
+    conn: &rusqlite::Connection,
+    inputs_hash: &str,
+    new_txid: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO tx_history
+        (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate_kwu, archived_at)
+        SELECT inputs_hash, tx_id, tx_data, found_at, absolute_fee,
+        fee_rate_kwu, ?1
+        FROM transactions WHERE inputs_hash = ?2 AND tx_id != ?3",
+        params![now!(), inputs_hash, new_txid],
+    )?;
+    Ok(())
+}
+
+/// Overwrite the tx stored under `tx`'s inputs_hash with `tx`, archiving the replaced version
+/// and keeping its txid in prior_txids (see `txid_history`). found_at and the links to parents and children belong to
+/// the tx across versions and are kept, everything describing the version itself is replaced.
+pub(crate) fn replace_tx_version(
+    conn: &rusqlite::Connection,
+    tx: &Transaction,
+    absolute_fee: Amount,
+    fee_rate: FeeRate,
+) -> Result<()> {
+    let inputs_hash = get_inputs_hash(tx.clone().input)?;
+    let tx_id = tx.compute_txid().to_string();
+    let mut tx_bytes = vec![];
+    tx.consensus_encode(&mut tx_bytes)?;
+    archive_tx_version(conn, &inputs_hash, &tx_id)?;
+    conn.execute(
+        "UPDATE tx_inputs SET txid = ?1 WHERE txid = (SELECT tx_id FROM transactions WHERE inputs_hash = ?2)",
+        params![tx_id, inputs_hash],
+    )?;
+    conn.execute(
+        "UPDATE transactions SET prior_txids = json_insert(COALESCE(prior_txids, '[]'), '$[#]', tx_id),
+        tx_id = ?1, tx_data = ?3, absolute_fee = ?4, fee_rate = ?5, fee_rate_kwu = ?6, size = ?7,
+        weight = ?8, signals_rbf = ?9, lock_time = ?10, uses_csv = ?11, is_final = ?12,
+        burned_amount = ?13, data_outputs = ?14, tx_version = ?15, wtxid = ?16
+        WHERE inputs_hash = ?2 AND tx_id != ?1",
+        params![
+            tx_id,
+            inputs_hash,
+            hex::encode(&tx_bytes),
+            absolute_fee.to_db_i64(),
+            fee_rate_sat_per_vb(fee_rate),
+            fee_rate.to_db_i64(),
+            tx_bytes.len() as i64,
+            tx.weight().to_wu() as i64,
+            signals_rbf(tx),
+            tx.lock_time.to_consensus_u32(),
+            uses_relative_timelock(tx),
+            is_final(tx),
+            burned_amount(tx).to_db_i64(),
+            data_output_count(tx),
+            tx.version.0,
+            tx.compute_wtxid().to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Values of the `outpoints` paid by the tracked `parents`, given as txid and hex tx_data
+pub(crate) fn prevout_values(
+    parents: impl IntoIterator<Item = (Txid, String)>,
+    outpoints: &[OutPoint],
+) -> Result<HashMap<OutPoint, Amount>> {
+    let mut values = HashMap::new();
+    for (txid, tx_data) in parents {
+        let bytes = hex::decode(tx_data)?;
+        let parent = Transaction::consensus_decode(&mut bytes.as_slice())?;
+        for outpoint in outpoints.iter().filter(|outpoint| outpoint.txid == txid) {
+            if let Some(txout) = parent.output.get(outpoint.vout as usize) {
+                values.insert(*outpoint, txout.value);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Index the outpoints spent by a tx in the tx_inputs table
+pub(crate) fn insert_tx_inputs(conn: &rusqlite::Connection, tx: &Transaction) -> Result<()> {
+    if tx.is_coinbase() {
+        return Ok(());
+    }
+    let tx_id = tx.compute_txid().to_string();
+    let mut stmt = conn.prepare(
+        "INSERT OR IGNORE INTO tx_inputs (txid, prev_txid, prev_vout) VALUES (?1, ?2, ?3)",
+    )?;
+    for input in tx.input.iter() {
+        stmt.execute(params![
+            tx_id,
+            input.previous_output.txid.to_string(),
+            input.previous_output.vout
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::TxFilter,
+        test_utils::{outpoint, spending_tx, test_db},
+        utils::{compute_fee_rate, is_batch_payout, sat_per_vb_fee_rate},
+    };
+    use bitcoin::{absolute::LockTime, Amount, ScriptBuf, Sequence, Transaction, TxOut, Witness};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_db_options() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let db_path = tempdir.path().join("test.db");
+        let options = DbOptions {
+            page_size: Some(16_384),
+            cache_mb: Some(64),
+        };
+        let db = Database::with_options(db_path.to_str().unwrap(), options)?;
+        let conn = db.0.get()?;
+        let page_size: u32 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        assert_eq!(page_size, 16_384);
+        let cache_size: i64 = conn.query_row("PRAGMA cache_size", [], |row| row.get(0))?;
+        assert_eq!(cache_size, -64 * 1024);
+
+        let options = DbOptions {
+            page_size: Some(1_000),
+            cache_mb: None,
+        };
+        let other_path = tempdir.path().join("other.db");
+        assert!(Database::with_options(other_path.to_str().unwrap(), options).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_rate_stored_as_decimal() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+
+        let absolute_fee = Amount::from_sat(150);
+        let fee_rate = compute_fee_rate(&tx, absolute_fee)?;
+        db.insert_mempool_tx(tx.clone(), None, absolute_fee, fee_rate)?;
+
+        let txid = tx.compute_txid();
+        let stored_fee_rate = db.get_fee_rate(&txid)?.expect("fee_rate should exist");
+
+        assert_ne!(
+            stored_fee_rate,
+            stored_fee_rate.floor(),
+            "fee_rate should be a decimal value"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_txs_are_recorded() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        // Both txs spend the same outpoint but tx_b has an extra input,
+        // so their inputs_hash differ and the rbf path won't link them
+        let tx_a = spending_tx(&[outpoint("01", 0)], 90_000);
+        let tx_b = spending_tx(&[outpoint("01", 0), outpoint("02", 1)], 180_000);
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        db.insert_mempool_tx(tx_a.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(tx_b.clone(), None, Amount::from_sat(2_000), fee_rate)?;
+
+        let txid_a = tx_a.compute_txid();
+        let txid_b = tx_b.compute_txid();
+        assert_eq!(db.conflicting_txids(&txid_b)?, vec![txid_a]);
+        assert_eq!(db.conflicting_txids(&txid_a)?, vec![txid_b]);
+
+        // An unrelated tx should not conflict with anything
+        let tx_c = spending_tx(&[outpoint("03", 0)], 50_000);
+        db.insert_mempool_tx(tx_c.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        assert!(db.conflicting_txids(&tx_c.compute_txid())?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spenders_of_outpoint() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let tx_a = spending_tx(&[outpoint("01", 0), outpoint("01", 1)], 90_000);
+        let tx_b = spending_tx(&[outpoint("01", 1), outpoint("02", 0)], 90_000);
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        db.insert_mempool_tx(tx_a.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(tx_b.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+
+        let txid_a = tx_a.compute_txid();
+        let txid_b = tx_b.compute_txid();
+        assert_eq!(db.spenders_of(&outpoint("01", 0))?, vec![txid_a]);
+        let mut spenders = db.spenders_of(&outpoint("01", 1))?;
+        spenders.sort();
+        let mut expected = vec![txid_a, txid_b];
+        expected.sort();
+        assert_eq!(spenders, expected);
+        assert!(db.spenders_of(&outpoint("03", 0))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_fee_tx_is_persisted() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let tx = spending_tx(&[outpoint("01", 0)], 100_000);
+        let fee_rate = compute_fee_rate(&tx, Amount::ZERO)?;
+        assert_eq!(fee_rate, FeeRate::ZERO);
+        db.insert_mempool_tx(tx.clone(), None, Amount::ZERO, fee_rate)?;
+
+        let txid = tx.compute_txid();
+        assert!(db.get_tx_by_txid(&txid)?.is_some());
+        assert_eq!(db.get_fee_rate(&txid)?, Some(0.0));
+
+        // Fees below 1 sat/vB are kept at sat/kwu precision rather than rounded to zero
+        let fee_rate = compute_fee_rate(&tx, Amount::from_sat(10))?;
+        assert!(fee_rate > FeeRate::ZERO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replaced_txs_are_not_pruned() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        // Replacement keyed by the same inputs_hash
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        db.insert_mempool_tx(original, None, Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&bumped, 11_000, fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(11_000), fee_rate)?;
+
+        // Replacement by a conflicting tx with a different inputs_hash
+        let conflicted = spending_tx(&[outpoint("02", 0)], 90_000);
+        let replacement = spending_tx(&[outpoint("02", 0), outpoint("03", 0)], 150_000);
+        db.insert_mempool_tx(conflicted.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(replacement.clone(), None, Amount::from_sat(5_000), fee_rate)?;
+        assert_eq!(
+            db.replaced_by(&conflicted.compute_txid())?,
+            Some(replacement.compute_txid())
+        );
+
+        // The node's mempool only holds the replacements, nothing should be flagged as pruned
+        let live_mempool = vec![bumped.compute_txid(), replacement.compute_txid()];
+        assert!(db.txids_of_txs_not_in_list(live_mempool)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let db_path = tempdir.path().join("test.db");
+        let db = Database::new(db_path.to_str().unwrap())?;
+        db.run_migrations()?;
+
+        let tx = spending_tx(&[outpoint("01", 0)], 100_000);
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        db.insert_mempool_tx(tx.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+
+        let snapshot_path = tempdir.path().join("snapshot.db");
+        db.snapshot(&snapshot_path)?;
+        // Never clobber an existing file
+        assert!(db.snapshot(&snapshot_path).is_err());
+
+        let restored_path = tempdir.path().join("restored.db");
+        Database::restore(restored_path.to_str().unwrap(), &snapshot_path)?;
+        let restored = Database::new(restored_path.to_str().unwrap())?;
+        assert!(restored.get_tx_by_txid(&tx.compute_txid())?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_rate_round_trip() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        for (i, sat_per_kwu) in [0u64, 1, 253, 250_000, 1_234_567].into_iter().enumerate() {
+            let tx = spending_tx(&[outpoint("01", i as u32)], 100_000);
+            let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kwu);
+            db.insert_mempool_tx(tx.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+
+            let txid = tx.compute_txid();
+            assert_eq!(db.get_fee_rate_kwu(&txid)?, Some(fee_rate));
+            assert_eq!(db.get_fee_rate(&txid)?, Some(sat_per_kwu as f64 / 250.0));
+        }
+
+        // Computed fee rates keep sub sat/vB precision
+        let tx = spending_tx(&[outpoint("02", 0)], 100_000);
+        let fee_rate = compute_fee_rate(&tx, Amount::from_sat(150))?;
+        assert_eq!(fee_rate.to_sat_per_kwu(), 150 * 1000 / tx.weight().to_wu());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwritten_versions_are_archived() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let replacement = spending_tx(&[outpoint("01", 0)], 80_000);
+        let inputs_hash = get_inputs_hash(original.input.clone())?;
+        assert_eq!(inputs_hash, get_inputs_hash(replacement.input.clone())?);
+
+        let original_fee_rate = compute_fee_rate(&original, Amount::from_sat(1_000))?;
+        let replacement_fee_rate = compute_fee_rate(&replacement, Amount::from_sat(11_000))?;
+        db.insert_mempool_tx(
+            original.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            original_fee_rate,
+        )?;
+        // Re-inserting the same tx is not a new version
+        db.insert_mempool_tx(
+            original.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            original_fee_rate,
+        )?;
+        db.insert_mempool_tx(
+            replacement.clone(),
+            Some(200),
+            Amount::from_sat(11_000),
+            replacement_fee_rate,
+        )?;
+
+        // An RBF overwriting the row in place archives the version it replaces too
+        let bumped = spending_tx(&[outpoint("01", 0)], 70_000);
+        let bumped_fee_rate = compute_fee_rate(&bumped, Amount::from_sat(21_000))?;
+        db.record_rbf(&bumped, 21_000, bumped_fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(21_000), bumped_fee_rate)?;
+
+        let versions = db.tx_versions(&inputs_hash)?;
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[0].txid, original.compute_txid());
+        assert_eq!(versions[0].tx, original);
+        assert_eq!(versions[0].found_at, 100);
+        assert_eq!(versions[0].absolute_fee, Amount::from_sat(1_000));
+        assert_eq!(versions[0].fee_rate, original_fee_rate);
+        assert!(versions[0].archived_at.is_some());
+        assert_eq!(versions[1].txid, replacement.compute_txid());
+        assert_eq!(versions[1].tx, replacement);
+        assert_eq!(versions[1].absolute_fee, Amount::from_sat(11_000));
+        assert!(versions[1].archived_at.is_some());
+        assert_eq!(versions[2].txid, bumped.compute_txid());
+        assert_eq!(versions[2].tx, bumped);
+        assert_eq!(versions[2].absolute_fee, Amount::from_sat(21_000));
+        assert!(versions[2].archived_at.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_tx_is_recorded() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let tx = spending_tx(&[outpoint("01", 0)], 100_000);
+        db.record_oversized_tx(&tx)?;
+        // Seeing it again keeps the first sighting
+        db.record_oversized_tx(&tx)?;
+
+        let conn = db.0.get()?;
+        let (size, weight): (u64, u64) = conn.query_row(
+            "SELECT size, weight FROM oversized_txs WHERE tx_id = ?1",
+            params![tx.compute_txid().to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(size, tx.total_size() as u64);
+        assert_eq!(weight, tx.weight().to_wu());
+        assert!(db.get_tx_by_txid(&tx.compute_txid())?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_state_without_block_fields() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        db.record_mempool_state(
+            1_000,
+            10,
+            Some(100),
+            Some(BlockHash::from_str(&"00".repeat(32))?),
+        )?;
+        db.record_mempool_state(2_000, 20, Some(101), None)?;
+        db.record_mempool_state(3_000, 30, None, None)?;
+
+        let conn = db.0.get()?;
+        let rows = conn
+            .prepare("SELECT size, block_height, block_hash FROM mempool_state ORDER BY size")?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, Option<u64>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].1, Some(100));
+        assert!(rows[0].2.is_some());
+        assert_eq!(rows[1], (2_000, Some(101), None));
+        assert_eq!(rows[2], (3_000, None, None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restart_keeps_found_at_of_live_txs() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let live = spending_tx(&[outpoint("01", 0)], 90_000);
+        let gone = spending_tx(&[outpoint("02", 0)], 90_000);
+        db.insert_mempool_tx(live.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(gone.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+
+        // Restart with only `live` still in the node's mempool
+        let kept = db.remove_stale_txs(vec![live.compute_txid()])?;
+        assert_eq!(kept, vec![live.compute_txid()]);
+        assert!(db.get_tx_by_txid(&gone.compute_txid())?.is_none());
+
+        let conn = db.0.get()?;
+        let found_at: u64 = conn.query_row(
+            "SELECT found_at FROM transactions WHERE tx_id = ?1",
+            params![live.compute_txid().to_string()],
+            |row| row.get(0),
+        )?;
+        assert_eq!(found_at, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signals_rbf_is_stored() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let opt_in = spending_tx(&[outpoint("01", 0)], 90_000);
+        let mut final_seq = spending_tx(&[outpoint("02", 0)], 90_000);
+        final_seq.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        assert!(signals_rbf(&opt_in));
+        assert!(!signals_rbf(&final_seq));
+
+        db.insert_mempool_tx(opt_in.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(final_seq.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+
+        let conn = db.0.get()?;
+        for (tx, expected) in [(opt_in, true), (final_seq, false)] {
+            let stored: bool = conn.query_row(
+                "SELECT signals_rbf FROM transactions WHERE tx_id = ?1",
+                params![tx.compute_txid().to_string()],
+                |row| row.get(0),
+            )?;
+            assert_eq!(stored, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_timelocked() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let plain = spending_tx(&[outpoint("01", 0)], 90_000);
+        let mut absolute = spending_tx(&[outpoint("02", 0)], 90_000);
+        absolute.lock_time = LockTime::from_height(800_000)?;
+        let mut relative = spending_tx(&[outpoint("03", 0)], 90_000);
+        relative.input[0].sequence = Sequence::from_height(144);
+        for tx in [&plain, &absolute, &relative] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+
+        let timelocked = db.query_timelocked(100)?;
+        assert_eq!(timelocked.len(), 2);
+        let absolute_row = timelocked
+            .iter()
+            .find(|tx| tx.txid == absolute.compute_txid())
+            .expect("absolute timelock is returned");
+        assert_eq!(absolute_row.lock_time, 800_000);
+        assert!(!absolute_row.is_final);
+        assert!(!absolute_row.uses_csv);
+        let relative_row = timelocked
+            .iter()
+            .find(|tx| tx.txid == relative.compute_txid())
+            .expect("relative timelock is returned");
+        assert!(relative_row.uses_csv);
+        assert!(relative_row.is_final);
+        assert_eq!(relative_row.absolute_fee, Amount::from_sat(1_000));
+
+        assert!(db.query_timelocked(101)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_renamed_to_mempool_state() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let db_path = tempdir.path().join("test.db");
+        let db = Database::new(db_path.to_str().unwrap())?;
+        // A snapshot recorded before any migration ran, without a tx_id
+        db.0.get()?.execute(
+            "INSERT INTO mempool (created_at, size, tx_count, block_height, block_hash, version)
+            VALUES (100, 1000, 10, 800000, '00', 1)",
+            [],
+        )?;
+        db.run_migrations()?;
+        db.record_mempool_state(2_000, 20, None, None)?;
+
+        // Reopening doesn't bring the old table back
+        let db = Database::new(db_path.to_str().unwrap())?;
+        db.run_migrations()?;
+        let conn = db.0.get()?;
+        let tables = conn
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'mempool%'",
+            )?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(tables, vec!["mempool_state".to_string()]);
+        let rows = conn
+            .prepare("SELECT id, size FROM mempool_state ORDER BY id")?
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, u64>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows, vec![(1, 1_000), (2, 2_000)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_snapshots_are_kept() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        // Recorded in the same second, so only the id tells them apart
+        db.record_mempool_state(1_000, 10, Some(100), None)?;
+        db.record_mempool_state(2_000, 20, Some(100), None)?;
+
+        let conn = db.0.get()?;
+        let rows = conn
+            .prepare("SELECT id, size FROM mempool_state ORDER BY id")?
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, u64>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].0 < rows[1].0);
+        assert_eq!(rows[0].1, 1_000);
+        assert_eq!(rows[1].1, 2_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_state_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        assert!(db.latest_mempool_state()?.is_none());
+
+        let block_hash = BlockHash::from_str(&"11".repeat(32))?;
+        for i in 0..10 {
+            db.record_mempool_state(
+                1_000 * (i + 1),
+                10 * (i + 1),
+                Some(100 + i),
+                Some(block_hash),
+            )?;
+        }
+
+        let series = db.mempool_state_series(0, u64::MAX, 100)?;
+        assert_eq!(series.len(), 10);
+        assert_eq!(series[0].size, 1_000);
+        assert_eq!(series[0].block_hash, Some(block_hash));
+        assert_eq!(series[9].tx_count, 100);
+
+        let downsampled = db.mempool_state_series(0, u64::MAX, 4)?;
+        assert!(downsampled.len() <= 4);
+        assert_eq!(downsampled[0], series[0]);
+
+        assert!(db.mempool_state_series(0, 1, 100)?.is_empty());
+
+        let latest = db.latest_mempool_state()?.expect("a snapshot was recorded");
+        assert_eq!(latest.size, 10_000);
+        assert_eq!(latest.block_height, Some(109));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_seen_chain() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        for (i, byte) in ["01", "02", "03"].iter().enumerate() {
+            let tx = spending_tx(&[outpoint(byte, 0)], 90_000);
+            db.insert_mempool_tx(
+                tx.clone(),
+                Some(100 + i as u64),
+                Amount::from_sat(1_000),
+                fee_rate,
+            )?;
+            // Seeing the same tx again doesn't extend the chain
+            db.insert_mempool_tx(tx, Some(200), Amount::from_sat(1_000), fee_rate)?;
+        }
+        assert_eq!(db.verify_first_seen_chain()?, None);
+
+        let conn = db.0.get()?;
+        let count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM first_seen_log", [], |row| row.get(0))?;
+        assert_eq!(count, 3);
+
+        // Backdating a record breaks its own hash
+        conn.execute("UPDATE first_seen_log SET found_at = 50 WHERE seq = 2", [])?;
+        assert_eq!(db.verify_first_seen_chain()?, Some(2));
+        conn.execute("UPDATE first_seen_log SET found_at = 101 WHERE seq = 2", [])?;
+        assert_eq!(db.verify_first_seen_chain()?, None);
+
+        // Removing a record breaks the link of the next one
+        conn.execute("DELETE FROM first_seen_log WHERE seq = 2", [])?;
+        assert_eq!(db.verify_first_seen_chain()?, Some(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_total_burned() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let op_return = ScriptBuf::from_hex("6a0400000000")?;
+        let mut burn = spending_tx(&[outpoint("01", 0)], 90_000);
+        burn.output.push(TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: op_return.clone(),
+        });
+        let mut data = spending_tx(&[outpoint("02", 0)], 90_000);
+        data.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: op_return,
+        });
+        assert_eq!(burned_amount(&burn), Amount::from_sat(5_000));
+        assert_eq!(data_output_count(&burn), 0);
+        assert_eq!(burned_amount(&data), Amount::ZERO);
+        assert_eq!(data_output_count(&data), 1);
+
+        db.insert_mempool_tx(burn, Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(data, Some(100), Amount::from_sat(1_000), fee_rate)?;
+        assert_eq!(db.total_burned(100)?, Amount::from_sat(5_000));
+        assert_eq!(db.total_burned(101)?, Amount::ZERO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_txid_history() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        let bumped_again = spending_tx(&[outpoint("01", 0)], 70_000);
+        let inputs_hash = get_inputs_hash(original.input.clone())?;
+        db.insert_mempool_tx(original.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        for tx in [&bumped, &bumped_again] {
+            db.record_rbf(tx, 11_000, fee_rate)?;
+            db.update_txid_by_inputs_hash(tx, Amount::from_sat(11_000), fee_rate)?;
+        }
+        // Seeing the latest replacement again doesn't repeat it
+        db.update_txid_by_inputs_hash(&bumped_again, Amount::from_sat(11_000), fee_rate)?;
+
+        assert_eq!(
+            db.txid_history(&inputs_hash)?,
+            vec![
+                original.compute_txid(),
+                bumped.compute_txid(),
+                bumped_again.compute_txid()
+            ]
+        );
+        assert!(db.txid_history("unknown")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_down_abs_up_bumps() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let sat_vb = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        db.insert_mempool_tx(original.clone(), None, Amount::from_sat(1_000), sat_vb(10))?;
+        // A plain bump, more fee at a higher rate
+        let bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        db.record_rbf(&bumped, 2_000, sat_vb(20))?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(2_000), sat_vb(20))?;
+        assert!(db.rate_down_abs_up_bumps(0)?.is_empty());
+
+        // Compared with the previous bump, not the original
+        let grown = spending_tx(&[outpoint("01", 0)], 70_000);
+        db.record_rbf(&grown, 3_000, sat_vb(15))?;
+        let bumps = db.rate_down_abs_up_bumps(0)?;
+        assert_eq!(bumps.len(), 1);
+        assert_eq!(bumps[0].txid, grown.compute_txid());
+        assert_eq!(bumps[0].delta.fee_delta, 1_000);
+        assert_eq!(bumps[0].delta.fee_rate_delta_kwu, -1_250);
+        assert_eq!(bumps[0].delta.vsize_delta, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_cpfp_links_child_before_parent() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let parent = spending_tx(&[outpoint("01", 0)], 90_000);
+        let child = spending_tx(
+            &[OutPoint {
+                txid: parent.compute_txid(),
+                vout: 0,
+            }],
+            80_000,
+        );
+        // A mempool scan may store the child first, so the parent isn't tracked yet to link
+        db.insert_mempool_tx(child.clone(), None, Amount::from_sat(10_000), fee_rate)?;
+        db.insert_mempool_tx(parent.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        assert_eq!(db.parent_txid(&child.compute_txid())?, None);
+        assert_eq!(db.child_txid(&parent.compute_txid())?, None);
+
+        assert_eq!(db.reconcile_cpfp_links()?, 2);
+        assert_eq!(
+            db.parent_txid(&child.compute_txid())?,
+            Some(parent.compute_txid())
+        );
+        assert_eq!(
+            db.child_txid(&parent.compute_txid())?,
+            Some(child.compute_txid())
+        );
+        // Already linked txs are left alone
+        assert_eq!(db.reconcile_cpfp_links()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracked_txids_in_block() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let mined = spending_tx(&[outpoint("01", 0)], 90_000);
+        let tracked = spending_tx(&[outpoint("02", 0)], 90_000);
+        // Replaced by a version we never saw before it was mined
+        let mined_replacement = spending_tx(&[outpoint("02", 0)], 80_000);
+        let unconfirmed = spending_tx(&[outpoint("03", 0)], 90_000);
+        for tx in [&mined, &tracked, &unconfirmed] {
+            db.insert_mempool_tx(tx.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        }
+        for tx in [&mined, &mined_replacement] {
+            db.record_mined_tx(tx)?;
+            db.record_mined_height(tx, 100)?;
+        }
+
+        let mut expected = vec![mined.compute_txid(), mined_replacement.compute_txid()];
+        expected.sort();
+        assert_eq!(db.tracked_txids_in_block(100)?, expected);
+        assert!(db.tracked_txids_in_block(101)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebroadcast_candidates() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let sat_vb = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+
+        let stuck = spending_tx(&[outpoint("01", 0)], 90_000);
+        let dropped = spending_tx(&[outpoint("02", 0)], 90_000);
+        let cheap = spending_tx(&[outpoint("03", 0)], 90_000);
+        let recent = spending_tx(&[outpoint("04", 0)], 90_000);
+        let mined = spending_tx(&[outpoint("05", 0)], 90_000);
+        db.insert_mempool_tx(dropped.clone(), Some(50), Amount::ZERO, sat_vb(10))?;
+        db.insert_mempool_tx(stuck.clone(), Some(100), Amount::ZERO, sat_vb(5))?;
+        db.insert_mempool_tx(cheap, Some(100), Amount::ZERO, sat_vb(1))?;
+        db.insert_mempool_tx(recent, Some(200), Amount::ZERO, sat_vb(5))?;
+        db.insert_mempool_tx(mined.clone(), Some(100), Amount::ZERO, sat_vb(5))?;
+        db.record_pruned_txs(vec![dropped.compute_txid()])?;
+        db.record_mined_tx(&mined)?;
+        // Rebroadcast as the replacement, the replaced version would be rejected
+        let replaced = spending_tx(&[outpoint("06", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("06", 0)], 80_000);
+        db.insert_mempool_tx(replaced, Some(150), Amount::ZERO, sat_vb(5))?;
+        db.record_rbf(&bumped, 10_000, sat_vb(6))?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(10_000), sat_vb(6))?;
+
+        assert_eq!(
+            db.rebroadcast_candidates(150, sat_vb(2))?,
+            vec![dropped, stuck, bumped]
+        );
+        assert!(db.rebroadcast_candidates(49, sat_vb(2))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deferred_fee_txs() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let deferred = |tx: &Transaction, found_at, retries| DeferredTx {
+            tx: tx.clone(),
+            found_at,
+            arrival_seq: found_at,
+            retries,
+        };
+        let first = spending_tx(&[outpoint("01", 0)], 90_000);
+        let second = spending_tx(&[outpoint("02", 0)], 90_000);
+        let stored_meanwhile = spending_tx(&[outpoint("03", 0)], 90_000);
+        db.defer_fee_tx(&deferred(&second, 200, 0))?;
+        db.defer_fee_tx(&deferred(&first, 100, 0))?;
+        db.defer_fee_tx(&deferred(&stored_meanwhile, 50, 0))?;
+        // A failed retry replaces the row with a higher count
+        db.defer_fee_tx(&deferred(&first, 100, 1))?;
+        db.insert_mempool_tx(
+            stored_meanwhile,
+            None,
+            Amount::from_sat(1_000),
+            FeeRate::from_sat_per_vb(1).expect("valid fee rate"),
+        )?;
+
+        assert_eq!(db.take_deferred_fee_txs(1)?, vec![deferred(&first, 100, 1)]);
+        assert_eq!(
+            db.take_deferred_fee_txs(10)?,
+            vec![deferred(&second, 200, 0)]
+        );
+        assert!(db.take_deferred_fee_txs(10)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_rate_percentiles() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let fee_rates = (1..=10)
+            .map(|kwu| FeeRate::from_sat_per_kwu(kwu * 250))
+            .collect::<Vec<_>>();
+        db.record_fee_rate_percentiles(&fee_rates)?;
+        // No unconfirmed txs records nothing
+        db.record_fee_rate_percentiles(&[])?;
+
+        let series = db
+            .fee_rate_percentile_series(0)?
+            .into_iter()
+            .map(|(_, p, fee_rate)| (p, fee_rate.to_sat_per_kwu()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![(10, 250), (25, 750), (50, 1_250), (75, 2_000), (90, 2_250)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_outcome_by_feerate_bucket() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let sat_vb = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+
+        let evicted = spending_tx(&[outpoint("01", 0)], 90_000);
+        let mined = spending_tx(&[outpoint("02", 0)], 90_000);
+        let waiting = spending_tx(&[outpoint("03", 0)], 90_000);
+        let replaced = spending_tx(&[outpoint("04", 0)], 90_000);
+        db.insert_mempool_tx(evicted.clone(), Some(100), Amount::ZERO, sat_vb(1))?;
+        db.insert_mempool_tx(waiting, Some(100), Amount::ZERO, sat_vb(1))?;
+        db.insert_mempool_tx(mined.clone(), Some(100), Amount::ZERO, sat_vb(12))?;
+        db.insert_mempool_tx(replaced.clone(), Some(100), Amount::ZERO, sat_vb(15))?;
+        db.record_pruned_txs(vec![evicted.compute_txid()])?;
+        db.record_mined_tx(&mined)?;
+        let replacement = spending_tx(&[outpoint("04", 0), outpoint("05", 0)], 150_000);
+        db.insert_mempool_tx(replacement, Some(100), Amount::ZERO, sat_vb(120))?;
+
+        let outcome = |min, max, mined, pruned, replaced, unconfirmed| FeeRateOutcome {
+            min_fee_rate: sat_vb(min),
+            max_fee_rate: Some(sat_vb(max)),
+            mined,
+            pruned,
+            replaced,
+            unconfirmed,
+        };
+        assert_eq!(
+            db.outcome_by_feerate_bucket(0)?,
+            vec![
+                outcome(1, 2, 0, 1, 0, 1),
+                outcome(10, 20, 1, 0, 1, 0),
+                FeeRateOutcome {
+                    max_fee_rate: None,
+                    ..outcome(100, 100, 0, 0, 0, 1)
+                },
+            ]
+        );
+        assert!(db.outcome_by_feerate_bucket(101)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fractional_sat_per_vb() {
+        // Shown to 1/250 sat/vB rather than rounded up to whole sat/vB
+        for (sat_per_kwu, sat_vb) in [(0, 0.0), (1, 0.004), (250, 1.0), (275, 1.1), (628, 2.512)] {
+            let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kwu);
+            assert_eq!(fee_rate_sat_per_vb(fee_rate), sat_vb);
+            assert_eq!(sat_per_vb_fee_rate(sat_vb), fee_rate);
+        }
+        // Minimums between two sat/kwu round up
+        assert_eq!(sat_per_vb_fee_rate(1.001), FeeRate::from_sat_per_kwu(251));
+        assert_eq!(sat_per_vb_fee_rate(-1.0), FeeRate::ZERO);
+    }
+
+    #[test]
+    fn test_inputs_hash_ignores_witness() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let mut segwit = spending_tx(&[outpoint("01", 0), outpoint("02", 1)], 90_000);
+        segwit.input[0].witness = Witness::from_slice(&[vec![0u8; 72], vec![0u8; 33]]);
+        let mut pruned = segwit.clone();
+        for input in pruned.input.iter_mut() {
+            input.witness.clear();
+        }
+        assert_eq!(
+            get_inputs_hash(segwit.input.clone())?,
+            get_inputs_hash(pruned.input.clone())?
+        );
+        // Byte for byte the inputs' consensus encoding, so stored hashes stay valid
+        let mut encoded = vec![];
+        for input in &segwit.input {
+            input.consensus_encode(&mut encoded)?;
+        }
+        assert_eq!(
+            get_inputs_hash(segwit.input.clone())?,
+            hex::encode(bitcoin_hashes::Sha256::hash(&encoded).as_byte_array())
+        );
+
+        // A mined copy without witnesses still matches the mempool row
+        db.insert_mempool_tx(segwit.clone(), None, Amount::ZERO, fee_rate)?;
+        db.record_mined_tx(&pruned)?;
+        let mined_at: Option<u64> = db.0.get()?.query_row(
+            "SELECT mined_at FROM transactions WHERE tx_id = ?1",
+            [segwit.compute_txid().to_string()],
+            |row| row.get(0),
+        )?;
+        assert!(mined_at.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_found_at_height() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let at_tip = spending_tx(&[outpoint("01", 0)], 90_000);
+        let before_snapshot = spending_tx(&[outpoint("02", 0)], 90_000);
+        db.insert_mempool_tx(before_snapshot.clone(), None, Amount::ZERO, fee_rate)?;
+        // Clones share the tip, as every worker gets a clone of the same db
+        db.clone().set_tip_height(Some(850_000));
+        db.insert_mempool_tx(at_tip.clone(), None, Amount::ZERO, fee_rate)?;
+
+        let conn = db.0.get()?;
+        let found_at_height = |tx: &Transaction| -> Result<Option<u64>> {
+            Ok(conn.query_row(
+                "SELECT found_at_height FROM transactions WHERE tx_id = ?1",
+                [tx.compute_txid().to_string()],
+                |row| row.get(0),
+            )?)
+        };
+        assert_eq!(found_at_height(&at_tip)?, Some(850_000));
+        assert_eq!(found_at_height(&before_snapshot)?, None);
+
+        // Takes precedence over the snapshots
+        db.record_mempool_state(1_000, 2, Some(850_002), None)?;
+        db.record_pruned_txs(vec![at_tip.compute_txid()])?;
+        assert_eq!(
+            db.eviction_survival_distribution(0)?,
+            vec![EvictionSurvival {
+                blocks_survived: 2,
+                tx_count: 1,
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction_survival_distribution() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        db.record_mempool_state(1_000, 1, Some(100), None)?;
+        let evicted = spending_tx(&[outpoint("01", 0)], 90_000);
+        let other_evicted = spending_tx(&[outpoint("02", 0)], 90_000);
+        let mined = spending_tx(&[outpoint("03", 0)], 90_000);
+        for tx in [&evicted, &other_evicted, &mined] {
+            db.insert_mempool_tx(tx.clone(), None, Amount::ZERO, fee_rate)?;
+        }
+        // Found after the snapshot at height 100, which predates the next one
+        let conn = db.0.get()?;
+        conn.execute("UPDATE transactions SET found_at = found_at - 5", [])?;
+        conn.execute("UPDATE mempool_state SET created_at = created_at - 10", [])?;
+        db.record_mempool_state(1_000, 1, Some(103), None)?;
+
+        db.record_mined_tx(&mined)?;
+        db.record_pruned_txs(vec![
+            evicted.compute_txid(),
+            other_evicted.compute_txid(),
+            mined.compute_txid(),
+        ])?;
+        let blocks_survived: Option<u64> = conn.query_row(
+            "SELECT blocks_survived FROM transactions WHERE tx_id = ?1",
+            [evicted.compute_txid().to_string()],
+            |row| row.get(0),
+        )?;
+        assert_eq!(blocks_survived, Some(3));
+        assert_eq!(
+            db.eviction_survival_distribution(0)?,
+            vec![EvictionSurvival {
+                blocks_survived: 3,
+                tx_count: 2,
+            }]
+        );
+        assert!(db.eviction_survival_distribution(now!() + 1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lifecycle_outcomes_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let evicted = spending_tx(&[outpoint("01", 0)], 90_000);
+        let mined = spending_tx(&[outpoint("02", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("03", 0)], 90_000);
+        let conflicted = spending_tx(&[outpoint("04", 0)], 90_000);
+        for tx in [&evicted, &mined, &bumped, &conflicted] {
+            db.insert_mempool_tx(tx.clone(), None, Amount::ZERO, fee_rate)?;
+        }
+        db.record_pruned_txs(vec![evicted.compute_txid()])?;
+        db.record_mined_tx(&mined)?;
+        // Same inputs, archived to tx_history
+        let bump = spending_tx(&[outpoint("03", 0)], 80_000);
+        db.insert_mempool_tx(bump, None, Amount::ZERO, fee_rate)?;
+        // Overlapping inputs, recorded as a conflict
+        let replacement = spending_tx(&[outpoint("04", 0), outpoint("05", 0)], 150_000);
+        db.insert_mempool_tx(replacement, None, Amount::ZERO, fee_rate)?;
+
+        let bucket_secs = 3_600;
+        let now = now!();
+        let series = db.lifecycle_outcomes_series(bucket_secs, 0)?;
+        // The bucket boundary may fall between the inserts
+        assert!(series.iter().all(
+            |outcomes| outcomes.bucket_start % bucket_secs == 0 && outcomes.bucket_start <= now
+        ));
+        let total =
+            |outcome: fn(&LifecycleOutcomes) -> u64| series.iter().map(outcome).sum::<u64>();
+        assert_eq!(total(|outcomes| outcomes.mined), 1);
+        assert_eq!(total(|outcomes| outcomes.pruned), 1);
+        assert_eq!(total(|outcomes| outcomes.replaced), 2);
+
+        assert!(db
+            .lifecycle_outcomes_series(bucket_secs, now + 1)?
+            .is_empty());
+        assert!(db.lifecycle_outcomes_series(0, 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks_to_clear_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let sat_vb = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+
+        let cheap = spending_tx(&[outpoint("01", 0)], 90_000);
+        let paying = spending_tx(&[outpoint("02", 0), outpoint("03", 0)], 90_000);
+        db.insert_mempool_tx(cheap.clone(), None, Amount::ZERO, sat_vb(2))?;
+        db.insert_mempool_tx(paying.clone(), None, Amount::ZERO, sat_vb(10))?;
+
+        // Without blocks every tracked tx counts
+        db.record_mempool_state(1_000, 2, Some(100), None)?;
+        db.record_block_tx(100, sat_vb(5))?;
+        db.record_mempool_state(1_000, 2, Some(100), None)?;
+
+        let blocks = |txs: &[&Transaction]| {
+            txs.iter().map(|tx| tx.vsize() as u64).sum::<u64>() as f64 / BLOCK_MAX_VSIZE as f64
+        };
+        let series = db.blocks_to_clear_series(0)?;
+        assert_eq!(
+            series.iter().map(|(_, blocks)| *blocks).collect::<Vec<_>>(),
+            vec![blocks(&[&cheap, &paying]), blocks(&[&paying])]
+        );
+        assert!(db.blocks_to_clear_series(now!() + 1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tx_labels() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let withdrawal = spending_tx(&[outpoint("01", 0)], 90_000).compute_txid();
+        let other = spending_tx(&[outpoint("02", 0)], 90_000).compute_txid();
+        assert!(db.add_label(&withdrawal, "my withdrawal")?);
+        assert!(db.add_label(&withdrawal, "suspected double-spend")?);
+        assert!(!db.add_label(&withdrawal, "my withdrawal")?);
+        assert!(db.add_label(&other, "suspected double-spend")?);
+        assert!(db.add_label(&other, " ").is_err());
+
+        assert_eq!(db.txs_with_label("my withdrawal")?, vec![withdrawal]);
+        let mut double_spends = db.txs_with_label("suspected double-spend")?;
+        double_spends.sort();
+        let mut expected = vec![withdrawal, other];
+        expected.sort();
+        assert_eq!(double_spends, expected);
+        assert!(db.txs_with_label("My withdrawal")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_address_reuse_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let reusing = spending_tx(&[outpoint("01", 0)], 90_000);
+        let fresh = spending_tx(&[outpoint("02", 0)], 90_000);
+        let mined = spending_tx(&[outpoint("03", 0)], 90_000);
+        for tx in [&reusing, &fresh, &mined] {
+            db.insert_mempool_tx(tx.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        }
+        db.record_reused_outputs(&reusing.compute_txid(), 2)?;
+        db.record_reused_outputs(&mined.compute_txid(), 1)?;
+        db.record_mined_tx(&mined)?;
+        db.record_address_reuse()?;
+
+        let series = db.address_reuse_series(0)?;
+        assert_eq!(series.len(), 1);
+        assert_eq!((series[0].1, series[0].2), (1, 2));
+        assert!(db.address_reuse_series(now!() + 1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_tx_by_wtxid() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let mut segwit = spending_tx(&[outpoint("01", 0)], 90_000);
+        segwit.input[0].witness = Witness::from_slice(&[vec![0u8; 72], vec![0u8; 33]]);
+        assert_ne!(
+            segwit.compute_wtxid().to_raw_hash(),
+            segwit.compute_txid().to_raw_hash()
+        );
+        db.insert_mempool_tx(segwit.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        assert_eq!(
+            db.get_tx_by_wtxid(&segwit.compute_wtxid())?,
+            Some(segwit.clone())
+        );
+
+        // A replacement overwriting the row in place moves the wtxid along
+        let mut replacement = spending_tx(&[outpoint("01", 0)], 80_000);
+        replacement.input[0].witness = segwit.input[0].witness.clone();
+        db.update_txid_by_inputs_hash(&replacement, Amount::from_sat(11_000), fee_rate)?;
+        assert!(db.get_tx_by_wtxid(&segwit.compute_wtxid())?.is_none());
+        assert_eq!(
+            db.get_tx_by_wtxid(&replacement.compute_wtxid())?,
+            Some(replacement)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rbf_overwrites_tx() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        db.insert_mempool_tx(
+            original.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            FeeRate::from_sat_per_vb(1).expect("valid fee rate"),
+        )?;
+        let mut bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        bumped.lock_time = LockTime::from_height(800_000)?;
+        bumped.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return([0u8; 4]),
+        });
+        let bumped_fee = Amount::from_sat(11_000);
+        let bumped_fee_rate = compute_fee_rate(&bumped, bumped_fee)?;
+        db.record_rbf(&bumped, bumped_fee.to_sat(), bumped_fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, bumped_fee, bumped_fee_rate)?;
+
+        // Every column describing the version is the replacement's, found_at is the original's
+        assert_eq!(
+            db.get_tx_by_txid(&bumped.compute_txid())?,
+            Some(bumped.clone())
+        );
+        let conn = db.0.get()?;
+        let row: (u64, i64, i64, u64, u64, u32, u32, String) = conn.query_row(
+            "SELECT found_at, absolute_fee, fee_rate_kwu, size, weight, lock_time, data_outputs, wtxid
+            FROM transactions",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            },
+        )?;
+        assert_eq!(
+            row,
+            (
+                100,
+                bumped_fee.to_db_i64(),
+                bumped_fee_rate.to_db_i64(),
+                bumped.total_size() as u64,
+                bumped.weight().to_wu(),
+                800_000,
+                1,
+                bumped.compute_wtxid().to_string(),
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_rbf_links() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        // Consistent replacement, left alone
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        db.insert_mempool_tx(original, Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&bumped, 11_000, fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(11_000), fee_rate)?;
+
+        // The txid update of a replacement never landed
+        let partial = spending_tx(&[outpoint("02", 0)], 90_000);
+        let partial_bump = spending_tx(&[outpoint("02", 0)], 80_000);
+        db.insert_mempool_tx(
+            partial.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            fee_rate,
+        )?;
+        db.record_rbf(&partial_bump, 11_000, fee_rate)?;
+
+        // Same, but recorded before rbf rows kept the replacement
+        let legacy = spending_tx(&[outpoint("06", 0)], 90_000);
+        db.insert_mempool_tx(legacy.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&spending_tx(&[outpoint("06", 0)], 80_000), 11_000, fee_rate)?;
+        db.0.get()?.execute(
+            "UPDATE rbf SET tx_data = NULL WHERE replaces != ?1",
+            params![partial_bump.compute_txid().to_string()],
+        )?;
+
+        // The replaced tx was deleted, and so was the conflicting tx replacing another
+        let gone = spending_tx(&[outpoint("03", 0)], 90_000);
+        db.insert_mempool_tx(gone.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&spending_tx(&[outpoint("03", 0)], 80_000), 11_000, fee_rate)?;
+        let conflicted = spending_tx(&[outpoint("04", 0)], 90_000);
+        let replacement = spending_tx(&[outpoint("04", 0), outpoint("05", 0)], 150_000);
+        db.insert_mempool_tx(
+            conflicted.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            fee_rate,
+        )?;
+        db.insert_mempool_tx(
+            replacement.clone(),
+            Some(100),
+            Amount::from_sat(1_000),
+            fee_rate,
+        )?;
+        {
+            let conn = db.0.get()?;
+            for txid in [gone.compute_txid(), replacement.compute_txid()] {
+                conn.execute(
+                    "DELETE FROM transactions WHERE tx_id = ?1",
+                    params![txid.to_string()],
+                )?;
+            }
+        }
+
+        let report = db.repair_rbf_links()?;
+        assert_eq!(
+            report,
+            RbfRepairReport {
+                dangling_rbfs: 1,
+                stale_rbfs: 0,
+                heads_fixed: 1,
+                unrepairable_rbfs: 1,
+                dangling_replaced_by: 1,
+            }
+        );
+        // The head is the replacement's row, not the replaced one under a new txid
+        assert_eq!(
+            db.get_tx_by_txid(&partial_bump.compute_txid())?,
+            Some(partial_bump.clone())
+        );
+        assert_eq!(
+            db.get_tx_by_wtxid(&partial_bump.compute_wtxid())?,
+            Some(partial_bump.clone())
+        );
+        assert_eq!(
+            db.tx_versions(&get_inputs_hash(partial.input.clone())?)?
+                .iter()
+                .map(|version| (version.txid, version.absolute_fee))
+                .collect::<Vec<_>>(),
+            vec![
+                (partial.compute_txid(), Amount::from_sat(1_000)),
+                (partial_bump.compute_txid(), Amount::from_sat(11_000)),
+            ]
+        );
+        assert_eq!(db.audit()?.orphaned_rbfs, 0);
+        assert_eq!(db.txids_in_mempool()?.len(), 4);
+        // Nothing left to repair
+        assert_eq!(db.repair_rbf_links()?, RbfRepairReport::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stuck_txs() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let stuck = spending_tx(&[outpoint("01", 0)], 90_000);
+        let pruned = spending_tx(&[outpoint("02", 0)], 90_000);
+        let recent = spending_tx(&[outpoint("03", 0)], 90_000);
+        db.insert_mempool_tx(stuck.clone(), Some(100), Amount::ZERO, fee_rate)?;
+        db.insert_mempool_tx(pruned.clone(), Some(100), Amount::ZERO, fee_rate)?;
+        db.insert_mempool_tx(recent, None, Amount::ZERO, fee_rate)?;
+        db.record_pruned_txs(vec![pruned.compute_txid()])?;
+
+        assert_eq!(db.stuck_txs(3_600)?, vec![stuck.compute_txid()]);
+        db.record_pruned_txs(db.stuck_txs(3_600)?)?;
+        assert!(db.stuck_txs(3_600)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracked_prevouts() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let parent = spending_tx(&[outpoint("01", 0)], 90_000);
+        let replaced = spending_tx(&[outpoint("02", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("02", 0)], 80_000);
+        db.insert_mempool_tx(parent.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.insert_mempool_tx(replaced, None, Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&bumped, 11_000, fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(11_000), fee_rate)?;
+
+        let parent_outpoint = OutPoint {
+            txid: parent.compute_txid(),
+            vout: 0,
+        };
+        let bumped_outpoint = OutPoint {
+            txid: bumped.compute_txid(),
+            vout: 0,
+        };
+        let prevouts =
+            db.tracked_prevouts(&[parent_outpoint, bumped_outpoint, outpoint("03", 0)])?;
+        // The bumped tx's row holds its own outputs, not the replaced version's
+        assert_eq!(
+            prevouts,
+            HashMap::from([
+                (parent_outpoint, Amount::from_sat(90_000)),
+                (bumped_outpoint, Amount::from_sat(80_000)),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let bumped = spending_tx(&[outpoint("01", 0)], 80_000);
+        db.insert_mempool_tx(original.clone(), None, Amount::from_sat(1_000), fee_rate)?;
+        db.record_rbf(&bumped, 11_000, fee_rate)?;
+        db.update_txid_by_inputs_hash(&bumped, Amount::from_sat(11_000), fee_rate)?;
+        assert_eq!(db.audit()?, AuditReport::default());
+
+        let conn = db.0.get()?;
+        conn.execute(
+            "UPDATE transactions SET mined_at = 1, pruned_at = 1, absolute_fee = -1",
+            [],
+        )?;
+        let report = db.audit()?;
+        assert_eq!(report.mined_and_pruned, 1);
+        assert_eq!(report.negative_fees, 1);
+        conn.execute("DELETE FROM transactions", [])?;
+        let report = db.audit()?;
+        assert_eq!(report.orphaned_rbfs, 1);
+        assert_eq!(report.violations(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_coin_age_distribution() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let fresh = spending_tx(&[outpoint("01", 0)], 90_000);
+        let old = spending_tx(&[outpoint("02", 0), outpoint("03", 0)], 90_000);
+        let untracked = spending_tx(&[outpoint("04", 0)], 90_000);
+        for tx in [&fresh, &old, &untracked] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+        db.record_coin_ages(&fresh, vec![0])?;
+        // Averages to 1_500 blocks, a month old
+        db.record_coin_ages(&old, vec![1_000, 2_000])?;
+
+        let distribution = db.coin_age_distribution(0)?;
+        assert_eq!(distribution.len(), COIN_AGE_BUCKETS.len());
+        assert_eq!(distribution[0].tx_count, 1);
+        assert_eq!(distribution[4].min_blocks, 1_008);
+        assert_eq!(distribution[4].tx_count, 1);
+        assert_eq!(distribution[6].max_blocks, None);
+        assert_eq!(
+            distribution
+                .iter()
+                .map(|bucket| bucket.tx_count)
+                .sum::<u64>(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bump_delay_distribution() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        db.insert_mempool_tx(
+            original,
+            Some(now!() - 600),
+            Amount::from_sat(1_000),
+            fee_rate,
+        )?;
+        let bumped = spending_tx(&[outpoint("01", 0)], 89_000);
+        db.record_rbf(&bumped, 2_000, fee_rate)?;
+
+        let distribution = db.bump_delay_distribution(0)?;
+        assert_eq!(distribution.len(), BUMP_DELAY_BUCKETS.len());
+        // Made about 10 minutes after the original
+        assert_eq!(distribution[2].min_secs, 300);
+        assert_eq!(distribution[2].rbf_count, 1);
+        assert_eq!(
+            distribution
+                .iter()
+                .map(|bucket| bucket.rbf_count)
+                .sum::<u64>(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrival_order() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        assert_eq!(db.max_arrival_seq()?, None);
+
+        // Found in the same second, in the opposite order of their outpoints
+        let first = spending_tx(&[outpoint("02", 0)], 90_000);
+        let second = spending_tx(&[outpoint("01", 0)], 90_000);
+        let untracked = spending_tx(&[outpoint("03", 0)], 90_000);
+        for (seq, tx) in [(7, &first), (8, &second)] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+            db.record_arrival_seq(&tx.compute_txid(), seq)?;
+        }
+        db.insert_mempool_tx(
+            untracked.clone(),
+            Some(200),
+            Amount::from_sat(1_000),
+            fee_rate,
+        )?;
+
+        assert_eq!(db.max_arrival_seq()?, Some(8));
+        assert_eq!(
+            db.arrival_order(100, 100)?,
+            vec![first.compute_txid(), second.compute_txid()]
+        );
+        assert_eq!(
+            db.arrived_before(&first.compute_txid(), &second.compute_txid())?,
+            Some(true)
+        );
+        assert_eq!(
+            db.arrived_before(&first.compute_txid(), &untracked.compute_txid())?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_clearing_feerate() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        for sat_vb in [12, 3, 7] {
+            let fee_rate = FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+            db.record_block_tx(800_000, fee_rate)?;
+        }
+        db.record_block_tx(
+            800_001,
+            FeeRate::from_sat_per_vb(20).expect("valid fee rate"),
+        )?;
+
+        assert_eq!(
+            db.block_clearing_feerate(800_000)?,
+            FeeRate::from_sat_per_vb(3)
+        );
+        assert_eq!(
+            db.block_clearing_feerate(800_001)?,
+            FeeRate::from_sat_per_vb(20)
+        );
+        assert_eq!(db.block_clearing_feerate(799_999)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_row_versions() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        assert_eq!(db.row_versions()?, RowVersions::default());
+
+        let tx = spending_tx(&[outpoint("01", 0)], 90_000);
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        db.insert_mempool_tx(tx, Some(100), Amount::from_sat(1_000), fee_rate)?;
+        db.record_mempool_state(1_000, 1, None, None)?;
+        assert!(db.row_versions()?.newer_than_supported().is_empty());
+
+        // As written by a future binary
+        let conn = db.0.get()?;
+        conn.execute(
+            "UPDATE mempool_state SET version = ?1",
+            [MEMPOOL_STATE_VERSION + 1],
+        )?;
+        let versions = db.row_versions()?;
+        assert_eq!(versions.transactions, Some(MEMPOOL_TRANSACTION_VERSION));
+        assert_eq!(
+            versions.newer_than_supported(),
+            vec![("mempool_state", MEMPOOL_STATE_VERSION + 1)]
+        );
+        // Still readable
+        assert!(db.latest_mempool_state()?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_txid_prefix() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let mut txids = vec![];
+        for byte in ["01", "02", "03"] {
+            let tx = spending_tx(&[outpoint(byte, 0)], 90_000);
+            txids.push(tx.compute_txid());
+            db.insert_mempool_tx(tx, Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+        let txid = txids[0].to_string();
+
+        assert_eq!(db.search_txid_prefix(&txid[..6], 10)?, vec![txids[0]]);
+        assert_eq!(
+            db.search_txid_prefix(&txid[..6].to_uppercase(), 10)?,
+            vec![txids[0]]
+        );
+        assert_eq!(db.search_txid_prefix(&txid, 10)?, vec![txids[0]]);
+        let mut found = 0;
+        for first in "0123456789abcdef".chars() {
+            found += db.search_txid_prefix(&first.to_string(), 10)?.len();
+        }
+        assert_eq!(found, 3);
+        assert_eq!(db.search_txid_prefix(&txid[..1], 1)?.len(), 1);
+        assert!(db.search_txid_prefix("xyz", 10).is_err());
+        assert!(db.search_txid_prefix("", 10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unconfirmed_found_at() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let tx = spending_tx(&[outpoint("01", 0)], 90_000);
+        db.insert_mempool_tx(tx.clone(), Some(200), Amount::from_sat(1_000), fee_rate)?;
+        assert_eq!(db.unconfirmed_found_at()?, vec![(tx.compute_txid(), 200)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_rate_kwu_backfill() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let tx = spending_tx(&[outpoint("01", 0)], 90_000);
+        let fee = Amount::from_sat(1_234);
+        let fee_rate = compute_fee_rate(&tx, fee)?;
+        db.insert_mempool_tx(tx.clone(), Some(100), fee, fee_rate)?;
+
+        let conn = db.0.get()?;
+        assert!(conn
+            .execute("UPDATE transactions SET fee_rate_kwu = NULL", [])
+            .is_err());
+
+        // As stored before fee_rate_kwu existed
+        conn.execute_batch(
+            "DROP TRIGGER transactions_fee_rate_kwu_insert;
+            DROP TRIGGER transactions_fee_rate_kwu_update;
+            UPDATE transactions SET fee_rate_kwu = NULL;
+            DELETE FROM migrations WHERE id IN ('backfill_fee_rate_kwu', 'require_fee_rate_kwu');",
+        )?;
+        db.run_migrations()?;
+
+        let fee_rate_kwu: u64 = conn.query_row(
+            "SELECT fee_rate_kwu FROM transactions WHERE tx_id = ?1",
+            params![tx.compute_txid().to_string()],
+            |row| row.get(0),
+        )?;
+        assert_eq!(fee_rate_kwu, fee_rate.to_sat_per_kwu());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_payouts() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let mut batch = spending_tx(&[outpoint("01", 0)], 10_000);
+        batch.output = (0..12u8)
+            .map(|i| TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::from_bytes(vec![0x51, i]),
+            })
+            .collect();
+        let mut dusty = batch.clone();
+        dusty.input[0].previous_output = outpoint("02", 0);
+        for output in dusty.output.iter_mut().skip(2) {
+            output.value = Amount::from_sat(500);
+        }
+        let single = spending_tx(&[outpoint("03", 0)], 90_000);
+        assert!(is_batch_payout(&batch, 10));
+        assert!(!is_batch_payout(&dusty, 10));
+        assert!(!is_batch_payout(&single, 10));
+
+        let mut tagged = vec![];
+        for tx in [&batch, &dusty, &single] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+            tagged.push((tx.compute_txid(), is_batch_payout(tx, 10)));
+        }
+        db.record_batch_payouts(tagged)?;
+        assert_eq!(db.batch_payouts(0)?, vec![batch.compute_txid()]);
+        assert!(db.batch_payouts(101)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nonstandard_txs() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        for (byte, reason) in [
+            ("01", "bare_multisig"),
+            ("02", "scriptsig_not_push_only"),
+            ("03", "version"),
+        ] {
+            let tx = spending_tx(&[outpoint(byte, 0)], 10_000);
+            db.record_nonstandard_tx(&tx.compute_txid(), reason)?;
+        }
+        // A tx is recorded once
+        let version = spending_tx(&[outpoint("03", 0)], 10_000);
+        db.record_nonstandard_tx(&version.compute_txid(), "version")?;
+
+        assert_eq!(
+            db.nonstandard_reasons(0)?,
+            vec![
+                ("bare_multisig".to_string(), 1),
+                ("scriptsig_not_push_only".to_string(), 1),
+                ("version".to_string(), 1),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truc_topology() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        let store = |tx: &Transaction| -> Result<()> {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+            db.record_truc_tx(tx)
+        };
+
+        let mut parent = spending_tx(&[outpoint("01", 0)], 90_000);
+        parent.version = bitcoin::transaction::Version(3);
+        store(&parent)?;
+        let mut child = spending_tx(
+            &[OutPoint {
+                txid: parent.compute_txid(),
+                vout: 0,
+            }],
+            80_000,
+        );
+        child.version = bitcoin::transaction::Version(3);
+        store(&child)?;
+
+        let truc_txs = db.truc_txs(0)?;
+        assert_eq!(truc_txs.len(), 2);
+        assert!(truc_txs.iter().all(|truc_tx| truc_tx.respects_topology));
+        assert!(truc_txs
+            .iter()
+            .all(|truc_tx| truc_tx.absolute_fee == Some(Amount::from_sat(1_000))));
+        let parent_row = |db: &Database| -> Result<TrucTx> {
+            Ok(db
+                .truc_txs(0)?
+                .into_iter()
+                .find(|truc_tx| truc_tx.txid == parent.compute_txid())
+                .expect("parent is tracked"))
+        };
+        assert_eq!(parent_row(&db)?.unconfirmed_children, 1);
+        assert_eq!(
+            truc_txs
+                .iter()
+                .find(|truc_tx| truc_tx.txid == child.compute_txid())
+                .map(|truc_tx| truc_tx.unconfirmed_parents),
+            Some(1)
+        );
+
+        // A second child spending another output breaks the 1-parent-1-child package
+        let sibling = spending_tx(
+            &[OutPoint {
+                txid: parent.compute_txid(),
+                vout: 1,
+            }],
+            70_000,
+        );
+        store(&sibling)?;
+        let parent_truc = parent_row(&db)?;
+        assert_eq!(parent_truc.unconfirmed_children, 2);
+        assert!(!parent_truc.respects_topology);
+        // The non TRUC sibling itself isn't tracked
+        assert_eq!(db.truc_txs(0)?.len(), 2);
+
+        // Once the sibling is mined the package fits again
+        db.record_mined_tx(&sibling)?;
+        let parent_truc = parent_row(&db)?;
+        assert_eq!(parent_truc.unconfirmed_children, 1);
+        assert!(parent_truc.respects_topology);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tx_version_distribution() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let v2 = spending_tx(&[outpoint("01", 0)], 90_000);
+        let mut v3 = spending_tx(&[outpoint("02", 0)], 90_000);
+        v3.version = bitcoin::transaction::Version(3);
+        let mut mined_v3 = spending_tx(&[outpoint("03", 0)], 90_000);
+        mined_v3.version = bitcoin::transaction::Version(3);
+        for tx in [&v2, &v3, &mined_v3] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+        db.record_mined_tx(&mined_v3)?;
+
+        db.record_tx_version_counts()?;
+        let counts = db.tx_version_distribution(0)?;
+        assert_eq!(
+            counts
+                .iter()
+                .map(|count| (count.tx_version, count.count))
+                .collect::<Vec<_>>(),
+            vec![(2, 1), (3, 1)]
+        );
+        assert!(db.tx_version_distribution(now!() + 1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_distribution_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        // Each witnessless input adds 41 vbytes
+        let inputs = |first: u8, count: u8| {
+            (first..first + count)
+                .map(|i| outpoint(&format!("{:02x}", i), 0))
+                .collect::<Vec<_>>()
+        };
+        let small = spending_tx(&inputs(1, 1), 90_000);
+        let other_small = spending_tx(&inputs(2, 2), 90_000);
+        let medium = spending_tx(&inputs(10, 10), 90_000);
+        let large = spending_tx(&inputs(30, 30), 90_000);
+        for tx in [&small, &other_small, &medium, &large] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+
+        db.record_size_distribution()?;
+        let counts = db.size_distribution_series(0)?;
+        assert_eq!(
+            counts
+                .iter()
+                .map(|count| (count.min_vsize, count.max_vsize, count.tx_count))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, Some(250), 2),
+                (250, Some(1_000), 1),
+                (1_000, Some(10_000), 1)
+            ]
+        );
+        assert_eq!(
+            counts[0].total_vsize,
+            (small.vsize() + other_small.vsize()) as u64
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_drift_distribution() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let txids = (1..=4)
+            .map(|i| spending_tx(&[outpoint(&format!("0{}", i), 0)], 90_000).compute_txid())
+            .collect::<Vec<_>>();
+        db.record_time_drifts(vec![
+            // Seen a second before the node's entry time, e.g. on clock skew
+            (txids[0], 99, 100),
+            (txids[1], 100, 100),
+            (txids[2], 103, 100),
+            (txids[3], 500, 100),
+        ])?;
+        // Only the first comparison of a tx is kept
+        db.record_time_drifts(vec![(txids[3], 101, 100)])?;
+
+        let counts = db
+            .time_drift_distribution(0)?
+            .iter()
+            .map(|bucket| bucket.tx_count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![2, 1, 0, 0, 0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_feerate_target_series() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let kwu = FeeRate::from_sat_per_kwu;
+
+        db.record_mempool_state(1_000, 10, Some(100), None)?;
+        // No blocks seen yet
+        db.update_feerate_targets()?;
+        assert!(db.feerate_target_series(0)?.is_empty());
+
+        db.record_block_tx(101, kwu(5_000))?;
+        db.record_block_tx(102, kwu(2_000))?;
+        db.record_block_tx(103, kwu(8_000))?;
+        db.record_block_tx(105, kwu(1_000))?;
+        db.update_feerate_targets()?;
+
+        let targets = db.feerate_target_series(0)?;
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].bucket_start % FEERATE_TARGET_BUCKET_SECS, 0);
+        assert_eq!(targets[0].block_height, 100);
+        assert_eq!(targets[0].next_block, Some(kwu(5_000)));
+        assert_eq!(targets[0].next_3_blocks, Some(kwu(2_000)));
+        assert_eq!(targets[0].next_6_blocks, Some(kwu(1_000)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meta() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        assert_eq!(db.meta(INSTANCE_NAME_KEY)?, None);
+        db.set_meta(INSTANCE_NAME_KEY, "mainnet-a")?;
+        db.set_meta(INSTANCE_NAME_KEY, "mainnet-b")?;
+        assert_eq!(db.meta(INSTANCE_NAME_KEY)?, Some("mainnet-b".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_stats() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        assert_eq!(db.fee_stats(0)?, FeeStats::default());
+
+        for (i, fee) in [1_000, 4_000, 2_000, 3_000].into_iter().enumerate() {
+            let tx = spending_tx(&[outpoint(&format!("0{}", i + 1), 0)], 90_000);
+            db.insert_mempool_tx(tx, Some(100 + i as u64), Amount::from_sat(fee), fee_rate)?;
+        }
+
+        let stats = db.fee_stats(0)?;
+        assert_eq!(stats.tx_count, 4);
+        assert_eq!(stats.total, Amount::from_sat(10_000));
+        assert_eq!(stats.mean, Amount::from_sat(2_500));
+        assert_eq!(stats.median, Amount::from_sat(2_000));
+        // Only the txs found at 102 and 103
+        assert_eq!(db.fee_stats(102)?.total, Amount::from_sat(5_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtered_txs_are_counted() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let filter = TxFilter {
+            min_fee_rate: FeeRate::from_sat_per_vb(2),
+            op_return: Some(true),
+            ..TxFilter::default()
+        };
+        let tx = spending_tx(&[outpoint("01", 0)], 90_000);
+        let low = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        let high = FeeRate::from_sat_per_vb(5).expect("valid fee rate");
+        assert_eq!(filter.rejection(&tx, low), Some("min_fee_rate"));
+        assert_eq!(filter.rejection(&tx, high), Some("require_op_return"));
+        assert_eq!(TxFilter::default().rejection(&tx, low), None);
+
+        db.record_filtered_tx("min_fee_rate")?;
+        db.record_filtered_tx("min_fee_rate")?;
+        db.record_filtered_tx("require_op_return")?;
+        assert_eq!(
+            db.filtered_tx_counts()?,
+            vec![
+                ("min_fee_rate".to_string(), 2),
+                ("require_op_return".to_string(), 1)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_mined() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let tx = spending_tx(&[outpoint("01", 0)], 90_000);
+        let untracked = spending_tx(&[outpoint("02", 0)], 90_000);
+        db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        assert!(!db.is_mined(&tx.compute_txid())?);
+        assert!(!db.is_mined(&untracked.compute_txid())?);
+
+        db.record_mined_tx(&tx)?;
+        assert!(db.is_mined(&tx.compute_txid())?);
 
         Ok(())
     }
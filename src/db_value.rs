@@ -0,0 +1,68 @@
+use bitcoin::{Amount, FeeRate};
+
+/// How amounts and fee rates are stored in integer db columns, shared by both backends so
+/// every insert and read path uses the same unit: amounts in sats, fee rates in sat/kwu.
+/// The `fee_rate` REAL columns are the exception, see [`crate::utils::fee_rate_sat_per_vb`].
+pub trait DbValue: Sized {
+    fn to_db_i64(self) -> i64;
+
+    /// Negative values, which only a bug can store (see `Database::audit`), read as zero
+    fn from_db_i64(value: i64) -> Self;
+}
+
+impl DbValue for Amount {
+    fn to_db_i64(self) -> i64 {
+        // Any real amount is far below i64::MAX sats
+        self.to_sat() as i64
+    }
+
+    fn from_db_i64(value: i64) -> Self {
+        Amount::from_sat(value.max(0) as u64)
+    }
+}
+
+impl DbValue for FeeRate {
+    fn to_db_i64(self) -> i64 {
+        i64::try_from(self.to_sat_per_kwu()).unwrap_or(i64::MAX)
+    }
+
+    fn from_db_i64(value: i64) -> Self {
+        FeeRate::from_sat_per_kwu(value.max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_round_trip() {
+        for amount in [
+            Amount::ZERO,
+            Amount::from_sat(1),
+            Amount::ONE_BTC,
+            Amount::MAX_MONEY,
+        ] {
+            assert_eq!(Amount::from_db_i64(amount.to_db_i64()), amount);
+        }
+        assert_eq!(Amount::ONE_BTC.to_db_i64(), 100_000_000);
+        assert_eq!(Amount::from_db_i64(-1), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_fee_rate_round_trip() {
+        // 1.1 sat/vB isn't a whole sat/vB but is exact in sat/kwu
+        let fee_rates = [
+            FeeRate::ZERO,
+            FeeRate::BROADCAST_MIN,
+            FeeRate::from_sat_per_kwu(275),
+            FeeRate::from_sat_per_vb(1_000).expect("valid fee rate"),
+        ];
+        for fee_rate in fee_rates {
+            assert_eq!(FeeRate::from_db_i64(fee_rate.to_db_i64()), fee_rate);
+        }
+        assert_eq!(FeeRate::from_sat_per_kwu(275).to_db_i64(), 275);
+        assert_eq!(FeeRate::from_db_i64(-250), FeeRate::ZERO);
+        assert_eq!(FeeRate::MAX.to_db_i64(), i64::MAX);
+    }
+}
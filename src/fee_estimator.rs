@@ -0,0 +1,136 @@
+use std::{future::Future, sync::Arc};
+
+use anyhow::Result;
+use bitcoin::FeeRate;
+use serde_json::{json, Value};
+
+use crate::{database::FeerateTarget, fees::RecommendedFees, now, rpc::RawRpcClient, store::Store};
+
+/// A fee estimation strategy, so estimators can be compared against each other
+pub trait FeeEstimator: Send + Sync {
+    /// Fee rate expected to confirm within `target_blocks` blocks
+    fn estimate(&self, target_blocks: u16) -> impl Future<Output = Result<FeeRate>> + Send;
+}
+
+/// Estimates from the fee rates that would have been mined within 1, 3 and 6 blocks
+/// over a recent window of `feerate_targets`, falling back to the percentiles of the
+/// tracked unconfirmed txs when no block was seen in the window
+#[derive(Debug, Clone)]
+pub struct LocalEstimator<S: Store> {
+    db: S,
+    window_secs: u64,
+}
+
+impl<S: Store> LocalEstimator<S> {
+    pub fn new(db: S, window_secs: u64) -> Self {
+        Self { db, window_secs }
+    }
+}
+
+impl<S: Store> FeeEstimator for LocalEstimator<S> {
+    async fn estimate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let since = now!().saturating_sub(self.window_secs);
+        let targets = self.db.feerate_target_series(since).await?;
+        let fallback = RecommendedFees::from_fee_rates(&self.db.unconfirmed_fee_rates().await?);
+        Ok(local_estimate(&targets, target_blocks, &fallback))
+    }
+}
+
+/// Median over the window of the target matching `target_blocks`, rounded down to 1, 3 or 6 blocks
+fn local_estimate(
+    targets: &[FeerateTarget],
+    target_blocks: u16,
+    fallback: &RecommendedFees,
+) -> FeeRate {
+    let mut fee_rates = targets
+        .iter()
+        .filter_map(|target| match target_blocks {
+            0..=2 => target.next_block,
+            3..=5 => target.next_3_blocks,
+            _ => target.next_6_blocks,
+        })
+        .collect::<Vec<_>>();
+    if fee_rates.is_empty() {
+        let sat_vb = match target_blocks {
+            0..=2 => fallback.fastest_fee,
+            3..=5 => fallback.half_hour_fee,
+            _ => fallback.hour_fee,
+        };
+        return FeeRate::from_sat_per_vb(sat_vb).unwrap_or(FeeRate::MAX);
+    }
+    fee_rates.sort();
+    fee_rates[fee_rates.len() / 2]
+}
+
+/// Delegates to the node's `estimatesmartfee`, as a baseline for the local estimator
+#[derive(Debug, Clone)]
+pub struct CoreEstimator {
+    rpc: Arc<RawRpcClient>,
+}
+
+impl CoreEstimator {
+    pub fn new(rpc: Arc<RawRpcClient>) -> Self {
+        Self { rpc }
+    }
+}
+
+impl FeeEstimator for CoreEstimator {
+    async fn estimate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let result = self
+            .rpc
+            .result("estimatesmartfee", json!([target_blocks]))
+            .await?;
+        fee_rate_from_estimate(&result)
+    }
+}
+
+/// `estimatesmartfee` reports BTC/kvB, and errors instead of a fee rate without enough data
+fn fee_rate_from_estimate(result: &Value) -> Result<FeeRate> {
+    let btc_per_kvb = result["feerate"].as_f64().ok_or(anyhow::anyhow!(
+        "estimatesmartfee returned no fee rate: {}",
+        result["errors"]
+    ))?;
+    // 1 BTC/kvB is 1e8 sat per 4000 wu
+    Ok(FeeRate::from_sat_per_kwu(
+        (btc_per_kvb * 100_000_000.0 / 4.0).round() as u64,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_estimate() {
+        let kwu = FeeRate::from_sat_per_kwu;
+        let target = |next_block, next_6_blocks| FeerateTarget {
+            bucket_start: 0,
+            block_height: 100,
+            next_block: Some(kwu(next_block)),
+            next_3_blocks: None,
+            next_6_blocks: Some(kwu(next_6_blocks)),
+        };
+        let targets = [target(5_000, 500), target(3_000, 250), target(9_000, 1_000)];
+        let fallback = RecommendedFees::from_fee_rates(&[]);
+
+        assert_eq!(local_estimate(&targets, 1, &fallback), kwu(5_000));
+        assert_eq!(local_estimate(&targets, 12, &fallback), kwu(500));
+        // No 3 block targets in the window, the 3 block estimate uses the half hour tier
+        assert_eq!(
+            local_estimate(&targets, 3, &fallback),
+            FeeRate::from_sat_per_vb(1).expect("valid fee rate")
+        );
+    }
+
+    #[test]
+    fn test_fee_rate_from_estimate() -> Result<()> {
+        // 0.0001 BTC/kvB is 10 sat/vB
+        let fee_rate = fee_rate_from_estimate(&json!({ "feerate": 0.0001, "blocks": 2 }))?;
+        assert_eq!(
+            fee_rate,
+            FeeRate::from_sat_per_vb(10).expect("valid fee rate")
+        );
+        assert!(fee_rate_from_estimate(&json!({ "errors": ["Insufficient data"] })).is_err());
+        Ok(())
+    }
+}
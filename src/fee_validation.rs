@@ -0,0 +1,64 @@
+use anyhow::Result;
+use bitcoin::{Amount, Txid};
+use serde_json::{json, Value};
+
+use crate::rpc::{RawRpcClient, RpcCredentials};
+
+/// Cross-checks computed fees against the node's `getmempoolentry`, to catch bugs in summing prevouts
+#[derive(Debug)]
+pub struct FeeValidator {
+    rpc: RawRpcClient,
+    /// Fraction of txs checked, picked the same way as `--sample-rate`
+    pub sample_rate: f64,
+}
+
+impl FeeValidator {
+    pub fn new(url: String, credentials: RpcCredentials, sample_rate: f64) -> Self {
+        Self {
+            rpc: RawRpcClient::new(url, credentials),
+            sample_rate,
+        }
+    }
+
+    /// Base fee of a mempool tx per the node, `None` once it has left the mempool
+    pub async fn node_fee(&self, txid: &Txid) -> Result<Option<Amount>> {
+        let response = self
+            .rpc
+            .request("getmempoolentry", json!([txid.to_string()]))
+            .await?;
+        node_fee_from_response(&response)
+    }
+}
+
+fn node_fee_from_response(response: &Value) -> Result<Option<Amount>> {
+    if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+        // RPC_INVALID_ADDRESS_OR_KEY, not in the mempool
+        if error["code"].as_i64() == Some(-5) {
+            return Ok(None);
+        }
+        return Err(anyhow::anyhow!("getmempoolentry failed: {}", error));
+    }
+    let base = response["result"]["fees"]["base"]
+        .as_f64()
+        .ok_or(anyhow::anyhow!("getmempoolentry is missing fees.base"))?;
+    Ok(Some(Amount::from_btc(base)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_fee_from_response() -> Result<()> {
+        let entry = json!({ "result": { "fees": { "base": 0.00001234 } }, "error": null });
+        assert_eq!(
+            node_fee_from_response(&entry)?,
+            Some(Amount::from_sat(1_234))
+        );
+        let missing = json!({ "result": null, "error": { "code": -5, "message": "Transaction not in mempool" } });
+        assert_eq!(node_fee_from_response(&missing)?, None);
+        let failed = json!({ "result": null, "error": { "code": -28, "message": "Loading" } });
+        assert!(node_fee_from_response(&failed).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use bitcoin::FeeRate;
+use serde_json::{json, Value};
+
+use crate::utils::{fee_rate_percentile, fee_rate_sat_per_vb};
+
+/// Fee estimates in whole sat/vB, in the shape of mempool.space's `GET /api/v1/fees/recommended`.
+///
+/// Computed from the fee rates of tracked unconfirmed txs:
+/// - `fastestFee`: 90th percentile
+/// - `halfHourFee`: 75th percentile
+/// - `hourFee`: 50th percentile
+/// - `minimumFee`: 10th percentile
+///
+/// Each is rounded up and at least 1 sat/vB, and tiers never exceed the one above them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecommendedFees {
+    pub fastest_fee: u64,
+    pub half_hour_fee: u64,
+    pub hour_fee: u64,
+    pub minimum_fee: u64,
+}
+
+impl RecommendedFees {
+    pub fn from_fee_rates(fee_rates: &[FeeRate]) -> Self {
+        let tier = |p| {
+            fee_rate_percentile(fee_rates, p)
+                .map(|fee_rate| fee_rate_sat_per_vb(fee_rate).ceil() as u64)
+                .unwrap_or(0)
+                .max(1)
+        };
+        let fastest_fee = tier(90);
+        let half_hour_fee = tier(75).min(fastest_fee);
+        let hour_fee = tier(50).min(half_hour_fee);
+        let minimum_fee = tier(10).min(hour_fee);
+        Self {
+            fastest_fee,
+            half_hour_fee,
+            hour_fee,
+            minimum_fee,
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "fastestFee": self.fastest_fee,
+            "halfHourFee": self.half_hour_fee,
+            "hourFee": self.hour_fee,
+            "minimumFee": self.minimum_fee,
+        })
+    }
+}
+
+/// Vbytes of a full block template, ignoring the coinbase
+pub const BLOCK_VSIZE: u64 = 1_000_000;
+
+/// Where a candidate tx would land if broadcast now, backing `GET /simulate/feerate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSimulation {
+    pub fee_rate: FeeRate,
+    pub vsize: u64,
+    /// Vbytes of tracked unconfirmed txs paying a higher fee rate, mined first
+    pub vbytes_ahead: u64,
+    /// The block, counting from the next one, the tx would fill into if nothing else arrived
+    pub expected_block: u64,
+    /// Recent blocks whose clearing fee rate the candidate meets, out of `recent_blocks`
+    pub recent_blocks_cleared: usize,
+    pub recent_blocks: usize,
+}
+
+impl FeeSimulation {
+    /// `mempool` holds the fee rate and vsize of each tracked unconfirmed tx
+    pub fn simulate(
+        fee_rate: FeeRate,
+        vsize: u64,
+        mempool: &[(FeeRate, u64)],
+        recent_clearing_fee_rates: &[FeeRate],
+    ) -> Self {
+        let vbytes_ahead = mempool
+            .iter()
+            .filter(|(tx_fee_rate, _)| *tx_fee_rate > fee_rate)
+            .map(|(_, tx_vsize)| tx_vsize)
+            .sum::<u64>();
+        Self {
+            fee_rate,
+            vsize,
+            vbytes_ahead,
+            expected_block: (vbytes_ahead + vsize).div_ceil(BLOCK_VSIZE).max(1),
+            recent_blocks_cleared: recent_clearing_fee_rates
+                .iter()
+                .filter(|clearing| **clearing <= fee_rate)
+                .count(),
+            recent_blocks: recent_clearing_fee_rates.len(),
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "feeRate": fee_rate_sat_per_vb(self.fee_rate),
+            "vsize": self.vsize,
+            "vbytesAhead": self.vbytes_ahead,
+            "expectedBlock": self.expected_block,
+            "recentBlocksCleared": self.recent_blocks_cleared,
+            "recentBlocks": self.recent_blocks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_simulation() {
+        let sat_vb = |sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate");
+        // 1.5 blocks worth paying 10 sat/vB, half a block paying 2 sat/vB
+        let mempool = [(sat_vb(10), 1_500_000), (sat_vb(2), 500_000)];
+        let recent = [sat_vb(3), sat_vb(5), sat_vb(8)];
+
+        let simulation = FeeSimulation::simulate(sat_vb(5), 200, &mempool, &recent);
+        assert_eq!(simulation.vbytes_ahead, 1_500_000);
+        assert_eq!(simulation.expected_block, 2);
+        assert_eq!(simulation.recent_blocks_cleared, 2);
+        assert_eq!(simulation.recent_blocks, 3);
+
+        let simulation = FeeSimulation::simulate(sat_vb(20), 200, &mempool, &[]);
+        assert_eq!(simulation.vbytes_ahead, 0);
+        assert_eq!(simulation.expected_block, 1);
+        assert_eq!(simulation.recent_blocks, 0);
+    }
+
+    #[test]
+    fn test_recommended_fees() {
+        let fee_rates = (1..=100)
+            .map(|sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate"))
+            .collect::<Vec<_>>();
+        let fees = RecommendedFees::from_fee_rates(&fee_rates);
+        assert_eq!(
+            fees.to_json(),
+            json!({ "fastestFee": 90, "halfHourFee": 75, "hourFee": 50, "minimumFee": 10 })
+        );
+
+        // An empty mempool still recommends the relay minimum
+        let fees = RecommendedFees::from_fee_rates(&[]);
+        assert_eq!(fees.fastest_fee, 1);
+        assert_eq!(fees.minimum_fee, 1);
+    }
+}
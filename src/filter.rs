@@ -0,0 +1,121 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use bitcoin::{FeeRate, Transaction};
+
+use crate::utils::sat_per_vb_fee_rate;
+
+/// Predicate on tx characteristics, txs it rejects are counted but not stored.
+/// Every unset field accepts all txs.
+#[derive(Debug, Clone, Default)]
+pub struct TxFilter {
+    pub min_fee_rate: Option<FeeRate>,
+    pub min_vsize: Option<usize>,
+    pub max_vsize: Option<usize>,
+    /// `Some(true)` keeps only txs with an OP_RETURN output, `Some(false)` only txs without one
+    pub op_return: Option<bool>,
+}
+
+impl TxFilter {
+    /// Read a filter from a TOML file with any of `min_fee_rate` (sat/vB), `min_vsize`,
+    /// `max_vsize`, `require_op_return` and `forbid_op_return`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    fn from_toml(s: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(s)?;
+        let invalid = |key: &str| anyhow::anyhow!("Invalid value for filter {}", key);
+        let vsize = |key: &str, value: &toml::Value| {
+            value
+                .as_integer()
+                .and_then(|vsize| usize::try_from(vsize).ok())
+                .ok_or_else(|| invalid(key))
+        };
+        let mut filter = Self::default();
+        let (mut require_op_return, mut forbid_op_return) = (false, false);
+        for (key, value) in &table {
+            match key.as_str() {
+                "min_fee_rate" => {
+                    // A whole number of sat/vB parses as a TOML integer
+                    let sat_vb = value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|sat_vb| sat_vb as f64))
+                        .ok_or_else(|| invalid(key))?;
+                    filter.min_fee_rate = Some(sat_per_vb_fee_rate(sat_vb));
+                }
+                "min_vsize" => filter.min_vsize = Some(vsize(key, value)?),
+                "max_vsize" => filter.max_vsize = Some(vsize(key, value)?),
+                "require_op_return" => {
+                    require_op_return = value.as_bool().ok_or_else(|| invalid(key))?
+                }
+                "forbid_op_return" => {
+                    forbid_op_return = value.as_bool().ok_or_else(|| invalid(key))?
+                }
+                _ => return Err(anyhow::anyhow!("Unknown filter {}", key)),
+            }
+        }
+        filter.op_return = match (require_op_return, forbid_op_return) {
+            (true, true) => {
+                return Err(anyhow::anyhow!(
+                    "require_op_return and forbid_op_return can't both be set"
+                ))
+            }
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+        Ok(filter)
+    }
+
+    /// Why the filter rejects `tx`, `None` if it should be stored
+    pub fn rejection(&self, tx: &Transaction, fee_rate: FeeRate) -> Option<&'static str> {
+        if self
+            .min_fee_rate
+            .is_some_and(|min_fee_rate| fee_rate < min_fee_rate)
+        {
+            return Some("min_fee_rate");
+        }
+        let vsize = tx.vsize();
+        if self.min_vsize.is_some_and(|min_vsize| vsize < min_vsize) {
+            return Some("min_vsize");
+        }
+        if self.max_vsize.is_some_and(|max_vsize| vsize > max_vsize) {
+            return Some("max_vsize");
+        }
+        let has_op_return = tx
+            .output
+            .iter()
+            .any(|output| output.script_pubkey.is_op_return());
+        match self.op_return {
+            Some(true) if !has_op_return => Some("require_op_return"),
+            Some(false) if has_op_return => Some("forbid_op_return"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_from_toml() -> Result<()> {
+        let filter = TxFilter::from_toml(
+            "min_fee_rate = 2\nmin_vsize = 100\nmax_vsize = 1000\nforbid_op_return = true",
+        )?;
+        assert_eq!(filter.min_fee_rate, Some(sat_per_vb_fee_rate(2.0)));
+        assert_eq!(filter.min_vsize, Some(100));
+        assert_eq!(filter.max_vsize, Some(1000));
+        assert_eq!(filter.op_return, Some(false));
+
+        let filter = TxFilter::from_toml("min_fee_rate = 1.5")?;
+        assert_eq!(filter.min_fee_rate, Some(sat_per_vb_fee_rate(1.5)));
+        assert_eq!(filter.op_return, None);
+
+        assert!(TxFilter::from_toml("min_feerate = 1").is_err());
+        assert!(TxFilter::from_toml("min_vsize = -1").is_err());
+        assert!(TxFilter::from_toml("require_op_return = true\nforbid_op_return = true").is_err());
+        Ok(())
+    }
+}
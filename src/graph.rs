@@ -0,0 +1,105 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use anyhow::Result;
+use bitcoin::Txid;
+use log::warn;
+
+use crate::database::{Database, LineageEdge, LineageKind};
+
+/// Stop walking a lineage after this many txs, long chains render unreadably anyway
+const MAX_GRAPH_TXS: usize = 500;
+
+/// Walk the package and replacement lineage of `root` and render it as Graphviz DOT
+pub fn lineage_dot(db: &Database, root: &Txid) -> Result<String> {
+    let mut seen = BTreeSet::from([*root]);
+    let mut queue = VecDeque::from([*root]);
+    let mut edges = BTreeSet::new();
+    while let Some(txid) = queue.pop_front() {
+        for edge in db.lineage(&txid)? {
+            for next in [edge.from, edge.to] {
+                if seen.len() >= MAX_GRAPH_TXS {
+                    break;
+                }
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+            if seen.contains(&edge.from) && seen.contains(&edge.to) {
+                edges.insert(edge);
+            }
+        }
+    }
+    if seen.len() >= MAX_GRAPH_TXS {
+        warn!("Lineage of {} truncated at {} txs", root, MAX_GRAPH_TXS);
+    }
+    Ok(render_dot(root, &seen, &edges))
+}
+
+fn render_dot(root: &Txid, txids: &BTreeSet<Txid>, edges: &BTreeSet<LineageEdge>) -> String {
+    let mut dot = String::from("digraph lineage {\n    rankdir=LR;\n    node [shape=box];\n");
+    for txid in txids {
+        let style = if txid == root { ", style=bold" } else { "" };
+        let txid = txid.to_string();
+        dot.push_str(&format!(
+            "    \"{}\" [label=\"{}...\"{}];\n",
+            txid,
+            &txid[..16],
+            style
+        ));
+    }
+    for edge in edges {
+        let attrs = match edge.kind {
+            LineageKind::Spends => "label=\"spends\"",
+            LineageKind::ReplacedBy => "label=\"replaced by\", style=dashed",
+        };
+        dot.push_str(&format!(
+            "    \"{}\" -> \"{}\" [{}];\n",
+            edge.from, edge.to, attrs
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{outpoint, spending_tx, test_db};
+    use bitcoin::{Amount, FeeRate, OutPoint};
+
+    #[test]
+    fn test_lineage_dot() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+
+        let funding = outpoint("01", 0);
+        let parent = spending_tx(&[funding], 90_000);
+        let child = spending_tx(
+            &[OutPoint {
+                txid: parent.compute_txid(),
+                vout: 0,
+            }],
+            80_000,
+        );
+        // Re-stored under the same inputs, so the parent is archived as replaced
+        let replacement = spending_tx(&[funding], 85_000);
+        for tx in [&parent, &child, &replacement] {
+            db.insert_mempool_tx(tx.clone(), Some(100), Amount::from_sat(1_000), fee_rate)?;
+        }
+
+        let dot = lineage_dot(&db, &child.compute_txid())?;
+        assert!(dot.starts_with("digraph lineage {"));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [label=\"spends\"]",
+            parent.compute_txid(),
+            child.compute_txid()
+        )));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [label=\"replaced by\", style=dashed]",
+            parent.compute_txid(),
+            replacement.compute_txid()
+        )));
+
+        Ok(())
+    }
+}
@@ -1,7 +1,29 @@
 pub mod app;
+pub mod bench;
+pub mod bloom;
 pub mod database;
+pub mod db_value;
+pub mod fee_estimator;
+pub mod fee_validation;
+pub mod fees;
+pub mod filter;
+pub mod graph;
 pub mod migrations;
+pub mod network;
+pub mod node_info;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod rebroadcast;
+pub mod replay;
+pub mod rpc;
+pub mod shutdown_snapshot;
+pub mod snapshot_file;
+pub mod status_line;
+pub mod store;
+#[cfg(test)]
+mod test_utils;
 pub mod utils;
+pub mod watch;
 pub mod worker;
 pub mod zmq_factory;
 // Re-export bitcoincore_zmq
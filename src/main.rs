@@ -1,19 +1,71 @@
-use std::{path::PathBuf, time::Duration};
+use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::Result;
+use app::{MemoryLimits, OverflowPolicies, OverflowPolicy};
+use bitcoin::{consensus::encode::serialize_hex, Amount, Network, ScriptBuf};
 use bitcoind_async_client::{Auth, Client};
-use clap::Parser;
-use zmq_factory::BitcoinZmqFactory;
+use clap::{Parser, Subcommand, ValueEnum};
+use database::{DbOptions, INSTANCE_NAME_KEY};
+use fee_estimator::{CoreEstimator, FeeEstimator, LocalEstimator};
+use fee_validation::FeeValidator;
+use filter::TxFilter;
+use network::ChainParams;
+use rpc::{RawRpcClient, RpcCredentials};
+use shutdown_snapshot::ShutdownSnapshot;
+use snapshot_file::SnapshotFile;
+use status_line::StatusLine;
+use store::Store;
+use utils::{fee_rate_sat_per_vb, sat_per_vb_fee_rate};
+use watch::WatchedScripts;
+use worker::{
+    Debounce, LiveFeeRates, PruneCheckSkip, PruneGrace, QueueLag, RecentScripts, RecentTxids,
+    WorkerConfig,
+};
+use zmq_factory::{BitcoinZmqFactory, ZmqTopic};
 
 mod app;
+mod bench;
+mod bloom;
 mod database;
+mod db_value;
+mod fee_estimator;
+mod fee_validation;
+mod fees;
+mod filter;
+mod graph;
 mod migrations;
+mod network;
+mod node_info;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod rebroadcast;
+mod replay;
+mod rpc;
+mod shutdown_snapshot;
+mod snapshot_file;
+mod status_line;
+mod store;
+#[cfg(test)]
+mod test_utils;
 mod utils;
+mod watch;
 mod worker;
 mod zmq_factory;
 
+/// Scheduled tasks that can be switched on and off with --enable-tasks and --disable-tasks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+#[value(rename_all = "snake_case")]
+enum ScheduledTask {
+    MempoolState,
+    Prune,
+    MiningInfo,
+    /// Tx counts by nVersion and by size, taken along with each mempool state snapshot
+    Histogram,
+}
+
 // Command line arguments
 #[derive(Clone, Debug, Parser)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
     #[clap(long)]
     bitcoind_user: Option<String>,
@@ -21,49 +73,871 @@ struct Args {
     bitcoind_password: Option<String>,
     #[clap(long)]
     bitcoind_cookie_file: Option<PathBuf>,
+    /// Required to run the tracker, subcommands that talk to the node check it themselves
+    #[clap(long, required = true)]
+    bitcoind_host: Option<String>,
+    #[clap(long, required = true)]
+    bitcoind_rpc_port: Option<u16>,
+    /// Send rpcs to this wallet, i.e. `/wallet/<name>` of the rpc url
     #[clap(long)]
-    bitcoind_host: String,
+    bitcoind_wallet: Option<String>,
+    #[clap(long, required = true)]
+    bitcoind_zmq_port: Option<u16>,
+    /// Chain the node must be on (bitcoin, testnet, testnet4, signet, regtest), checked on startup
     #[clap(long)]
-    bitcoind_rpc_port: u16,
+    network: Option<Network>,
+    /// Challenge script hex of a custom signet, checked against the node with --network signet
     #[clap(long)]
-    bitcoind_zmq_port: u16,
+    signet_challenge: Option<String>,
     #[clap(long, default_value_t = 2)]
     num_workers: u32,
     #[clap(long, default_value_t = 25)]
     mempool_state_check_interval: u64,
+    /// Skip mempool state snapshots taken within this many seconds of the last one, 0 to disable
+    #[clap(long, default_value_t = 1)]
+    mempool_state_debounce_secs: u64,
     #[clap(long, default_value_t = 120)]
     prune_check_interval: u64,
     #[clap(long, default_value_t = false)]
     disable_prune_check: bool,
+    /// Skip the full getrawmempool of a prune check when getmempoolinfo shows the node's mempool
+    /// tx count and size unchanged since the last one
+    #[clap(long, default_value_t = false)]
+    prune_check_skip_unchanged: bool,
+    /// Attempts at looking up the tip hash and height per mempool state snapshot, retried when
+    /// a lookup fails, e.g. mid reorg
+    #[clap(long, default_value_t = 3)]
+    tip_lookup_attempts: u32,
+    /// Only mark a tx pruned once it was missing from the mempool for this many prune checks in a row
+    #[clap(long, default_value_t = 2)]
+    prune_grace_cycles: u32,
     #[clap(long, default_value_t = 60 * 60)]
     track_mining_interval: u64,
     #[clap(long, default_value_t = false)]
     enable_mining_info: bool,
+    /// Run only these scheduled tasks (comma separated), instead of the ones enabled by default
+    /// and by --enable-mining-info
+    #[clap(long, value_delimiter = ',')]
+    enable_tasks: Option<Vec<ScheduledTask>>,
+    /// Don't run these scheduled tasks (comma separated), applied after --enable-tasks
+    #[clap(long, value_delimiter = ',')]
+    disable_tasks: Vec<ScheduledTask>,
+    /// Value inputs spending tracked unconfirmed txs from the db instead of asking the node
+    #[clap(long, default_value_t = false)]
+    prevouts_from_db: bool,
+    /// Check every tx against our standardness rules and record failures in `nonstandard_txs`
+    #[clap(long, default_value_t = false)]
+    check_standardness: bool,
+    /// Record the age of every coin spent by a tx, read from the same parent lookups as the fee
+    #[clap(long, default_value_t = false)]
+    track_coin_age: bool,
+    /// Check stored data for invariant violations every this many seconds, off by default
+    #[clap(long)]
+    audit_interval: Option<u64>,
+    /// Record the node's connection count and peer protocol versions every this many seconds, off by default
+    #[clap(long)]
+    node_info_interval: Option<u64>,
+    /// Defer txs whose fee lookup fails and retry them every this many seconds, off by default
+    #[clap(long)]
+    recompute_fees_interval: Option<u64>,
+    /// Every this many seconds, mark txs unconfirmed for over --stuck-tx-age-secs pruned if
+    /// they're gone from the node's mempool, off by default
+    #[clap(long)]
+    stuck_tx_check_interval: Option<u64>,
+    /// Unconfirmed txs first seen this many seconds ago are cross-checked by --stuck-tx-check-interval
+    #[clap(long, default_value_t = 24 * 60 * 60)]
+    stuck_tx_age_secs: u64,
+    /// Summarize each mempool state snapshot in a status line, redrawn in place on a terminal
+    /// and logged otherwise
+    #[clap(long)]
+    status_line: bool,
+    /// Warn, suggesting more workers, when over this many tasks stay queued for --queue-lag-secs
+    #[clap(long)]
+    queue_lag_threshold: Option<usize>,
+    #[clap(long, default_value_t = 60)]
+    queue_lag_secs: u64,
+    /// Drop a deferred tx after this many failed fee lookup retries
+    #[clap(long, default_value_t = 5)]
+    max_fee_retries: u32,
+    /// How many mempool txs to fetch in parallel when scanning the mempool on startup
+    #[clap(long, default_value_t = 16)]
+    startup_concurrency: usize,
+    /// Skip an unconfirmed txid already stored within this many seconds, 0 to disable
+    #[clap(long, default_value_t = 0)]
+    dedup_window_secs: u64,
+    /// Size the task queue and in-memory filters to roughly fit this many MB
+    #[clap(long)]
+    max_memory_mb: Option<u64>,
+    /// Write the tracked unconfirmed txs here on shutdown, a fresh one skips the startup mempool scan
+    #[clap(long)]
+    shutdown_snapshot: Option<PathBuf>,
+    /// Ignore shutdown snapshots older than this many seconds
+    #[clap(long, default_value_t = 600)]
+    shutdown_snapshot_max_age_secs: u64,
+    /// Cross-check computed fees against the node's getmempoolentry, recording mismatches
+    #[clap(long, default_value_t = false)]
+    validate_fees: bool,
+    /// Fraction (0.0-1.0) of txs whose fees are cross-checked with --validate-fees
+    #[clap(long, default_value_t = 0.01)]
+    validate_fees_rate: f64,
+    /// zmq topics to subscribe to (comma separated), matching the node's -zmqpub<topic> options
+    #[clap(long, value_delimiter = ',', default_value = "rawtx")]
+    zmq_topics: Vec<ZmqTopic>,
+    /// What to do with a zmq tx when the task queue is full: block, drop-oldest or drop-newest.
+    /// Blocking stops reading zmq, so the node may drop messages instead. drop-oldest evicts the
+    /// oldest queued tx.
+    #[clap(long, default_value = "block")]
+    rawtx_overflow: OverflowPolicy,
+    /// What to do with a mempool state, prune or other scheduled task when the task queue is full
+    #[clap(long, default_value = "block")]
+    scheduled_overflow: OverflowPolicy,
+    /// Warn when no zmq message arrived for this many seconds, 0 to disable
+    #[clap(long, default_value_t = 300)]
+    zmq_recv_timeout_secs: u64,
+    /// Postpone migrations that rewrite existing rows, e.g. on a huge db
+    #[clap(long, default_value_t = false)]
+    skip_backfill: bool,
+    /// Fraction (0.0-1.0) of tracked txs whose first seen time is compared with the node's
+    /// mempool entry time on prune checks, 0 to disable
+    #[clap(long, default_value_t = 0.0)]
+    time_drift_sample_rate: f64,
+    /// Record txs paying a script derived from this output descriptor in `watched_txs`
+    #[clap(long)]
+    watch_descriptor: Option<String>,
+    /// How many child indexes of a ranged --watch-descriptor to derive
+    #[clap(long, default_value_t = 1_000)]
+    watch_descriptor_range: u32,
+    /// Tag txs with a few inputs paying at least this many distinct non-dust outputs as batch payouts
+    #[clap(long, default_value_t = 10)]
+    batch_payout_min_outputs: usize,
+    /// Fraction (0.0-1.0) of mempool transactions to store individually
+    #[clap(long, default_value_t = 1.0)]
+    sample_rate: f64,
+    /// Skip fee lookups and storage for txs heavier than this many weight units
+    #[clap(long)]
+    max_tx_weight: Option<u64>,
+    /// Only store aggregates (mempool state, fee rate percentiles, block clearing fee rates),
+    /// no individual txs. See the README for what becomes unavailable.
+    #[clap(long, default_value_t = false)]
+    aggregates_only: bool,
+    /// Count outputs paying one of the last this many distinct output scripts seen (address reuse)
+    #[clap(long)]
+    address_reuse_scripts: Option<usize>,
+    /// Flag txs paying an absolute fee above this many sats as fee anomalies
+    #[clap(long, default_value_t = 100_000_000)]
+    fee_anomaly_sats: u64,
+    /// Append each mempool state snapshot as a line of JSON to this file
+    #[clap(long)]
+    snapshot_file: Option<PathBuf>,
+    /// Rotate the snapshot file once it grows past this size
+    #[clap(long, default_value_t = 100)]
+    snapshot_file_max_mb: u64,
+    /// TOML file with the filters below (`min_fee_rate`, `min_vsize`, `max_vsize`,
+    /// `require_op_return` and `forbid_op_return`), the `--filter-*` flags override it
+    #[clap(long)]
+    filter_config: Option<PathBuf>,
+    /// Only store txs paying at least this fee rate, in sat/vB
+    #[clap(long)]
+    filter_min_fee_rate: Option<f64>,
+    /// Only store txs of at least this many vbytes
+    #[clap(long)]
+    filter_min_vsize: Option<usize>,
+    /// Only store txs of at most this many vbytes
+    #[clap(long)]
+    filter_max_vsize: Option<usize>,
+    /// Only store txs with an OP_RETURN output
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with = "filter_forbid_op_return"
+    )]
+    filter_require_op_return: bool,
+    /// Only store txs without an OP_RETURN output
+    #[clap(long, default_value_t = false)]
+    filter_forbid_op_return: bool,
+    /// Sqlite file path (optionally `sqlite://<path>`) or a `postgres://` url
+    #[clap(long, global = true, default_value = "mempool-tracker.db")]
+    db_url: String,
+    /// Sqlite page size in bytes, only applies when the db is created
+    #[clap(long, global = true)]
+    db_page_size: Option<u32>,
+    /// Sqlite page cache per connection, in MB
+    #[clap(long, global = true)]
+    db_cache_mb: Option<u64>,
+    /// Log level (error, warn, info, debug, trace), takes precedence over RUST_LOG
+    #[clap(long)]
+    log_level: Option<log::LevelFilter>,
+    /// Name of this tracker, stored in the db's `meta` table and included in every log line,
+    /// to tell apart the data of trackers on different nodes or networks
+    #[clap(long, global = true)]
+    instance_name: Option<String>,
+    /// Log filter directives in RUST_LOG syntax (e.g. `mempool_tracker=debug,reqwest=warn`),
+    /// takes precedence over RUST_LOG
+    #[clap(long)]
+    log_filter: Option<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// Write a consistent backup of the sqlite db, safe to run while the tracker is running
+    Snapshot {
+        #[clap(long)]
+        out: PathBuf,
+    },
+    /// Restore the sqlite db from a snapshot, the tracker must be stopped
+    Restore {
+        #[clap(long = "in")]
+        input: PathBuf,
+    },
+    /// Process raw txs from a file without a node, see the README for the format
+    ReplayFile { path: PathBuf },
+    /// Print the CPFP package and RBF lineage of a tx as a Graphviz DOT graph
+    Graph {
+        #[clap(long)]
+        txid: String,
+        /// Write the graph here instead of stdout
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Print the txids of the tracked txs mined at a height, one per line, sorted, to join
+    /// against the block's BIP158 filter matches
+    BlockTxids {
+        #[clap(long)]
+        height: u64,
+        /// Write the txids here instead of stdout
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Fix inconsistencies left in the db by older versions or partial writes
+    Repair {
+        #[clap(subcommand)]
+        target: RepairTarget,
+    },
+    /// Annotate txs with free-form labels, or list the txs with a label
+    Label {
+        #[clap(subcommand)]
+        action: LabelAction,
+    },
+    /// Re-submit tracked txs that stayed unconfirmed for a while to the node with sendrawtransaction
+    Rebroadcast {
+        /// Only txs first seen at least this many seconds ago
+        #[clap(long)]
+        older_than: u64,
+        /// Only txs paying at least this fee rate in sat/vB
+        #[clap(long, default_value_t = 0.0)]
+        min_feerate: f64,
+    },
+    /// Print the tracked txids starting with a hex prefix, one per line, sorted
+    Search {
+        #[clap(long)]
+        txid_prefix: String,
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print a query over the tracked txs as JSON
+    Stats {
+        #[clap(subcommand)]
+        query: StatsQuery,
+    },
+    /// Print the local fee estimate next to the node's estimatesmartfee
+    EstimateFee {
+        /// Confirmation target in blocks
+        #[clap(long, default_value_t = 6)]
+        target: u16,
+        /// Take the median of the fee rate targets of this many recent seconds
+        #[clap(long, default_value_t = 24 * 60 * 60)]
+        window_secs: u64,
+    },
+    /// Measure ingestion throughput with synthetic txs against a temporary db, without a node
+    Bench {
+        #[clap(long, default_value_t = 10_000)]
+        txs: usize,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum RepairTarget {
+    /// Drop rbf rows and replaced_by pointers to missing txs, and move replaced txs' txids to
+    /// their latest replacement
+    RbfLinks,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum StatsQuery {
+    /// mempool.space style fee estimates in the shape of its `GET /api/v1/fees/recommended`
+    RecommendedFees,
+    /// Run the consistency checks of the periodic audit task once, counting violations per check
+    Audit,
+    /// How many txs each `--filter-*` flag rejected
+    FilteredTxs,
+    /// Highest row format version of each table next to the one this binary writes
+    RowVersions,
+    /// Mined, pruned, replaced and still unconfirmed txs found since a unix time, per fee rate bucket
+    OutcomeByFeerate {
+        #[clap(long, default_value_t = 0)]
+        since: u64,
+    },
+    /// Txs mined, pruned and replaced per time bucket since a unix time
+    LifecycleOutcomes {
+        #[clap(long, default_value_t = 3_600)]
+        bucket_secs: u64,
+        #[clap(long, default_value_t = 0)]
+        since: u64,
+    },
+    /// Where a tx would land among the tracked unconfirmed txs if broadcast now
+    SimulateFeeRate {
+        #[clap(long)]
+        vsize: u64,
+        /// sat/vB
+        #[clap(long)]
+        fee_rate: f64,
+        /// Recent blocks to compare the fee rate with their clearing fee rate
+        #[clap(long, default_value_t = 6)]
+        recent_blocks: usize,
+    },
+    /// TRUC (v3) txs found since a unix time, with their package topology
+    TrucTxs {
+        #[clap(long, default_value_t = 0)]
+        since: u64,
+    },
+    /// Every version of a tx stored under an inputs hash, oldest first
+    TxVersions {
+        #[clap(long)]
+        inputs_hash: String,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum LabelAction {
+    /// Label a tx, which doesn't need to be tracked
+    Add {
+        #[clap(long)]
+        txid: String,
+        #[clap(long)]
+        label: String,
+    },
+    /// Print the txids with a label, one per line
+    Txs {
+        #[clap(long)]
+        label: String,
+    },
+}
+
+fn is_postgres_url(db_url: &str) -> bool {
+    db_url.starts_with("postgres://") || db_url.starts_with("postgresql://")
+}
+
+fn sqlite_path(db_url: &str) -> &str {
+    db_url.strip_prefix("sqlite://").unwrap_or(db_url)
+}
+
+fn db_options(args: &Args) -> DbOptions {
+    DbOptions {
+        page_size: args.db_page_size,
+        cache_mb: args.db_cache_mb,
+    }
+}
+
+async fn run_command(command: &Command, args: &Args) -> Result<()> {
+    let db_url = args.db_url.as_str();
+    if let Command::ReplayFile { path } = command {
+        let raw_txs = replay::read_raw_txs(path)?;
+        let config = WorkerConfig {
+            sample_rate: args.sample_rate,
+            max_tx_weight: args.max_tx_weight,
+            ..WorkerConfig::default()
+        };
+        if is_postgres_url(db_url) {
+            #[cfg(feature = "postgres")]
+            return replay::replay(postgres::PgStore::connect(db_url).await?, raw_txs, config)
+                .await;
+            #[cfg(not(feature = "postgres"))]
+            return Err(anyhow::anyhow!(
+                "postgres db url requires building with the `postgres` feature"
+            ));
+        }
+        return replay::replay(
+            database::Database::with_options(sqlite_path(db_url), db_options(args))?,
+            raw_txs,
+            config,
+        )
+        .await;
+    }
+    if let Command::EstimateFee {
+        target,
+        window_secs,
+    } = command
+    {
+        let rpc = Arc::new(RawRpcClient::new(
+            bitcoind_url(args)?,
+            rpc_credentials(args)?,
+        ));
+        if is_postgres_url(db_url) {
+            #[cfg(feature = "postgres")]
+            return print_fee_estimates(
+                postgres::PgStore::connect(db_url).await?,
+                rpc,
+                *target,
+                *window_secs,
+            )
+            .await;
+            #[cfg(not(feature = "postgres"))]
+            return Err(anyhow::anyhow!(
+                "postgres db url requires building with the `postgres` feature"
+            ));
+        }
+        return print_fee_estimates(
+            database::Database::with_options(sqlite_path(db_url), db_options(args))?,
+            rpc,
+            *target,
+            *window_secs,
+        )
+        .await;
+    }
+    if let Command::Bench { txs } = command {
+        println!("{}", bench::run(*txs, db_options(args)).await?);
+        return Ok(());
+    }
+    if is_postgres_url(db_url) {
+        return Err(anyhow::anyhow!(
+            "snapshot, restore, graph, block-txids, label, search, stats, rebroadcast and repair only support sqlite, use pg_dump for postgres"
+        ));
+    }
+    match command {
+        Command::Snapshot { out } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            db.snapshot(out)?;
+            log::info!("Snapshot written to {}", out.display());
+        }
+        Command::Restore { input } => {
+            database::Database::restore(sqlite_path(db_url), input)?;
+            log::info!("Restored {} from {}", sqlite_path(db_url), input.display());
+        }
+        Command::Graph { txid, out } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            let dot = graph::lineage_dot(&db, &txid.parse()?)?;
+            match out {
+                Some(out) => {
+                    std::fs::write(out, dot)?;
+                    log::info!("Graph written to {}", out.display());
+                }
+                None => print!("{}", dot),
+            }
+        }
+        Command::BlockTxids { height, out } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            let txids = db
+                .tracked_txids_in_block(*height)?
+                .iter()
+                .map(|txid| format!("{}\n", txid))
+                .collect::<String>();
+            match out {
+                Some(out) => {
+                    std::fs::write(out, txids)?;
+                    log::info!("Txids written to {}", out.display());
+                }
+                None => print!("{}", txids),
+            }
+        }
+        Command::Repair {
+            target: RepairTarget::RbfLinks,
+        } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            let report = db.repair_rbf_links()?;
+            log::info!(
+                "Deleted {} dangling, {} stale and {} unrepairable rbf rows, rebuilt {} txs from their latest replacement, cleared {} dangling replaced_by",
+                report.dangling_rbfs,
+                report.stale_rbfs,
+                report.unrepairable_rbfs,
+                report.heads_fixed,
+                report.dangling_replaced_by
+            );
+        }
+        Command::Label {
+            action: LabelAction::Add { txid, label },
+        } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            if db.add_label(&txid.parse()?, label)? {
+                log::info!("Labeled {} {}", txid, label);
+            } else {
+                log::info!("{} is already labeled {}", txid, label);
+            }
+        }
+        Command::Label {
+            action: LabelAction::Txs { label },
+        } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            for txid in db.txs_with_label(label)? {
+                println!("{}", txid);
+            }
+        }
+        Command::Search { txid_prefix, limit } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            for txid in db.search_txid_prefix(txid_prefix, *limit)? {
+                println!("{}", txid);
+            }
+        }
+        Command::Stats { query } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            println!("{}", stats(&db, query)?);
+        }
+        Command::Rebroadcast {
+            older_than,
+            min_feerate,
+        } => {
+            let db = database::Database::new(sqlite_path(db_url))?;
+            let rpc = RawRpcClient::new(bitcoind_url(args)?, rpc_credentials(args)?);
+            let min_fee_rate = sat_per_vb_fee_rate(*min_feerate);
+            let summary = rebroadcast::rebroadcast(&db, &rpc, *older_than, min_fee_rate).await?;
+            log::info!(
+                "Rebroadcast {} txs, {} rejected by the node",
+                summary.sent,
+                summary.rejected
+            );
+        }
+        Command::ReplayFile { .. } | Command::EstimateFee { .. } | Command::Bench { .. } => {
+            unreachable!("handled above")
+        }
+    }
+    Ok(())
+}
+
+fn stats(db: &database::Database, query: &StatsQuery) -> Result<serde_json::Value> {
+    Ok(match query {
+        StatsQuery::RecommendedFees => db.recommended_fees()?.to_json(),
+        StatsQuery::FilteredTxs => db
+            .filtered_tx_counts()?
+            .into_iter()
+            .map(|(reason, count)| (reason, serde_json::Value::from(count)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        StatsQuery::RowVersions => db
+            .row_versions()?
+            .with_supported()
+            .into_iter()
+            .map(|(table, max, supported)| {
+                let versions = serde_json::json!({ "max": max, "supported": supported });
+                (table.to_string(), versions)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        StatsQuery::OutcomeByFeerate { since } => db
+            .outcome_by_feerate_bucket(*since)?
+            .iter()
+            .map(|outcome| {
+                serde_json::json!({
+                    "minFeeRate": fee_rate_sat_per_vb(outcome.min_fee_rate),
+                    "maxFeeRate": outcome.max_fee_rate.map(fee_rate_sat_per_vb),
+                    "mined": outcome.mined,
+                    "pruned": outcome.pruned,
+                    "replaced": outcome.replaced,
+                    "unconfirmed": outcome.unconfirmed,
+                })
+            })
+            .collect(),
+        StatsQuery::LifecycleOutcomes { bucket_secs, since } => db
+            .lifecycle_outcomes_series(*bucket_secs, *since)?
+            .iter()
+            .map(|outcomes| {
+                serde_json::json!({
+                    "bucketStart": outcomes.bucket_start,
+                    "mined": outcomes.mined,
+                    "pruned": outcomes.pruned,
+                    "replaced": outcomes.replaced,
+                })
+            })
+            .collect(),
+        StatsQuery::SimulateFeeRate {
+            vsize,
+            fee_rate,
+            recent_blocks,
+        } => db
+            .simulate_fee_rate(sat_per_vb_fee_rate(*fee_rate), *vsize, *recent_blocks)?
+            .to_json(),
+        StatsQuery::TrucTxs { since } => db
+            .truc_txs(*since)?
+            .iter()
+            .map(|truc_tx| {
+                serde_json::json!({
+                    "txid": truc_tx.txid.to_string(),
+                    "foundAt": truc_tx.found_at,
+                    "unconfirmedParents": truc_tx.unconfirmed_parents,
+                    "unconfirmedChildren": truc_tx.unconfirmed_children,
+                    "respectsTopology": truc_tx.respects_topology,
+                    "absoluteFee": truc_tx.absolute_fee.map(Amount::to_sat),
+                })
+            })
+            .collect(),
+        StatsQuery::TxVersions { inputs_hash } => db
+            .tx_versions(inputs_hash)?
+            .iter()
+            .map(|version| {
+                serde_json::json!({
+                    "txid": version.txid.to_string(),
+                    "hex": serialize_hex(&version.tx),
+                    "foundAt": version.found_at,
+                    "absoluteFee": version.absolute_fee.to_sat(),
+                    "feeRate": fee_rate_sat_per_vb(version.fee_rate),
+                    "archivedAt": version.archived_at,
+                })
+            })
+            .collect(),
+        StatsQuery::Audit => {
+            let report = db.audit()?;
+            serde_json::json!({
+                "minedAndPruned": report.mined_and_pruned,
+                "orphanedRbfs": report.orphaned_rbfs,
+                "negativeFees": report.negative_fees,
+                "coinbaseInputs": report.coinbase_inputs,
+                "violations": report.violations(),
+            })
+        }
+    })
+}
+
+async fn print_fee_estimates<S: Store>(
+    db: S,
+    rpc: Arc<RawRpcClient>,
+    target: u16,
+    window_secs: u64,
+) -> Result<()> {
+    let local = LocalEstimator::new(db, window_secs)
+        .estimate(target)
+        .await?;
+    println!("local: {:.2} sat/vB", fee_rate_sat_per_vb(local));
+    // Core has no estimate for a while after starting
+    match CoreEstimator::new(rpc).estimate(target).await {
+        Ok(core) => println!("core: {:.2} sat/vB", fee_rate_sat_per_vb(core)),
+        Err(e) => println!("core: {}", e),
+    }
+    Ok(())
+}
+
+/// A bitcoind flag clap only requires without a subcommand
+fn required_flag<T: Clone>(value: &Option<T>, flag: &str) -> Result<T> {
+    value
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("--{} is required", flag))
+}
+
+fn bitcoind_url(args: &Args) -> Result<String> {
+    rpc::rpc_url(
+        &required_flag(&args.bitcoind_host, "bitcoind-host")?,
+        required_flag(&args.bitcoind_rpc_port, "bitcoind-rpc-port")?,
+        args.bitcoind_wallet.as_deref(),
+    )
+}
+
+fn rpc_credentials(args: &Args) -> Result<RpcCredentials> {
+    if let Some(cookie_file) = args.bitcoind_cookie_file.clone() {
+        Ok(RpcCredentials::CookieFile(cookie_file))
+    } else if let (Some(user), Some(password)) =
+        (args.bitcoind_user.clone(), args.bitcoind_password.clone())
+    {
+        Ok(RpcCredentials::UserPass(user, password))
+    } else {
+        Err(anyhow::anyhow!("no auth method provided"))
+    }
+}
+
+/// Init the logger from the cli flags, falling back to RUST_LOG when neither is set
+fn init_logger(
+    log_level: Option<log::LevelFilter>,
+    log_filter: Option<&str>,
+    instance_name: Option<&str>,
+) {
+    let mut builder = if log_level.is_none() && log_filter.is_none() {
+        env_logger::Builder::from_default_env()
+    } else {
+        env_logger::Builder::new()
+    };
+    if let Some(level) = log_level {
+        builder.filter_level(level);
+    }
+    // Filter directives are applied last so per target levels override the global level
+    if let Some(filter) = log_filter {
+        builder.parse_filters(filter);
+    }
+    if let Some(instance_name) = instance_name {
+        let instance_name = instance_name.to_string();
+        builder.format(move |buf, record| {
+            use std::io::Write;
+            writeln!(
+                buf,
+                "[{} {} {} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                instance_name,
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    builder.init();
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logger(
+        args.log_level,
+        args.log_filter.as_deref(),
+        args.instance_name.as_deref(),
+    );
     log::info!("welcome to mempool tracker");
-    env_logger::init();
+    migrations::set_skip_backfill(args.skip_backfill);
 
-    let args = Args::parse();
-    let zmq_factory = BitcoinZmqFactory::new(args.bitcoind_host.clone(), args.bitcoind_zmq_port);
-    let db = database::Database::new("mempool-tracker.db")?;
-    let bitcoind_url = format!("http://{}:{}", args.bitcoind_host, args.bitcoind_rpc_port);
+    if let Some(command) = &args.command {
+        return run_command(command, &args).await;
+    }
+
+    if is_postgres_url(&args.db_url) {
+        #[cfg(feature = "postgres")]
+        {
+            let db = postgres::PgStore::connect(&args.db_url).await?;
+            return run_app(args, db).await;
+        }
+        #[cfg(not(feature = "postgres"))]
+        return Err(anyhow::anyhow!(
+            "postgres db url requires building with the `postgres` feature"
+        ));
+    }
+
+    let db = database::Database::with_options(sqlite_path(&args.db_url), db_options(&args))?;
+    run_app(args, db).await
+}
+
+/// Scheduled tasks to run, --enable-tasks replaces the set the other flags select
+fn enabled_tasks(args: &Args) -> HashSet<ScheduledTask> {
+    let mut enabled = match &args.enable_tasks {
+        Some(tasks) => tasks.iter().copied().collect(),
+        None => {
+            let mut tasks = HashSet::from([ScheduledTask::MempoolState, ScheduledTask::Histogram]);
+            if !args.disable_prune_check {
+                tasks.insert(ScheduledTask::Prune);
+            }
+            if args.enable_mining_info {
+                tasks.insert(ScheduledTask::MiningInfo);
+            }
+            tasks
+        }
+    };
+    for task in &args.disable_tasks {
+        enabled.remove(task);
+    }
+    enabled
+}
+
+async fn run_app<S: Store>(args: Args, db: S) -> Result<()> {
+    if !args.zmq_topics.contains(&ZmqTopic::RawTx) {
+        log::warn!("rawtx is not in --zmq-topics, no new transactions will be tracked");
+    }
+    let mut zmq_factory = BitcoinZmqFactory::new(
+        required_flag(&args.bitcoind_host, "bitcoind-host")?,
+        required_flag(&args.bitcoind_zmq_port, "bitcoind-zmq-port")?,
+    )
+    .with_topics(args.zmq_topics.clone());
+    if args.zmq_recv_timeout_secs > 0 {
+        zmq_factory =
+            zmq_factory.with_recv_timeout(Duration::from_secs(args.zmq_recv_timeout_secs));
+    }
+    let bitcoind_url = bitcoind_url(&args)?;
 
     // parse u64 to duration
     // TODO: add some validation
     let mempool_state_check_interval = Duration::from_secs(args.mempool_state_check_interval);
     let prune_check_interval = Duration::from_secs(args.prune_check_interval);
     let track_mining_interval = Duration::from_secs(args.track_mining_interval);
+    let enabled_tasks = enabled_tasks(&args);
+    if enabled_tasks.contains(&ScheduledTask::Histogram)
+        && !enabled_tasks.contains(&ScheduledTask::MempoolState)
+    {
+        log::warn!("Histograms are only recorded with the mempool_state task");
+    }
+    if !(0.0..=1.0).contains(&args.sample_rate) {
+        return Err(anyhow::anyhow!("sample rate must be between 0.0 and 1.0"));
+    }
+    if !(0.0..=1.0).contains(&args.time_drift_sample_rate) {
+        return Err(anyhow::anyhow!(
+            "time drift sample rate must be between 0.0 and 1.0"
+        ));
+    }
+    let memory_limits = match args.max_memory_mb {
+        Some(max_memory_mb) => MemoryLimits::from_max_memory_mb(max_memory_mb)?,
+        None => MemoryLimits::default(),
+    };
+    let file_filter = match &args.filter_config {
+        Some(path) => TxFilter::from_file(path)?,
+        None => TxFilter::default(),
+    };
 
-    let auth = if let Some(cookie_file) = args.bitcoind_cookie_file {
-        Auth::CookieFile(cookie_file)
-    } else if let (Some(user), Some(password)) = (args.bitcoind_user, args.bitcoind_password) {
-        Auth::UserPass(user, password)
+    let credentials = rpc_credentials(&args)?;
+    let zmq_notifications = RawRpcClient::new(bitcoind_url.clone(), credentials.clone())
+        .result("getzmqnotifications", serde_json::json!([]))
+        .await?;
+    zmq_factory.check_notifications(&zmq_notifications)?;
+    match args.network {
+        Some(network) => {
+            let signet_challenge = args
+                .signet_challenge
+                .as_deref()
+                .map(ScriptBuf::from_hex)
+                .transpose()?;
+            let chain_params = ChainParams::new(network, signet_challenge)?;
+            let blockchain_info = RawRpcClient::new(bitcoind_url.clone(), credentials.clone())
+                .result("getblockchaininfo", serde_json::json!([]))
+                .await?;
+            chain_params.check(&blockchain_info)?;
+            log::info!("Node is on {} with magic {}", network, chain_params.magic());
+        }
+        None if args.signet_challenge.is_some() => {
+            return Err(anyhow::anyhow!("--signet-challenge needs --network signet"));
+        }
+        None => {}
+    }
+    let fee_validator = if args.validate_fees {
+        if !(0.0..=1.0).contains(&args.validate_fees_rate) {
+            return Err(anyhow::anyhow!(
+                "validate fees rate must be between 0.0 and 1.0"
+            ));
+        }
+        Some(Arc::new(FeeValidator::new(
+            bitcoind_url.clone(),
+            credentials.clone(),
+            args.validate_fees_rate,
+        )))
     } else {
-        return Err(anyhow::anyhow!("no auth method provided"));
+        None
+    };
+    let watched_scripts = match &args.watch_descriptor {
+        Some(descriptor) => {
+            let watched_scripts =
+                WatchedScripts::from_descriptor(descriptor, args.watch_descriptor_range)?;
+            log::info!("Watching {} scripts", watched_scripts.script_count());
+            Some(Arc::new(watched_scripts))
+        }
+        None => None,
     };
+    let raw_rpc = Arc::new(RawRpcClient::new(bitcoind_url.clone(), credentials.clone()));
+    let auth = match credentials {
+        RpcCredentials::CookieFile(cookie_file) => Auth::CookieFile(cookie_file),
+        RpcCredentials::UserPass(user, password) => Auth::UserPass(user, password),
+    };
+
+    if let Some(instance_name) = &args.instance_name {
+        match db.meta(INSTANCE_NAME_KEY).await? {
+            Some(previous) if previous != *instance_name => log::warn!(
+                "The db was last written by instance {}, now by {}",
+                previous,
+                instance_name
+            ),
+            _ => {}
+        }
+        db.set_meta(INSTANCE_NAME_KEY, instance_name.clone())
+            .await?;
+    }
 
     let rpc_client = Client::new(bitcoind_url, auth, None, None)?;
     let mut app = app::App::new(
@@ -71,10 +945,96 @@ async fn main() -> Result<()> {
         zmq_factory,
         db,
         args.num_workers as usize,
-        mempool_state_check_interval,
+        enabled_tasks
+            .contains(&ScheduledTask::MempoolState)
+            .then_some(mempool_state_check_interval),
         prune_check_interval,
-        args.disable_prune_check,
-        args.enable_mining_info.then_some(track_mining_interval),
+        !enabled_tasks.contains(&ScheduledTask::Prune),
+        enabled_tasks
+            .contains(&ScheduledTask::MiningInfo)
+            .then_some(track_mining_interval),
+        args.audit_interval.map(Duration::from_secs),
+        args.node_info_interval.map(Duration::from_secs),
+        args.recompute_fees_interval.map(Duration::from_secs),
+        args.stuck_tx_check_interval.map(Duration::from_secs),
+        args.queue_lag_threshold
+            .map(|threshold| QueueLag::new(threshold, Duration::from_secs(args.queue_lag_secs))),
+        args.startup_concurrency,
+        memory_limits,
+        OverflowPolicies {
+            raw_tx: args.rawtx_overflow,
+            scheduled: args.scheduled_overflow,
+        },
+        args.shutdown_snapshot.clone().map(|path| {
+            ShutdownSnapshot::new(
+                path,
+                Duration::from_secs(args.shutdown_snapshot_max_age_secs),
+            )
+        }),
+        WorkerConfig {
+            sample_rate: args.sample_rate,
+            max_tx_weight: args.max_tx_weight,
+            track_coin_age: args.track_coin_age,
+            prevouts_from_db: args.prevouts_from_db,
+            check_standardness: args.check_standardness,
+            fee_anomaly_threshold: Amount::from_sat(args.fee_anomaly_sats),
+            max_fee_retries: args.recompute_fees_interval.map(|_| args.max_fee_retries),
+            live_fee_rates: args
+                .aggregates_only
+                .then(|| Arc::new(LiveFeeRates::default())),
+            recent_scripts: args.address_reuse_scripts.map(|capacity| {
+                let max = memory_limits.max_recent_scripts.unwrap_or(capacity);
+                if capacity > max {
+                    log::warn!(
+                        "Keeping {} recent scripts instead of {} to fit --max-memory-mb",
+                        max,
+                        capacity
+                    );
+                }
+                Arc::new(RecentScripts::new(capacity.min(max)))
+            }),
+            snapshot_file: args.snapshot_file.map(|path| {
+                Arc::new(SnapshotFile::new(
+                    path,
+                    args.snapshot_file_max_mb * 1024 * 1024,
+                ))
+            }),
+            filter: TxFilter {
+                min_fee_rate: args
+                    .filter_min_fee_rate
+                    .map(sat_per_vb_fee_rate)
+                    .or(file_filter.min_fee_rate),
+                min_vsize: args.filter_min_vsize.or(file_filter.min_vsize),
+                max_vsize: args.filter_max_vsize.or(file_filter.max_vsize),
+                op_return: if args.filter_require_op_return {
+                    Some(true)
+                } else if args.filter_forbid_op_return {
+                    Some(false)
+                } else {
+                    file_filter.op_return
+                },
+            },
+            recent_txids: Arc::new(RecentTxids::new(Duration::from_secs(
+                args.dedup_window_secs,
+            ))),
+            fee_validator,
+            raw_rpc: Some(raw_rpc),
+            watched_scripts,
+            time_drift_sample_rate: args.time_drift_sample_rate,
+            batch_payout_min_outputs: args.batch_payout_min_outputs,
+            prune_grace: Arc::new(PruneGrace::new(args.prune_grace_cycles)),
+            stuck_tx_age: Duration::from_secs(args.stuck_tx_age_secs),
+            tip_lookup_attempts: args.tip_lookup_attempts,
+            status_line: args.status_line.then(|| Arc::new(StatusLine::default())),
+            prune_check_skip: args
+                .prune_check_skip_unchanged
+                .then(|| Arc::new(PruneCheckSkip::default())),
+            mempool_state_debounce: Arc::new(Debounce::new(Duration::from_secs(
+                args.mempool_state_debounce_secs,
+            ))),
+            record_histograms: enabled_tasks.contains(&ScheduledTask::Histogram),
+            ..WorkerConfig::default()
+        },
     );
     app.init().await?;
     app.run().await?;
@@ -1,10 +1,27 @@
-use crate::now;
+use crate::{database::insert_tx_inputs, now};
 use anyhow::Result;
-use std::time::SystemTime;
+use bitcoin::{consensus::Decodable, Transaction};
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub(crate) trait Migration {
     fn migrate(&self, conn: &rusqlite::Connection) -> Result<()>;
     fn id(&self) -> &'static str;
+    /// Backfills only rewrite existing rows, they can be postponed on huge dbs with `--skip-backfill`
+    fn is_backfill(&self) -> bool {
+        false
+    }
+}
+
+static SKIP_BACKFILL: AtomicBool = AtomicBool::new(false);
+
+/// Leave backfill migrations unapplied, they run on the first start without it
+pub fn set_skip_backfill(skip: bool) {
+    SKIP_BACKFILL.store(skip, Ordering::Relaxed);
+}
+
+pub(crate) fn skip_backfill() -> bool {
+    SKIP_BACKFILL.load(Ordering::Relaxed)
 }
 
 pub(crate) struct UpdateChildTxidColName;
@@ -90,6 +107,714 @@ impl Migration for AddIsCpfpParent {
     }
 }
 
+pub(crate) struct AddReplacedBy;
+
+impl Migration for AddReplacedBy {
+    fn id(&self) -> &'static str {
+        "add_replaced_by"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        conn.execute("ALTER TABLE transactions ADD COLUMN replaced_by TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct BackfillTxInputs;
+
+impl Migration for BackfillTxInputs {
+    fn id(&self) -> &'static str {
+        "backfill_tx_inputs"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Index the inputs of txs that were recorded before tx_inputs existed
+        let mut stmt = conn.prepare("SELECT tx_data FROM transactions")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        for row in rows {
+            let bytes = hex::decode(row?)?;
+            let tx = Transaction::consensus_decode(&mut bytes.as_slice())?;
+            insert_tx_inputs(conn, &tx)?;
+        }
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddFeeRateKwu;
+
+impl Migration for AddFeeRateKwu {
+    fn id(&self) -> &'static str {
+        "add_fee_rate_kwu"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Integer sat/kwu alongside the lossy sat/vB REAL column
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN fee_rate_kwu INTEGER",
+            [],
+        )?;
+        conn.execute("ALTER TABLE rbf ADD COLUMN fee_rate_kwu INTEGER", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct MempoolBlockFieldsNullable;
+
+impl Migration for MempoolBlockFieldsNullable {
+    fn id(&self) -> &'static str {
+        "mempool_block_fields_nullable"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Sqlite can't drop a NOT NULL constraint, so rebuild the table.
+        // Lets a snapshot be recorded when only the block lookups fail.
+        conn.execute_batch(
+            "CREATE TABLE mempool_new (
+                tx_id TEXT PRIMARY KEY,
+                created_at DATETIME NOT NULL,
+                size INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                block_height INTEGER,
+                block_hash TEXT,
+                version INTEGER NOT NULL
+            );
+            INSERT INTO mempool_new SELECT tx_id, created_at, size, tx_count, block_height, block_hash, version FROM mempool;
+            DROP TABLE mempool;
+            ALTER TABLE mempool_new RENAME TO mempool;",
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddSignalsRbf;
+
+impl Migration for AddSignalsRbf {
+    fn id(&self) -> &'static str {
+        "add_signals_rbf"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Null for txs stored before this was tracked
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN signals_rbf BOOLEAN",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTimelockFlags;
+
+impl Migration for AddTimelockFlags {
+    fn id(&self) -> &'static str {
+        "add_timelock_flags"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        conn.execute("ALTER TABLE transactions ADD COLUMN lock_time INTEGER", [])?;
+        conn.execute("ALTER TABLE transactions ADD COLUMN uses_csv BOOLEAN", [])?;
+        conn.execute("ALTER TABLE transactions ADD COLUMN is_final BOOLEAN", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct MempoolStateId;
+
+impl Migration for MempoolStateId {
+    fn id(&self) -> &'static str {
+        "mempool_state_id"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // The tx_id primary key was never set for state snapshots, key them by an autoincrement id
+        conn.execute_batch(
+            "CREATE TABLE mempool_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at DATETIME NOT NULL,
+                size INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                block_height INTEGER,
+                block_hash TEXT,
+                version INTEGER NOT NULL
+            );
+            INSERT INTO mempool_new (created_at, size, tx_count, block_height, block_hash, version)
+            SELECT created_at, size, tx_count, block_height, block_hash, version FROM mempool ORDER BY created_at;
+            DROP TABLE mempool;
+            ALTER TABLE mempool_new RENAME TO mempool;
+            CREATE INDEX IF NOT EXISTS idx_mempool_created_at ON mempool(created_at);",
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBurnColumns;
+
+impl Migration for AddBurnColumns {
+    fn id(&self) -> &'static str {
+        "add_burn_columns"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN burned_amount INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN data_outputs INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddCoinAge;
+
+impl Migration for AddCoinAge {
+    fn id(&self) -> &'static str {
+        "add_coin_age"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Ages in blocks of the coins a tx spends, null unless coin age tracking is enabled
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN coin_age_min INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN coin_age_max INTEGER",
+            [],
+        )?;
+        conn.execute("ALTER TABLE transactions ADD COLUMN coin_age_avg REAL", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBumpDelay;
+
+impl Migration for AddBumpDelay {
+    fn id(&self) -> &'static str {
+        "add_bump_delay"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Seconds between the original tx being first seen and its replacement
+        conn.execute("ALTER TABLE rbf ADD COLUMN bump_delay_secs INTEGER", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddArrivalSeq;
+
+impl Migration for AddArrivalSeq {
+    fn id(&self) -> &'static str {
+        "add_arrival_seq"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Order txs arrived in, finer than the second resolution found_at
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN arrival_seq INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_found_at_arrival_seq ON transactions(found_at, arrival_seq)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBatchPayout;
+
+impl Migration for AddBatchPayout {
+    fn id(&self) -> &'static str {
+        "add_batch_payout"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Null for txs stored before batch payouts were tagged
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN is_batch_payout BOOLEAN",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddTxVersion;
+
+impl Migration for AddTxVersion {
+    fn id(&self) -> &'static str {
+        "add_tx_version"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        conn.execute("ALTER TABLE transactions ADD COLUMN tx_version INTEGER", [])?;
+        // tx_data starts with the little endian nVersion, only the standard ones are filled in
+        conn.execute(
+            "UPDATE transactions SET tx_version = CASE substr(tx_data, 1, 8)
+                WHEN '01000000' THEN 1
+                WHEN '02000000' THEN 2
+                WHEN '03000000' THEN 3
+            END
+            WHERE version != 0",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddPriorTxids;
+
+impl Migration for AddPriorTxids {
+    fn id(&self) -> &'static str {
+        "add_prior_txids"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Json array of the txids an RBF overwrote in place, oldest first
+        conn.execute("ALTER TABLE transactions ADD COLUMN prior_txids TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddRbfDeltas;
+
+impl Migration for AddRbfDeltas {
+    fn id(&self) -> &'static str {
+        "add_rbf_deltas"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // The replacement's vsize, and how it changed the replaced version's fee, fee rate and vsize
+        conn.execute("ALTER TABLE rbf ADD COLUMN vsize INTEGER", [])?;
+        conn.execute("ALTER TABLE rbf ADD COLUMN fee_delta INTEGER", [])?;
+        conn.execute("ALTER TABLE rbf ADD COLUMN fee_rate_delta_kwu INTEGER", [])?;
+        conn.execute("ALTER TABLE rbf ADD COLUMN vsize_delta INTEGER", [])?;
+        conn.execute("ALTER TABLE rbf ADD COLUMN rate_down_abs_up BOOLEAN", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddUnconfirmedInputs;
+
+impl Migration for AddUnconfirmedInputs {
+    fn id(&self) -> &'static str {
+        "add_unconfirmed_inputs"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Inputs whose value came from a tracked unconfirmed parent, NULL when not looked up
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN unconfirmed_inputs INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddMinedHeight;
+
+impl Migration for AddMinedHeight {
+    fn id(&self) -> &'static str {
+        "add_mined_height"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Height of the block a tracked tx confirmed in, NULL for txs mined before this column
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN mined_height INTEGER",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_mined_height ON transactions(mined_height)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddFeeAnomaly;
+
+impl Migration for AddFeeAnomaly {
+    fn id(&self) -> &'static str {
+        "add_fee_anomaly"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Set when the fee is above --fee-anomaly-sats, a real fat finger or a bug of ours
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN fee_anomaly BOOLEAN",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddReusedOutputs;
+
+impl Migration for AddReusedOutputs {
+    fn id(&self) -> &'static str {
+        "add_reused_outputs"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Outputs paying a recently seen script, only set with --address-reuse-scripts
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN reused_outputs INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddWtxid;
+
+impl Migration for AddWtxid {
+    fn id(&self) -> &'static str {
+        "add_wtxid"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Witness txid, differs from tx_id for segwit txs. NULL for coinbase rows and txs stored before.
+        conn.execute("ALTER TABLE transactions ADD COLUMN wtxid TEXT", [])?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_wtxid ON transactions(wtxid)",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBlocksSurvived;
+
+impl Migration for AddBlocksSurvived {
+    fn id(&self) -> &'static str {
+        "add_blocks_survived"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Blocks mined between first seen and pruned, set on prune. NULL for txs pruned before.
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN blocks_survived INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddFoundAtHeight;
+
+impl Migration for AddFoundAtHeight {
+    fn id(&self) -> &'static str {
+        "add_found_at_height"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Chain tip when the tx was first stored. NULL before the first mempool state snapshot.
+        conn.execute(
+            "ALTER TABLE transactions ADD COLUMN found_at_height INTEGER",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddBlocksToClear;
+
+impl Migration for AddBlocksToClear {
+    fn id(&self) -> &'static str {
+        "add_blocks_to_clear"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Blocks the tracked txs paying the clearing fee rate would fill. NULL for snapshots taken before.
+        conn.execute(
+            "ALTER TABLE mempool_state ADD COLUMN blocks_to_clear REAL",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct AddRbfTxData;
+
+impl Migration for AddRbfTxData {
+    fn id(&self) -> &'static str {
+        "add_rbf_tx_data"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // The replacement itself, so a head whose txid update never landed can be rebuilt.
+        // NULL for rbfs recorded before.
+        conn.execute("ALTER TABLE rbf ADD COLUMN tx_data TEXT", [])?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct RenameMempoolToMempoolState;
+
+impl Migration for RenameMempoolToMempoolState {
+    fn id(&self) -> &'static str {
+        "rename_mempool_to_mempool_state"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // The table holds state snapshots, not mempool txs, which live in `transactions`.
+        // Ids are copied so the autoincrement sequence continues after the existing rows.
+        // One transaction, so an interrupted copy leaves the mempool table and no record behind.
+        let db_tx = conn.unchecked_transaction()?;
+        db_tx.execute_batch(
+            "CREATE TABLE mempool_state (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at DATETIME NOT NULL,
+                size INTEGER NOT NULL,
+                tx_count INTEGER NOT NULL,
+                block_height INTEGER,
+                block_hash TEXT,
+                version INTEGER NOT NULL
+            );
+            INSERT INTO mempool_state (id, created_at, size, tx_count, block_height, block_hash, version)
+            SELECT id, created_at, size, tx_count, block_height, block_hash, version FROM mempool ORDER BY id;
+            DROP TABLE mempool;
+            CREATE INDEX IF NOT EXISTS idx_mempool_state_created_at ON mempool_state(created_at);",
+        )?;
+
+        let applied_at = now!().to_string();
+        db_tx.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+}
+
+pub(crate) struct BackfillFeeRateKwu;
+
+impl Migration for BackfillFeeRateKwu {
+    fn id(&self) -> &'static str {
+        "backfill_fee_rate_kwu"
+    }
+
+    fn is_backfill(&self) -> bool {
+        true
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Rows from before add_fee_rate_kwu only have the lossy sat/vB fee rate, recompute it
+        // from the exact fee and weight. The stored weight is used rather than re-decoding tx_data
+        // since pruned witnesses would understate it.
+        // Coinbase rows pay no fee.
+        conn.execute(
+            "UPDATE transactions SET fee_rate_kwu = CASE
+                WHEN version = 0 OR weight = 0 THEN 0
+                ELSE absolute_fee * 1000 / weight
+            END
+            WHERE fee_rate_kwu IS NULL",
+            [],
+        )?;
+        // rbf rows share the inputs_hash of the tx row that holds their chain's head, whose weight
+        // is only an approximation of this replacement's when the chain was replaced again
+        conn.execute(
+            "UPDATE rbf SET fee_rate_kwu = (
+                SELECT CASE WHEN t.weight = 0 THEN 0 ELSE rbf.fee_total * 1000 / t.weight END
+                FROM transactions t WHERE t.inputs_hash = rbf.inputs_hash
+            )
+            WHERE fee_rate_kwu IS NULL",
+            [],
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) struct RequireFeeRateKwu;
+
+impl Migration for RequireFeeRateKwu {
+    fn id(&self) -> &'static str {
+        "require_fee_rate_kwu"
+    }
+
+    fn migrate(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // Readers no longer fall back to the lossy sat/vB column, so rows left over by a skipped
+        // backfill_fee_rate_kwu are filled here regardless of `--skip-backfill`.
+        conn.execute(
+            "UPDATE transactions SET fee_rate_kwu = CASE
+                WHEN version = 0 OR weight = 0 THEN 0
+                ELSE absolute_fee * 1000 / weight
+            END
+            WHERE fee_rate_kwu IS NULL",
+            [],
+        )?;
+        // sqlite can't add NOT NULL to an existing column without rebuilding the table
+        conn.execute_batch(
+            "CREATE TRIGGER IF NOT EXISTS transactions_fee_rate_kwu_insert
+            BEFORE INSERT ON transactions WHEN NEW.fee_rate_kwu IS NULL
+            BEGIN SELECT RAISE(ABORT, 'transactions.fee_rate_kwu may not be NULL'); END;
+            CREATE TRIGGER IF NOT EXISTS transactions_fee_rate_kwu_update
+            BEFORE UPDATE OF fee_rate_kwu ON transactions WHEN NEW.fee_rate_kwu IS NULL
+            BEGIN SELECT RAISE(ABORT, 'transactions.fee_rate_kwu may not be NULL'); END;",
+        )?;
+
+        let applied_at = now!().to_string();
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at) VALUES (?1, ?2)",
+            [self.id(), &applied_at],
+        )?;
+        Ok(())
+    }
+}
+
 fn already_applied(conn: &rusqlite::Connection, migration: &str) -> Result<bool> {
     let mut stmt = conn.prepare("SELECT COUNT(*) FROM migrations WHERE id = ?")?;
     let count: i32 = stmt.query_row([migration], |row| row.get(0))?;
@@ -102,11 +827,42 @@ pub(crate) fn run_migrations(conn: &rusqlite::Connection) -> Result<()> {
         Box::new(AddTxNotSeenInMempool),
         Box::new(AddReplacementTxid),
         Box::new(AddIsCpfpParent),
+        Box::new(BackfillTxInputs),
+        Box::new(AddReplacedBy),
+        Box::new(AddFeeRateKwu),
+        Box::new(MempoolBlockFieldsNullable),
+        Box::new(AddSignalsRbf),
+        Box::new(AddTimelockFlags),
+        Box::new(MempoolStateId),
+        Box::new(AddBurnColumns),
+        Box::new(AddCoinAge),
+        Box::new(AddBumpDelay),
+        Box::new(AddArrivalSeq),
+        Box::new(BackfillFeeRateKwu),
+        Box::new(AddBatchPayout),
+        Box::new(AddTxVersion),
+        Box::new(AddPriorTxids),
+        Box::new(AddRbfDeltas),
+        Box::new(AddUnconfirmedInputs),
+        Box::new(AddMinedHeight),
+        Box::new(AddFeeAnomaly),
+        Box::new(RenameMempoolToMempoolState),
+        Box::new(AddReusedOutputs),
+        Box::new(AddWtxid),
+        Box::new(AddBlocksSurvived),
+        Box::new(AddFoundAtHeight),
+        Box::new(AddBlocksToClear),
+        Box::new(AddRbfTxData),
+        Box::new(RequireFeeRateKwu),
     ];
     for migration in migrations {
         if already_applied(conn, migration.id())? {
             continue;
         }
+        if migration.is_backfill() && skip_backfill() {
+            info!("Skipping backfill migration {}", migration.id());
+            continue;
+        }
         migration.migrate(conn)?;
     }
     Ok(())
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+use bitcoin::{consensus::encode::serialize, p2p::Magic, Network, ScriptBuf};
+use bitcoin_hashes::Sha256d;
+use log::warn;
+use serde_json::Value;
+
+/// The chain the node is expected to be on, checked against its `getblockchaininfo` on startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    pub network: Network,
+    /// Challenge script of a custom signet, `None` for the default signet
+    pub signet_challenge: Option<ScriptBuf>,
+}
+
+impl ChainParams {
+    pub fn new(network: Network, signet_challenge: Option<ScriptBuf>) -> Result<Self> {
+        if signet_challenge.is_some() && network != Network::Signet {
+            return Err(anyhow!(
+                "A signet challenge needs the signet network, not {}",
+                network
+            ));
+        }
+        Ok(Self {
+            network,
+            signet_challenge,
+        })
+    }
+
+    /// P2P message start, custom signets derive theirs from the challenge
+    pub fn magic(&self) -> Magic {
+        match &self.signet_challenge {
+            Some(challenge) => signet_magic(challenge),
+            None => self.network.magic(),
+        }
+    }
+
+    /// Fail when the node reports another chain than these params describe
+    pub fn check(&self, blockchain_info: &Value) -> Result<()> {
+        let chain = blockchain_info["chain"]
+            .as_str()
+            .ok_or(anyhow!("getblockchaininfo is missing chain"))?;
+        if chain != self.network.to_core_arg() {
+            return Err(anyhow!(
+                "Node is on chain {}, expected {}",
+                chain,
+                self.network.to_core_arg()
+            ));
+        }
+        let Some(challenge) = &self.signet_challenge else {
+            return Ok(());
+        };
+        // Older nodes don't report the challenge
+        match blockchain_info["signet_challenge"].as_str() {
+            Some(node_challenge) if node_challenge != challenge.to_hex_string() => Err(anyhow!(
+                "Node is on a signet with challenge {}, expected {}",
+                node_challenge,
+                challenge.to_hex_string()
+            )),
+            Some(_) => Ok(()),
+            None => {
+                warn!("Node doesn't report its signet challenge, it can't be checked");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// First 4 bytes of the double sha256 of the serialized challenge, as bitcoind derives it
+fn signet_magic(challenge: &ScriptBuf) -> Magic {
+    let hash = Sha256d::hash(&serialize(challenge));
+    let mut magic = [0; 4];
+    magic.copy_from_slice(&hash.as_byte_array()[..4]);
+    Magic::from_bytes(magic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // The default signet's 1-of-2 multisig challenge
+    const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+    #[test]
+    fn test_signet_magic() -> Result<()> {
+        let challenge = ScriptBuf::from_hex(DEFAULT_SIGNET_CHALLENGE)?;
+        let params = ChainParams::new(Network::Signet, Some(challenge))?;
+        assert_eq!(params.magic(), Network::Signet.magic());
+
+        let custom = ChainParams::new(Network::Signet, Some(ScriptBuf::from_hex("51")?))?;
+        assert_ne!(custom.magic(), Network::Signet.magic());
+
+        assert!(ChainParams::new(Network::Regtest, Some(ScriptBuf::from_hex("51")?)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_chain() -> Result<()> {
+        let mainnet = ChainParams::new(Network::Bitcoin, None)?;
+        assert!(mainnet.check(&json!({ "chain": "main" })).is_ok());
+        assert!(mainnet.check(&json!({ "chain": "signet" })).is_err());
+        assert!(mainnet.check(&json!({})).is_err());
+
+        let custom = ChainParams::new(Network::Signet, Some(ScriptBuf::from_hex("51")?))?;
+        assert!(custom
+            .check(&json!({ "chain": "signet", "signet_challenge": "51" }))
+            .is_ok());
+        assert!(custom
+            .check(&json!({ "chain": "signet", "signet_challenge": DEFAULT_SIGNET_CHALLENGE }))
+            .is_err());
+        // Unverifiable on nodes that don't report it
+        assert!(custom.check(&json!({ "chain": "signet" })).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::rpc::RawRpcClient;
+
+/// The node's connectivity when the mempool was observed, a poorly connected node sees a skewed mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub connections: u64,
+    /// Only reported by nodes since v0.21
+    pub connections_in: Option<u64>,
+    pub connections_out: Option<u64>,
+    /// Peers by the p2p protocol version they advertise
+    pub protocol_versions: BTreeMap<i64, u64>,
+}
+
+impl NodeInfo {
+    /// Query `getnetworkinfo` and `getpeerinfo`, which the async client doesn't expose
+    pub async fn fetch(rpc: &RawRpcClient) -> Result<Self> {
+        let network_info = rpc.result("getnetworkinfo", json!([])).await?;
+        let peer_info = rpc.result("getpeerinfo", json!([])).await?;
+        Self::from_results(&network_info, &peer_info)
+    }
+
+    fn from_results(network_info: &Value, peer_info: &Value) -> Result<Self> {
+        let connections = network_info["connections"]
+            .as_u64()
+            .ok_or(anyhow::anyhow!("getnetworkinfo is missing connections"))?;
+        let peers = peer_info
+            .as_array()
+            .ok_or(anyhow::anyhow!("getpeerinfo did not return a list"))?;
+        let mut protocol_versions = BTreeMap::new();
+        for peer in peers {
+            if let Some(version) = peer["version"].as_i64() {
+                *protocol_versions.entry(version).or_default() += 1;
+            }
+        }
+        Ok(Self {
+            connections,
+            connections_in: network_info["connections_in"].as_u64(),
+            connections_out: network_info["connections_out"].as_u64(),
+            protocol_versions,
+        })
+    }
+
+    /// Protocol version counts as a json object, the way `node_info` stores them
+    pub fn protocol_versions_json(&self) -> String {
+        let versions = self
+            .protocol_versions
+            .iter()
+            .map(|(version, count)| (version.to_string(), json!(count)))
+            .collect::<serde_json::Map<_, _>>();
+        Value::Object(versions).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_info_from_results() -> Result<()> {
+        let network_info = json!({ "connections": 3, "connections_in": 1, "connections_out": 2 });
+        let peer_info = json!([{ "version": 70016 }, { "version": 70016 }, { "version": 70015 }]);
+        let node_info = NodeInfo::from_results(&network_info, &peer_info)?;
+        assert_eq!(node_info.connections, 3);
+        assert_eq!(node_info.connections_in, Some(1));
+        assert_eq!(
+            node_info.protocol_versions_json(),
+            r#"{"70015":1,"70016":2}"#
+        );
+
+        assert!(NodeInfo::from_results(&json!({}), &peer_info).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,1853 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use bitcoin::{
+    consensus::encode::{deserialize_hex, serialize_hex},
+    Amount, BlockHash, FeeRate, OutPoint, Transaction, Txid,
+};
+use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use log::{error, info};
+use std::str::FromStr;
+use tokio_postgres::{Config, NoTls, Transaction as PgTransaction};
+
+use crate::{
+    database::{
+        prevout_values, size_bucket_sql, AuditReport, DeferredTx, FeerateTarget, RbfDelta,
+        RowVersions, BLOCK_MAX_VSIZE, FEERATE_TARGET_BUCKET_SECS,
+    },
+    db_value::DbValue,
+    migrations::skip_backfill,
+    node_info::NodeInfo,
+    now,
+    snapshot_file::FEE_RATE_PERCENTILES,
+    store::Store,
+    utils::{
+        burned_amount, data_output_count, fee_rate_percentile, fee_rate_sat_per_vb,
+        first_seen_hash, get_inputs_hash, is_final, is_truc, signals_rbf, truc_respects_topology,
+        uses_relative_timelock, FIRST_SEEN_GENESIS_HASH,
+    },
+};
+
+// Same versioning as the sqlite backend
+const MEMPOOL_TRANSACTION_VERSION: i32 = 1;
+const RBF_TRANSACTION_VERSION: i32 = 1;
+const COINBASE_TRANSACTION_VERSION: i32 = 0;
+const MEMPOOL_STATE_VERSION: i32 = 1;
+
+/// Postgres migrations, applied in order. Mirrors the logical schema of the sqlite backend,
+/// the initial schema includes every sqlite migration that predates the postgres backend.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "initial_schema",
+        "CREATE TABLE IF NOT EXISTS transactions (
+        inputs_hash TEXT PRIMARY KEY,
+        tx_id TEXT NOT NULL,
+        tx_data TEXT NOT NULL,
+        found_at BIGINT NOT NULL,
+        mined_at BIGINT,
+        pruned_at BIGINT,
+        parent_txid TEXT,
+        child_txid TEXT,
+        seen_in_mempool BOOLEAN NOT NULL DEFAULT TRUE,
+        absolute_fee BIGINT NOT NULL,
+        fee_rate DOUBLE PRECISION NOT NULL,
+        size BIGINT NOT NULL,
+        weight BIGINT NOT NULL,
+        version INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_transactions_tx_id ON transactions(tx_id);
+    CREATE TABLE IF NOT EXISTS rbf (
+        inputs_hash TEXT PRIMARY KEY,
+        created_at BIGINT NOT NULL,
+        fee_total BIGINT NOT NULL,
+        replaces TEXT,
+        version INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS mempool (
+        id BIGSERIAL PRIMARY KEY,
+        created_at BIGINT NOT NULL,
+        size BIGINT NOT NULL,
+        tx_count BIGINT NOT NULL,
+        block_height BIGINT NOT NULL,
+        block_hash TEXT NOT NULL,
+        version INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS mining_info (
+        created_at BIGINT NOT NULL,
+        hash_rate_distribution TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS conflicts (
+        tx_id TEXT NOT NULL,
+        conflicting_txid TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        PRIMARY KEY (tx_id, conflicting_txid)
+    );
+    CREATE TABLE IF NOT EXISTS tx_inputs (
+        txid TEXT NOT NULL,
+        prev_txid TEXT NOT NULL,
+        prev_vout BIGINT NOT NULL,
+        PRIMARY KEY (txid, prev_txid, prev_vout)
+    );
+    CREATE INDEX IF NOT EXISTS idx_tx_inputs_outpoint ON tx_inputs(prev_txid, prev_vout);",
+    ),
+    (
+        "add_replaced_by",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS replaced_by TEXT",
+    ),
+    (
+        "add_fee_rate_kwu",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS fee_rate_kwu BIGINT;
+        ALTER TABLE rbf ADD COLUMN IF NOT EXISTS fee_rate_kwu BIGINT;",
+    ),
+    (
+        "add_tx_history",
+        "CREATE TABLE IF NOT EXISTS tx_history (
+            id BIGSERIAL PRIMARY KEY,
+            inputs_hash TEXT NOT NULL,
+            tx_id TEXT NOT NULL,
+            tx_data TEXT NOT NULL,
+            found_at BIGINT NOT NULL,
+            absolute_fee BIGINT NOT NULL,
+            fee_rate_kwu BIGINT NOT NULL,
+            archived_at BIGINT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_tx_history_inputs_hash ON tx_history(inputs_hash);",
+    ),
+    (
+        "add_oversized_txs",
+        "CREATE TABLE IF NOT EXISTS oversized_txs (
+            tx_id TEXT PRIMARY KEY,
+            found_at BIGINT NOT NULL,
+            size BIGINT NOT NULL,
+            weight BIGINT NOT NULL
+        );",
+    ),
+    (
+        "mempool_block_fields_nullable",
+        "ALTER TABLE mempool ALTER COLUMN block_height DROP NOT NULL;
+        ALTER TABLE mempool ALTER COLUMN block_hash DROP NOT NULL;",
+    ),
+    (
+        "add_signals_rbf",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS signals_rbf BOOLEAN;",
+    ),
+    (
+        "add_timelock_flags",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS lock_time BIGINT;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS uses_csv BOOLEAN;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS is_final BOOLEAN;",
+    ),
+    (
+        "mempool_state_id",
+        "CREATE INDEX IF NOT EXISTS idx_mempool_created_at ON mempool(created_at);",
+    ),
+    (
+        "add_first_seen_log",
+        "CREATE TABLE IF NOT EXISTS first_seen_log (
+            seq BIGSERIAL PRIMARY KEY,
+            tx_id TEXT NOT NULL UNIQUE,
+            found_at BIGINT NOT NULL,
+            prev_hash TEXT NOT NULL,
+            hash TEXT NOT NULL
+        );",
+    ),
+    (
+        "add_burn_columns",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS burned_amount BIGINT;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS data_outputs BIGINT;",
+    ),
+    (
+        "add_coin_age",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS coin_age_min BIGINT;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS coin_age_max BIGINT;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS coin_age_avg DOUBLE PRECISION;",
+    ),
+    (
+        "add_filtered_txs",
+        "CREATE TABLE IF NOT EXISTS filtered_txs (
+            reason TEXT PRIMARY KEY,
+            count BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_bump_delay",
+        "ALTER TABLE rbf ADD COLUMN IF NOT EXISTS bump_delay_secs BIGINT;",
+    ),
+    (
+        "add_arrival_seq",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS arrival_seq BIGINT;
+        CREATE INDEX IF NOT EXISTS idx_transactions_found_at_arrival_seq ON transactions(found_at, arrival_seq);",
+    ),
+    (
+        "add_blocks",
+        "CREATE TABLE IF NOT EXISTS blocks (
+            height BIGINT PRIMARY KEY,
+            clearing_fee_rate_kwu BIGINT NOT NULL,
+            tx_count BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_fee_discrepancies",
+        "CREATE TABLE IF NOT EXISTS fee_discrepancies (
+            tx_id TEXT PRIMARY KEY,
+            found_at BIGINT NOT NULL,
+            computed_fee BIGINT NOT NULL,
+            node_fee BIGINT NOT NULL
+        );",
+    ),
+    (
+        "backfill_fee_rate_kwu",
+        "UPDATE transactions SET fee_rate_kwu = CASE
+            WHEN version = 0 OR weight = 0 THEN 0
+            ELSE absolute_fee * 1000 / weight
+        END
+        WHERE fee_rate_kwu IS NULL;
+        UPDATE rbf SET fee_rate_kwu = (
+            SELECT CASE WHEN t.weight = 0 THEN 0 ELSE rbf.fee_total * 1000 / t.weight END
+            FROM transactions t WHERE t.inputs_hash = rbf.inputs_hash
+        )
+        WHERE fee_rate_kwu IS NULL;",
+    ),
+    (
+        "add_batch_payout",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS is_batch_payout BOOLEAN;",
+    ),
+    (
+        "add_tx_version",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS tx_version INTEGER;
+        UPDATE transactions SET tx_version = CASE substr(tx_data, 1, 8)
+            WHEN '01000000' THEN 1
+            WHEN '02000000' THEN 2
+            WHEN '03000000' THEN 3
+        END
+        WHERE version != 0;
+        CREATE TABLE IF NOT EXISTS tx_version_counts (
+            created_at BIGINT NOT NULL,
+            tx_version INTEGER NOT NULL,
+            count BIGINT NOT NULL,
+            PRIMARY KEY (created_at, tx_version)
+        );",
+    ),
+    (
+        "add_truc_txs",
+        "CREATE TABLE IF NOT EXISTS truc_txs (
+            tx_id TEXT PRIMARY KEY,
+            found_at BIGINT NOT NULL,
+            unconfirmed_parents BIGINT NOT NULL,
+            unconfirmed_children BIGINT NOT NULL,
+            respects_topology BOOLEAN NOT NULL
+        );",
+    ),
+    (
+        "add_node_info",
+        "CREATE TABLE IF NOT EXISTS node_info (
+            created_at BIGINT PRIMARY KEY,
+            connections BIGINT NOT NULL,
+            connections_in BIGINT,
+            connections_out BIGINT,
+            protocol_versions TEXT NOT NULL
+        );",
+    ),
+    (
+        "add_watched_txs",
+        "CREATE TABLE IF NOT EXISTS watched_txs (
+            tx_id TEXT NOT NULL,
+            vout BIGINT NOT NULL,
+            value BIGINT NOT NULL,
+            found_at BIGINT NOT NULL,
+            PRIMARY KEY (tx_id, vout)
+        );",
+    ),
+    (
+        "add_time_drift",
+        "CREATE TABLE IF NOT EXISTS time_drift (
+            tx_id TEXT PRIMARY KEY,
+            found_at BIGINT NOT NULL,
+            node_time BIGINT NOT NULL,
+            drift_secs BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_prior_txids",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS prior_txids TEXT;",
+    ),
+    (
+        "add_feerate_targets",
+        "CREATE TABLE IF NOT EXISTS feerate_targets (
+            bucket_start BIGINT PRIMARY KEY,
+            block_height BIGINT NOT NULL,
+            next_block_kwu BIGINT,
+            next_3_blocks_kwu BIGINT,
+            next_6_blocks_kwu BIGINT,
+            updated_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_rbf_deltas",
+        "ALTER TABLE rbf ADD COLUMN IF NOT EXISTS vsize BIGINT;
+        ALTER TABLE rbf ADD COLUMN IF NOT EXISTS fee_delta BIGINT;
+        ALTER TABLE rbf ADD COLUMN IF NOT EXISTS fee_rate_delta_kwu BIGINT;
+        ALTER TABLE rbf ADD COLUMN IF NOT EXISTS vsize_delta BIGINT;
+        ALTER TABLE rbf ADD COLUMN IF NOT EXISTS rate_down_abs_up BOOLEAN;",
+    ),
+    (
+        "add_unconfirmed_inputs",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS unconfirmed_inputs BIGINT;",
+    ),
+    (
+        "add_size_bucket_counts",
+        "CREATE TABLE IF NOT EXISTS size_bucket_counts (
+            created_at BIGINT NOT NULL,
+            min_vsize BIGINT NOT NULL,
+            tx_count BIGINT NOT NULL,
+            total_vsize BIGINT NOT NULL,
+            PRIMARY KEY (created_at, min_vsize)
+        );",
+    ),
+    (
+        "add_meta",
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_nonstandard_txs",
+        "CREATE TABLE IF NOT EXISTS nonstandard_txs (
+            tx_id TEXT PRIMARY KEY,
+            reason TEXT NOT NULL,
+            found_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_mined_height",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS mined_height BIGINT;
+        CREATE INDEX IF NOT EXISTS idx_transactions_mined_height ON transactions(mined_height);",
+    ),
+    (
+        "add_fee_anomaly",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS fee_anomaly BOOLEAN;",
+    ),
+    (
+        "add_deferred_fee_txs",
+        "CREATE TABLE IF NOT EXISTS deferred_fee_txs (
+            tx_id TEXT PRIMARY KEY,
+            tx_data TEXT NOT NULL,
+            found_at BIGINT NOT NULL,
+            arrival_seq BIGINT NOT NULL,
+            retries BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_fee_rate_percentiles",
+        "CREATE TABLE IF NOT EXISTS fee_rate_percentiles (
+            created_at BIGINT NOT NULL,
+            percentile BIGINT NOT NULL,
+            fee_rate_kwu BIGINT NOT NULL,
+            PRIMARY KEY (created_at, percentile)
+        );",
+    ),
+    (
+        "rename_mempool_to_mempool_state",
+        "ALTER TABLE mempool RENAME TO mempool_state;
+        CREATE INDEX IF NOT EXISTS idx_mempool_state_created_at ON mempool_state(created_at);",
+    ),
+    (
+        "add_reused_outputs",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS reused_outputs BIGINT;
+        CREATE TABLE IF NOT EXISTS address_reuse_counts (
+            created_at BIGINT PRIMARY KEY,
+            tx_count BIGINT NOT NULL,
+            reused_outputs BIGINT NOT NULL
+        );",
+    ),
+    (
+        "add_wtxid",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS wtxid TEXT;
+        CREATE INDEX IF NOT EXISTS idx_transactions_wtxid ON transactions(wtxid);",
+    ),
+    (
+        "add_blocks_survived",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS blocks_survived BIGINT;",
+    ),
+    (
+        "add_found_at_height",
+        "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS found_at_height BIGINT;",
+    ),
+    (
+        "add_blocks_to_clear",
+        "ALTER TABLE mempool_state ADD COLUMN IF NOT EXISTS blocks_to_clear DOUBLE PRECISION;",
+    ),
+    (
+        "add_rbf_tx_data",
+        "ALTER TABLE rbf ADD COLUMN IF NOT EXISTS tx_data TEXT;",
+    ),
+    (
+        // Fills rows a skipped backfill_fee_rate_kwu left behind, readers don't fall back to fee_rate
+        "require_fee_rate_kwu",
+        "UPDATE transactions SET fee_rate_kwu = CASE
+            WHEN version = 0 OR weight = 0 THEN 0
+            ELSE absolute_fee * 1000 / weight
+        END
+        WHERE fee_rate_kwu IS NULL;
+        ALTER TABLE transactions ALTER COLUMN fee_rate_kwu SET NOT NULL;",
+    ),
+];
+
+/// Postgres migrations that only rewrite existing rows, skipped with `--skip-backfill`
+const BACKFILL_MIGRATIONS: [&str; 1] = ["backfill_fee_rate_kwu"];
+
+/// Postgres backed [`Store`], selected with a `postgres://` db url
+#[derive(Clone)]
+pub struct PgStore {
+    // Each worker checks out its own connection
+    pool: Pool,
+    /// Stored as the `found_at_height` of new txs, shared by every clone
+    tip_height: Arc<Mutex<Option<u64>>>,
+}
+
+impl PgStore {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::with_config(url.parse()?).await
+    }
+
+    pub async fn with_config(config: Config) -> Result<Self> {
+        let manager = Manager::from_config(
+            config,
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let pool = Pool::builder(manager).build()?;
+        // Fail on startup rather than on the first write
+        drop(pool.get().await?);
+        Ok(Self {
+            pool,
+            tip_height: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn tip_height(&self) -> Option<u64> {
+        *self.tip_height.lock().expect("tip height lock poisoned")
+    }
+
+    async fn client(&self) -> Result<Object> {
+        Ok(self.pool.get().await?)
+    }
+
+    async fn tx_exists_by_inputs_hash(&self, inputs_hash: &str) -> Result<bool> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                "SELECT COUNT(*) FROM transactions WHERE inputs_hash = $1",
+                &[&inputs_hash],
+            )
+            .await?;
+        Ok(row.get::<_, i64>(0) > 0)
+    }
+}
+
+async fn insert_tx(
+    db_tx: &PgTransaction<'_>,
+    tx: Transaction,
+    found_at: Option<u64>,
+    absolute_fee: Amount,
+    fee_rate: FeeRate,
+    found_at_height: Option<u64>,
+) -> Result<()> {
+    let inputs_hash = get_inputs_hash(tx.clone().input)?;
+    let tx_str = serialize_hex(&tx);
+    let tx_id = tx.compute_txid().to_string();
+    let found_at = found_at.unwrap_or(now!()) as i64;
+    let mut parent_txid = None;
+
+    for input in tx.input.iter() {
+        let maybe_parent_txid = input.previous_output.txid.to_string();
+        let row = db_tx
+            .query_one(
+                "SELECT COUNT(*) FROM transactions WHERE tx_id = $1 AND mined_at IS NULL AND pruned_at IS NULL",
+                &[&maybe_parent_txid],
+            )
+            .await?;
+        if row.get::<_, i64>(0) > 0 {
+            db_tx
+                .execute(
+                    "UPDATE transactions SET child_txid = $1 WHERE tx_id = $2",
+                    &[&tx_id, &maybe_parent_txid],
+                )
+                .await?;
+            parent_txid = Some(maybe_parent_txid);
+        }
+    }
+
+    let mut conflicting_txids = vec![];
+    for input in tx.input.iter() {
+        let rows = db_tx
+            .query(
+                "SELECT DISTINCT t.tx_id FROM tx_inputs i
+                JOIN transactions t ON t.tx_id = i.txid
+                WHERE i.prev_txid = $1 AND i.prev_vout = $2
+                AND t.mined_at IS NULL AND t.pruned_at IS NULL AND t.inputs_hash != $3",
+                &[
+                    &input.previous_output.txid.to_string(),
+                    &(input.previous_output.vout as i64),
+                    &inputs_hash,
+                ],
+            )
+            .await?;
+        for row in rows {
+            let conflicting_txid: String = row.get(0);
+            if !conflicting_txids.contains(&conflicting_txid) {
+                conflicting_txids.push(conflicting_txid);
+            }
+        }
+    }
+
+    // Keep the version about to be overwritten
+    archive_tx_version(db_tx, &inputs_hash, &tx_id).await?;
+
+    // Mirror sqlite's INSERT OR REPLACE, which resets every column not inserted
+    db_tx
+        .execute(
+            "INSERT INTO transactions
+            (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate, fee_rate_kwu, size, weight, version, parent_txid, signals_rbf,
+            lock_time, uses_csv, is_final, burned_amount, data_outputs, tx_version, wtxid, found_at_height)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+            ON CONFLICT (inputs_hash) DO UPDATE SET
+            tx_id = EXCLUDED.tx_id, tx_data = EXCLUDED.tx_data, found_at = EXCLUDED.found_at,
+            absolute_fee = EXCLUDED.absolute_fee, fee_rate = EXCLUDED.fee_rate,
+            fee_rate_kwu = EXCLUDED.fee_rate_kwu, size = EXCLUDED.size,
+            weight = EXCLUDED.weight, version = EXCLUDED.version, parent_txid = EXCLUDED.parent_txid,
+            signals_rbf = EXCLUDED.signals_rbf, lock_time = EXCLUDED.lock_time,
+            uses_csv = EXCLUDED.uses_csv, is_final = EXCLUDED.is_final,
+            burned_amount = EXCLUDED.burned_amount, data_outputs = EXCLUDED.data_outputs,
+            tx_version = EXCLUDED.tx_version, wtxid = EXCLUDED.wtxid,
+            found_at_height = EXCLUDED.found_at_height, mined_at = NULL, pruned_at = NULL, child_txid = NULL, seen_in_mempool = TRUE,
+            replaced_by = NULL",
+            &[
+                &inputs_hash,
+                &tx_id,
+                &tx_str,
+                &found_at,
+                &absolute_fee.to_db_i64(),
+                &fee_rate_sat_per_vb(fee_rate),
+                &fee_rate.to_db_i64(),
+                &(tx.total_size() as i64),
+                &(tx.weight().to_wu() as i64),
+                &MEMPOOL_TRANSACTION_VERSION,
+                &parent_txid,
+                &signals_rbf(&tx),
+                &(tx.lock_time.to_consensus_u32() as i64),
+                &uses_relative_timelock(&tx),
+                &is_final(&tx),
+                &burned_amount(&tx).to_db_i64(),
+                &(data_output_count(&tx) as i64),
+                &tx.version.0,
+                &tx.compute_wtxid().to_string(),
+                &found_at_height.map(|height| height as i64),
+            ],
+        )
+        .await?;
+
+    if !tx.is_coinbase() {
+        for input in tx.input.iter() {
+            db_tx
+                .execute(
+                    "INSERT INTO tx_inputs (txid, prev_txid, prev_vout) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                    &[
+                        &tx_id,
+                        &input.previous_output.txid.to_string(),
+                        &(input.previous_output.vout as i64),
+                    ],
+                )
+                .await?;
+        }
+    }
+    record_first_seen(db_tx, &tx_id, found_at).await?;
+
+    for conflicting_txid in conflicting_txids {
+        info!(
+            "Transaction {} conflicts with tracked tx {}",
+            tx_id, conflicting_txid
+        );
+        db_tx
+            .execute(
+                "INSERT INTO conflicts (tx_id, conflicting_txid, created_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                &[&tx_id, &conflicting_txid, &(now!() as i64)],
+            )
+            .await?;
+        db_tx
+            .execute(
+                "UPDATE transactions SET replaced_by = $1 WHERE tx_id = $2",
+                &[&tx_id, &conflicting_txid],
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Copy the row keyed by `inputs_hash` into tx_history before it is overwritten by `new_txid`
+async fn archive_tx_version(
+    db_tx: &PgTransaction<'_>,
+    inputs_hash: &str,
+    new_txid: &str,
+) -> Result<()> {
+    db_tx
+        .execute(
+            "INSERT INTO tx_history
+            (inputs_hash, tx_id, tx_data, found_at, absolute_fee, fee_rate_kwu, archived_at)
+            SELECT inputs_hash, tx_id, tx_data, found_at, absolute_fee,
+            fee_rate_kwu, $1
+            FROM transactions WHERE inputs_hash = $2 AND tx_id != $3",
+            &[&(now!() as i64), &inputs_hash, &new_txid],
+        )
+        .await?;
+    Ok(())
+}
+
+/// See the sqlite `replace_tx_version`
+async fn replace_tx_version(
+    db_tx: &PgTransaction<'_>,
+    tx: &Transaction,
+    absolute_fee: Amount,
+    fee_rate: FeeRate,
+) -> Result<()> {
+    let inputs_hash = get_inputs_hash(tx.clone().input)?;
+    let tx_id = tx.compute_txid().to_string();
+    archive_tx_version(db_tx, &inputs_hash, &tx_id).await?;
+    db_tx
+        .execute(
+            "UPDATE tx_inputs SET txid = $1 WHERE txid = (SELECT tx_id FROM transactions WHERE inputs_hash = $2)",
+            &[&tx_id, &inputs_hash],
+        )
+        .await?;
+    db_tx
+        .execute(
+            "UPDATE transactions SET prior_txids = (COALESCE(prior_txids, '[]')::jsonb || to_jsonb(tx_id))::text,
+            tx_id = $1, tx_data = $3, absolute_fee = $4, fee_rate = $5, fee_rate_kwu = $6, size = $7,
+            weight = $8, signals_rbf = $9, lock_time = $10, uses_csv = $11, is_final = $12,
+            burned_amount = $13, data_outputs = $14, tx_version = $15, wtxid = $16
+            WHERE inputs_hash = $2 AND tx_id != $1",
+            &[
+                &tx_id,
+                &inputs_hash,
+                &serialize_hex(tx),
+                &absolute_fee.to_db_i64(),
+                &fee_rate_sat_per_vb(fee_rate),
+                &fee_rate.to_db_i64(),
+                &(tx.total_size() as i64),
+                &(tx.weight().to_wu() as i64),
+                &signals_rbf(tx),
+                &(tx.lock_time.to_consensus_u32() as i64),
+                &uses_relative_timelock(tx),
+                &is_final(tx),
+                &burned_amount(tx).to_db_i64(),
+                &(data_output_count(tx) as i64),
+                &tx.version.0,
+                &tx.compute_wtxid().to_string(),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Append a txid to the first-seen log, unless it was already seen
+async fn record_first_seen(db_tx: &PgTransaction<'_>, tx_id: &str, found_at: i64) -> Result<()> {
+    // Held until commit so concurrent connections can't chain off the same record
+    db_tx
+        .batch_execute("LOCK TABLE first_seen_log IN SHARE ROW EXCLUSIVE MODE")
+        .await?;
+    let row = db_tx
+        .query_one(
+            "SELECT COUNT(*) FROM first_seen_log WHERE tx_id = $1",
+            &[&tx_id],
+        )
+        .await?;
+    if row.get::<_, i64>(0) > 0 {
+        return Ok(());
+    }
+    let prev_hash = db_tx
+        .query_opt(
+            "SELECT hash FROM first_seen_log ORDER BY seq DESC LIMIT 1",
+            &[],
+        )
+        .await?
+        .map(|row| row.get::<_, String>(0))
+        .unwrap_or(FIRST_SEEN_GENESIS_HASH.to_string());
+    let hash = first_seen_hash(&prev_hash, tx_id, found_at as u64);
+    db_tx
+        .execute(
+            "INSERT INTO first_seen_log (tx_id, found_at, prev_hash, hash) VALUES ($1, $2, $3, $4)",
+            &[&tx_id, &found_at, &prev_hash, &hash],
+        )
+        .await?;
+    Ok(())
+}
+
+impl Store for PgStore {
+    async fn run_migrations(&self) -> Result<()> {
+        let mut client = self.client().await?;
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS migrations (
+                    id TEXT PRIMARY KEY,
+                    applied_at BIGINT NOT NULL
+                )",
+            )
+            .await?;
+        for (id, sql) in MIGRATIONS {
+            let row = client
+                .query_one("SELECT COUNT(*) FROM migrations WHERE id = $1", &[id])
+                .await?;
+            if row.get::<_, i64>(0) > 0 {
+                continue;
+            }
+            if BACKFILL_MIGRATIONS.contains(id) && skip_backfill() {
+                info!("Skipping postgres backfill migration {}", id);
+                continue;
+            }
+            info!("Applying postgres migration {}", id);
+            // A failed migration leaves neither its changes nor its record behind
+            let db_tx = client.transaction().await?;
+            db_tx.batch_execute(sql).await?;
+            db_tx
+                .execute(
+                    "INSERT INTO migrations (id, applied_at) VALUES ($1, $2)",
+                    &[id, &(now!() as i64)],
+                )
+                .await?;
+            db_tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        // Every write commits before it returns
+        Ok(())
+    }
+
+    async fn remove_stale_txs(&self, live_txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        let live_txids = live_txids
+            .iter()
+            .map(|txid| txid.to_string())
+            .collect::<Vec<_>>();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "DELETE FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                AND NOT (tx_id = ANY($1)) RETURNING tx_id",
+                &[&live_txids],
+            )
+            .await?;
+        info!("Removed {} stale txs", rows.len());
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT tx_id FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+                &[],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| Ok(Txid::from_str(row.get(0))?))
+            .collect()
+    }
+
+    fn set_tip_height(&self, height: Option<u64>) {
+        *self.tip_height.lock().expect("tip height lock poisoned") = height;
+    }
+
+    async fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let found_at_height = self.tip_height();
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        insert_tx(
+            &db_tx,
+            tx,
+            found_at,
+            absolute_fee,
+            fee_rate,
+            found_at_height,
+        )
+        .await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_mempool_txs(
+        &self,
+        txs: Vec<(Transaction, Option<u64>, Amount, FeeRate)>,
+    ) -> Result<usize> {
+        let found_at_height = self.tip_height();
+        let mut client = self.client().await?;
+        let mut db_tx = client.transaction().await?;
+        let mut inserted = 0;
+        // One transaction for the batch, a savepoint per tx so a failing one is rolled back alone
+        for (tx, found_at, absolute_fee, fee_rate) in txs {
+            let txid = tx.compute_txid();
+            let savepoint = db_tx.savepoint("insert_tx").await?;
+            let stored = insert_tx(
+                &savepoint,
+                tx,
+                found_at,
+                absolute_fee,
+                fee_rate,
+                found_at_height,
+            )
+            .await;
+            match stored {
+                Ok(()) => {
+                    savepoint.commit().await?;
+                    inserted += 1;
+                }
+                Err(e) => {
+                    error!("Error inserting tx {}: {}", txid, e);
+                    savepoint.rollback().await?;
+                }
+            }
+        }
+        db_tx.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn record_coin_ages(&self, tx: &Transaction, coin_ages: Vec<u64>) -> Result<()> {
+        if coin_ages.is_empty() {
+            return Ok(());
+        }
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let avg = coin_ages.iter().sum::<u64>() as f64 / coin_ages.len() as f64;
+        self
+            .client()
+            .await?
+            .execute(
+                "UPDATE transactions SET coin_age_min = $1, coin_age_max = $2, coin_age_avg = $3 WHERE inputs_hash = $4",
+                &[
+                    &coin_ages.iter().min().map(|age| *age as i64),
+                    &coin_ages.iter().max().map(|age| *age as i64),
+                    &avg,
+                    &inputs_hash,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_batch_payouts(&self, txs: Vec<(Txid, bool)>) -> Result<()> {
+        let (txids, is_batch_payouts): (Vec<String>, Vec<bool>) = txs
+            .into_iter()
+            .map(|(txid, is_batch_payout)| (txid.to_string(), is_batch_payout))
+            .unzip();
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions t SET is_batch_payout = s.is_batch_payout
+                FROM UNNEST($1::TEXT[], $2::BOOLEAN[]) AS s(tx_id, is_batch_payout)
+                WHERE t.tx_id = s.tx_id",
+                &[&txids, &is_batch_payouts],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_arrival_seq(&self, txid: Txid, arrival_seq: u64) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET arrival_seq = $1 WHERE tx_id = $2",
+                &[&(arrival_seq as i64), &txid.to_string()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn tracked_prevouts(
+        &self,
+        outpoints: Vec<OutPoint>,
+    ) -> Result<HashMap<OutPoint, Amount>> {
+        let txids = outpoints
+            .iter()
+            .map(|outpoint| outpoint.txid.to_string())
+            .collect::<Vec<_>>();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT tx_id, tx_data FROM transactions WHERE tx_id = ANY($1)
+                AND mined_at IS NULL AND pruned_at IS NULL AND replaced_by IS NULL",
+                &[&txids],
+            )
+            .await?;
+        let parents = rows
+            .iter()
+            .map(|row| Ok((Txid::from_str(row.get(0))?, row.get::<_, String>(1))))
+            .collect::<Result<Vec<_>>>()?;
+        prevout_values(parents, &outpoints)
+    }
+
+    async fn record_unconfirmed_inputs(&self, txid: Txid, count: usize) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET unconfirmed_inputs = $1 WHERE tx_id = $2",
+                &[&(count as i64), &txid.to_string()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn max_arrival_seq(&self) -> Result<Option<u64>> {
+        let row = self
+            .client()
+            .await?
+            .query_one("SELECT MAX(arrival_seq) FROM transactions", &[])
+            .await?;
+        Ok(row.get::<_, Option<i64>>(0).map(|seq| seq as u64))
+    }
+
+    async fn row_versions(&self) -> Result<RowVersions> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                "SELECT (SELECT MAX(version) FROM transactions), (SELECT MAX(version) FROM rbf),
+                (SELECT MAX(version) FROM mempool_state)",
+                &[],
+            )
+            .await?;
+        let version = |i: usize| row.get::<_, Option<i32>>(i).map(|version| version as u32);
+        Ok(RowVersions {
+            transactions: version(0),
+            rbf: version(1),
+            mempool_state: version(2),
+        })
+    }
+
+    async fn unconfirmed_found_at(&self) -> Result<Vec<(Txid, u64)>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT tx_id, found_at FROM transactions
+                WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+                &[],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let txid: String = row.get(0);
+                let found_at: i64 = row.get(1);
+                Ok((Txid::from_str(&txid)?, found_at as u64))
+            })
+            .collect()
+    }
+
+    async fn reconcile_cpfp_links(&self) -> Result<usize> {
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        let mut linked = db_tx
+            .execute(
+                "UPDATE transactions t SET parent_txid = (
+                    SELECT MIN(p.tx_id) FROM tx_inputs i JOIN transactions p ON p.tx_id = i.prev_txid
+                    WHERE i.txid = t.tx_id AND p.mined_at IS NULL AND p.pruned_at IS NULL
+                )
+                WHERE t.parent_txid IS NULL AND t.mined_at IS NULL AND t.pruned_at IS NULL AND EXISTS (
+                    SELECT 1 FROM tx_inputs i JOIN transactions p ON p.tx_id = i.prev_txid
+                    WHERE i.txid = t.tx_id AND p.mined_at IS NULL AND p.pruned_at IS NULL
+                )",
+                &[],
+            )
+            .await?;
+        linked += db_tx
+            .execute(
+                "UPDATE transactions t SET child_txid = (
+                    SELECT MIN(c.tx_id) FROM tx_inputs i JOIN transactions c ON c.tx_id = i.txid
+                    WHERE i.prev_txid = t.tx_id AND c.mined_at IS NULL AND c.pruned_at IS NULL
+                )
+                WHERE t.child_txid IS NULL AND t.mined_at IS NULL AND t.pruned_at IS NULL AND EXISTS (
+                    SELECT 1 FROM tx_inputs i JOIN transactions c ON c.tx_id = i.txid
+                    WHERE i.prev_txid = t.tx_id AND c.mined_at IS NULL AND c.pruned_at IS NULL
+                )",
+                &[],
+            )
+            .await?;
+        db_tx.commit().await?;
+        Ok(linked as usize)
+    }
+
+    async fn set_meta(&self, key: &'static str, value: String) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO meta (key, value, updated_at) VALUES ($1, $2, $3)
+                ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at",
+                &[&key, &value, &(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn meta(&self, key: &'static str) -> Result<Option<String>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT value FROM meta WHERE key = $1", &[&key])
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn record_filtered_tx(&self, reason: &'static str) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO filtered_txs (reason, count, updated_at) VALUES ($1, 1, $2)
+                ON CONFLICT (reason) DO UPDATE SET count = filtered_txs.count + 1, updated_at = EXCLUDED.updated_at",
+                &[&reason, &(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn is_mined(&self, txid: Txid) -> Result<bool> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT mined_at FROM transactions WHERE tx_id = $1",
+                &[&txid.to_string()],
+            )
+            .await?;
+        Ok(row.is_some_and(|row| row.get::<_, Option<i64>>(0).is_some()))
+    }
+
+    async fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        self.tx_exists_by_inputs_hash(&inputs_hash).await
+    }
+
+    async fn inputs_hashes(&self) -> Result<Vec<String>> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT inputs_hash FROM transactions", &[])
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn record_coinbase_tx(&self, tx: &Transaction) -> Result<()> {
+        if !tx.is_coinbase() {
+            return Ok(());
+        }
+
+        // special case for coinbase tx, key is the txid
+        let tx_id = tx.compute_txid().to_string();
+        let now = now!() as i64;
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO transactions
+                (inputs_hash, tx_data, tx_id, found_at, mined_at, absolute_fee, fee_rate, fee_rate_kwu, size, weight, version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (inputs_hash) DO UPDATE SET
+                tx_data = EXCLUDED.tx_data, found_at = EXCLUDED.found_at, mined_at = EXCLUDED.mined_at",
+                &[
+                    &tx_id,
+                    &serialize_hex(tx),
+                    &tx_id,
+                    &now,
+                    &now,
+                    &Amount::ZERO.to_db_i64(),
+                    &fee_rate_sat_per_vb(FeeRate::ZERO),
+                    &FeeRate::ZERO.to_db_i64(),
+                    &(tx.total_size() as i64),
+                    &(tx.weight().to_wu() as i64),
+                    &COINBASE_TRANSACTION_VERSION,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_mined_tx(&self, tx: &Transaction) -> Result<()> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        let tx_in_mempool = self.tx_exists_by_inputs_hash(&inputs_hash).await?;
+        if !tx_in_mempool {
+            info!("Received tx that was not in my mempool: {}", inputs_hash);
+        }
+        self
+            .client()
+            .await?
+            .execute(
+                "UPDATE transactions SET mined_at = $1, tx_data = $2, seen_in_mempool = $3 WHERE inputs_hash = $4",
+                &[&(now!() as i64), &serialize_hex(tx), &tx_in_mempool, &inputs_hash],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_mined_height(&self, tx: &Transaction, height: u64) -> Result<()> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET mined_height = $1 WHERE inputs_hash = $2",
+                &[&(height as i64), &inputs_hash],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_rbf(&self, tx: &Transaction, fee_total: u64, fee_rate: FeeRate) -> Result<()> {
+        let inputs_hash = get_inputs_hash(tx.clone().input)?;
+        if !self.tx_exists_by_inputs_hash(&inputs_hash).await? {
+            info!("Replaced Tx not found in database, ignoring RBF");
+            return Ok(());
+        }
+
+        // found_at is kept across replacements, so this is the delay since the original tx.
+        // The version being replaced is the last rbf, or the stored tx if there was none.
+        let created_at = now!() as i64;
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        let row = db_tx
+            .query_one(
+                "SELECT t.found_at,
+                CASE WHEN r.inputs_hash IS NULL THEN t.absolute_fee ELSE r.fee_total END,
+                CASE WHEN r.inputs_hash IS NULL
+                    THEN t.fee_rate_kwu ELSE r.fee_rate_kwu END,
+                CASE WHEN r.inputs_hash IS NULL THEN (t.weight + 3) / 4 ELSE r.vsize END
+                FROM transactions t LEFT JOIN rbf r ON r.inputs_hash = t.inputs_hash
+                WHERE t.inputs_hash = $1",
+                &[&inputs_hash],
+            )
+            .await?;
+        let found_at: i64 = row.get(0);
+        let bump_delay_secs = (created_at - found_at).max(0);
+        let delta = RbfDelta::between(
+            (row.get(1), row.get(2), row.get(3)),
+            tx,
+            fee_total,
+            fee_rate,
+        );
+
+        let txid = tx.compute_txid().to_string();
+        db_tx
+            .execute(
+                "INSERT INTO rbf (inputs_hash, created_at, fee_total, fee_rate_kwu, replaces, version, bump_delay_secs,
+                vsize, fee_delta, fee_rate_delta_kwu, vsize_delta, rate_down_abs_up, tx_data)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                ON CONFLICT (inputs_hash) DO UPDATE SET
+                created_at = EXCLUDED.created_at, fee_total = EXCLUDED.fee_total, fee_rate_kwu = EXCLUDED.fee_rate_kwu,
+                replaces = EXCLUDED.replaces, version = EXCLUDED.version, bump_delay_secs = EXCLUDED.bump_delay_secs,
+                vsize = EXCLUDED.vsize, fee_delta = EXCLUDED.fee_delta,
+                fee_rate_delta_kwu = EXCLUDED.fee_rate_delta_kwu, vsize_delta = EXCLUDED.vsize_delta,
+                rate_down_abs_up = EXCLUDED.rate_down_abs_up, tx_data = EXCLUDED.tx_data",
+                &[
+                    &inputs_hash,
+                    &created_at,
+                    &(fee_total as i64),
+                    &fee_rate.to_db_i64(),
+                    &txid,
+                    &RBF_TRANSACTION_VERSION,
+                    &bump_delay_secs,
+                    &(tx.vsize() as i64),
+                    &delta.map(|delta| delta.fee_delta),
+                    &delta.map(|delta| delta.fee_rate_delta_kwu),
+                    &delta.map(|delta| delta.vsize_delta),
+                    &delta.map(|delta| delta.rate_down_abs_up()),
+                    &serialize_hex(tx),
+                ],
+            )
+            .await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_txid_by_inputs_hash(
+        &self,
+        tx: &Transaction,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        replace_tx_version(&db_tx, tx, absolute_fee, fee_rate).await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    async fn stuck_txs(&self, older_than: u64) -> Result<Vec<Txid>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT tx_id FROM transactions
+                WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL AND found_at < $1
+                ORDER BY found_at",
+                &[&(now!().saturating_sub(older_than) as i64)],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| Ok(Txid::from_str(row.get(0))?))
+            .collect()
+    }
+
+    async fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        // See the sqlite backend, an empty mempool never marks anything as pruned
+        if txids.is_empty() {
+            return Ok(vec![]);
+        }
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT tx_id FROM transactions WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL",
+                &[],
+            )
+            .await?;
+        let mut txids_not_in_list = vec![];
+        for row in rows {
+            let txid = Txid::from_str(row.get(0))?;
+            if !txids.contains(&txid) {
+                txids_not_in_list.push(txid);
+            }
+        }
+        Ok(txids_not_in_list)
+    }
+
+    async fn unconfirmed_fee_rates(&self) -> Result<Vec<FeeRate>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT fee_rate_kwu AS kwu FROM transactions
+                WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL ORDER BY kwu",
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| FeeRate::from_db_i64(row.get(0)))
+            .collect())
+    }
+
+    async fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
+        if txids.is_empty() {
+            return Ok(());
+        }
+        let txids = txids
+            .iter()
+            .map(|txid| txid.to_string())
+            .collect::<Vec<_>>();
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET pruned_at = $1,
+                    blocks_survived = (
+                        SELECT block_height FROM mempool_state WHERE block_height IS NOT NULL
+                        ORDER BY created_at DESC LIMIT 1
+                    ) - COALESCE(found_at_height, (
+                        SELECT block_height FROM mempool_state
+                        WHERE block_height IS NOT NULL AND created_at <= transactions.found_at
+                        ORDER BY created_at DESC LIMIT 1
+                    ))
+                WHERE tx_id = ANY($2)",
+                &[&(now!() as i64), &txids],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_mempool_state(
+        &self,
+        mempool_size: u64,
+        mempool_tx_count: u64,
+        block_height: Option<u64>,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                // See the sqlite backend
+                "INSERT INTO mempool_state (created_at, size, tx_count, block_height, block_hash, version, blocks_to_clear)
+                VALUES ($1, $2, $3, $4, $5, $6, (
+                    SELECT CAST(COALESCE(SUM((weight + 3) / 4), 0) AS DOUBLE PRECISION) / $7 FROM transactions
+                    WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                    AND fee_rate_kwu >= COALESCE(
+                        (SELECT clearing_fee_rate_kwu FROM blocks ORDER BY height DESC LIMIT 1), 0
+                    )
+                ))",
+                &[
+                    &(now!() as i64),
+                    &(mempool_size as i64),
+                    &(mempool_tx_count as i64),
+                    &block_height.map(|block_height| block_height as i64),
+                    &block_hash.map(|block_hash| serialize_hex(&block_hash)),
+                    &MEMPOOL_STATE_VERSION,
+                    &(BLOCK_MAX_VSIZE as f64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_truc_tx(&self, tx: &Transaction) -> Result<()> {
+        // See the sqlite backend
+        if tx.is_coinbase() || !is_truc(tx) {
+            return Ok(());
+        }
+        let tx_id = tx.compute_txid().to_string();
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        let parent_txids = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid.to_string())
+            .collect::<std::collections::BTreeSet<_>>();
+        let mut unconfirmed_parents = 0;
+        let mut truc_parents = 0;
+        for parent_txid in parent_txids {
+            let Some(row) = db_tx
+                .query_opt(
+                    "SELECT tx_version FROM transactions WHERE tx_id = $1
+                    AND mined_at IS NULL AND pruned_at IS NULL AND replaced_by IS NULL",
+                    &[&parent_txid],
+                )
+                .await?
+            else {
+                continue;
+            };
+            unconfirmed_parents += 1;
+            if row.get::<_, Option<i32>>(0) == Some(3) {
+                truc_parents += 1;
+            }
+        }
+        let respects_topology =
+            truc_respects_topology(tx, unconfirmed_parents, truc_parents == unconfirmed_parents);
+        db_tx
+            .execute(
+                "INSERT INTO truc_txs
+                (tx_id, found_at, unconfirmed_parents, unconfirmed_children, respects_topology)
+                VALUES ($1, $2, $3, 0, $4)
+                ON CONFLICT (tx_id) DO UPDATE SET found_at = EXCLUDED.found_at,
+                unconfirmed_parents = EXCLUDED.unconfirmed_parents, unconfirmed_children = 0,
+                respects_topology = EXCLUDED.respects_topology",
+                &[
+                    &tx_id,
+                    &(now!() as i64),
+                    &(unconfirmed_parents as i64),
+                    &respects_topology,
+                ],
+            )
+            .await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_time_drifts(&self, txs: Vec<(Txid, u64, u64)>) -> Result<()> {
+        let mut txids = vec![];
+        let mut found_ats = vec![];
+        let mut node_times = vec![];
+        for (txid, found_at, node_time) in txs {
+            txids.push(txid.to_string());
+            found_ats.push(found_at as i64);
+            node_times.push(node_time as i64);
+        }
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO time_drift (tx_id, found_at, node_time, drift_secs)
+                SELECT s.tx_id, s.found_at, s.node_time, s.found_at - s.node_time
+                FROM UNNEST($1::TEXT[], $2::BIGINT[], $3::BIGINT[]) AS s(tx_id, found_at, node_time)
+                ON CONFLICT (tx_id) DO NOTHING",
+                &[&txids, &found_ats, &node_times],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_fee_rate_percentiles(&self, fee_rates: Vec<FeeRate>) -> Result<()> {
+        let (percentiles, fee_rates_kwu): (Vec<i64>, Vec<i64>) = FEE_RATE_PERCENTILES
+            .into_iter()
+            .filter_map(|p| {
+                let fee_rate = fee_rate_percentile(&fee_rates, p)?;
+                Some((p as i64, fee_rate.to_db_i64()))
+            })
+            .unzip();
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO fee_rate_percentiles (created_at, percentile, fee_rate_kwu)
+                SELECT $1, s.percentile, s.fee_rate_kwu
+                FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS s(percentile, fee_rate_kwu)
+                ON CONFLICT (created_at, percentile) DO UPDATE SET fee_rate_kwu = EXCLUDED.fee_rate_kwu",
+                &[&(now!() as i64), &percentiles, &fee_rates_kwu],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn defer_fee_tx(&self, deferred: DeferredTx) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO deferred_fee_txs (tx_id, tx_data, found_at, arrival_seq, retries, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (tx_id) DO UPDATE SET retries = EXCLUDED.retries, updated_at = EXCLUDED.updated_at",
+                &[
+                    &deferred.tx.compute_txid().to_string(),
+                    &serialize_hex(&deferred.tx),
+                    &(deferred.found_at as i64),
+                    &(deferred.arrival_seq as i64),
+                    &(deferred.retries as i64),
+                    &(now!() as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn take_deferred_fee_txs(&self, limit: usize) -> Result<Vec<DeferredTx>> {
+        let mut client = self.client().await?;
+        let db_tx = client.transaction().await?;
+        db_tx
+            .execute(
+                "DELETE FROM deferred_fee_txs WHERE tx_id IN (SELECT tx_id FROM transactions)",
+                &[],
+            )
+            .await?;
+        let rows = db_tx
+            .query(
+                "DELETE FROM deferred_fee_txs WHERE tx_id IN (
+                    SELECT tx_id FROM deferred_fee_txs ORDER BY found_at, arrival_seq LIMIT $1
+                )
+                RETURNING tx_data, found_at, arrival_seq, retries",
+                &[&(limit as i64)],
+            )
+            .await?;
+        db_tx.commit().await?;
+        let mut deferred = rows
+            .into_iter()
+            .map(|row| {
+                Ok(DeferredTx {
+                    tx: deserialize_hex(row.get::<_, &str>(0))?,
+                    found_at: row.get::<_, i64>(1) as u64,
+                    arrival_seq: row.get::<_, i64>(2) as u64,
+                    retries: row.get::<_, i64>(3) as u32,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // RETURNING doesn't keep the subquery's order
+        deferred.sort_by_key(|deferred| (deferred.found_at, deferred.arrival_seq));
+        Ok(deferred)
+    }
+
+    async fn record_fee_anomaly(&self, txid: Txid) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET fee_anomaly = TRUE WHERE tx_id = $1",
+                &[&txid.to_string()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_reused_outputs(&self, txid: Txid, reused_outputs: u64) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE transactions SET reused_outputs = $1 WHERE tx_id = $2",
+                &[&(reused_outputs as i64), &txid.to_string()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_address_reuse(&self) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO address_reuse_counts (created_at, tx_count, reused_outputs)
+                SELECT $1, COUNT(*), COALESCE(SUM(reused_outputs), 0) FROM transactions
+                WHERE reused_outputs IS NOT NULL AND pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                ON CONFLICT (created_at) DO UPDATE
+                SET tx_count = EXCLUDED.tx_count, reused_outputs = EXCLUDED.reused_outputs",
+                &[&(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_nonstandard_tx(&self, txid: Txid, reason: &'static str) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO nonstandard_txs (tx_id, reason, found_at) VALUES ($1, $2, $3)
+                ON CONFLICT (tx_id) DO NOTHING",
+                &[&txid.to_string(), &reason, &(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_watched_outputs(&self, txid: Txid, outputs: Vec<(u32, Amount)>) -> Result<()> {
+        let (vouts, values): (Vec<i64>, Vec<i64>) = outputs
+            .into_iter()
+            .map(|(vout, value)| (vout as i64, value.to_db_i64()))
+            .unzip();
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO watched_txs (tx_id, vout, value, found_at)
+                SELECT $1, s.vout, s.value, $4 FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS s(vout, value)
+                ON CONFLICT (tx_id, vout) DO NOTHING",
+                &[&txid.to_string(), &vouts, &values, &(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_tx_version_counts(&self) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO tx_version_counts (created_at, tx_version, count)
+                SELECT $1, tx_version, COUNT(*) FROM transactions
+                WHERE tx_version IS NOT NULL AND pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                GROUP BY tx_version
+                ON CONFLICT (created_at, tx_version) DO UPDATE SET count = EXCLUDED.count",
+                &[&(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_size_distribution(&self) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                &format!(
+                    "INSERT INTO size_bucket_counts (created_at, min_vsize, tx_count, total_vsize)
+                    SELECT $1, {} AS bucket, COUNT(*), SUM(vsize) FROM (
+                        SELECT (weight + 3) / 4 AS vsize FROM transactions
+                        WHERE pruned_at IS NULL AND mined_at IS NULL AND replaced_by IS NULL
+                    ) t GROUP BY bucket
+                    ON CONFLICT (created_at, min_vsize) DO UPDATE SET
+                    tx_count = EXCLUDED.tx_count, total_vsize = EXCLUDED.total_vsize",
+                    size_bucket_sql("vsize")
+                ),
+                &[&(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn update_feerate_targets(&self) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO feerate_targets
+                (bucket_start, block_height, next_block_kwu, next_3_blocks_kwu, next_6_blocks_kwu, updated_at)
+                SELECT bucket_start, block_height,
+                    (SELECT MIN(clearing_fee_rate_kwu) FROM blocks WHERE height = b.block_height + 1),
+                    (SELECT MIN(clearing_fee_rate_kwu) FROM blocks
+                        WHERE height BETWEEN b.block_height + 1 AND b.block_height + 3),
+                    (SELECT MIN(clearing_fee_rate_kwu) FROM blocks
+                        WHERE height BETWEEN b.block_height + 1 AND b.block_height + 6),
+                    $2
+                FROM (
+                    SELECT created_at - created_at % $1 AS bucket_start, MIN(block_height) AS block_height
+                    FROM mempool_state WHERE block_height >= (SELECT MAX(height) FROM blocks) - 7
+                    GROUP BY 1
+                ) b
+                ON CONFLICT (bucket_start) DO UPDATE SET
+                block_height = EXCLUDED.block_height, next_block_kwu = EXCLUDED.next_block_kwu,
+                next_3_blocks_kwu = EXCLUDED.next_3_blocks_kwu,
+                next_6_blocks_kwu = EXCLUDED.next_6_blocks_kwu, updated_at = EXCLUDED.updated_at",
+                &[&(FEERATE_TARGET_BUCKET_SECS as i64), &(now!() as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn feerate_target_series(&self, since: u64) -> Result<Vec<FeerateTarget>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT bucket_start, block_height, next_block_kwu, next_3_blocks_kwu, next_6_blocks_kwu
+                FROM feerate_targets WHERE bucket_start >= $1 ORDER BY bucket_start",
+                &[&(since as i64)],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let fee_rate = |i: usize| row.get::<_, Option<i64>>(i).map(FeeRate::from_db_i64);
+                FeerateTarget {
+                    bucket_start: row.get::<_, i64>(0) as u64,
+                    block_height: row.get::<_, i64>(1) as u64,
+                    next_block: fee_rate(2),
+                    next_3_blocks: fee_rate(3),
+                    next_6_blocks: fee_rate(4),
+                }
+            })
+            .collect())
+    }
+
+    async fn record_block_tx(&self, block_height: u64, fee_rate: FeeRate) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO blocks (height, clearing_fee_rate_kwu, tx_count, updated_at) VALUES ($1, $2, 1, $3)
+                ON CONFLICT (height) DO UPDATE SET
+                clearing_fee_rate_kwu = LEAST(blocks.clearing_fee_rate_kwu, EXCLUDED.clearing_fee_rate_kwu),
+                tx_count = blocks.tx_count + 1, updated_at = EXCLUDED.updated_at",
+                &[
+                    &(block_height as i64),
+                    &fee_rate.to_db_i64(),
+                    &(now!() as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_fee_discrepancy(
+        &self,
+        txid: Txid,
+        computed_fee: Amount,
+        node_fee: Amount,
+    ) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO fee_discrepancies (tx_id, found_at, computed_fee, node_fee) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (tx_id) DO UPDATE SET
+                found_at = EXCLUDED.found_at, computed_fee = EXCLUDED.computed_fee, node_fee = EXCLUDED.node_fee",
+                &[
+                    &txid.to_string(),
+                    &(now!() as i64),
+                    &computed_fee.to_db_i64(),
+                    &node_fee.to_db_i64(),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_oversized_tx(&self, tx: &Transaction) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO oversized_txs (tx_id, found_at, size, weight) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (tx_id) DO NOTHING",
+                &[
+                    &tx.compute_txid().to_string(),
+                    &(now!() as i64),
+                    &(tx.total_size() as i64),
+                    &(tx.weight().to_wu() as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn audit(&self) -> Result<AuditReport> {
+        let count = |query: &'static str| async move {
+            let row = self.client().await?.query_one(query, &[]).await?;
+            Ok::<_, anyhow::Error>(row.get::<_, i64>(0) as u64)
+        };
+        Ok(AuditReport {
+            mined_and_pruned: count(
+                "SELECT COUNT(*) FROM transactions WHERE mined_at IS NOT NULL AND pruned_at IS NOT NULL",
+            )
+            .await?,
+            orphaned_rbfs: count(
+                "SELECT COUNT(*) FROM rbf r WHERE NOT EXISTS
+                (SELECT 1 FROM transactions t WHERE t.inputs_hash = r.inputs_hash)",
+            )
+            .await?,
+            negative_fees: count("SELECT COUNT(*) FROM transactions WHERE absolute_fee < 0").await?,
+            coinbase_inputs: count(
+                "SELECT COUNT(*) FROM tx_inputs i JOIN transactions t ON t.tx_id = i.txid
+                WHERE t.version = 0",
+            )
+            .await?,
+        })
+    }
+
+    async fn record_mining_info(&self, hash_rate_distribution: String) -> Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO mining_info (created_at, hash_rate_distribution) VALUES ($1, $2)",
+                &[&(now!() as i64), &hash_rate_distribution],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_node_info(&self, node_info: NodeInfo) -> Result<()> {
+        self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO node_info
+                (created_at, connections, connections_in, connections_out, protocol_versions)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (created_at) DO UPDATE SET connections = EXCLUDED.connections,
+                connections_in = EXCLUDED.connections_in, connections_out = EXCLUDED.connections_out,
+                protocol_versions = EXCLUDED.protocol_versions",
+                &[
+                    &(now!() as i64),
+                    &(node_info.connections as i64),
+                    &node_info.connections_in.map(|connections| connections as i64),
+                    &node_info.connections_out.map(|connections| connections as i64),
+                    &node_info.protocol_versions_json(),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{outpoint, spending_tx};
+
+    /// A migrated store in a fresh schema of the db at `MEMPOOL_TRACKER_TEST_PG_URL`, or None
+    /// when it isn't set
+    async fn test_store() -> Result<Option<PgStore>> {
+        let Ok(url) = std::env::var("MEMPOOL_TRACKER_TEST_PG_URL") else {
+            return Ok(None);
+        };
+        let schema = format!("mempool_tracker_test_{:016x}", rand::random::<u64>());
+        PgStore::connect(&url)
+            .await?
+            .client()
+            .await?
+            .batch_execute(&format!("CREATE SCHEMA {}", schema))
+            .await?;
+        let mut config: Config = url.parse()?;
+        config.options(format!("-c search_path={}", schema));
+        let store = PgStore::with_config(config).await?;
+        store.run_migrations().await?;
+        Ok(Some(store))
+    }
+
+    #[tokio::test]
+    async fn test_pg_replacement_overwrites_tx() -> Result<()> {
+        let Some(store) = test_store().await? else {
+            return Ok(());
+        };
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        let original = spending_tx(&[outpoint("01", 0)], 90_000);
+        let replacement = spending_tx(&[outpoint("01", 0)], 85_000);
+        store
+            .insert_mempool_tx(
+                original.clone(),
+                Some(100),
+                Amount::from_sat(1_000),
+                fee_rate,
+            )
+            .await?;
+        store.record_rbf(&replacement, 5_000, fee_rate).await?;
+        store
+            .update_txid_by_inputs_hash(&replacement, Amount::from_sat(5_000), fee_rate)
+            .await?;
+
+        let client = store.client().await?;
+        let row = client
+            .query_one("SELECT tx_id, prior_txids FROM transactions", &[])
+            .await?;
+        assert_eq!(
+            row.get::<_, String>(0),
+            replacement.compute_txid().to_string()
+        );
+        assert_eq!(
+            row.get::<_, String>(1),
+            format!("[\"{}\"]", original.compute_txid())
+        );
+        let row = client
+            .query_one("SELECT tx_data FROM tx_history", &[])
+            .await?;
+        assert_eq!(row.get::<_, String>(0), serialize_hex(&original));
+        let row = client.query_one("SELECT txid FROM tx_inputs", &[]).await?;
+        assert_eq!(
+            row.get::<_, String>(0),
+            replacement.compute_txid().to_string()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_concurrent_inserts_chain_first_seen() -> Result<()> {
+        let Some(store) = test_store().await? else {
+            return Ok(());
+        };
+        let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+        let mut handles = vec![];
+        for vout in 0..20 {
+            let store = store.clone();
+            handles.push(tokio::spawn(async move {
+                let tx = spending_tx(&[outpoint("01", vout)], 90_000);
+                store
+                    .insert_mempool_tx(tx, Some(100), Amount::from_sat(1_000), fee_rate)
+                    .await
+            }));
+        }
+        for handle in handles {
+            handle.await??;
+        }
+
+        // Each record chains off the one before it, even when written from several connections
+        let rows = store
+            .client()
+            .await?
+            .query(
+                "SELECT prev_hash, hash FROM first_seen_log ORDER BY seq",
+                &[],
+            )
+            .await?;
+        assert_eq!(rows.len(), 20);
+        let mut prev_hash = FIRST_SEEN_GENESIS_HASH.to_string();
+        for row in rows {
+            assert_eq!(row.get::<_, String>(0), prev_hash);
+            prev_hash = row.get(1);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use bitcoin::{consensus::encode::serialize_hex, FeeRate};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{database::Database, now, rpc::RawRpcClient};
+
+/// Outcome of a [`rebroadcast`] run
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RebroadcastSummary {
+    /// Accepted by the node, including txs it already had
+    pub sent: usize,
+    /// Rejected by the node, e.g. because an input was spent since
+    pub rejected: usize,
+}
+
+/// Re-submit the tracked txs first seen at least `older_than` secs ago that pay at least
+/// `min_fee_rate` and were neither mined nor replaced, from their stored `tx_data`
+pub async fn rebroadcast(
+    db: &Database,
+    rpc: &RawRpcClient,
+    older_than: u64,
+    min_fee_rate: FeeRate,
+) -> Result<RebroadcastSummary> {
+    let found_before = now!().saturating_sub(older_than);
+    let mut summary = RebroadcastSummary::default();
+    for tx in db.rebroadcast_candidates(found_before, min_fee_rate)? {
+        let txid = tx.compute_txid();
+        let response = rpc
+            .request("sendrawtransaction", json!([serialize_hex(&tx)]))
+            .await?;
+        match response.get("error").filter(|error| !error.is_null()) {
+            Some(error) => {
+                warn!("Node rejected rebroadcast of {}: {}", txid, error);
+                summary.rejected += 1;
+            }
+            None => {
+                info!("Rebroadcast {}", txid);
+                summary.sent += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
+
+use anyhow::Result;
+use bitcoin::{
+    consensus::{encode::serialize, Decodable},
+    Transaction,
+};
+use bitcoind_async_client::{Auth, Client};
+use log::info;
+
+use crate::{
+    bloom::BloomFilter,
+    store::Store,
+    worker::{InputsLocks, Task, TaskContext, WorkerConfig},
+};
+
+/// Read raw txs from a replay file: either one hex encoded tx per line
+/// (blank lines and lines starting with `#` are skipped), or consensus encoded txs back to back
+pub fn read_raw_txs(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let bytes = fs::read(path)?;
+    if let Ok(text) = std::str::from_utf8(&bytes) {
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect::<Vec<_>>();
+        if lines
+            .iter()
+            .all(|line| line.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return lines
+                .into_iter()
+                .map(|line| Ok(hex::decode(line)?))
+                .collect();
+        }
+    }
+
+    let mut reader = bytes.as_slice();
+    let mut raw_txs = vec![];
+    while !reader.is_empty() {
+        let tx = Transaction::consensus_decode(&mut reader)?;
+        raw_txs.push(serialize(&tx));
+    }
+    Ok(raw_txs)
+}
+
+/// Feed raw txs through a worker as if they arrived over ZMQ, without a node
+pub async fn replay<S: Store>(db: S, raw_txs: Vec<Vec<u8>>, config: WorkerConfig) -> Result<()> {
+    db.run_migrations().await?;
+    let (tasks_tx, tasks_rx) = async_channel::unbounded();
+    // Offline workers never make rpc calls, the client only satisfies the worker
+    let bitcoind = Client::new(
+        "http://127.0.0.1:0".to_string(),
+        Auth::UserPass(String::new(), String::new()),
+        None,
+        None,
+    )?;
+    let known_inputs = BloomFilter::new(raw_txs.len(), 0.01);
+    for inputs_hash in db.inputs_hashes().await? {
+        known_inputs.insert(&inputs_hash);
+    }
+    if let Some(max_arrival_seq) = db.max_arrival_seq().await? {
+        config
+            .arrival_seq
+            .store(max_arrival_seq + 1, Ordering::Relaxed);
+    }
+    let mut worker = TaskContext::new(
+        bitcoind,
+        db.clone(),
+        tasks_rx,
+        // Nothing is scheduled during a replay
+        async_channel::bounded(1).1,
+        WorkerConfig {
+            offline: true,
+            ..config
+        },
+        Arc::new(known_inputs),
+        Arc::new(InputsLocks::new(1)),
+    );
+
+    info!("Replaying {} txs", raw_txs.len());
+    for raw_tx in raw_txs {
+        tasks_tx.send(Task::RawTx(raw_tx)).await?;
+    }
+    // The worker exits once every queued tx is processed
+    tasks_tx.close();
+    worker.run().await?;
+    db.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount, ScriptBuf, TxIn, TxOut};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_raw_txs() -> Result<()> {
+        let txs = (1..=2)
+            .map(|value| Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            })
+            .map(|tx| serialize(&tx))
+            .collect::<Vec<_>>();
+        let tempdir = TempDir::new()?;
+
+        let hex_path = tempdir.path().join("txs.hex");
+        let hex_lines = txs.iter().map(hex::encode).collect::<Vec<_>>();
+        fs::write(&hex_path, format!("# replay\n{}\n\n", hex_lines.join("\n")))?;
+        assert_eq!(read_raw_txs(&hex_path)?, txs);
+
+        let bin_path = tempdir.path().join("txs.bin");
+        fs::write(&bin_path, txs.concat())?;
+        assert_eq!(read_raw_txs(&bin_path)?, txs);
+
+        Ok(())
+    }
+}
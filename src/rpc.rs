@@ -0,0 +1,200 @@
+use std::{fs, future::Future, path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use bitcoind_async_client::Client;
+use log::warn;
+use reqwest::Client as ReqwestClient;
+use serde_json::{json, Value};
+
+/// Retries after the first failed attempt
+const MAX_RETRIES: u32 = 8;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// bitcoind rpc client that retries calls with exponential backoff while the node is unreachable,
+/// e.g. during a restart, rather than failing and dropping the tx being processed.
+/// Errors returned by a reachable node are not retried.
+#[derive(Clone)]
+pub struct ResilientClient {
+    inner: Client,
+}
+
+impl ResilientClient {
+    pub fn new(inner: Client) -> Self {
+        Self { inner }
+    }
+
+    /// Run `f` against the node, retrying while it fails with a connection error
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn(Client) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match f(self.inner.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < MAX_RETRIES && is_connection_error(&e) => {
+                    let delay = backoff_delay(attempt);
+                    warn!("bitcoind unreachable, retrying in {:?}: {:#}", delay, e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Credentials for the raw json-rpc calls the async client doesn't expose
+#[derive(Debug, Clone)]
+pub enum RpcCredentials {
+    UserPass(String, String),
+    /// Read on every call, the cookie changes when the node restarts
+    CookieFile(PathBuf),
+}
+
+/// Plain json-rpc client for the node methods missing from the async client's `Reader`
+#[derive(Debug)]
+pub struct RawRpcClient {
+    http: ReqwestClient,
+    url: String,
+    credentials: RpcCredentials,
+}
+
+impl RawRpcClient {
+    pub fn new(url: String, credentials: RpcCredentials) -> Self {
+        Self {
+            http: ReqwestClient::new(),
+            url,
+            credentials,
+        }
+    }
+
+    fn user_pass(&self) -> Result<(String, String)> {
+        match &self.credentials {
+            RpcCredentials::UserPass(user, password) => Ok((user.clone(), password.clone())),
+            RpcCredentials::CookieFile(path) => {
+                let cookie = fs::read_to_string(path)?;
+                let (user, password) = cookie
+                    .trim()
+                    .split_once(':')
+                    .ok_or(anyhow::anyhow!("malformed cookie file"))?;
+                Ok((user.to_string(), password.to_string()))
+            }
+        }
+    }
+
+    /// Call `method`, returning the whole response so callers can handle rpc errors
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let (user, password) = self.user_pass()?;
+        let request = json!({
+            "jsonrpc": "1.0",
+            "id": "mempool-tracker",
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .http
+            .post(&self.url)
+            .basic_auth(user, Some(password))
+            .header("content-type", "application/json")
+            .body(request.to_string())
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    /// Call `method`, failing on an rpc error
+    pub async fn result(&self, method: &str, params: Value) -> Result<Value> {
+        let mut response = self.request(method, params).await?;
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            return Err(anyhow::anyhow!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].take())
+    }
+}
+
+/// Node rpc url, scoped to `wallet` when set so wallet rpcs don't fail with no wallet loaded.
+/// Wallet names are restricted to characters that need no escaping in a url path.
+pub fn rpc_url(host: &str, port: u16, wallet: Option<&str>) -> Result<String> {
+    let url = format!("http://{}:{}", host, port);
+    let Some(wallet) = wallet else {
+        return Ok(url);
+    };
+    let url_safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~');
+    if wallet.is_empty() || !wallet.chars().all(url_safe) {
+        return Err(anyhow::anyhow!(
+            "wallet name {:?} must only use letters, digits, '-', '_', '.' and '~'",
+            wallet
+        ));
+    }
+    Ok(format!("{}/wallet/{}", url, wallet))
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY)
+}
+
+/// The client's error type doesn't distinguish transport failures, so match on the message
+fn is_connection_error(e: &anyhow::Error) -> bool {
+    let message = format!("{:#}", e).to_lowercase();
+    [
+        "connection refused",
+        "connection reset",
+        "connection closed",
+        "error sending request",
+        "timed out",
+        "timeout",
+        "broken pipe",
+        // The node answers but is still loading after a restart
+        "warming up",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(3), Duration::from_secs(4));
+        assert_eq!(backoff_delay(7), MAX_DELAY);
+        assert_eq!(backoff_delay(40), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_rpc_url() {
+        assert_eq!(
+            rpc_url("127.0.0.1", 8332, None).unwrap(),
+            "http://127.0.0.1:8332"
+        );
+        assert_eq!(
+            rpc_url("127.0.0.1", 8332, Some("mempool_tracker-1.0")).unwrap(),
+            "http://127.0.0.1:8332/wallet/mempool_tracker-1.0"
+        );
+        for wallet in ["", "my wallet", "../admin", "a/b", "caf\u{e9}"] {
+            assert!(rpc_url("127.0.0.1", 8332, Some(wallet)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_is_connection_error() {
+        assert!(is_connection_error(&anyhow::anyhow!(
+            "error sending request: tcp connect error: Connection refused (os error 111)"
+        )));
+        assert!(is_connection_error(&anyhow::anyhow!(
+            "RPC error -28: Loading block index... (warming up)"
+        )));
+        assert!(!is_connection_error(&anyhow::anyhow!(
+            "No such mempool or blockchain transaction"
+        )));
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fs, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::Result;
+use bitcoin::Txid;
+use log::warn;
+use serde_json::{json, Value};
+
+use crate::now;
+
+/// Tracked unconfirmed txs and when they were found, written on clean shutdown.
+/// A fresh snapshot lets the next startup skip the mempool scan.
+#[derive(Debug, Clone)]
+pub struct ShutdownSnapshot {
+    path: PathBuf,
+    /// Older snapshots are ignored, the mempool has likely moved on too far
+    max_age: Duration,
+}
+
+impl ShutdownSnapshot {
+    pub fn new(path: PathBuf, max_age: Duration) -> Self {
+        Self { path, max_age }
+    }
+
+    pub fn write(&self, txs: &[(Txid, u64)]) -> Result<()> {
+        let txs = txs
+            .iter()
+            .map(|(txid, found_at)| json!({ "txid": txid.to_string(), "found_at": found_at }))
+            .collect::<Vec<_>>();
+        let snapshot = json!({ "created_at": now!(), "txs": txs });
+        // Written aside then renamed so a crash mid-write can't leave a truncated snapshot
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&snapshot)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Read and remove the snapshot, `None` if missing, stale or unreadable.
+    /// It is only used once so a later unclean shutdown doesn't reuse it.
+    pub fn take_fresh(&self) -> Result<Option<Vec<(Txid, u64)>>> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let txs = match self.decode(&contents) {
+            Ok(txs) => txs,
+            Err(e) => {
+                warn!(
+                    "Ignoring invalid shutdown snapshot {}: {}",
+                    self.path.display(),
+                    e
+                );
+                None
+            }
+        };
+        fs::remove_file(&self.path)?;
+        Ok(txs)
+    }
+
+    fn decode(&self, contents: &[u8]) -> Result<Option<Vec<(Txid, u64)>>> {
+        let snapshot: Value = serde_json::from_slice(contents)?;
+        let created_at = snapshot["created_at"]
+            .as_u64()
+            .ok_or(anyhow::anyhow!("snapshot is missing created_at"))?;
+        if now!().saturating_sub(created_at) > self.max_age.as_secs() {
+            return Ok(None);
+        }
+        let txs = snapshot["txs"]
+            .as_array()
+            .ok_or(anyhow::anyhow!("snapshot is missing txs"))?
+            .iter()
+            .map(|tx| {
+                let txid = tx["txid"]
+                    .as_str()
+                    .ok_or(anyhow::anyhow!("snapshot tx is missing txid"))?;
+                let found_at = tx["found_at"]
+                    .as_u64()
+                    .ok_or(anyhow::anyhow!("snapshot tx is missing found_at"))?;
+                Ok((Txid::from_str(txid)?, found_at))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(txs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_is_taken_once() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let snapshot = ShutdownSnapshot::new(
+            tempdir.path().join("shutdown.json"),
+            Duration::from_secs(600),
+        );
+        assert_eq!(snapshot.take_fresh()?, None);
+
+        let txs = vec![(Txid::from_str(&"01".repeat(32))?, 100)];
+        snapshot.write(&txs)?;
+        assert_eq!(snapshot.take_fresh()?, Some(txs.clone()));
+        assert_eq!(snapshot.take_fresh()?, None);
+
+        let stale = ShutdownSnapshot::new(tempdir.path().join("stale.json"), Duration::ZERO);
+        fs::write(
+            tempdir.path().join("stale.json"),
+            serde_json::to_vec(&json!({ "created_at": 0, "txs": [] }))?,
+        )?;
+        assert_eq!(stale.take_fresh()?, None);
+
+        // An unreadable snapshot falls back to the scan and is removed too
+        let corrupt_path = tempdir.path().join("corrupt.json");
+        let corrupt = ShutdownSnapshot::new(corrupt_path.clone(), Duration::from_secs(600));
+        fs::write(&corrupt_path, b"{\"created_at\": ")?;
+        assert_eq!(corrupt.take_fresh()?, None);
+        assert!(!corrupt_path.exists());
+
+        Ok(())
+    }
+}
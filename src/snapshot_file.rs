@@ -0,0 +1,122 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use bitcoin::FeeRate;
+use serde_json::Value;
+
+use crate::utils::{fee_rate_percentile, fee_rate_sat_per_vb};
+
+/// Fee rate percentiles included in each snapshot record
+pub const FEE_RATE_PERCENTILES: [u8; 5] = [10, 25, 50, 75, 90];
+
+/// Appends each mempool state snapshot to a file as newline-delimited JSON.
+/// Once the file would exceed `max_bytes` it is rotated to `<path>.1`, replacing any older rotation.
+#[derive(Debug)]
+pub struct SnapshotFile {
+    path: PathBuf,
+    max_bytes: u64,
+    // Workers record snapshots concurrently
+    lock: Mutex<()>,
+}
+
+impl SnapshotFile {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            path,
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        rotated.into()
+    }
+
+    pub fn append(&self, record: &Value) -> Result<()> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("snapshot file lock poisoned"))?;
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let len = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if len > 0 && len + line.len() as u64 > self.max_bytes {
+            fs::rename(&self.path, self.rotated_path())?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// [`Self::append`] on the blocking pool, so the worker calling it isn't stalled on the fs
+    pub async fn append_blocking(self: Arc<Self>, record: Value) -> Result<()> {
+        tokio::task::spawn_blocking(move || self.append(&record)).await?
+    }
+}
+
+/// Nearest-rank percentiles in sat/vB of ascending `fee_rates`, keyed e.g. `p50`
+pub fn fee_rate_percentiles(fee_rates: &[FeeRate]) -> Value {
+    let mut percentiles = serde_json::Map::new();
+    for p in FEE_RATE_PERCENTILES {
+        let value = fee_rate_percentile(fee_rates, p)
+            .map(|fee_rate| fee_rate_sat_per_vb(fee_rate).into())
+            .unwrap_or(Value::Null);
+        percentiles.insert(format!("p{}", p), value);
+    }
+    Value::Object(percentiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_file_rotates() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let path = tempdir.path().join("snapshots.ndjson");
+        let record = json!({ "size": 1000, "tx_count": 10 });
+        let line_len = serde_json::to_string(&record)?.len() as u64 + 1;
+        let file = SnapshotFile::new(path.clone(), line_len * 2);
+
+        file.append(&record)?;
+        file.append(&record)?;
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 2);
+
+        // The third record would exceed the cap
+        file.append(&record)?;
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 1);
+        assert_eq!(
+            fs::read_to_string(tempdir.path().join("snapshots.ndjson.1"))?
+                .lines()
+                .count(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_rate_percentiles() {
+        let fee_rates = (1..=10)
+            .map(|sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate"))
+            .collect::<Vec<_>>();
+        let percentiles = fee_rate_percentiles(&fee_rates);
+        assert_eq!(percentiles["p10"], json!(1.0));
+        assert_eq!(percentiles["p50"], json!(5.0));
+        assert_eq!(percentiles["p90"], json!(9.0));
+        assert_eq!(fee_rate_percentiles(&[])["p50"], Value::Null);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use bitcoin::FeeRate;
+use log::info;
+
+use crate::utils::{fee_rate_percentile, fee_rate_sat_per_vb};
+
+/// Fee rate percentiles shown in the status line
+const STATUS_PERCENTILES: [u8; 3] = [10, 50, 90];
+
+/// One line summary of the mempool and of ingestion, refreshed on every mempool state snapshot.
+/// Redrawn in place on a terminal, otherwise logged.
+#[derive(Debug)]
+pub struct StatusLine {
+    /// Txs stored by any worker since the last redraw
+    inserted: AtomicU64,
+    /// Of which replacements
+    replaced: AtomicU64,
+    last_draw: Mutex<Instant>,
+    is_terminal: bool,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        Self {
+            inserted: AtomicU64::new(0),
+            replaced: AtomicU64::new(0),
+            last_draw: Mutex::new(Instant::now()),
+            is_terminal: io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl StatusLine {
+    pub fn record_tx(&self, is_rbf: bool) {
+        self.inserted.fetch_add(1, Ordering::Relaxed);
+        if is_rbf {
+            self.replaced.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Show the node's mempool `tx_count` and `size` in bytes and the ascending tracked
+    /// `fee_rates`, along with the txs stored since the last redraw and the txs `dropped` since startup
+    pub fn draw(
+        &self,
+        tx_count: u64,
+        size: u64,
+        block_height: Option<u64>,
+        fee_rates: &[FeeRate],
+        dropped: u64,
+    ) {
+        let elapsed = {
+            let mut last_draw = self.last_draw.lock().expect("status line lock poisoned");
+            let now = Instant::now();
+            let elapsed = now.saturating_duration_since(*last_draw);
+            *last_draw = now;
+            elapsed
+        };
+        let inserted = self.inserted.swap(0, Ordering::Relaxed);
+        let replaced = self.replaced.swap(0, Ordering::Relaxed);
+        let tx_rate = inserted as f64 / elapsed.as_secs_f64().max(1.0);
+        let line = render(
+            tx_count,
+            size,
+            block_height,
+            fee_rates,
+            tx_rate,
+            replaced,
+            dropped,
+        );
+        if self.is_terminal {
+            // Clear the previous line rather than scroll
+            let mut stdout = io::stdout().lock();
+            let _ = write!(stdout, "\r\x1b[2K{}", line);
+            let _ = stdout.flush();
+        } else {
+            info!("{}", line);
+        }
+    }
+}
+
+fn render(
+    tx_count: u64,
+    size: u64,
+    block_height: Option<u64>,
+    fee_rates: &[FeeRate],
+    tx_rate: f64,
+    replaced: u64,
+    dropped: u64,
+) -> String {
+    let percentiles = STATUS_PERCENTILES
+        .iter()
+        .map(|p| match fee_rate_percentile(fee_rates, *p) {
+            Some(fee_rate) => format!("{:.1}", fee_rate_sat_per_vb(fee_rate)),
+            None => "-".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let tip = block_height.map_or("-".to_string(), |height| height.to_string());
+    format!(
+        "mempool {} txs {:.1} MB | tip {} | p10/p50/p90 {} sat/vB | {:.1} tx/s | {} rbf | {} dropped",
+        tx_count,
+        size as f64 / 1_000_000.0,
+        tip,
+        percentiles,
+        tx_rate,
+        replaced,
+        dropped
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let fee_rates = (1..=10)
+            .map(|sat_vb| FeeRate::from_sat_per_vb(sat_vb).expect("valid fee rate"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            render(12_345, 6_100_000, Some(850_000), &fee_rates, 15.24, 3, 7),
+            "mempool 12345 txs 6.1 MB | tip 850000 | p10/p50/p90 1.0/5.0/9.0 sat/vB | 15.2 tx/s | 3 rbf | 7 dropped"
+        );
+        assert_eq!(
+            render(0, 0, None, &[], 0.0, 0, 0),
+            "mempool 0 txs 0.0 MB | tip - | p10/p50/p90 -/-/- sat/vB | 0.0 tx/s | 0 rbf | 0 dropped"
+        );
+    }
+}
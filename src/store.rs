@@ -0,0 +1,526 @@
+use std::{collections::HashMap, future::Future};
+
+use anyhow::Result;
+use bitcoin::{Amount, BlockHash, FeeRate, OutPoint, Transaction, Txid};
+
+use crate::{
+    database::{AuditReport, Database, DeferredTx, FeerateTarget, RowVersions},
+    node_info::NodeInfo,
+};
+
+/// Storage backend used by the app and its workers.
+/// Implementations must be cheap to clone, each worker holds its own handle.
+pub trait Store: Clone + Send + Sync + 'static {
+    fn run_migrations(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn flush(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Remove unconfirmed txs missing from `live_txids`, returning the txids that were kept
+    fn remove_stale_txs(
+        &self,
+        live_txids: Vec<Txid>,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    /// Chain tip stored as the `found_at_height` of txs inserted from now on, None if unknown
+    fn set_tip_height(&self, height: Option<u64>);
+
+    fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Insert many txs in one go, logging and skipping any that fail. Returns how many were inserted.
+    fn insert_mempool_txs(
+        &self,
+        txs: Vec<(Transaction, Option<u64>, Amount, FeeRate)>,
+    ) -> impl Future<Output = Result<usize>> + Send;
+
+    /// Store the ages in blocks of the coins spent by a tracked tx
+    fn record_coin_ages(
+        &self,
+        tx: &Transaction,
+        coin_ages: Vec<u64>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Tag stored txs as batch payouts or not
+    fn record_batch_payouts(
+        &self,
+        txs: Vec<(Txid, bool)>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Stamp a newly inserted tx with the order it arrived in
+    fn record_arrival_seq(
+        &self,
+        txid: Txid,
+        arrival_seq: u64,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Values of the outpoints paid by tracked unconfirmed txs
+    fn tracked_prevouts(
+        &self,
+        outpoints: Vec<OutPoint>,
+    ) -> impl Future<Output = Result<HashMap<OutPoint, Amount>>> + Send;
+
+    /// Note how many of a tx's inputs were valued from tracked unconfirmed parents
+    fn record_unconfirmed_inputs(
+        &self,
+        txid: Txid,
+        count: usize,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Highest arrival sequence number stored, workers continue counting from it after a restart
+    fn max_arrival_seq(&self) -> impl Future<Output = Result<Option<u64>>> + Send;
+
+    /// Highest row version in each versioned table, to detect rows written by a newer binary
+    fn row_versions(&self) -> impl Future<Output = Result<RowVersions>> + Send;
+
+    /// Txid and found_at of every tracked unconfirmed tx
+    fn unconfirmed_found_at(&self) -> impl Future<Output = Result<Vec<(Txid, u64)>>> + Send;
+
+    /// Set missing CPFP links between tracked unconfirmed txs, returns how many were set
+    fn reconcile_cpfp_links(&self) -> impl Future<Output = Result<usize>> + Send;
+
+    fn set_meta(&self, key: &'static str, value: String)
+        -> impl Future<Output = Result<()>> + Send;
+
+    fn meta(&self, key: &'static str) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    /// Count a tx rejected by the worker's filter under `reason`
+    fn record_filtered_tx(&self, reason: &'static str) -> impl Future<Output = Result<()>> + Send;
+
+    /// Whether a tracked tx was already recorded as mined
+    fn is_mined(&self, txid: Txid) -> impl Future<Output = Result<bool>> + Send;
+
+    fn tx_exists(&self, tx: &Transaction) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Inputs hashes of every tracked tx, used to seed the in-memory `tx_exists` filter
+    fn inputs_hashes(&self) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    fn record_coinbase_tx(&self, tx: &Transaction) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_mined_tx(&self, tx: &Transaction) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record the height of the block a tracked tx confirmed in
+    fn record_mined_height(
+        &self,
+        tx: &Transaction,
+        height: u64,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record a just stored TRUC tx, see [`Database::record_truc_tx`]
+    fn record_truc_tx(&self, tx: &Transaction) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record found_at vs the node's entry time of txs, given as txid, found_at and node time
+    fn record_time_drifts(
+        &self,
+        txs: Vec<(Txid, u64, u64)>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record the fee rate percentiles of ascending `fee_rates`, e.g. those of `LiveFeeRates`
+    fn record_fee_rate_percentiles(
+        &self,
+        fee_rates: Vec<FeeRate>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Keep a tx whose fee lookup failed for a later retry, replacing an earlier deferral
+    fn defer_fee_tx(&self, deferred: DeferredTx) -> impl Future<Output = Result<()>> + Send;
+
+    /// Remove and return up to `limit` deferred txs, oldest first
+    fn take_deferred_fee_txs(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<DeferredTx>>> + Send;
+
+    /// Flag a stored tx whose fee is above the anomaly ceiling
+    fn record_fee_anomaly(&self, txid: Txid) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record how many outputs of a stored tx pay a recently seen script
+    fn record_reused_outputs(
+        &self,
+        txid: Txid,
+        reused_outputs: u64,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Snapshot how many tracked unconfirmed txs, and how many of their outputs, reuse scripts
+    fn record_address_reuse(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record a tx breaking one of our standardness checks
+    fn record_nonstandard_tx(
+        &self,
+        txid: Txid,
+        reason: &'static str,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Record the outputs of `txid` paying watched scripts
+    fn record_watched_outputs(
+        &self,
+        txid: Txid,
+        outputs: Vec<(u32, Amount)>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Snapshot how many tracked unconfirmed txs use each nVersion
+    fn record_tx_version_counts(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Snapshot how many tracked unconfirmed txs, and how many vbytes of them, fall in each size bucket
+    fn record_size_distribution(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Refresh the recent `feerate_targets` from the block clearing fee rates, on each new block
+    fn update_feerate_targets(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Fee rate targets of the time buckets starting at or after `since` (unix secs), oldest first
+    fn feerate_target_series(
+        &self,
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<FeerateTarget>>> + Send;
+
+    /// Lower the clearing fee rate of the block at `block_height` to `fee_rate` if it is below it
+    fn record_block_tx(
+        &self,
+        block_height: u64,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Note a tx whose computed fee differs from the node's
+    fn record_fee_discrepancy(
+        &self,
+        txid: Txid,
+        computed_fee: Amount,
+        node_fee: Amount,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Note a tx over the max weight without storing it
+    fn record_oversized_tx(&self, tx: &Transaction) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_rbf(
+        &self,
+        tx: &Transaction,
+        fee_total: u64,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Overwrite the stored version of `tx`'s inputs with `tx`, see `replace_tx_version`
+    fn update_txid_by_inputs_hash(
+        &self,
+        tx: &Transaction,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Tracked unconfirmed txids first seen more than `older_than` secs ago
+    fn stuck_txs(&self, older_than: u64) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    /// Tracked unconfirmed txids that are missing from `txids`
+    fn txids_of_txs_not_in_list(
+        &self,
+        txids: Vec<Txid>,
+    ) -> impl Future<Output = Result<Vec<Txid>>> + Send;
+
+    /// Fee rates of tracked unconfirmed txs, ascending
+    fn unconfirmed_fee_rates(&self) -> impl Future<Output = Result<Vec<FeeRate>>> + Send;
+
+    fn record_pruned_txs(&self, txids: Vec<Txid>) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_mempool_state(
+        &self,
+        mempool_size: u64,
+        mempool_tx_count: u64,
+        block_height: Option<u64>,
+        block_hash: Option<BlockHash>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Count rows breaking data invariants
+    fn audit(&self) -> impl Future<Output = Result<AuditReport>> + Send;
+
+    fn record_mining_info(
+        &self,
+        hash_rate_distribution: String,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn record_node_info(&self, node_info: NodeInfo) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl Database {
+    /// Run a synchronous db operation on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Database) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db)).await?
+    }
+}
+
+impl Store for Database {
+    async fn run_migrations(&self) -> Result<()> {
+        self.blocking(|db| db.run_migrations()).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.blocking(|db| db.flush()).await
+    }
+
+    async fn remove_stale_txs(&self, live_txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        self.blocking(move |db| db.remove_stale_txs(live_txids))
+            .await
+    }
+
+    fn set_tip_height(&self, height: Option<u64>) {
+        Database::set_tip_height(self, height)
+    }
+
+    async fn insert_mempool_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        self.blocking(move |db| db.insert_mempool_tx(tx, found_at, absolute_fee, fee_rate))
+            .await
+    }
+
+    async fn insert_mempool_txs(
+        &self,
+        txs: Vec<(Transaction, Option<u64>, Amount, FeeRate)>,
+    ) -> Result<usize> {
+        self.blocking(move |db| {
+            let inserted = db.insert_mempool_txs(txs)?;
+            db.flush()?;
+            Ok(inserted)
+        })
+        .await
+    }
+
+    async fn record_coin_ages(&self, tx: &Transaction, coin_ages: Vec<u64>) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_coin_ages(&tx, coin_ages))
+            .await
+    }
+
+    async fn record_batch_payouts(&self, txs: Vec<(Txid, bool)>) -> Result<()> {
+        self.blocking(move |db| db.record_batch_payouts(txs)).await
+    }
+
+    async fn record_arrival_seq(&self, txid: Txid, arrival_seq: u64) -> Result<()> {
+        self.blocking(move |db| db.record_arrival_seq(&txid, arrival_seq))
+            .await
+    }
+
+    async fn max_arrival_seq(&self) -> Result<Option<u64>> {
+        self.blocking(|db| db.max_arrival_seq()).await
+    }
+
+    async fn tracked_prevouts(
+        &self,
+        outpoints: Vec<OutPoint>,
+    ) -> Result<HashMap<OutPoint, Amount>> {
+        self.blocking(move |db| db.tracked_prevouts(&outpoints))
+            .await
+    }
+
+    async fn record_unconfirmed_inputs(&self, txid: Txid, count: usize) -> Result<()> {
+        self.blocking(move |db| db.record_unconfirmed_inputs(&txid, count))
+            .await
+    }
+
+    async fn row_versions(&self) -> Result<RowVersions> {
+        self.blocking(|db| db.row_versions()).await
+    }
+
+    async fn unconfirmed_found_at(&self) -> Result<Vec<(Txid, u64)>> {
+        self.blocking(|db| db.unconfirmed_found_at()).await
+    }
+
+    async fn reconcile_cpfp_links(&self) -> Result<usize> {
+        self.blocking(|db| db.reconcile_cpfp_links()).await
+    }
+
+    async fn set_meta(&self, key: &'static str, value: String) -> Result<()> {
+        self.blocking(move |db| db.set_meta(key, &value)).await
+    }
+
+    async fn meta(&self, key: &'static str) -> Result<Option<String>> {
+        self.blocking(move |db| db.meta(key)).await
+    }
+
+    async fn record_filtered_tx(&self, reason: &'static str) -> Result<()> {
+        self.blocking(move |db| db.record_filtered_tx(reason)).await
+    }
+
+    async fn is_mined(&self, txid: Txid) -> Result<bool> {
+        self.blocking(move |db| db.is_mined(&txid)).await
+    }
+
+    async fn tx_exists(&self, tx: &Transaction) -> Result<bool> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.tx_exists(&tx)).await
+    }
+
+    async fn inputs_hashes(&self) -> Result<Vec<String>> {
+        self.blocking(|db| db.inputs_hashes()).await
+    }
+
+    async fn record_coinbase_tx(&self, tx: &Transaction) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_coinbase_tx(&tx)).await
+    }
+
+    async fn record_mined_tx(&self, tx: &Transaction) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_mined_tx(&tx)).await
+    }
+
+    async fn record_mined_height(&self, tx: &Transaction, height: u64) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_mined_height(&tx, height))
+            .await
+    }
+
+    async fn record_truc_tx(&self, tx: &Transaction) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_truc_tx(&tx)).await
+    }
+
+    async fn record_time_drifts(&self, txs: Vec<(Txid, u64, u64)>) -> Result<()> {
+        self.blocking(move |db| db.record_time_drifts(txs)).await
+    }
+
+    async fn record_fee_rate_percentiles(&self, fee_rates: Vec<FeeRate>) -> Result<()> {
+        self.blocking(move |db| db.record_fee_rate_percentiles(&fee_rates))
+            .await
+    }
+
+    async fn defer_fee_tx(&self, deferred: DeferredTx) -> Result<()> {
+        self.blocking(move |db| db.defer_fee_tx(&deferred)).await
+    }
+
+    async fn take_deferred_fee_txs(&self, limit: usize) -> Result<Vec<DeferredTx>> {
+        self.blocking(move |db| db.take_deferred_fee_txs(limit))
+            .await
+    }
+
+    async fn record_fee_anomaly(&self, txid: Txid) -> Result<()> {
+        self.blocking(move |db| db.record_fee_anomaly(&txid)).await
+    }
+
+    async fn record_reused_outputs(&self, txid: Txid, reused_outputs: u64) -> Result<()> {
+        self.blocking(move |db| db.record_reused_outputs(&txid, reused_outputs))
+            .await
+    }
+
+    async fn record_address_reuse(&self) -> Result<()> {
+        self.blocking(|db| db.record_address_reuse()).await
+    }
+
+    async fn record_nonstandard_tx(&self, txid: Txid, reason: &'static str) -> Result<()> {
+        self.blocking(move |db| db.record_nonstandard_tx(&txid, reason))
+            .await
+    }
+
+    async fn record_watched_outputs(&self, txid: Txid, outputs: Vec<(u32, Amount)>) -> Result<()> {
+        self.blocking(move |db| db.record_watched_outputs(&txid, outputs))
+            .await
+    }
+
+    async fn record_tx_version_counts(&self) -> Result<()> {
+        self.blocking(|db| db.record_tx_version_counts()).await
+    }
+
+    async fn record_size_distribution(&self) -> Result<()> {
+        self.blocking(|db| db.record_size_distribution()).await
+    }
+
+    async fn update_feerate_targets(&self) -> Result<()> {
+        self.blocking(|db| db.update_feerate_targets()).await
+    }
+
+    async fn feerate_target_series(&self, since: u64) -> Result<Vec<FeerateTarget>> {
+        self.blocking(move |db| db.feerate_target_series(since))
+            .await
+    }
+
+    async fn record_block_tx(&self, block_height: u64, fee_rate: FeeRate) -> Result<()> {
+        self.blocking(move |db| db.record_block_tx(block_height, fee_rate))
+            .await
+    }
+
+    async fn record_fee_discrepancy(
+        &self,
+        txid: Txid,
+        computed_fee: Amount,
+        node_fee: Amount,
+    ) -> Result<()> {
+        self.blocking(move |db| db.record_fee_discrepancy(&txid, computed_fee, node_fee))
+            .await
+    }
+
+    async fn record_oversized_tx(&self, tx: &Transaction) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_oversized_tx(&tx)).await
+    }
+
+    async fn record_rbf(&self, tx: &Transaction, fee_total: u64, fee_rate: FeeRate) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.record_rbf(&tx, fee_total, fee_rate))
+            .await
+    }
+
+    async fn update_txid_by_inputs_hash(
+        &self,
+        tx: &Transaction,
+        absolute_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let tx = tx.clone();
+        self.blocking(move |db| db.update_txid_by_inputs_hash(&tx, absolute_fee, fee_rate))
+            .await
+    }
+
+    async fn stuck_txs(&self, older_than: u64) -> Result<Vec<Txid>> {
+        self.blocking(move |db| db.stuck_txs(older_than)).await
+    }
+
+    async fn txids_of_txs_not_in_list(&self, txids: Vec<Txid>) -> Result<Vec<Txid>> {
+        self.blocking(move |db| db.txids_of_txs_not_in_list(txids))
+            .await
+    }
+
+    async fn unconfirmed_fee_rates(&self) -> Result<Vec<FeeRate>> {
+        self.blocking(|db| db.unconfirmed_fee_rates()).await
+    }
+
+    async fn record_pruned_txs(&self, txids: Vec<Txid>) -> Result<()> {
+        self.blocking(move |db| db.record_pruned_txs(txids)).await
+    }
+
+    async fn record_mempool_state(
+        &self,
+        mempool_size: u64,
+        mempool_tx_count: u64,
+        block_height: Option<u64>,
+        block_hash: Option<BlockHash>,
+    ) -> Result<()> {
+        self.blocking(move |db| {
+            db.record_mempool_state(mempool_size, mempool_tx_count, block_height, block_hash)
+        })
+        .await
+    }
+
+    async fn audit(&self) -> Result<AuditReport> {
+        self.blocking(|db| db.audit()).await
+    }
+
+    async fn record_mining_info(&self, hash_rate_distribution: String) -> Result<()> {
+        self.blocking(move |db| db.record_mining_info(hash_rate_distribution))
+            .await
+    }
+
+    async fn record_node_info(&self, node_info: NodeInfo) -> Result<()> {
+        self.blocking(move |db| db.record_node_info(&node_info))
+            .await
+    }
+}
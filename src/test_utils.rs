@@ -0,0 +1,84 @@
+//! Fixtures shared by the unit tests
+
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::Result;
+use async_channel::Receiver;
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxOut, Txid, Witness,
+};
+use bitcoind_async_client::{Auth, Client};
+use tempfile::TempDir;
+
+use crate::{
+    bloom::BloomFilter,
+    database::Database,
+    worker::{InputsLocks, Task, TaskContext, WorkerConfig},
+};
+
+/// A migrated db in a fresh temp dir, which is deleted once the returned `TempDir` is dropped
+pub(crate) fn test_db() -> Result<(TempDir, Database)> {
+    let tempdir = TempDir::new()?;
+    let db_path = tempdir.path().join("test.db");
+    let db = Database::new(db_path.to_str().unwrap())?;
+    db.run_migrations()?;
+    Ok((tempdir, db))
+}
+
+/// An rpc client for a node that is never contacted, for code paths that only touch the db
+pub(crate) fn offline_client() -> Client {
+    Client::new(
+        "http://127.0.0.1:1".to_string(),
+        Auth::UserPass("user".to_string(), "password".to_string()),
+        None,
+        None,
+    )
+    .expect("valid rpc client")
+}
+
+/// A worker over `db` with an [`offline_client`] and the default config
+pub(crate) fn offline_task_context(
+    db: Database,
+    tasks: Receiver<Task>,
+    maintenance: Receiver<Task>,
+) -> TaskContext {
+    TaskContext::new(
+        offline_client(),
+        db,
+        tasks,
+        maintenance,
+        WorkerConfig::default(),
+        Arc::new(BloomFilter::new(1_000, 0.01)),
+        Arc::new(InputsLocks::new(1)),
+    )
+}
+
+/// A non-witness RBF signaling tx spending `outpoints` into one output of `value` sats
+pub(crate) fn spending_tx(outpoints: &[OutPoint], value: u64) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: outpoints
+            .iter()
+            .map(|outpoint| TxIn {
+                previous_output: *outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+/// Outpoint `vout` of the txid made of `byte` repeated, e.g. `outpoint("01", 0)`
+pub(crate) fn outpoint(byte: &str, vout: u32) -> OutPoint {
+    OutPoint {
+        txid: Txid::from_str(&byte.repeat(32)).expect("valid txid"),
+        vout,
+    }
+}
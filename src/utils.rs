@@ -1,14 +1,28 @@
 use anyhow::Result;
-use bitcoin::{consensus::Encodable, Amount, FeeRate, Transaction, TxIn};
+use bitcoin::{
+    absolute::LockTime,
+    consensus::{encode::serialize, Encodable},
+    Amount, FeeRate, Script, Transaction, TxIn, Txid,
+};
 use bitcoin_hashes::Sha256;
 use reqwest::Client as ReqwestClient;
 use serde_json::Value;
 
+/// Identity of a tx across replacements and witness pruning: the hash of each input's outpoint,
+/// scriptSig and sequence, the same bytes as the input's consensus encoding. Witnesses are
+/// never hashed, so a tx hashes the same before and after they are stripped.
 pub fn get_inputs_hash(inputs: impl IntoIterator<Item = TxIn>) -> Result<String> {
     let mut engine = Sha256::engine();
     for i in inputs {
         let mut writer = vec![];
-        i.consensus_encode(&mut writer)
+        i.previous_output
+            .consensus_encode(&mut writer)
+            .expect("encoding doesn't error");
+        i.script_sig
+            .consensus_encode(&mut writer)
+            .expect("encoding doesn't error");
+        i.sequence
+            .consensus_encode(&mut writer)
             .expect("encoding doesn't error");
         std::io::copy(&mut writer.as_slice(), &mut engine).expect("engine writes don't error");
     }
@@ -18,15 +32,237 @@ pub fn get_inputs_hash(inputs: impl IntoIterator<Item = TxIn>) -> Result<String>
     Ok(hex::encode(hash_bytes))
 }
 
-/// Compute the fee rate of a transaction
+/// `prev_hash` of the first record in the first-seen log
+pub const FIRST_SEEN_GENESIS_HASH: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Hash of a first-seen log record, committing to the previous record so reordering,
+/// editing or removing a record breaks every later link
+pub fn first_seen_hash(prev_hash: &str, txid: &str, found_at: u64) -> String {
+    let mut engine = Sha256::engine();
+    for part in [
+        prev_hash.as_bytes(),
+        txid.as_bytes(),
+        &found_at.to_be_bytes(),
+    ] {
+        std::io::copy(&mut &part[..], &mut engine).expect("engine writes don't error");
+    }
+    hex::encode(Sha256::from_engine(engine).as_byte_array())
+}
+
+/// Deterministically decide whether a tx is kept for a given sample rate.
+/// The same txid is always either kept or skipped.
+pub fn is_sampled(txid: &Txid, sample_rate: f64) -> bool {
+    is_key_sampled(&serialize(txid), sample_rate)
+}
+
+/// Like [`is_sampled`] but keyed by the inputs hash, so a tx and all its replacements are
+/// either kept or skipped together
+pub fn is_inputs_sampled(inputs_hash: &str, sample_rate: f64) -> bool {
+    is_key_sampled(inputs_hash.as_bytes(), sample_rate)
+}
+
+fn is_key_sampled(key: &[u8], sample_rate: f64) -> bool {
+    if sample_rate >= 1.0 {
+        return true;
+    }
+    if sample_rate <= 0.0 {
+        return false;
+    }
+    let hash = Sha256::hash(key);
+    let point = u64::from_be_bytes(
+        hash.as_byte_array()[..8]
+            .try_into()
+            .expect("sha256 is 32 bytes"),
+    );
+    (point as f64) < sample_rate * u64::MAX as f64
+}
+
+/// Whether a tx opts in to replacement per BIP125, i.e any input has nSequence below 0xfffffffe
+pub fn signals_rbf(tx: &Transaction) -> bool {
+    tx.input.iter().any(|input| input.sequence.0 < 0xfffffffe)
+}
+
+/// Whether any input enforces a BIP68 relative timelock (CSV), only valid from tx version 2
+pub fn uses_relative_timelock(tx: &Transaction) -> bool {
+    tx.version.0 >= 2
+        && tx
+            .input
+            .iter()
+            .any(|input| input.sequence.is_relative_lock_time())
+}
+
+/// Whether a tx has no absolute timelock in effect, either nLockTime is zero or every input disables it
+pub fn is_final(tx: &Transaction) -> bool {
+    tx.lock_time == LockTime::ZERO || !tx.is_lock_time_enabled()
+}
+
+/// scriptPubKeys of well known burn addresses, as hex
+const KNOWN_BURN_SCRIPTS: &[&str] = &[
+    // 1BitcoinEaterAddressDontSendf59kuE
+    "76a914759d6677091e973b9e9d99f19c68fbf43e3f05f988ac",
+];
+
+fn is_burn_script(script: &Script) -> bool {
+    script.is_op_return() || KNOWN_BURN_SCRIPTS.contains(&hex::encode(script.as_bytes()).as_str())
+}
+
+/// Value sent to OP_RETURN outputs or known burn addresses.
+/// Zero value OP_RETURN data outputs burn nothing, see [`data_output_count`].
+pub fn burned_amount(tx: &Transaction) -> Amount {
+    tx.output
+        .iter()
+        .filter(|output| output.value > Amount::ZERO && is_burn_script(&output.script_pubkey))
+        .map(|output| output.value)
+        .sum()
+}
+
+/// Outputs at or below this value are treated as dust by [`is_batch_payout`]
+const BATCH_PAYOUT_DUST_LIMIT: Amount = Amount::from_sat(546);
+/// Batch payouts are funded from a few consolidated inputs
+const BATCH_PAYOUT_MAX_INPUTS: usize = 3;
+
+/// Whether a tx looks like an exchange style batch payout: a few inputs paying at least
+/// `min_outputs` distinct scripts above dust
+pub fn is_batch_payout(tx: &Transaction, min_outputs: usize) -> bool {
+    if tx.is_coinbase() || tx.input.len() > BATCH_PAYOUT_MAX_INPUTS {
+        return false;
+    }
+    let scripts = tx
+        .output
+        .iter()
+        .filter(|output| output.value > BATCH_PAYOUT_DUST_LIMIT)
+        .map(|output| &output.script_pubkey)
+        .collect::<std::collections::HashSet<_>>();
+    scripts.len() >= min_outputs
+}
+
+/// TRUC (v3) txs are limited to this many vbytes
+const TRUC_MAX_VSIZE: usize = 10_000;
+/// A TRUC tx with an unconfirmed parent is limited to this many vbytes
+const TRUC_CHILD_MAX_VSIZE: usize = 1_000;
+
+pub fn is_truc(tx: &Transaction) -> bool {
+    tx.version == bitcoin::transaction::Version(3)
+}
+
+/// Whether a TRUC tx fits the v3 package topology given its unconfirmed parents:
+/// at most one, itself TRUC, with the child under the smaller size limit
+pub fn truc_respects_topology(
+    tx: &Transaction,
+    unconfirmed_parents: usize,
+    parents_are_truc: bool,
+) -> bool {
+    let vsize = tx.vsize();
+    match unconfirmed_parents {
+        0 => vsize <= TRUC_MAX_VSIZE,
+        1 => parents_are_truc && vsize <= TRUC_CHILD_MAX_VSIZE,
+        _ => false,
+    }
+}
+
+/// Heaviest tx relayed by default, `MAX_STANDARD_TX_WEIGHT`
+const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+/// Largest standard scriptSig, enough for a 15-of-15 p2sh multisig
+const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+/// Legacy sigops count 4x towards the `MAX_STANDARD_TX_SIGOPS_COST` of 16000
+const MAX_STANDARD_LEGACY_SIGOPS: usize = 16_000 / 4;
+
+/// First of our own standardness rules a tx breaks, mirroring Bitcoin Core's default policy.
+/// Checks only what the decoded tx shows, so e.g. p2sh sigops and dust are not considered.
+pub fn nonstandard_reason(tx: &Transaction) -> Option<&'static str> {
+    if !(1..=3).contains(&tx.version.0) {
+        return Some("version");
+    }
+    if tx.weight().to_wu() > MAX_STANDARD_TX_WEIGHT {
+        return Some("tx_weight");
+    }
+    for input in &tx.input {
+        if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+            return Some("scriptsig_size");
+        }
+        if !input.script_sig.is_push_only() {
+            return Some("scriptsig_not_push_only");
+        }
+    }
+    if tx
+        .output
+        .iter()
+        .any(|output| output.script_pubkey.is_multisig())
+    {
+        return Some("bare_multisig");
+    }
+    let legacy_sigops = tx
+        .input
+        .iter()
+        .map(|input| input.script_sig.count_sigops_legacy())
+        .chain(
+            tx.output
+                .iter()
+                .map(|output| output.script_pubkey.count_sigops_legacy()),
+        )
+        .sum::<usize>();
+    if legacy_sigops > MAX_STANDARD_LEGACY_SIGOPS {
+        return Some("sigops");
+    }
+    None
+}
+
+/// Number of zero value OP_RETURN outputs, used to carry data rather than burn coins
+pub fn data_output_count(tx: &Transaction) -> usize {
+    tx.output
+        .iter()
+        .filter(|output| output.value == Amount::ZERO && output.script_pubkey.is_op_return())
+        .count()
+}
+
+/// Fee rates are stored as integer sat/kwu, the native unit of `FeeRate`, so they round-trip exactly.
+/// The `fee_rate` REAL columns hold the same value as decimal sat/vB (1 sat/vB = 250 sat/kwu).
+/// Every output showing a fee rate (snapshots, the status line, fee estimates) goes through this
+/// rather than `FeeRate::to_sat_per_vb_ceil`, so e.g. 1.1 sat/vB isn't shown as 2.
+pub fn fee_rate_sat_per_vb(fee_rate: FeeRate) -> f64 {
+    fee_rate.to_sat_per_kwu() as f64 / 250.0
+}
+
+/// Fee rate of a decimal sat/vB given on the command line, rounded up to the next sat/kwu so
+/// minimums stay minimums
+pub fn sat_per_vb_fee_rate(sat_vb: f64) -> FeeRate {
+    FeeRate::from_sat_per_kwu((sat_vb.max(0.0) * 250.0).ceil() as u64)
+}
+
+/// Fee rate of a whole sat/vB, e.g. a [`crate::database::FEE_RATE_BUCKETS`] bound
+pub fn whole_sat_per_vb_fee_rate(sat_vb: u64) -> FeeRate {
+    FeeRate::from_sat_per_kwu(sat_vb * 250)
+}
+
+/// Nearest-rank percentile `p` (0-100) of ascending `fee_rates`
+pub fn fee_rate_percentile(fee_rates: &[FeeRate], p: u8) -> Option<FeeRate> {
+    if fee_rates.is_empty() {
+        return None;
+    }
+    let rank = (p as usize * fee_rates.len()).div_ceil(100).max(1);
+    Some(fee_rates[rank.min(fee_rates.len()) - 1])
+}
+
+/// Compute the fee rate of a transaction, in sat/kwu precision.
+/// Zero fee txs are valid and get `FeeRate::ZERO`, only an overflowing fee is an error.
 pub fn compute_fee_rate(tx: &Transaction, absolute_fee: Amount) -> Result<FeeRate> {
     if tx.is_coinbase() {
         return Ok(FeeRate::ZERO);
     }
-    let weight = tx.weight();
-    let fee_rate = FeeRate::from_sat_per_vb(absolute_fee.to_sat() / weight.to_vbytes_ceil())
-        .ok_or(anyhow::anyhow!("Fee rate is 0"))?;
-    Ok(fee_rate)
+    let weight = tx.weight().to_wu();
+    if weight == 0 || absolute_fee == Amount::ZERO {
+        return Ok(FeeRate::ZERO);
+    }
+    let sat_per_kwu = absolute_fee
+        .to_sat()
+        .checked_mul(1000)
+        .ok_or(anyhow::anyhow!(
+            "Fee rate overflows for fee {}",
+            absolute_fee
+        ))?
+        / weight;
+    Ok(FeeRate::from_sat_per_kwu(sat_per_kwu))
 }
 
 pub async fn get_hash_rate_distribution() -> Result<String> {
@@ -41,3 +277,55 @@ pub async fn get_hash_rate_distribution() -> Result<String> {
     let json: Value = serde_json::from_str(&response)?;
     Ok(json.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{outpoint, spending_tx};
+    use bitcoin::{blockdata::script::Builder, opcodes::all, transaction::Version};
+
+    #[test]
+    fn test_replacements_share_sampling() {
+        let sampled = (0..100u32)
+            .filter(|vout| {
+                let original = spending_tx(&[outpoint("01", *vout)], 90_000);
+                let replacement = spending_tx(&[outpoint("01", *vout)], 80_000);
+                let inputs_hash = get_inputs_hash(original.input.clone()).unwrap();
+                assert_eq!(
+                    inputs_hash,
+                    get_inputs_hash(replacement.input.clone()).unwrap()
+                );
+                is_inputs_sampled(&inputs_hash, 0.5)
+            })
+            .count();
+        assert!(sampled > 0 && sampled < 100);
+        assert!(is_inputs_sampled("any", 1.0));
+        assert!(!is_inputs_sampled("any", 0.0));
+    }
+
+    #[test]
+    fn test_nonstandard_reason() {
+        let standard = spending_tx(&[outpoint("01", 0)], 10_000);
+        assert_eq!(nonstandard_reason(&standard), None);
+
+        let mut bare_multisig = spending_tx(&[outpoint("02", 0)], 10_000);
+        bare_multisig.output[0].script_pubkey = Builder::new()
+            .push_int(1)
+            .push_slice([2u8; 33])
+            .push_int(1)
+            .push_opcode(all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(nonstandard_reason(&bare_multisig), Some("bare_multisig"));
+
+        let mut non_push = spending_tx(&[outpoint("03", 0)], 10_000);
+        non_push.input[0].script_sig = Builder::new().push_opcode(all::OP_DUP).into_script();
+        assert_eq!(
+            nonstandard_reason(&non_push),
+            Some("scriptsig_not_push_only")
+        );
+
+        let mut version = spending_tx(&[outpoint("04", 0)], 10_000);
+        version.version = Version(4);
+        assert_eq!(nonstandard_reason(&version), Some("version"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashSet, str::FromStr};
+
+use anyhow::Result;
+use bitcoin::{Amount, ScriptBuf, Transaction};
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+/// Scripts derived from a watch-only descriptor, matched against the outputs of incoming txs
+#[derive(Debug, Clone)]
+pub struct WatchedScripts {
+    scripts: HashSet<ScriptBuf>,
+}
+
+impl WatchedScripts {
+    /// Derive the scripts of `descriptor` at indexes `0..range`, for both paths of a
+    /// `<0;1>` multipath descriptor. Descriptors without a wildcard yield a single script.
+    pub fn from_descriptor(descriptor: &str, range: u32) -> Result<Self> {
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor)?;
+        let mut scripts = HashSet::new();
+        for descriptor in descriptor.into_single_descriptors()? {
+            let indexes = if descriptor.has_wildcard() { range } else { 1 };
+            for index in 0..indexes {
+                scripts.insert(descriptor.at_derivation_index(index)?.script_pubkey());
+            }
+        }
+        Ok(Self { scripts })
+    }
+
+    pub fn script_count(&self) -> usize {
+        self.scripts.len()
+    }
+
+    /// Index and value of every output of `tx` paying a watched script
+    pub fn matching_outputs(&self, tx: &Transaction) -> Vec<(u32, Amount)> {
+        tx.output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| self.scripts.contains(&output.script_pubkey))
+            .map(|(vout, output)| (vout as u32, output.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute::LockTime, transaction::Version, TxIn, TxOut};
+
+    // BIP84 test vector account xpub
+    const DESCRIPTOR: &str = "wpkh([73c5da0a/84h/0h/0h]xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*)";
+
+    #[test]
+    fn test_watched_scripts_match_derived_outputs() -> Result<()> {
+        let watched = WatchedScripts::from_descriptor(DESCRIPTOR, 10)?;
+        assert_eq!(watched.script_count(), 10);
+
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(DESCRIPTOR)?;
+        let fifth = descriptor.at_derivation_index(5)?.script_pubkey();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                TxOut {
+                    value: Amount::from_sat(2_000),
+                    script_pubkey: fifth,
+                },
+            ],
+        };
+        assert_eq!(
+            watched.matching_outputs(&tx),
+            vec![(1, Amount::from_sat(2_000))]
+        );
+        // Past the derived range
+        let beyond = descriptor.at_derivation_index(10)?.script_pubkey();
+        assert!(!watched.scripts.contains(&beyond));
+
+        Ok(())
+    }
+}
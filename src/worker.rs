@@ -1,12 +1,45 @@
+use std::{
+    collections::{
+        hash_map::{Entry, RandomState},
+        BTreeMap, HashMap, HashSet, VecDeque,
+    },
+    future::Future,
+    hash::BuildHasher,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
 use crate::{
-    database::Database,
-    utils::{compute_fee_rate, get_hash_rate_distribution},
+    bloom::BloomFilter,
+    database::{Database, DeferredTx},
+    fee_validation::FeeValidator,
+    filter::TxFilter,
+    node_info::NodeInfo,
+    now,
+    rpc::{RawRpcClient, ResilientClient},
+    snapshot_file::{fee_rate_percentiles, SnapshotFile},
+    status_line::StatusLine,
+    store::Store,
+    utils::{
+        compute_fee_rate, get_hash_rate_distribution, get_inputs_hash, is_batch_payout,
+        is_inputs_sampled, is_sampled, nonstandard_reason,
+    },
+    watch::WatchedScripts,
 };
 use anyhow::Result;
 use async_channel::Receiver;
-use bitcoin::{consensus::Decodable, Amount, Transaction};
+use bitcoin::{
+    consensus::{encode::serialize_hex, Decodable},
+    Amount, BlockHash, FeeRate, OutPoint, ScriptBuf, Transaction, Txid,
+};
 use bitcoind_async_client::{traits::Reader, Client};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use serde_json::json;
+use tokio::sync::{Mutex, MutexGuard};
 
 // Macro to execute a function, if its error, log it and continue
 macro_rules! log_error {
@@ -35,83 +68,715 @@ pub enum Task {
     PruneCheck,
     MempoolState,
     MiningInfo,
+    Audit,
+    NodeInfo,
+    /// Retry the fee lookup of txs deferred after a failed one
+    RecomputeFees,
+    /// Mark txs tracked as unconfirmed for longer than `WorkerConfig::stuck_tx_age` pruned
+    /// if they're gone from the node's mempool
+    ReconcileStuckTxs,
+}
+
+/// Options controlling how workers process incoming transactions
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// Fraction (0.0-1.0) of mempool txs to store individually
+    pub sample_rate: f64,
+    /// Txs heavier than this are only noted in `oversized_txs`, their fees are never fetched
+    pub max_tx_weight: Option<u64>,
+    /// Where mempool state snapshots are also streamed as NDJSON
+    pub snapshot_file: Option<Arc<SnapshotFile>>,
+    /// Process txs without a node, they are stored as unconfirmed with a zero fee
+    pub offline: bool,
+    /// Look up the age of every spent coin, an extra rpc call per distinct parent tx
+    pub track_coin_age: bool,
+    /// Txs the filter rejects are only counted in `filtered_txs`
+    pub filter: TxFilter,
+    /// Txids processed recently by any worker, repeats within the window are skipped
+    pub recent_txids: Arc<RecentTxids>,
+    /// Next arrival sequence number, shared by all workers.
+    /// Orders txs found within the same second, which `found_at` can't.
+    pub arrival_seq: Arc<AtomicU64>,
+    /// Cross-checks a sample of computed fees against the node when set
+    pub fee_validator: Option<Arc<FeeValidator>>,
+    /// Distinct non-dust outputs from which a tx is tagged as a batch payout
+    pub batch_payout_min_outputs: usize,
+    /// Txs dropped on errors by any worker, summarized in a periodic warning
+    pub dropped_txs: Arc<DroppedTxs>,
+    /// Fraction (0.0-1.0) of tracked txs whose found_at is compared with the node's entry time
+    /// on prune checks, 0 keeps the cheaper non-verbose getrawmempool
+    pub time_drift_sample_rate: f64,
+    /// Outputs paying these scripts are recorded in `watched_txs`
+    pub watched_scripts: Option<Arc<WatchedScripts>>,
+    /// Raw json-rpc client for methods the async client lacks, used by `NodeInfo` tasks and
+    /// the tip lookup of mempool state snapshots
+    pub raw_rpc: Option<Arc<RawRpcClient>>,
+    /// Consecutive prune checks a tx must be missing from the node's mempool for before it's marked pruned
+    pub prune_grace: Arc<PruneGrace>,
+    /// Count the tracked unconfirmed txs by nVersion and by size on every mempool state snapshot
+    pub record_histograms: bool,
+    /// Value inputs spending tracked unconfirmed txs from the db instead of the node,
+    /// which doesn't need txindex for mempool parents. The count lands in `unconfirmed_inputs`.
+    pub prevouts_from_db: bool,
+    /// Run our standardness checks on every tx, recording failures in `nonstandard_txs`
+    pub check_standardness: bool,
+    /// Mempool state tasks arriving shortly after the last snapshot, e.g. from two triggers
+    /// firing together, are skipped rather than stored as a near duplicate
+    pub mempool_state_debounce: Arc<Debounce>,
+    /// Absolute fees above this are flagged in `fee_anomaly` and logged, rather than trusted
+    pub fee_anomaly_threshold: Amount,
+    /// Set with --aggregates-only: unconfirmed txs are only kept here to feed the fee rate
+    /// percentiles of each snapshot, never stored individually
+    pub live_fee_rates: Option<Arc<LiveFeeRates>>,
+    /// Txs whose fee lookup fails are kept in `deferred_fee_txs` and retried by
+    /// `Task::RecomputeFees` up to this many times, instead of dropped right away
+    pub max_fee_retries: Option<u32>,
+    /// Outputs of new txs paying a recently seen script are counted in `reused_outputs`,
+    /// and each mempool state snapshot sums them in `address_reuse_counts`
+    pub recent_scripts: Option<Arc<RecentScripts>>,
+    /// Prune checks first ask getmempoolinfo and skip the full getrawmempool when the
+    /// node's mempool tx count and size are unchanged since the last full fetch
+    pub prune_check_skip: Option<Arc<PruneCheckSkip>>,
+    /// Unconfirmed txs first seen longer ago than this are cross-checked with the node's mempool
+    /// by `Task::ReconcileStuckTxs`
+    pub stuck_tx_age: Duration,
+    /// Summarizes each mempool state snapshot and the txs stored since the last in one line
+    pub status_line: Option<Arc<StatusLine>>,
+    /// Tries at a matching tip height and hash for a mempool state snapshot while blocks arrive or
+    /// reorg between the lookups
+    pub tip_lookup_attempts: u32,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 1.0,
+            max_tx_weight: None,
+            snapshot_file: None,
+            offline: false,
+            track_coin_age: false,
+            filter: TxFilter::default(),
+            recent_txids: Arc::new(RecentTxids::new(Duration::ZERO)),
+            arrival_seq: Arc::new(AtomicU64::new(0)),
+            fee_validator: None,
+            batch_payout_min_outputs: 10,
+            dropped_txs: Arc::new(DroppedTxs::new(Duration::from_secs(60))),
+            time_drift_sample_rate: 0.0,
+            watched_scripts: None,
+            raw_rpc: None,
+            prune_grace: Arc::new(PruneGrace::new(1)),
+            record_histograms: true,
+            prevouts_from_db: false,
+            check_standardness: false,
+            mempool_state_debounce: Arc::new(Debounce::new(Duration::ZERO)),
+            fee_anomaly_threshold: Amount::ONE_BTC,
+            max_fee_retries: None,
+            live_fee_rates: None,
+            recent_scripts: None,
+            prune_check_skip: None,
+            stuck_tx_age: Duration::from_secs(24 * 60 * 60),
+            status_line: None,
+            tip_lookup_attempts: 3,
+        }
+    }
+}
+
+impl WorkerConfig {
+    pub fn is_oversized(&self, tx: &Transaction) -> bool {
+        self.max_tx_weight
+            .is_some_and(|max_tx_weight| tx.weight().to_wu() > max_tx_weight)
+    }
+
+    pub fn is_fee_anomaly(&self, fee: Amount) -> bool {
+        fee > self.fee_anomaly_threshold
+    }
+
+    /// found_at and retry count to defer a tx with after a failed fee lookup, `deferred` being
+    /// those of an earlier deferral. None when it should be dropped instead.
+    pub fn fee_deferral(&self, deferred: Option<(u64, u32)>, now: u64) -> Option<(u64, u32)> {
+        let max_fee_retries = self.max_fee_retries?;
+        let (found_at, retries) = match deferred {
+            Some((found_at, retries)) => (found_at, retries + 1),
+            None => (now, 0),
+        };
+        (retries < max_fee_retries).then_some((found_at, retries))
+    }
 }
 
-pub struct TaskContext {
-    bitcoind: Client,
-    db: Database,
+/// Deferred txs retried per `Task::RecomputeFees`, the rest wait for the next one
+const RECOMPUTE_FEES_BATCH: usize = 1_000;
+
+pub struct TaskContext<S: Store = Database> {
+    bitcoind: ResilientClient,
+    db: S,
     tasks: Receiver<Task>,
+    /// Scheduled tasks, taken before `tasks` so a flood of txs can't delay them
+    maintenance: Receiver<Task>,
+    config: WorkerConfig,
+    /// Inputs hashes of tracked txs, checked before hitting the db in `tx_exists`
+    known_inputs: Arc<BloomFilter>,
+    inputs_locks: Arc<InputsLocks>,
+}
+
+/// The tip hash and its height. The height is read from the header of that hash, so the pair
+/// matches even if blocks arrive or reorg in between, and the lookups are retried when either
+/// fails, e.g. mid reorg.
+pub(crate) async fn consistent_tip<B, BF, H, HF>(
+    mut best_block_hash: B,
+    mut header_height: H,
+    max_attempts: u32,
+) -> Result<(u64, BlockHash)>
+where
+    B: FnMut() -> BF,
+    BF: Future<Output = Result<BlockHash>>,
+    H: FnMut(BlockHash) -> HF,
+    HF: Future<Output = Result<u64>>,
+{
+    let max_attempts = max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let lookup = match best_block_hash().await {
+            Ok(hash) => header_height(hash).await.map(|height| (height, hash)),
+            Err(e) => Err(e),
+        };
+        match lookup {
+            Ok(tip) => return Ok(tip),
+            Err(e) if attempt < max_attempts => {
+                debug!("Error looking up the tip, retrying: {}", e);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The node's best block hash and the height from its header, see [`consistent_tip`]
+async fn rpc_tip(rpc: &RawRpcClient, max_attempts: u32) -> Result<(u64, BlockHash)> {
+    consistent_tip(
+        || async move {
+            let hash = rpc.result("getbestblockhash", json!([])).await?;
+            Ok(BlockHash::from_str(hash.as_str().ok_or(
+                anyhow::anyhow!("getbestblockhash did not return a string"),
+            )?)?)
+        },
+        |hash| async move {
+            let header = rpc
+                .result("getblockheader", json!([hash.to_string(), true]))
+                .await?;
+            header["height"]
+                .as_u64()
+                .ok_or(anyhow::anyhow!("getblockheader returned no height"))
+        },
+        max_attempts,
+    )
+    .await
 }
 
 /// Return absolute fee of a transaction
-pub async fn get_absolute_fee(tx: &Transaction, rpc_client: &Client) -> Result<Amount> {
+pub async fn get_absolute_fee(tx: &Transaction, rpc_client: &ResilientClient) -> Result<Amount> {
+    let prevouts = get_absolute_fee_with_prevouts(tx, rpc_client, &HashMap::new(), false).await?;
+    Ok(prevouts.fee)
+}
+
+/// What [`get_absolute_fee_with_prevouts`] learned from the prevouts of a tx
+pub struct PrevoutFee {
+    pub fee: Amount,
+    /// How many inputs were valued from the known prevouts
+    pub known_inputs: usize,
+    /// Age of each spent coin if asked for, see [`get_absolute_fee_with_prevouts`]
+    pub coin_ages: Option<Vec<u64>>,
+}
+
+/// Like [`get_absolute_fee`], but prevouts found in `known_prevouts` aren't fetched from the node.
+/// With `coin_ages` the parents are fetched verbose so the age in blocks of each spent coin,
+/// i.e how many blocks ago its prevout was confirmed, comes with the same lookups. Known
+/// prevouts spend tracked unconfirmed txs and have an age of 0.
+pub async fn get_absolute_fee_with_prevouts(
+    tx: &Transaction,
+    rpc_client: &ResilientClient,
+    known_prevouts: &HashMap<OutPoint, Amount>,
+    coin_ages: bool,
+) -> Result<PrevoutFee> {
     if tx.is_coinbase() {
-        return Ok(Amount::ZERO);
+        return Ok(PrevoutFee {
+            fee: Amount::ZERO,
+            known_inputs: 0,
+            coin_ages: coin_ages.then(Vec::new),
+        });
     }
     let mut input_value = Amount::from_sat(0);
+    let mut known_inputs = 0;
+    let mut ages = vec![];
+    let mut parents: HashMap<Txid, (Transaction, u64)> = HashMap::new();
     for vin in tx.input.iter() {
         if vin.previous_output.is_null() {
             continue;
         }
-        debug!("Getting input tx: {:?}", vin.previous_output.txid);
-        let prev_tx = rpc_client
-            .get_raw_transaction_verbosity_zero(&vin.previous_output.txid)
-            .await?
-            .transaction()?;
-        let prev_txout = prev_tx.output[vin.previous_output.vout as usize].clone();
-        let prev_txout_value = prev_txout.value;
-        input_value += prev_txout_value;
+        if let Some(value) = known_prevouts.get(&vin.previous_output) {
+            input_value = input_value
+                .checked_add(*value)
+                .ok_or(anyhow::anyhow!("Input value overflows"))?;
+            known_inputs += 1;
+            ages.push(0);
+            continue;
+        }
+        let prev_txid = vin.previous_output.txid;
+        let (prev_tx, prev_confirmations) = match parents.entry(prev_txid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                debug!("Getting input tx: {:?}", prev_txid);
+                let parent = rpc_client
+                    .call(|client| async move {
+                        if !coin_ages {
+                            let prev_tx = client
+                                .get_raw_transaction_verbosity_zero(&prev_txid)
+                                .await?
+                                .transaction()?;
+                            return anyhow::Ok((prev_tx, 0));
+                        }
+                        let prev_tx_info =
+                            client.get_raw_transaction_verbosity_one(&prev_txid).await?;
+                        anyhow::Ok((
+                            prev_tx_info.transaction,
+                            prev_tx_info.confirmations.unwrap_or(0) as u64,
+                        ))
+                    })
+                    .await?;
+                entry.insert(parent)
+            }
+        };
+        let prev_txout = prev_tx
+            .output
+            .get(vin.previous_output.vout as usize)
+            .ok_or(anyhow::anyhow!(
+                "Prevout {} not found in its tx",
+                vin.previous_output
+            ))?;
+        input_value = input_value
+            .checked_add(prev_txout.value)
+            .ok_or(anyhow::anyhow!("Input value overflows"))?;
+        ages.push(prev_confirmations.saturating_sub(1));
     }
-    let output_value = tx.output.iter().map(|vout| vout.value).sum();
-    let fee = input_value - output_value;
-    Ok(fee)
+    let output_value = tx
+        .output
+        .iter()
+        .try_fold(Amount::ZERO, |total, vout| total.checked_add(vout.value))
+        .ok_or(anyhow::anyhow!("Output value overflows"))?;
+    let fee = input_value.checked_sub(output_value).ok_or(NegativeFee {
+        txid: tx.compute_txid(),
+        input_value,
+        output_value,
+    })?;
+    Ok(PrevoutFee {
+        fee,
+        known_inputs,
+        coin_ages: coin_ages.then_some(ages),
+    })
+}
+
+/// A tx spending less than it pays out, i.e a misfetched prevout rather than a valid tx
+#[derive(Debug)]
+pub struct NegativeFee {
+    pub txid: Txid,
+    pub input_value: Amount,
+    pub output_value: Amount,
 }
 
-impl TaskContext {
-    pub fn new(bitcoind: Client, db: Database, tasks: Receiver<Task>) -> Self {
+impl std::fmt::Display for NegativeFee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Inputs of {} ({}) are worth less than its outputs ({})",
+            self.txid, self.input_value, self.output_value
+        )
+    }
+}
+
+impl std::error::Error for NegativeFee {}
+
+impl<S: Store> TaskContext<S> {
+    pub fn new(
+        bitcoind: Client,
+        db: S,
+        tasks: Receiver<Task>,
+        maintenance: Receiver<Task>,
+        config: WorkerConfig,
+        known_inputs: Arc<BloomFilter>,
+        inputs_locks: Arc<InputsLocks>,
+    ) -> Self {
         Self {
-            bitcoind,
+            bitcoind: ResilientClient::new(bitcoind),
             db,
             tasks,
+            maintenance,
+            config,
+            known_inputs,
+            inputs_locks,
         }
     }
 
     async fn check_for_pruned_txs(&self) -> Result<()> {
         info!("Checking for pruned txs");
-        let txids = self.bitcoind.get_raw_mempool().await?;
-        let db = self.db.clone();
-        let pruned_txids =
-            tokio::task::spawn_blocking(move || db.txids_of_txs_not_in_list(txids)).await??;
-        info!("Found {} pruned txs", pruned_txids.len());
-        self.db.record_pruned_txs(pruned_txids)?;
-        self.db.flush()?;
+        let Some(prune_check_skip) = &self.config.prune_check_skip else {
+            return self.full_prune_check().await;
+        };
+        // Without mempool info the full list is fetched as usual
+        let mempool = match self
+            .bitcoind
+            .call(|client| async move { anyhow::Ok(client.get_mempool_info().await?) })
+            .await
+        {
+            Ok(mempool_info) => {
+                let mempool = (mempool_info.size as u64, mempool_info.bytes as u64);
+                if prune_check_skip.should_skip(mempool) {
+                    debug!("Mempool unchanged since the last prune check, skipping it");
+                    return Ok(());
+                }
+                Some(mempool)
+            }
+            Err(e) => {
+                warn!("Error getting mempool info for the prune check: {}", e);
+                None
+            }
+        };
+        self.full_prune_check().await?;
+        // Only a completed check may let the next ones be skipped
+        if let Some(mempool) = mempool {
+            prune_check_skip.record_full_check(mempool);
+        }
         Ok(())
     }
 
+    async fn full_prune_check(&self) -> Result<()> {
+        let txids = if self.config.time_drift_sample_rate > 0.0 {
+            let entry_times = self
+                .bitcoind
+                .call(|client| async move {
+                    let mempool = client.get_raw_mempool_verbose().await?;
+                    anyhow::Ok(
+                        mempool
+                            .into_iter()
+                            .map(|(txid, entry)| (txid, entry.time))
+                            .collect::<HashMap<_, _>>(),
+                    )
+                })
+                .await?;
+            if let Err(e) = self.record_time_drifts(&entry_times).await {
+                warn!("Error recording time drift: {}", e);
+            }
+            entry_times.into_keys().collect()
+        } else {
+            self.raw_mempool().await?
+        };
+        if let Some(live_fee_rates) = &self.config.live_fee_rates {
+            live_fee_rates.retain(&txids);
+            return Ok(());
+        }
+        let absent_txids = self.db.txids_of_txs_not_in_list(txids).await?;
+        let absent = absent_txids.len();
+        let pruned_txids = self.config.prune_grace.confirm_absent(absent_txids);
+        info!(
+            "Found {} txs gone from the mempool, {} more missing within the grace period",
+            pruned_txids.len(),
+            absent - pruned_txids.len()
+        );
+        self.record_gone_txs(pruned_txids).await
+    }
+
+    async fn raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.bitcoind
+            .call(|client| async move { anyhow::Ok(client.get_raw_mempool().await?) })
+            .await
+    }
+
+    /// Record txs gone from the node's mempool as mined if the node has them confirmed, as
+    /// pruned otherwise. Without -txindex the node can't look up a mined tx, so it counts as pruned.
+    async fn record_gone_txs(&self, gone: Vec<Txid>) -> Result<()> {
+        let mut pruned_txids = vec![];
+        for txid in gone {
+            match self.confirmed_tx(txid).await {
+                Ok(Some(tx)) => {
+                    info!("Transaction gone from the mempool was mined: {:?}", txid);
+                    self.db.record_mined_tx(&tx).await?;
+                }
+                Ok(None) => pruned_txids.push(txid),
+                Err(e) => {
+                    debug!(
+                        "Looking up gone tx {:?} failed, marking it pruned: {}",
+                        txid, e
+                    );
+                    pruned_txids.push(txid);
+                }
+            }
+        }
+        info!("Marking {} txs pruned", pruned_txids.len());
+        self.db.record_pruned_txs(pruned_txids).await?;
+        self.db.flush().await?;
+        Ok(())
+    }
+
+    /// The tx if the node has it confirmed
+    async fn confirmed_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let confirmations = self
+            .bitcoind
+            .call(|client| async move {
+                let tx_info = client.get_raw_transaction_verbosity_one(&txid).await?;
+                anyhow::Ok(tx_info.confirmations.unwrap_or(0))
+            })
+            .await?;
+        if confirmations == 0 {
+            return Ok(None);
+        }
+        let tx = self
+            .bitcoind
+            .call(|client| async move {
+                anyhow::Ok(
+                    client
+                        .get_raw_transaction_verbosity_zero(&txid)
+                        .await?
+                        .transaction()?,
+                )
+            })
+            .await?;
+        Ok(Some(tx))
+    }
+
+    /// Unlike prune checks there's no grace period, these txs are old enough already
+    async fn reconcile_stuck_txs(&self) -> Result<()> {
+        let stuck_txids = self
+            .db
+            .stuck_txs(self.config.stuck_tx_age.as_secs())
+            .await?;
+        if stuck_txids.is_empty() {
+            return Ok(());
+        }
+        let mempool = self
+            .raw_mempool()
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        // An empty mempool is more likely a node issue, see txids_of_txs_not_in_list
+        if mempool.is_empty() {
+            return Ok(());
+        }
+        let gone = stuck_txids
+            .iter()
+            .filter(|txid| !mempool.contains(*txid))
+            .copied()
+            .collect::<Vec<_>>();
+        info!(
+            "Found {} stuck txs, {} gone from the mempool",
+            stuck_txids.len(),
+            gone.len()
+        );
+        self.record_gone_txs(gone).await
+    }
+
+    /// The next maintenance task if any is queued, else the next task. None once both
+    /// channels are closed and drained.
+    async fn next_task(&self) -> Option<Task> {
+        tokio::select! {
+            biased;
+            Ok(task) = self.maintenance.recv() => Some(task),
+            Ok(task) = self.tasks.recv() => Some(task),
+            else => None,
+        }
+    }
+
     pub async fn run(&mut self) -> Result<()> {
-        while let Ok(task) = self.tasks.recv().await {
+        while let Some(task) = self.next_task().await {
             match task {
                 Task::MiningInfo => {
                     info!("Mining info task received");
                     let hash_rate_distribution = get_hash_rate_distribution().await?;
                     info!("Hash rate distribution: {}", hash_rate_distribution);
-                    self.db.record_mining_info(hash_rate_distribution)?;
-                    self.db.flush()?;
+                    self.db.record_mining_info(hash_rate_distribution).await?;
+                    self.db.flush().await?;
+                }
+                Task::NodeInfo => {
+                    info!("Node info task received");
+                    let Some(rpc) = &self.config.raw_rpc else {
+                        warn!("Node info task received without a raw rpc client");
+                        continue;
+                    };
+                    let node_info = match NodeInfo::fetch(rpc).await {
+                        Ok(node_info) => node_info,
+                        Err(e) => {
+                            error!("Error getting node info: {}", e);
+                            continue;
+                        }
+                    };
+                    info!(
+                        "Node has {} connections, peer protocol versions: {:?}",
+                        node_info.connections, node_info.protocol_versions
+                    );
+                    if let Err(e) = self.db.record_node_info(node_info).await {
+                        error!("Error recording node info: {}", e);
+                    }
+                }
+                Task::Audit => {
+                    info!("Audit task received");
+                    let report = match self.db.audit().await {
+                        Ok(report) => report,
+                        Err(e) => {
+                            error!("Error auditing db: {}", e);
+                            continue;
+                        }
+                    };
+                    if report.violations() > 0 {
+                        warn!(
+                            "Audit found {} violations: {:?}",
+                            report.violations(),
+                            report
+                        );
+                    } else {
+                        info!("Audit found no violations");
+                    }
                 }
                 Task::MempoolState => {
                     info!("Mempool state task received");
-                    let mempool_info = self.bitcoind.get_mempool_info().await?;
-                    let block_height = self.bitcoind.get_block_count().await?;
-                    let block_hash = self.bitcoind.get_block_hash(block_height).await?;
-                    if let Err(e) = self.db.record_mempool_state(
-                        mempool_info.bytes as u64,
-                        mempool_info.size as u64,
-                        block_height,
-                        block_hash,
-                    ) {
+                    if !self.config.mempool_state_debounce.ready() {
+                        debug!("Skipping mempool state, the last snapshot is too recent");
+                        continue;
+                    }
+                    let mempool_info = match self
+                        .bitcoind
+                        .call(|client| async move { anyhow::Ok(client.get_mempool_info().await?) })
+                        .await
+                    {
+                        Ok(mempool_info) => mempool_info,
+                        Err(e) => {
+                            error!("Error getting mempool info: {}", e);
+                            self.config.mempool_state_debounce.finish(false);
+                            continue;
+                        }
+                    };
+                    // Still record size and count when the block lookups fail
+                    let bitcoind = &self.bitcoind;
+                    let tip = match &self.config.raw_rpc {
+                        Some(rpc) => rpc_tip(rpc, self.config.tip_lookup_attempts).await,
+                        None => Err(anyhow::anyhow!("no raw rpc client to look up the tip")),
+                    };
+                    let (block_height, block_hash) = match tip {
+                        Ok((block_height, block_hash)) => (Some(block_height), Some(block_hash)),
+                        Err(e) => {
+                            warn!("Recording mempool state without block hash: {}", e);
+                            // A height alone still places the snapshot
+                            match bitcoind
+                                .call(|client| async move {
+                                    anyhow::Ok(client.get_block_count().await?)
+                                })
+                                .await
+                            {
+                                Ok(block_height) => (Some(block_height), None),
+                                Err(e) => {
+                                    warn!("Recording mempool state without block height: {}", e);
+                                    (None, None)
+                                }
+                            }
+                        }
+                    };
+                    if block_height.is_some() {
+                        self.db.set_tip_height(block_height);
+                    }
+                    let recorded = self
+                        .db
+                        .record_mempool_state(
+                            mempool_info.bytes as u64,
+                            mempool_info.size as u64,
+                            block_height,
+                            block_hash,
+                        )
+                        .await;
+                    self.config.mempool_state_debounce.finish(recorded.is_ok());
+                    if let Err(e) = recorded {
                         error!("Error recording mempool state: {}", e);
                         continue;
                     }
+                    if self.config.record_histograms {
+                        if let Err(e) = self.db.record_tx_version_counts().await {
+                            warn!("Error recording tx version counts: {}", e);
+                        }
+                        if let Err(e) = self.db.record_size_distribution().await {
+                            warn!("Error recording size distribution: {}", e);
+                        }
+                    }
+                    if let Some(live_fee_rates) = &self.config.live_fee_rates {
+                        if let Err(e) = self
+                            .db
+                            .record_fee_rate_percentiles(live_fee_rates.fee_rates())
+                            .await
+                        {
+                            warn!("Error recording fee rate percentiles: {}", e);
+                        }
+                    }
+                    if self.config.recent_scripts.is_some() {
+                        if let Err(e) = self.db.record_address_reuse().await {
+                            warn!("Error recording address reuse: {}", e);
+                        }
+                    }
+                    if self.config.snapshot_file.is_none() && self.config.status_line.is_none() {
+                        continue;
+                    }
+                    // Shared by the snapshot file and the status line
+                    let fee_rates = match &self.config.live_fee_rates {
+                        Some(live_fee_rates) => Ok(live_fee_rates.fee_rates()),
+                        None => self.db.unconfirmed_fee_rates().await,
+                    };
+                    let fee_rates = match fee_rates {
+                        Ok(fee_rates) => fee_rates,
+                        Err(e) => {
+                            error!("Error getting fee rates of tracked txs: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Some(snapshot_file) = &self.config.snapshot_file {
+                        let record = json!({
+                            "created_at": now!(),
+                            "size": mempool_info.bytes,
+                            "tx_count": mempool_info.size,
+                            "block_height": block_height,
+                            // Same encoding as the stored mempool_state rows
+                            "block_hash": block_hash.map(|block_hash| serialize_hex(&block_hash)),
+                            "fee_rate_percentiles": fee_rate_percentiles(&fee_rates),
+                        });
+                        if let Err(e) = snapshot_file.clone().append_blocking(record).await {
+                            error!("Error writing snapshot file: {}", e);
+                        }
+                    }
+                    if let Some(status_line) = &self.config.status_line {
+                        status_line.draw(
+                            mempool_info.size as u64,
+                            mempool_info.bytes as u64,
+                            block_height,
+                            &fee_rates,
+                            self.config.dropped_txs.total(),
+                        );
+                    }
+                }
+                Task::RecomputeFees => {
+                    let deferred = match self.db.take_deferred_fee_txs(RECOMPUTE_FEES_BATCH).await {
+                        Ok(deferred) => deferred,
+                        Err(e) => {
+                            error!("Error loading deferred txs: {}", e);
+                            continue;
+                        }
+                    };
+                    if !deferred.is_empty() {
+                        info!("Recomputing fees of {} deferred txs", deferred.len());
+                    }
+                    for deferred in deferred {
+                        self.process_tx(
+                            deferred.tx,
+                            deferred.arrival_seq,
+                            Some((deferred.found_at, deferred.retries)),
+                        )
+                        .await?;
+                    }
+                }
+                Task::ReconcileStuckTxs => {
+                    info!("Reconcile stuck txs task received");
+                    log_error!(Self::reconcile_stuck_txs, self);
                 }
                 Task::PruneCheck => {
                     info!("Prune check task received");
@@ -120,59 +785,1167 @@ impl TaskContext {
                 Task::RawTx(raw_tx) => {
                     debug!("Received raw tx");
                     let tx_bytes = raw_tx;
-                    let tx = Transaction::consensus_decode(&mut tx_bytes.as_slice())?;
+                    let tx = match Transaction::consensus_decode(&mut tx_bytes.as_slice()) {
+                        Ok(tx) => tx,
+                        Err(e) => {
+                            error!("Error decoding raw tx: {}", e);
+                            self.drop_tx("decode");
+                            continue;
+                        }
+                    };
                     if tx.is_coinbase() {
                         info!("Record coinbase tx");
                         // Record coinbase sperately
-                        self.db.record_coinbase_tx(&tx)?;
+                        self.db.record_coinbase_tx(&tx).await?;
+                        // A coinbase means a new block
+                        if let Err(e) = self.db.update_feerate_targets().await {
+                            warn!("Error updating fee rate targets: {}", e);
+                        }
                         continue;
                     }
 
                     let txid = tx.compute_txid();
-                    let tx_info = match self.bitcoind.get_raw_transaction_verbosity_one(&txid).await
-                    {
-                        Ok(tx_info) => tx_info,
-                        Err(e) => {
-                            error!("Error getting transaction info: {}", e);
+                    // Confirmations from the node may lag, don't resurrect a tx already seen mined
+                    match self.db.is_mined(txid).await {
+                        Ok(true) => {
+                            debug!("Skipping already mined tx: {:?}", txid);
                             continue;
                         }
-                    };
-                    let is_mined = tx_info.confirmations.unwrap_or(0) > 0;
-                    let fee = match get_absolute_fee(&tx, &self.bitcoind).await {
-                        Ok(fee) => fee,
+                        Ok(false) => {}
                         Err(e) => {
-                            error!("Error getting transaction fee: {}", e);
+                            error!("Error checking if tx was mined: {}", e);
+                            self.drop_tx("mined-check");
                             continue;
                         }
-                    };
-                    let fee_rate = match compute_fee_rate(&tx, fee) {
-                        Ok(fee_rate) => fee_rate,
-                        Err(e) => {
-                            error!("Error computing fee rate: {}", e);
-                            continue;
-                        }
-                    };
-
-                    if is_mined {
-                        self.db.record_mined_tx(&tx)?;
-                        info!("Transaction was mined: {:?}", txid);
+                    }
+                    let arrival_seq = self.config.arrival_seq.fetch_add(1, Ordering::Relaxed);
+                    // By inputs so a replacement of a stored tx is always processed, and watched
+                    // txs are never sampled out
+                    if self.config.sample_rate < 1.0
+                        && !is_inputs_sampled(
+                            &get_inputs_hash(tx.input.clone())?,
+                            self.config.sample_rate,
+                        )
+                        && self.watched_outputs(&tx).is_empty()
+                    {
+                        debug!("Skipping unsampled tx: {:?}", txid);
                         continue;
                     }
-
-                    if self.db.tx_exists(&tx)? {
-                        info!("Transaction was RBF'd: {:?}", txid);
-                        self.db.record_rbf(&tx, fee.to_sat(), fee_rate)?;
-                        self.db.update_txid_by_inputs_hash(&tx)?;
+                    if self.config.is_oversized(&tx) {
+                        info!("Recording oversized tx: {:?}", txid);
+                        self.db.record_oversized_tx(&tx).await?;
                         continue;
                     }
-
-                    self.db.insert_mempool_tx(tx, None, fee, fee_rate)?;
-                    self.db.flush()?;
-                    info!("Transaction inserted: {:?}", txid);
+                    self.process_tx(tx, arrival_seq, None).await?;
                 }
             }
         }
         info!("Worker shutting down");
         Ok(())
     }
+
+    /// Outputs of `tx` paying a `--watch-descriptor` script, as vout and value
+    fn watched_outputs(&self, tx: &Transaction) -> Vec<(u32, Amount)> {
+        self.config
+            .watched_scripts
+            .as_ref()
+            .map(|watched_scripts| watched_scripts.matching_outputs(tx))
+            .unwrap_or_default()
+    }
+
+    /// Whether an unconfirmed tx was already stored within the dedup window. Replacements have
+    /// a different txid, and a repeat with confirmations is the tx being mined, so neither is.
+    fn is_repeat(&self, txid: &Txid, confirmations: u64) -> bool {
+        if confirmations > 0 || !self.config.recent_txids.seen_recently(txid, Instant::now()) {
+            return false;
+        }
+        debug!("Skipping recently stored tx: {:?}", txid);
+        true
+    }
+
+    /// Look up the fee of a decoded tx and store it. `deferred` holds the found_at and retries
+    /// so far of a tx whose lookup failed before, see `Task::RecomputeFees`.
+    async fn process_tx(
+        &self,
+        tx: Transaction,
+        arrival_seq: u64,
+        deferred: Option<(u64, u32)>,
+    ) -> Result<()> {
+        let txid = tx.compute_txid();
+        let (confirmations, fee, unconfirmed_inputs, coin_ages) = if self.config.offline {
+            if self.is_repeat(&txid, 0) {
+                return Ok(());
+            }
+            // No node to look up confirmations or prevouts
+            (0, Amount::ZERO, None, None)
+        } else {
+            let confirmations = match self
+                .bitcoind
+                .call(|client| async move {
+                    let tx_info = client.get_raw_transaction_verbosity_one(&txid).await?;
+                    anyhow::Ok(tx_info.confirmations.unwrap_or(0) as u64)
+                })
+                .await
+            {
+                Ok(confirmations) => confirmations,
+                Err(e) => {
+                    error!("Error getting transaction info: {}", e);
+                    self.defer_or_drop(tx, arrival_seq, deferred, "tx-info")
+                        .await;
+                    return Ok(());
+                }
+            };
+            if self.is_repeat(&txid, confirmations) {
+                return Ok(());
+            }
+            let known_prevouts = if self.config.prevouts_from_db {
+                let outpoints = tx.input.iter().map(|vin| vin.previous_output).collect();
+                match self.db.tracked_prevouts(outpoints).await {
+                    Ok(known_prevouts) => known_prevouts,
+                    Err(e) => {
+                        warn!("Error looking up tracked prevouts: {}", e);
+                        HashMap::new()
+                    }
+                }
+            } else {
+                HashMap::new()
+            };
+            let prevouts = match get_absolute_fee_with_prevouts(
+                &tx,
+                &self.bitcoind,
+                &known_prevouts,
+                self.config.track_coin_age,
+            )
+            .await
+            {
+                Ok(prevouts) => prevouts,
+                Err(e) => {
+                    error!("Error getting transaction fee: {}", e);
+                    // A negative fee won't change on a retry
+                    if e.is::<NegativeFee>() {
+                        self.drop_tx("negative-fee");
+                    } else {
+                        self.defer_or_drop(tx, arrival_seq, deferred, "fee-lookup")
+                            .await;
+                    }
+                    return Ok(());
+                }
+            };
+            (
+                confirmations,
+                prevouts.fee,
+                self.config
+                    .prevouts_from_db
+                    .then_some(prevouts.known_inputs),
+                prevouts.coin_ages,
+            )
+        };
+        let fee_rate = match compute_fee_rate(&tx, fee) {
+            Ok(fee_rate) => fee_rate,
+            Err(e) => {
+                error!("Error computing fee rate: {}", e);
+                self.drop_tx("fee-rate");
+                return Ok(());
+            }
+        };
+
+        if let (true, Some(live_fee_rates)) = (confirmations > 0, &self.config.live_fee_rates) {
+            live_fee_rates.remove(&txid);
+            if let Err(e) = self.record_block_tx(&tx, confirmations, fee_rate).await {
+                warn!("Error recording block fee rate: {}", e);
+            }
+            return Ok(());
+        }
+        if confirmations > 0 {
+            self.db.record_mined_tx(&tx).await?;
+            info!("Transaction was mined: {:?}", txid);
+            if let Err(e) = self.record_block_tx(&tx, confirmations, fee_rate).await {
+                warn!("Error recording block fee rate: {}", e);
+            }
+            return Ok(());
+        }
+
+        if let Some(fee_validator) = &self.config.fee_validator {
+            if !self.config.offline && is_sampled(&txid, fee_validator.sample_rate) {
+                self.validate_fee(fee_validator, txid, fee).await;
+            }
+        }
+
+        // Matched before the filters so they can't hide a watched tx
+        let watched_outputs = self.watched_outputs(&tx);
+        if !watched_outputs.is_empty() {
+            info!(
+                "Watched tx entered the mempool: {:?} ({} outputs)",
+                txid,
+                watched_outputs.len()
+            );
+            if let Err(e) = self.db.record_watched_outputs(txid, watched_outputs).await {
+                error!("Error recording watched outputs: {}", e);
+            }
+        } else if let Some(reason) = self.config.filter.rejection(&tx, fee_rate) {
+            debug!("Filtered out tx {:?}: {}", txid, reason);
+            if let Err(e) = self.db.record_filtered_tx(reason).await {
+                error!("Error recording filtered tx: {}", e);
+            }
+            return Ok(());
+        }
+
+        if let Some(live_fee_rates) = &self.config.live_fee_rates {
+            live_fee_rates.insert(txid, fee_rate);
+            return Ok(());
+        }
+
+        if self.config.check_standardness {
+            if let Some(reason) = nonstandard_reason(&tx) {
+                info!("Non-standard tx relayed: {:?} ({})", txid, reason);
+                if let Err(e) = self.db.record_nonstandard_tx(txid, reason).await {
+                    error!("Error recording non-standard tx: {}", e);
+                }
+            }
+        }
+
+        let is_batch = is_batch_payout(&tx, self.config.batch_payout_min_outputs);
+        let stored_tx = tx.clone();
+        let found_at = deferred.map(|(found_at, _)| found_at);
+        let is_rbf = self
+            .store_unconfirmed_tx(tx, found_at, fee, fee_rate)
+            .await?;
+        self.config.recent_txids.record(txid, Instant::now());
+        if let Some(status_line) = &self.config.status_line {
+            status_line.record_tx(is_rbf);
+        }
+        if is_rbf {
+            info!("Transaction was RBF'd: {:?}", txid);
+        } else {
+            info!("Transaction inserted: {:?}", txid);
+            if let Err(e) = self.db.record_arrival_seq(txid, arrival_seq).await {
+                error!("Error recording arrival seq: {}", e);
+            }
+            if let Err(e) = self.db.record_truc_tx(&stored_tx).await {
+                error!("Error recording TRUC tx: {}", e);
+            }
+            // Not counted for same-input replacements, which mostly pay the same scripts again
+            if let Some(recent_scripts) = &self.config.recent_scripts {
+                let reused_outputs = recent_scripts.count_reused(&stored_tx);
+                if reused_outputs > 0 {
+                    if let Err(e) = self.db.record_reused_outputs(txid, reused_outputs).await {
+                        error!("Error recording reused outputs: {}", e);
+                    }
+                }
+            }
+        }
+        self.flag_fee_anomaly(txid, fee).await;
+        if let Some(unconfirmed_inputs) = unconfirmed_inputs {
+            if let Err(e) = self
+                .db
+                .record_unconfirmed_inputs(txid, unconfirmed_inputs)
+                .await
+            {
+                error!("Error recording unconfirmed inputs: {}", e);
+            }
+        }
+        // Also rewritten for replacements, which may pay out differently
+        if let Err(e) = self.db.record_batch_payouts(vec![(txid, is_batch)]).await {
+            error!("Error recording batch payout: {}", e);
+        }
+        if let Some(coin_ages) = coin_ages {
+            if let Err(e) = self.db.record_coin_ages(&stored_tx, coin_ages).await {
+                error!("Error recording coin ages: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare our found_at of sampled tracked txs with the node's mempool entry time
+    async fn record_time_drifts(&self, entry_times: &HashMap<Txid, u64>) -> Result<()> {
+        let rate = self.config.time_drift_sample_rate;
+        let drifts = self
+            .db
+            .unconfirmed_found_at()
+            .await?
+            .into_iter()
+            .filter(|(txid, _)| is_sampled(txid, rate))
+            .filter_map(|(txid, found_at)| {
+                let node_time = entry_times.get(&txid)?;
+                Some((txid, found_at, *node_time))
+            })
+            .collect::<Vec<_>>();
+        debug!("Recording time drift of {} txs", drifts.len());
+        self.db.record_time_drifts(drifts).await
+    }
+
+    /// Keep a tx whose fee lookup failed for `Task::RecomputeFees`, or drop it without retries
+    /// left or when deferral is off
+    async fn defer_or_drop(
+        &self,
+        tx: Transaction,
+        arrival_seq: u64,
+        deferred: Option<(u64, u32)>,
+        reason: &'static str,
+    ) {
+        let Some((found_at, retries)) = self.config.fee_deferral(deferred, now!()) else {
+            self.drop_tx(if deferred.is_some() {
+                "fee-retries"
+            } else {
+                reason
+            });
+            return;
+        };
+        let deferred = DeferredTx {
+            tx,
+            found_at,
+            arrival_seq,
+            retries,
+        };
+        if let Err(e) = self.db.defer_fee_tx(deferred).await {
+            error!("Error deferring tx: {}", e);
+            self.drop_tx(reason);
+        }
+    }
+
+    fn drop_tx(&self, reason: &'static str) {
+        if let Some(summary) = self.config.dropped_txs.record(reason) {
+            warn!("{}", summary);
+        }
+    }
+
+    /// Compare a computed fee with the node's, recording any discrepancy
+    async fn validate_fee(&self, fee_validator: &FeeValidator, txid: Txid, fee: Amount) {
+        let node_fee = match fee_validator.node_fee(&txid).await {
+            Ok(Some(node_fee)) => node_fee,
+            // Already mined or evicted
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Error validating fee of {:?}: {}", txid, e);
+                return;
+            }
+        };
+        if node_fee == fee {
+            return;
+        }
+        warn!(
+            "Computed fee {} of {:?} differs from the node's {}",
+            fee, txid, node_fee
+        );
+        if let Err(e) = self.db.record_fee_discrepancy(txid, fee, node_fee).await {
+            error!("Error recording fee discrepancy: {}", e);
+        }
+    }
+
+    /// Record the height a mined tx confirmed at, and count it towards that block's clearing fee rate
+    async fn record_block_tx(
+        &self,
+        tx: &Transaction,
+        confirmations: u64,
+        fee_rate: FeeRate,
+    ) -> Result<()> {
+        let tip = self
+            .bitcoind
+            .call(|client| async move { anyhow::Ok(client.get_block_count().await?) })
+            .await?;
+        let block_height = (tip + 1).saturating_sub(confirmations);
+        self.db.record_mined_height(tx, block_height).await?;
+        self.db.record_block_tx(block_height, fee_rate).await
+    }
+
+    /// Flag a stored tx whose absolute fee is above the anomaly ceiling
+    async fn flag_fee_anomaly(&self, txid: Txid, fee: Amount) {
+        if !self.config.is_fee_anomaly(fee) {
+            return;
+        }
+        warn!(
+            "Tx {:?} pays an absurd fee of {}, flagging it as a fee anomaly",
+            txid, fee
+        );
+        if let Err(e) = self.db.record_fee_anomaly(txid).await {
+            error!("Error recording fee anomaly: {}", e);
+        }
+    }
+
+    /// Insert an unconfirmed tx, or record it as an RBF of the tracked tx spending the same inputs.
+    /// Returns whether it was an RBF.
+    async fn store_unconfirmed_tx(
+        &self,
+        tx: Transaction,
+        found_at: Option<u64>,
+        fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<bool> {
+        let inputs_hash = get_inputs_hash(tx.input.clone())?;
+        // Held until the tx is stored so a concurrent replacement can't also miss `tx_exists`
+        let _guard = self.inputs_locks.lock(&inputs_hash).await;
+
+        // A filter miss means the inputs were never tracked, hits may be false positives
+        if self.known_inputs.contains(&inputs_hash) && self.db.tx_exists(&tx).await? {
+            self.db.record_rbf(&tx, fee.to_sat(), fee_rate).await?;
+            self.db
+                .update_txid_by_inputs_hash(&tx, fee, fee_rate)
+                .await?;
+            return Ok(true);
+        }
+
+        self.known_inputs.insert(&inputs_hash);
+        self.db
+            .insert_mempool_tx(tx, found_at, fee, fee_rate)
+            .await?;
+        self.db.flush().await?;
+        Ok(false)
+    }
+}
+
+/// Async locks sharded by inputs hash, shared by all workers so txs spending
+/// the same inputs are stored one at a time
+#[derive(Debug)]
+pub struct InputsLocks(Vec<Mutex<()>>);
+
+impl InputsLocks {
+    pub fn new(shards: usize) -> Self {
+        Self((0..shards.max(1)).map(|_| Mutex::new(())).collect())
+    }
+
+    pub async fn lock(&self, inputs_hash: &str) -> MutexGuard<'_, ()> {
+        // Inputs hashes are hex sha256, so any prefix is uniformly distributed
+        let prefix = inputs_hash.get(..8).unwrap_or(inputs_hash);
+        let shard = u64::from_str_radix(prefix, 16).unwrap_or(0) as usize % self.0.len();
+        self.0[shard].lock().await
+    }
+}
+
+/// Txids stored within the last `ttl`, so the same rawtx published more than once
+/// (e.g on rebroadcast) doesn't rerun the fee lookup and db pipeline. A zero `ttl` disables it.
+#[derive(Debug)]
+pub struct RecentTxids {
+    ttl: Duration,
+    seen: std::sync::Mutex<SeenTxids>,
+}
+
+/// When each txid was last stored, and the same in storage order for expiring them
+type SeenTxids = (HashMap<Txid, Instant>, VecDeque<(Instant, Txid)>);
+
+impl RecentTxids {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: std::sync::Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Whether `txid` was recorded within the window before `now`
+    pub fn seen_recently(&self, txid: &Txid, now: Instant) -> bool {
+        if self.ttl.is_zero() {
+            return false;
+        }
+        let seen = self.seen.lock().expect("recent txids lock poisoned");
+        seen.0
+            .get(txid)
+            .is_some_and(|seen_at| now.duration_since(*seen_at) < self.ttl)
+    }
+
+    /// Records `txid` as stored at `now`, expiring txids that fell out of the window
+    pub fn record(&self, txid: Txid, now: Instant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut seen = self.seen.lock().expect("recent txids lock poisoned");
+        let (seen_at, by_age) = &mut *seen;
+        // Oldest first, so expiring stops at the first txid still in the window
+        while let Some((at, expired)) = by_age.front().copied() {
+            if now.duration_since(at) < self.ttl {
+                break;
+            }
+            by_age.pop_front();
+            // A txid recorded again since has a newer entry further back
+            if seen_at.get(&expired) == Some(&at) {
+                seen_at.remove(&expired);
+            }
+        }
+        seen_at.insert(txid, now);
+        by_age.push_back((now, txid));
+    }
+}
+
+/// Fee rates of the unconfirmed txs seen since startup, the in memory stand in for
+/// `transactions` with --aggregates-only. Txs leave it when mined or on prune checks.
+#[derive(Debug, Default)]
+pub struct LiveFeeRates(std::sync::Mutex<HashMap<Txid, FeeRate>>);
+
+impl LiveFeeRates {
+    pub fn insert(&self, txid: Txid, fee_rate: FeeRate) {
+        self.0
+            .lock()
+            .expect("live fee rates lock poisoned")
+            .insert(txid, fee_rate);
+    }
+
+    pub fn remove(&self, txid: &Txid) {
+        self.0
+            .lock()
+            .expect("live fee rates lock poisoned")
+            .remove(txid);
+    }
+
+    /// Keep only the txs still in the node's mempool, which also drops replaced txs
+    pub fn retain(&self, mempool_txids: &[Txid]) {
+        let mempool_txids = mempool_txids.iter().collect::<HashSet<_>>();
+        self.0
+            .lock()
+            .expect("live fee rates lock poisoned")
+            .retain(|txid, _| mempool_txids.contains(txid));
+    }
+
+    /// Ascending fee rates of the live txs
+    pub fn fee_rates(&self) -> Vec<FeeRate> {
+        let mut fee_rates = self
+            .0
+            .lock()
+            .expect("live fee rates lock poisoned")
+            .values()
+            .copied()
+            .collect::<Vec<_>>();
+        fee_rates.sort();
+        fee_rates
+    }
+}
+
+/// The last `capacity` distinct output scripts seen, forgetting the oldest first, to count
+/// outputs paying a script seen before (address reuse) in bounded memory
+#[derive(Debug)]
+pub struct RecentScripts {
+    capacity: usize,
+    hasher: RandomState,
+    /// Each script is kept once, keyed by its hash, with the hashes in the order they were seen
+    scripts: std::sync::Mutex<(HashMap<u64, ScriptBuf>, VecDeque<u64>)>,
+}
+
+impl RecentScripts {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hasher: RandomState::new(),
+            scripts: std::sync::Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// How many outputs of `tx` pay a recently seen script, recording its scripts as seen.
+    /// OP_RETURN outputs don't pay anyone and are skipped.
+    pub fn count_reused(&self, tx: &Transaction) -> u64 {
+        if self.capacity == 0 {
+            return 0;
+        }
+        let mut scripts = self.scripts.lock().expect("recent scripts lock poisoned");
+        let (seen, order) = &mut *scripts;
+        let mut reused = 0;
+        for output in tx.output.iter() {
+            let script = &output.script_pubkey;
+            if script.is_op_return() {
+                continue;
+            }
+            let hash = self.hasher.hash_one(script);
+            if seen.get(&hash) == Some(script) {
+                reused += 1;
+                continue;
+            }
+            // A colliding script takes the slot, the one it replaces is forgotten early
+            if seen.insert(hash, script.clone()).is_some() {
+                continue;
+            }
+            if order.len() >= self.capacity {
+                if let Some(oldest) = order.pop_front() {
+                    seen.remove(&oldest);
+                }
+            }
+            order.push_back(hash);
+        }
+        reused
+    }
+}
+
+/// Tracks how long the task queue has held more than `threshold` tasks
+#[derive(Debug, Clone)]
+pub struct QueueLag {
+    pub threshold: usize,
+    /// How long the queue must stay above the threshold before it's reported
+    pub sustain: Duration,
+    above_since: Option<Instant>,
+}
+
+impl QueueLag {
+    pub fn new(threshold: usize, sustain: Duration) -> Self {
+        Self {
+            threshold,
+            sustain,
+            above_since: None,
+        }
+    }
+
+    /// Record the queue depth at `now`, true once it stayed above the threshold for `sustain`.
+    /// The period then restarts, so a lasting backlog is reported once per period.
+    pub fn observe(&mut self, depth: usize, now: Instant) -> bool {
+        if depth <= self.threshold {
+            self.above_since = None;
+            return false;
+        }
+        let above_since = *self.above_since.get_or_insert(now);
+        if now.saturating_duration_since(above_since) < self.sustain {
+            return false;
+        }
+        self.above_since = Some(now);
+        true
+    }
+}
+
+/// Lets an action run at most once per `window`, shared by all workers. A zero `window` disables it.
+/// Only a run reported successful with [`Self::finish`] starts a new window.
+#[derive(Debug)]
+pub struct Debounce {
+    window: Duration,
+    /// The last successful run, and whether a run is in progress
+    state: std::sync::Mutex<(Option<Instant>, bool)>,
+}
+
+impl Debounce {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: std::sync::Mutex::new((None, false)),
+        }
+    }
+
+    /// Whether the action may run now, if so [`Self::finish`] must be called once it's done
+    pub fn ready(&self) -> bool {
+        self.ready_at(Instant::now())
+    }
+
+    fn ready_at(&self, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+        let mut state = self.state.lock().expect("debounce lock poisoned");
+        let (last, running) = &mut *state;
+        if *running || last.is_some_and(|last| now.saturating_duration_since(last) < self.window) {
+            return false;
+        }
+        *running = true;
+        true
+    }
+
+    /// End a run let through by [`Self::ready`], a failed one lets the next attempt run right away
+    pub fn finish(&self, succeeded: bool) {
+        self.finish_at(Instant::now(), succeeded)
+    }
+
+    fn finish_at(&self, now: Instant, succeeded: bool) {
+        if self.window.is_zero() {
+            return;
+        }
+        let mut state = self.state.lock().expect("debounce lock poisoned");
+        let (last, running) = &mut *state;
+        *running = false;
+        if succeeded {
+            *last = Some(now);
+        }
+    }
+}
+
+/// Counts how many prune checks in a row each tracked tx was missing from the node's mempool,
+/// so a tx briefly absent (e.g. while the node handles a reorg) isn't marked pruned
+#[derive(Debug)]
+pub struct PruneGrace {
+    cycles: u32,
+    absences: std::sync::Mutex<HashMap<Txid, u32>>,
+}
+
+impl PruneGrace {
+    pub fn new(cycles: u32) -> Self {
+        Self {
+            cycles: cycles.max(1),
+            absences: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the txs missing in this check, returns the ones missing for `cycles` checks in a row
+    pub fn confirm_absent(&self, absent: Vec<Txid>) -> Vec<Txid> {
+        let mut absences = self.absences.lock().expect("prune grace lock poisoned");
+        let previous = std::mem::take(&mut *absences);
+        let mut pruned = vec![];
+        // Txs not missing this time reappeared and start over
+        for txid in absent {
+            let count = previous.get(&txid).copied().unwrap_or(0) + 1;
+            if count >= self.cycles {
+                pruned.push(txid);
+            } else {
+                absences.insert(txid, count);
+            }
+        }
+        pruned
+    }
+}
+
+/// Full prune checks skipped in a row at most, so a mempool whose churn happens to keep its tx
+/// count and size unchanged is still checked
+const MAX_SKIPPED_PRUNE_CHECKS: u32 = 10;
+
+/// The node's mempool tx count and size at the last full prune check, shared by all workers
+#[derive(Debug, Default)]
+pub struct PruneCheckSkip(std::sync::Mutex<(Option<(u64, u64)>, u32)>);
+
+impl PruneCheckSkip {
+    /// Whether a prune check seeing `(tx_count, bytes)` can skip the full fetch
+    pub fn should_skip(&self, mempool: (u64, u64)) -> bool {
+        let mut state = self.0.lock().expect("prune check skip lock poisoned");
+        let (last, skipped) = &mut *state;
+        if *last == Some(mempool) && *skipped < MAX_SKIPPED_PRUNE_CHECKS {
+            *skipped += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Record the `(tx_count, bytes)` seen by a full prune check once it succeeded
+    pub fn record_full_check(&self, mempool: (u64, u64)) {
+        let mut state = self.0.lock().expect("prune check skip lock poisoned");
+        *state = (Some(mempool), 0);
+    }
+}
+
+/// Per reason counts of txs dropped on errors, reported at most once per `interval`
+#[derive(Debug)]
+pub struct DroppedTxs {
+    interval: Duration,
+    window: std::sync::Mutex<DropWindow>,
+    /// Every tx dropped since startup, shown by the status line
+    total: AtomicU64,
+}
+
+#[derive(Debug)]
+struct DropWindow {
+    started: Instant,
+    counts: BTreeMap<&'static str, u64>,
+}
+
+impl DroppedTxs {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            window: std::sync::Mutex::new(DropWindow {
+                started: Instant::now(),
+                counts: BTreeMap::new(),
+            }),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Count a dropped tx, returns a summary of the window once `interval` has passed
+    pub fn record(&self, reason: &'static str) -> Option<String> {
+        self.record_at(reason, Instant::now())
+    }
+
+    fn record_at(&self, reason: &'static str, now: Instant) -> Option<String> {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        let mut window = self.window.lock().expect("dropped txs lock poisoned");
+        *window.counts.entry(reason).or_default() += 1;
+        let elapsed = now.saturating_duration_since(window.started);
+        if elapsed < self.interval {
+            return None;
+        }
+        let mut counts = std::mem::take(&mut window.counts)
+            .into_iter()
+            .collect::<Vec<_>>();
+        window.started = now;
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let total = counts.iter().map(|(_, count)| count).sum::<u64>();
+        let reasons = counts
+            .iter()
+            .map(|(reason, count)| format!("{} {}", count, reason))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "Dropped {} txs in last {}s: {}",
+            total,
+            elapsed.as_secs(),
+            reasons.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{offline_client, offline_task_context, outpoint, test_db};
+    use bitcoin::TxOut;
+    use std::{future, str::FromStr};
+
+    fn spending_tx(value: u64) -> Transaction {
+        crate::test_utils::spending_tx(&[outpoint("01", 0)], value)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_rbfs_insert_once() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let known_inputs = Arc::new(BloomFilter::new(1_000, 0.01));
+        let inputs_locks = Arc::new(InputsLocks::new(16));
+        let (_tasks_tx, tasks_rx) = async_channel::bounded(1);
+        let (_maintenance_tx, maintenance_rx) = async_channel::bounded(1);
+
+        let mut handles = vec![];
+        for i in 0..20 {
+            // Every worker shares the filter and locks, as in the app
+            let worker = TaskContext {
+                known_inputs: known_inputs.clone(),
+                inputs_locks: inputs_locks.clone(),
+                ..offline_task_context(db.clone(), tasks_rx.clone(), maintenance_rx.clone())
+            };
+            handles.push(tokio::spawn(async move {
+                let tx = spending_tx(90_000 - i * 100);
+                let fee = Amount::from_sat(1_000 + i * 100);
+                let fee_rate = FeeRate::from_sat_per_vb(1).expect("valid fee rate");
+                worker.store_unconfirmed_tx(tx, None, fee, fee_rate).await
+            }));
+        }
+
+        let mut inserts = 0;
+        for handle in handles {
+            if !handle.await?? {
+                inserts += 1;
+            }
+        }
+        // Exactly one version was inserted, every other one was seen as its replacement
+        assert_eq!(inserts, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_negative_fee() -> Result<()> {
+        // Never contacted, every prevout is known
+        let bitcoind = ResilientClient::new(offline_client());
+        let tx = spending_tx(90_000);
+        let prevouts = |value| HashMap::from([(outpoint("01", 0), Amount::from_sat(value))]);
+
+        let prevout_fee =
+            get_absolute_fee_with_prevouts(&tx, &bitcoind, &prevouts(100_000), true).await?;
+        assert_eq!(prevout_fee.fee, Amount::from_sat(10_000));
+        assert_eq!(prevout_fee.known_inputs, 1);
+        // Known prevouts are unconfirmed
+        assert_eq!(prevout_fee.coin_ages, Some(vec![0]));
+
+        // A misfetched prevout worth less than the outputs
+        let error = get_absolute_fee_with_prevouts(&tx, &bitcoind, &prevouts(80_000), false)
+            .await
+            .err()
+            .expect("inputs are worth less than the outputs");
+        let negative_fee = error
+            .downcast_ref::<NegativeFee>()
+            .expect("a negative fee error");
+        assert_eq!(negative_fee.txid, tx.compute_txid());
+        assert_eq!(negative_fee.input_value, Amount::from_sat(80_000));
+        assert_eq!(negative_fee.output_value, Amount::from_sat(90_000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_tasks_go_first() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+
+        let (tasks_tx, tasks_rx) = async_channel::bounded(1_000);
+        let (maintenance_tx, maintenance_rx) = async_channel::bounded(1);
+        // Undecodable txs and audits only touch the db
+        let mut worker = offline_task_context(db, tasks_rx.clone(), maintenance_rx);
+
+        for _ in 0..1_000 {
+            tasks_tx.send(Task::RawTx(vec![0])).await?;
+        }
+        maintenance_tx.send(Task::Audit).await?;
+        assert!(matches!(worker.next_task().await, Some(Task::Audit)));
+        assert_eq!(tasks_rx.len(), 1_000);
+
+        // Both queues drain before the worker exits
+        maintenance_tx.send(Task::Audit).await?;
+        tasks_tx.close();
+        maintenance_tx.close();
+        worker.run().await?;
+        assert!(tasks_rx.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fee_anomalies_are_flagged() -> Result<()> {
+        let (_tempdir, db) = test_db()?;
+        let (_tasks_tx, tasks_rx) = async_channel::bounded(1);
+        let (_maintenance_tx, maintenance_rx) = async_channel::bounded(1);
+        // Storing and flagging a tx only touches the db
+        let worker = offline_task_context(db.clone(), tasks_rx, maintenance_rx);
+
+        // The 19.82 BTC fee paid by mistake in November 2023
+        let fat_finger = spending_tx(10_000);
+        let fat_finger_fee = Amount::from_sat(1_982_000_000);
+        let regular = crate::test_utils::spending_tx(&[outpoint("02", 0)], 10_000);
+        let regular_fee = Amount::from_sat(5_000);
+        for (tx, fee) in [(&fat_finger, fat_finger_fee), (&regular, regular_fee)] {
+            let fee_rate = compute_fee_rate(tx, fee)?;
+            worker
+                .store_unconfirmed_tx(tx.clone(), Some(100), fee, fee_rate)
+                .await?;
+            worker.flag_fee_anomaly(tx.compute_txid(), fee).await;
+        }
+
+        assert_eq!(
+            db.fee_anomalies(0)?,
+            vec![(fat_finger.compute_txid(), fat_finger_fee, 100)]
+        );
+        assert!(db.fee_anomalies(101)?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_consistent_tip_retries_races() -> Result<()> {
+        let hash_at = |height: u64| {
+            BlockHash::from_str(&format!("{:064x}", height)).expect("valid block hash")
+        };
+        // Headers are looked up by hash, any hash ever seen keeps its height
+        let height_of = |hash: BlockHash| {
+            future::ready(
+                (90..110)
+                    .find(|height| hash_at(*height) == hash)
+                    .ok_or(anyhow::anyhow!("Block not found")),
+            )
+        };
+        let best_hashes = |hashes: Vec<Result<u64>>| {
+            let hashes = std::sync::Mutex::new(hashes.into_iter());
+            move || {
+                let hash = hashes.lock().unwrap().next();
+                future::ready(
+                    hash.unwrap_or(Err(anyhow::anyhow!("no more best hashes")))
+                        .map(hash_at),
+                )
+            }
+        };
+
+        // A block arriving after the best hash lookup doesn't change the height of that hash
+        let tip = consistent_tip(best_hashes(vec![Ok(100), Ok(101)]), height_of, 3).await?;
+        assert_eq!(tip, (100, hash_at(100)));
+
+        // A lookup failing mid reorg is retried
+        let tip = consistent_tip(
+            best_hashes(vec![Err(anyhow::anyhow!("Loading block index")), Ok(99)]),
+            height_of,
+            3,
+        )
+        .await?;
+        assert_eq!(tip, (99, hash_at(99)));
+        let failures = std::sync::Mutex::new(1);
+        let tip = consistent_tip(
+            best_hashes(vec![Ok(101), Ok(100)]),
+            |hash| {
+                let mut failures = failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return future::ready(Err(anyhow::anyhow!("Block not found")));
+                }
+                height_of(hash)
+            },
+            3,
+        )
+        .await?;
+        assert_eq!(tip, (100, hash_at(100)));
+
+        // Gives up once every attempt failed
+        assert!(
+            consistent_tip(best_hashes(vec![Ok(200), Ok(201)]), height_of, 2)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recent_txids_skips_repeats() {
+        let txid = spending_tx(90_000).compute_txid();
+        let replacement_txid = spending_tx(89_000).compute_txid();
+
+        let start = Instant::now();
+        let recent_txids = RecentTxids::new(Duration::from_secs(60));
+        // Only txids recorded once stored are skipped
+        assert!(!recent_txids.seen_recently(&txid, start));
+        assert!(!recent_txids.seen_recently(&txid, start));
+        recent_txids.record(txid, start);
+        assert!(recent_txids.seen_recently(&txid, start + Duration::from_secs(59)));
+        assert!(!recent_txids.seen_recently(&replacement_txid, start));
+
+        // Recording past the window expires the older txid
+        recent_txids.record(replacement_txid, start + Duration::from_secs(60));
+        assert!(!recent_txids.seen_recently(&txid, start + Duration::from_secs(60)));
+        assert_eq!(recent_txids.seen.lock().unwrap().0.len(), 1);
+
+        let disabled = RecentTxids::new(Duration::ZERO);
+        disabled.record(txid, start);
+        assert!(!disabled.seen_recently(&txid, start));
+    }
+
+    #[test]
+    fn test_mempool_state_debounce() {
+        let debounce = Debounce::new(Duration::from_secs(5));
+        let start = Instant::now();
+        // Two state tasks back to back store a single snapshot
+        assert!(debounce.ready_at(start));
+        assert!(!debounce.ready_at(start));
+        debounce.finish_at(start, true);
+        assert!(!debounce.ready_at(start + Duration::from_secs(4)));
+        assert!(debounce.ready_at(start + Duration::from_secs(5)));
+
+        // A failed snapshot doesn't hold back the next one
+        debounce.finish_at(start + Duration::from_secs(5), false);
+        assert!(debounce.ready_at(start + Duration::from_secs(6)));
+        debounce.finish_at(start + Duration::from_secs(6), true);
+        assert!(!debounce.ready_at(start + Duration::from_secs(7)));
+
+        let disabled = Debounce::new(Duration::ZERO);
+        assert!(disabled.ready_at(start));
+        assert!(disabled.ready_at(start));
+    }
+
+    #[test]
+    fn test_queue_lag() {
+        let start = Instant::now();
+        let mut queue_lag = QueueLag::new(100, Duration::from_secs(60));
+        assert!(!queue_lag.observe(500, start));
+        assert!(!queue_lag.observe(500, start + Duration::from_secs(30)));
+        assert!(queue_lag.observe(500, start + Duration::from_secs(60)));
+        // Reported once per period
+        assert!(!queue_lag.observe(500, start + Duration::from_secs(90)));
+        assert!(queue_lag.observe(500, start + Duration::from_secs(120)));
+        // Draining below the threshold restarts the period
+        assert!(!queue_lag.observe(100, start + Duration::from_secs(130)));
+        assert!(!queue_lag.observe(500, start + Duration::from_secs(180)));
+        assert!(queue_lag.observe(500, start + Duration::from_secs(240)));
+    }
+
+    #[test]
+    fn test_fee_deferral_retry_cap() {
+        let config = WorkerConfig {
+            max_fee_retries: Some(2),
+            ..WorkerConfig::default()
+        };
+        // The first failure keeps the time the tx was seen, retries keep it too
+        assert_eq!(config.fee_deferral(None, 100), Some((100, 0)));
+        assert_eq!(config.fee_deferral(Some((100, 0)), 200), Some((100, 1)));
+        assert_eq!(config.fee_deferral(Some((100, 1)), 300), None);
+
+        assert_eq!(WorkerConfig::default().fee_deferral(None, 100), None);
+    }
+
+    #[test]
+    fn test_live_fee_rates() {
+        let kwu = FeeRate::from_sat_per_kwu;
+        let mined = spending_tx(90_000).compute_txid();
+        let evicted = spending_tx(89_000).compute_txid();
+        let live = spending_tx(88_000).compute_txid();
+
+        let live_fee_rates = LiveFeeRates::default();
+        live_fee_rates.insert(mined, kwu(1_000));
+        live_fee_rates.insert(evicted, kwu(250));
+        live_fee_rates.insert(live, kwu(500));
+        assert_eq!(
+            live_fee_rates.fee_rates(),
+            vec![kwu(250), kwu(500), kwu(1_000)]
+        );
+
+        live_fee_rates.remove(&mined);
+        live_fee_rates.retain(&[live]);
+        assert_eq!(live_fee_rates.fee_rates(), vec![kwu(500)]);
+    }
+
+    #[test]
+    fn test_recent_scripts_counts_reuse() {
+        let pay = |scripts: &[u8]| {
+            let mut tx = spending_tx(0);
+            tx.output = scripts
+                .iter()
+                .map(|byte| TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x51, *byte]),
+                })
+                .collect();
+            tx
+        };
+
+        let recent_scripts = RecentScripts::new(2);
+        assert_eq!(recent_scripts.count_reused(&pay(&[1, 2])), 0);
+        assert_eq!(recent_scripts.count_reused(&pay(&[2])), 1);
+        // Paying the same script twice in one tx is reuse too
+        assert_eq!(recent_scripts.count_reused(&pay(&[3, 3])), 1);
+        // 1 was forgotten to make room for 3
+        assert_eq!(recent_scripts.count_reused(&pay(&[1, 3])), 1);
+
+        let mut data_carrier = pay(&[]);
+        data_carrier.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return([0u8; 4]),
+        });
+        assert_eq!(recent_scripts.count_reused(&data_carrier), 0);
+        assert_eq!(recent_scripts.count_reused(&data_carrier), 0);
+    }
+
+    #[test]
+    fn test_prune_check_skip() {
+        let prune_check_skip = PruneCheckSkip::default();
+        assert!(!prune_check_skip.should_skip((100, 50_000)));
+        // A full check that failed isn't recorded
+        assert!(!prune_check_skip.should_skip((100, 50_000)));
+        prune_check_skip.record_full_check((100, 50_000));
+        assert!(prune_check_skip.should_skip((100, 50_000)));
+        assert!(!prune_check_skip.should_skip((100, 51_000)));
+        prune_check_skip.record_full_check((100, 51_000));
+        for _ in 0..MAX_SKIPPED_PRUNE_CHECKS {
+            assert!(prune_check_skip.should_skip((100, 51_000)));
+        }
+        // Checked in full again after too many skips
+        assert!(!prune_check_skip.should_skip((100, 51_000)));
+        prune_check_skip.record_full_check((100, 51_000));
+        assert!(prune_check_skip.should_skip((100, 51_000)));
+    }
+
+    #[test]
+    fn test_dropped_txs_summary() {
+        let dropped_txs = DroppedTxs::new(Duration::from_secs(60));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(dropped_txs.record_at("fee-lookup", start).is_none());
+        }
+        assert!(dropped_txs.record_at("decode", start).is_none());
+
+        let summary = dropped_txs.record_at("decode", start + Duration::from_secs(61));
+        assert_eq!(
+            summary.as_deref(),
+            Some("Dropped 5 txs in last 61s: 3 fee-lookup, 2 decode")
+        );
+        // A new window starts after each summary
+        assert!(dropped_txs
+            .record_at("decode", start + Duration::from_secs(62))
+            .is_none());
+        assert_eq!(dropped_txs.total(), 6);
+    }
+
+    #[test]
+    fn test_prune_grace_resets_on_reappearance() {
+        let txid = spending_tx(90_000).compute_txid();
+        let prune_grace = PruneGrace::new(2);
+
+        assert!(prune_grace.confirm_absent(vec![txid]).is_empty());
+        // Back in the mempool for one cycle
+        assert!(prune_grace.confirm_absent(vec![]).is_empty());
+        assert!(prune_grace.confirm_absent(vec![txid]).is_empty());
+        assert_eq!(prune_grace.confirm_absent(vec![txid]), vec![txid]);
+
+        let immediate = PruneGrace::new(1);
+        assert_eq!(immediate.confirm_absent(vec![txid]), vec![txid]);
+    }
 }
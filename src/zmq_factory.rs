@@ -1,10 +1,80 @@
-use anyhow::Result;
-use bitcoincore_zmq::MessageStream;
+use std::{collections::HashSet, fmt, str::FromStr, time::Duration};
 
+use anyhow::{anyhow, Result};
+use bitcoincore_zmq::Message;
+use futures_util::{future, Stream, StreamExt};
+
+/// A zmq topic bitcoind can publish, enabled with `-zmqpub<topic>=<address>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZmqTopic {
+    RawTx,
+    RawBlock,
+    HashTx,
+    HashBlock,
+    Sequence,
+}
+
+impl ZmqTopic {
+    pub const ALL: [ZmqTopic; 5] = [
+        ZmqTopic::RawTx,
+        ZmqTopic::RawBlock,
+        ZmqTopic::HashTx,
+        ZmqTopic::HashBlock,
+        ZmqTopic::Sequence,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZmqTopic::RawTx => "rawtx",
+            ZmqTopic::RawBlock => "rawblock",
+            ZmqTopic::HashTx => "hashtx",
+            ZmqTopic::HashBlock => "hashblock",
+            ZmqTopic::Sequence => "sequence",
+        }
+    }
+
+    pub fn of(message: &Message) -> Self {
+        match message {
+            Message::Tx(..) => ZmqTopic::RawTx,
+            Message::Block(..) => ZmqTopic::RawBlock,
+            Message::HashTx(..) => ZmqTopic::HashTx,
+            Message::HashBlock(..) => ZmqTopic::HashBlock,
+            Message::Sequence(..) => ZmqTopic::Sequence,
+        }
+    }
+}
+
+impl fmt::Display for ZmqTopic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ZmqTopic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ZmqTopic::ALL
+            .into_iter()
+            .find(|topic| topic.name() == s)
+            .ok_or_else(|| {
+                let known = ZmqTopic::ALL.map(|topic| topic.name()).join(", ");
+                anyhow!("Unknown zmq topic {}, expected one of {}", s, known)
+            })
+    }
+}
+
+/// The receive high-water mark can't be set through `bitcoincore_zmq`, so drops under bursts
+/// are instead detected from gaps in the per-topic sequence numbers bitcoind attaches.
+/// Raise the node side limit with `-zmqpubrawtxhwm=<n>`.
 #[derive(Debug, Clone)]
 pub struct BitcoinZmqFactory {
     bitcoind_host: String,
     bitcoind_zmq_port: u16,
+    /// Warn when no message arrived for this long
+    recv_timeout: Option<Duration>,
+    /// Messages of other topics are dropped
+    topics: HashSet<ZmqTopic>,
 }
 
 impl BitcoinZmqFactory {
@@ -12,14 +82,138 @@ impl BitcoinZmqFactory {
         Self {
             bitcoind_host,
             bitcoind_zmq_port,
+            recv_timeout: None,
+            topics: HashSet::from([ZmqTopic::RawTx]),
+        }
+    }
+
+    pub fn with_topics(mut self, topics: impl IntoIterator<Item = ZmqTopic>) -> Self {
+        self.topics = topics.into_iter().collect();
+        self
+    }
+
+    pub fn with_recv_timeout(mut self, recv_timeout: Duration) -> Self {
+        self.recv_timeout = Some(recv_timeout);
+        self
+    }
+
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        self.recv_timeout
+    }
+
+    /// Check the enabled topics against the node's `getzmqnotifications`, each must be
+    /// published on our zmq port
+    pub fn check_notifications(&self, notifications: &serde_json::Value) -> Result<()> {
+        let notifications = notifications
+            .as_array()
+            .ok_or(anyhow!("getzmqnotifications did not return an array"))?;
+        let port_suffix = format!(":{}", self.bitcoind_zmq_port);
+        let mut missing = self
+            .topics
+            .iter()
+            .filter(|topic| {
+                let kind = format!("pub{}", topic.name());
+                !notifications.iter().any(|notification| {
+                    notification["type"].as_str() == Some(kind.as_str())
+                        && notification["address"]
+                            .as_str()
+                            .is_some_and(|address| address.ends_with(&port_suffix))
+                })
+            })
+            .map(|topic| topic.name())
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return Ok(());
         }
+        missing.sort();
+        Err(anyhow!(
+            "The node does not publish {} on port {}, enable it with -zmqpub<topic>=<address> or drop it from --zmq-topics",
+            missing.join(", "),
+            self.bitcoind_zmq_port
+        ))
     }
 
-    pub fn connect(&self) -> Result<MessageStream> {
+    /// Subscribe to the node, only yielding messages of the enabled topics
+    pub fn connect(
+        &self,
+    ) -> Result<impl Stream<Item = Result<Message, bitcoincore_zmq::Error>> + Unpin> {
         let zmq = bitcoincore_zmq::subscribe_async(&[&format!(
             "tcp://{}:{}",
             self.bitcoind_host, self.bitcoind_zmq_port
         )])?;
-        Ok(zmq)
+        let topics = self.topics.clone();
+        Ok(zmq.filter(move |message| {
+            future::ready(match message {
+                Ok(message) => topics.contains(&ZmqTopic::of(message)),
+                Err(_) => true,
+            })
+        }))
+    }
+}
+
+/// Counts messages lost between consecutive sequence numbers of a zmq topic
+#[derive(Debug, Default)]
+pub struct SequenceGaps {
+    last: Option<u32>,
+    pub total_dropped: u64,
+}
+
+impl SequenceGaps {
+    /// Record a received sequence number, returns how many messages were missed before it
+    pub fn observe(&mut self, sequence: u32) -> u32 {
+        // Sequence numbers wrap around and restart from 0 when the node restarts,
+        // a lower number is taken as a new baseline
+        let dropped = match self.last {
+            Some(last) if sequence > last => sequence - last - 1,
+            _ => 0,
+        };
+        self.last = Some(sequence);
+        self.total_dropped += dropped as u64;
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zmq_topic_from_str() {
+        for topic in ZmqTopic::ALL {
+            assert_eq!(topic.name().parse::<ZmqTopic>().unwrap(), topic);
+        }
+        assert!("rawtxs".parse::<ZmqTopic>().is_err());
+        assert!("RAWTX".parse::<ZmqTopic>().is_err());
+    }
+
+    #[test]
+    fn test_check_notifications() {
+        let notifications = serde_json::json!([
+            { "type": "pubrawtx", "address": "tcp://127.0.0.1:28332", "hwm": 1000 },
+            { "type": "pubrawblock", "address": "tcp://127.0.0.1:28333", "hwm": 1000 },
+        ]);
+        let factory = BitcoinZmqFactory::new("127.0.0.1".to_string(), 28332);
+        assert!(factory.check_notifications(&notifications).is_ok());
+
+        // rawblock is published on another port than the one we subscribe to
+        let factory = factory.with_topics([ZmqTopic::RawTx, ZmqTopic::RawBlock]);
+        let error = factory
+            .check_notifications(&notifications)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("rawblock"));
+        assert!(!error.contains("rawtx"));
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let mut gaps = SequenceGaps::default();
+        assert_eq!(gaps.observe(10), 0);
+        assert_eq!(gaps.observe(11), 0);
+        assert_eq!(gaps.observe(15), 3);
+        // A node restart restarts the sequence
+        assert_eq!(gaps.observe(0), 0);
+        assert_eq!(gaps.observe(2), 1);
+        assert_eq!(gaps.total_dropped, 4);
     }
 }
@@ -6,12 +6,41 @@ mod tests {
     use bitcoin::{Amount, Transaction, Txid};
     use bitcoind_async_client::{Auth as AsyncAuth, Client as AsyncClient};
     use corepc_node::{Client, Node, WalletCreateFundedPsbtInput};
-    use mempool_tracker::{app::App, database::Database, zmq_factory::BitcoinZmqFactory};
+    use mempool_tracker::{
+        app::{App, MemoryLimits, OverflowPolicies},
+        database::Database,
+        worker::WorkerConfig,
+        zmq_factory::BitcoinZmqFactory,
+    };
     use std::collections::BTreeMap;
     use std::str::FromStr;
     use std::time::Duration;
     use tempfile::TempDir;
 
+    /// ZMQ has no subscription handshake, so give the subscriber time to connect
+    /// before the node publishes anything we expect to see
+    const ZMQ_CONNECT_DELAY: Duration = Duration::from_secs(3);
+    /// Upper bound on how long the app may take to persist an event
+    const PROCESSING_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Poll `condition` until it holds, failing once `timeout` elapses.
+    /// Use this instead of sleeping for a fixed duration and hoping the app caught up.
+    async fn wait_for<F>(timeout: Duration, mut condition: F) -> Result<()>
+    where
+        F: FnMut() -> Result<bool>,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if condition()? {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("condition not met within {:?}", timeout));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
     struct TestContext {
         _node: Node,        // Keep node alive - dropping it kills the process
         rpc_client: Client, // RPC client connected to the wallet
@@ -67,10 +96,20 @@ mod tests {
                 zmq_factory,
                 db.clone(),
                 2,
-                Duration::from_secs(25),
+                Some(Duration::from_secs(25)),
                 Duration::from_secs(120),
                 false, // disable_prune_check
                 None,
+                None, // audit_interval
+                None, // node_info_interval
+                None, // recompute_fees_interval
+                None, // stuck_tx_check_interval
+                None, // queue_lag
+                8,    // startup_concurrency
+                MemoryLimits::default(),
+                OverflowPolicies::default(),
+                None, // shutdown_snapshot
+                WorkerConfig::default(),
             );
 
             app.init().await?;
@@ -92,11 +131,10 @@ mod tests {
         let app_handle = tokio::spawn(async move {
             ctx.app.run().await.unwrap();
         });
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        tokio::time::sleep(ZMQ_CONNECT_DELAY).await;
         let address = ctx.rpc_client.new_address()?;
         ctx.rpc_client.generate_to_address(1, &address)?;
 
-        tokio::time::sleep(Duration::from_secs(2)).await;
         // Check that we have one transaction in the db and it's a coinbase
         // Instead of iterating over all transactions in the db, look up the transaction by txid.
         // There should only be one transaction in the db after mining a single block, and it should be a coinbase.
@@ -104,6 +142,10 @@ mod tests {
         let block = ctx.rpc_client.get_block(latest_block_hash)?;
         // The coinbase transaction is always the first transaction in a block
         let coinbase_txid = block.txdata[0].compute_txid();
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&coinbase_txid)?.is_some())
+        })
+        .await?;
         let tx_opt = ctx.db.get_tx_by_txid(&coinbase_txid)?;
         assert!(
             tx_opt.is_some(),
@@ -131,7 +173,7 @@ mod tests {
         let app_handle = tokio::spawn(async move {
             ctx.app.run().await.unwrap();
         });
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        tokio::time::sleep(ZMQ_CONNECT_DELAY).await;
 
         // Create multiple transactions
         let address1 = ctx.rpc_client.new_address()?;
@@ -144,7 +186,12 @@ mod tests {
         let txid3 = ctx.rpc_client.send_to_address(&address3, amount)?.txid()?;
 
         // Wait for transactions to be processed
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&txid1)?.is_some()
+                && ctx.db.get_tx_by_txid(&txid2)?.is_some()
+                && ctx.db.get_tx_by_txid(&txid3)?.is_some())
+        })
+        .await?;
 
         // Verify transactions are in database as unconfirmed
 
@@ -160,7 +207,10 @@ mod tests {
         // Mine a block with these transactions
         ctx.rpc_client.generate_to_address(1, &address1)?;
 
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.is_mined(&txid1)? && ctx.db.is_mined(&txid2)? && ctx.db.is_mined(&txid3)?)
+        })
+        .await?;
 
         assert!(ctx.db.is_mined(&txid1)?);
         assert!(ctx.db.is_mined(&txid2)?);
@@ -178,7 +228,7 @@ mod tests {
         let app_handle = tokio::spawn(async move {
             ctx.app.run().await.unwrap();
         });
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        tokio::time::sleep(ZMQ_CONNECT_DELAY).await;
 
         // Create a transaction with RBF enabled (low fee)
         let address = ctx.rpc_client.new_address()?;
@@ -188,7 +238,10 @@ mod tests {
         let txid1 = ctx.rpc_client.send_to_address(&address, amount)?.txid()?;
 
         // Wait for first transaction to be processed
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&txid1)?.is_some())
+        })
+        .await?;
 
         let tx1 = ctx.rpc_client.get_raw_transaction(txid1)?.transaction()?;
         let txid1_computed = tx1.compute_txid();
@@ -213,7 +266,10 @@ mod tests {
         .expect("failed to parse txid");
 
         // Wait for RBF transaction to be processed
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&txid2)?.is_some() && ctx.db.is_rbf(&txid2)?)
+        })
+        .await?;
 
         let tx2 = ctx.rpc_client.get_raw_transaction(txid2)?.transaction()?;
         let txid2_computed = tx2.compute_txid();
@@ -236,7 +292,7 @@ mod tests {
         let app_handle = tokio::spawn(async move {
             ctx.app.run().await.unwrap();
         });
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        tokio::time::sleep(ZMQ_CONNECT_DELAY).await;
 
         let parent_txid = ctx
             .rpc_client
@@ -244,7 +300,10 @@ mod tests {
             .txid()?;
 
         // Wait for parent transaction to be processed
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&parent_txid)?.is_some())
+        })
+        .await?;
 
         let parent_tx = ctx
             .rpc_client
@@ -276,7 +335,10 @@ mod tests {
         let hex = hex::decode(signed_psbt.hex.unwrap())?;
         let child_tx = Transaction::consensus_decode(&mut hex.as_slice())?;
         let child_txid = ctx.rpc_client.send_raw_transaction(&child_tx)?.txid()?;
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        wait_for(PROCESSING_TIMEOUT, || {
+            Ok(ctx.db.get_tx_by_txid(&child_txid)?.is_some())
+        })
+        .await?;
 
         // Verify child transaction is in database
         assert!(ctx.db.get_tx_by_txid(&child_txid)?.is_some());